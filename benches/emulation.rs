@@ -0,0 +1,129 @@
+//! Benchmarks of the emulation hot paths. Each one runs a small synthetic ROM
+//! that stresses one part of the emulator and reports the time per frame.
+//! Run with:
+//!
+//! ```text
+//! cargo bench --bench emulation [name]
+//! ```
+
+use rustboy::Gameboy;
+use std::env;
+use std::time::{ Duration, Instant };
+
+/// Frames run before timing starts
+const WARMUP_FRAMES: u32 = 60;
+
+/// Frames timed in each run
+const FRAMES: u32 = 600;
+
+/// Runs of each benchmark. The fastest is reported.
+const RUNS: u32 = 5;
+
+/// Make a ROM that jumps from the entry point to `prog` at 0x0150
+fn rom(prog: &[u8]) -> Vec< u8 >
+{
+    let mut rom = vec![0; 0x8000];
+    rom[0x100..0x104].copy_from_slice(&[0x00, 0xC3, 0x50, 0x01]);
+    rom[0x150..0x150 + prog.len()].copy_from_slice(prog);
+    rom
+}
+
+/// A ROM that keeps reading and writing WRAM, HRAM, the timer, the keypad
+/// and VRAM with the LCD off, so the time goes to dispatching bus accesses
+fn bus_rom() -> Vec< u8 >
+{
+    rom(&[
+        0xAF, 0xE0, 0x40,               // xor a; ldh (LCDC), a
+        0x21, 0x00, 0xC0,               // loop: ld hl, 0xC000
+        0x7E, 0x34,                     // ld a, (hl); inc (hl)
+        0xF0, 0x80, 0x3C, 0xE0, 0x80,   // ldh a, (0x80); inc a; ldh (0x80), a
+        0xF0, 0x04,                     // ldh a, (DIV)
+        0xF0, 0x00,                     // ldh a, (P1)
+        0xEA, 0x00, 0x80,               // ld (0x8000), a
+        0xF0, 0x44,                     // ldh a, (LY)
+        0x18, 0xEB                      // jr loop
+    ])
+}
+
+/// A ROM that fills VRAM and OAM with a pattern and shows the background,
+/// the window over the bottom right and sprites on every line, so the time
+/// goes to rendering scanlines
+fn render_rom() -> Vec< u8 >
+{
+    rom(&[
+        0xAF, 0xE0, 0x40,               // xor a; ldh (LCDC), a
+        0x21, 0x00, 0x80,               // ld hl, 0x8000
+        0x7D, 0x22,                     // vram: ld a, l; ld (hl+), a
+        0x7C, 0xFE, 0xA0,               // ld a, h; cp 0xA0
+        0x20, 0xF9,                     // jr nz, vram
+        0x21, 0x00, 0xFE,               // ld hl, 0xFE00
+        0x7D, 0x22,                     // oam: ld a, l; ld (hl+), a
+        0xFE, 0x9F, 0x20, 0xFA,         // cp 0x9F; jr nz, oam
+        0x3E, 0xE4, 0xE0, 0x47,         // ld a, 0xE4; ldh (BGP), a
+        0xE0, 0x48,                     // ldh (OBP0), a
+        0x3E, 0x1B, 0xE0, 0x49,         // ld a, 0x1B; ldh (OBP1), a
+        0x3E, 0x40, 0xE0, 0x4A,         // ld a, 0x40; ldh (WY), a
+        0x3E, 0x57, 0xE0, 0x4B,         // ld a, 0x57; ldh (WX), a
+        0x3E, 0xF3, 0xE0, 0x40,         // ld a, 0xF3; ldh (LCDC), a
+        0x18, 0xFE                      // jr -2
+    ])
+}
+
+/// A ROM that keeps rewriting every tile with the LCD on, so the time goes
+/// to decoding tile rows. Compare with `--features tile-lut`.
+fn tiles_rom() -> Vec< u8 >
+{
+    rom(&[
+        0x3E, 0xE4, 0xE0, 0x47,         // ld a, 0xE4; ldh (BGP), a
+        0x3E, 0x91, 0xE0, 0x40,         // ld a, 0x91; ldh (LCDC), a
+        0x21, 0x00, 0x80,               // tiles: ld hl, 0x8000
+        0x7D, 0x80, 0x22,               // copy: ld a, l; add a, b; ld (hl+), a
+        0x7C, 0xFE, 0x98,               // ld a, h; cp 0x98
+        0x20, 0xF8,                     // jr nz, copy
+        0x04,                           // inc b
+        0x18, 0xF2                      // jr tiles
+    ])
+}
+
+/// Run `rom` and print the fastest time per frame
+fn bench(name: &str, rom: Vec< u8 >)
+{
+    let mut best = Duration::MAX;
+    for _ in 0..RUNS
+    {
+        let mut gb = Gameboy::from_rom(rom.clone());
+        for _ in 0..WARMUP_FRAMES
+        {
+            gb.run_frame();
+        }
+
+        let start = Instant::now();
+        for _ in 0..FRAMES
+        {
+            gb.run_frame();
+        }
+        best = best.min(start.elapsed());
+    }
+
+    let per_frame = best.as_secs_f64() / FRAMES as f64;
+    println!("{:<8} {:>8.1} us/frame {:>10.1} frames per second", name, per_frame * 1e6, 1.0 / per_frame);
+}
+
+fn main()
+{
+    // Cargo passes --bench, anything else picks the benchmarks to run
+    let filter = env::args().skip(1).find(|arg| !arg.starts_with('-'));
+    let benches: [(&str, fn() -> Vec< u8 >); 3] = [
+        ("bus", bus_rom),
+        ("render", render_rom),
+        ("tiles", tiles_rom)
+    ];
+
+    for (name, rom) in benches.iter()
+    {
+        if filter.as_ref().map_or(true, |f| name.contains(f.as_str()))
+        {
+            bench(name, rom());
+        }
+    }
+}
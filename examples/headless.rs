@@ -0,0 +1,119 @@
+//! Running a game without a window: the starting point for test runners,
+//! bots and batch tools. Runs a ROM for a number of frames while holding
+//! Start every other second, checks that a savestate taken halfway through
+//! replays to the same frame, and writes the last frame out as a PPM image.
+//! Run with:
+//!
+//! ```text
+//! cargo run --release --example headless -- <rom> [frames] [out.ppm]
+//! ```
+
+use rustboy::prelude::*;
+use std::env;
+use std::fs::File;
+use std::io::{ BufWriter, Result as IoResult, Write };
+use std::path::Path;
+use std::process;
+use std::sync::Arc;
+use std::sync::atomic::{ AtomicUsize, Ordering };
+
+fn main()
+{
+    let args: Vec< String > = env::args().collect();
+    if args.len() < 2 || args.len() > 4
+    {
+        eprintln!("Usage: {} <rom> [frames] [out.ppm]", args[0]);
+        process::exit(1);
+    }
+    let frames = match args.get(2).map(|f| f.parse::< u32 >())
+    {
+        Some(Ok(frames)) => frames,
+        Some(Err(_)) => {
+            eprintln!("Invalid frame count: {}", args[2]);
+            process::exit(1);
+        },
+        None => 600
+    };
+
+    // Loading from bytes rather than a path leaves the GameBoy without
+    // storage, so the game's save file is never touched
+    let rom = match std::fs::read(&args[1])
+    {
+        Ok(rom) => rom,
+        Err(e) => {
+            eprintln!("Unable to read {}: {}", args[1], e);
+            process::exit(1);
+        }
+    };
+    let mut gb = match Gameboy::try_from_rom(rom)
+    {
+        Ok(gb) => gb,
+        Err(e) => {
+            eprintln!("Unable to load {}: {}", args[1], e);
+            process::exit(1);
+        }
+    };
+    println!("Running {} for {} frames", gb.title(), frames);
+
+    // Audio can be pushed to a callback as it is produced instead of being
+    // pulled out of next_av_chunk
+    let samples = Arc::new(AtomicUsize::new(0));
+    let counter = samples.clone();
+    gb.set_audio_callback(move |batch| { counter.fetch_add(batch.len() / 2, Ordering::Relaxed); });
+
+    // Hold Start every other second
+    let second = gb.frame_rate().round() as u32;
+    let script = |frame: u32| if frame / second % 2 == 1 { ButtonState::START } else { ButtonState::empty() };
+
+    let mut checkpoint = None;
+    for frame in 0..frames
+    {
+        gb.set_inputs(script(frame));
+        if let RunExit::Hung(hang) = gb.run_frame()
+        {
+            eprintln!("Game hung: {}", hang);
+            process::exit(1);
+        }
+        if frame == frames / 2
+        {
+            checkpoint = Some(gb.save_state());
+        }
+    }
+    let hash = gb.frame_hash();
+    println!("Final frame hash {:016x}, {} samples of audio", hash, samples.load(Ordering::Relaxed));
+
+    // Emulation is deterministic, so replaying the second half from the
+    // savestate with the same inputs gives the same frame
+    if let Some(state) = checkpoint
+    {
+        gb.load_state(&state).unwrap();
+        for frame in frames / 2 + 1..frames
+        {
+            gb.set_inputs(script(frame));
+            gb.run_frame();
+        }
+        assert_eq!(gb.frame_hash(), hash, "Replaying from the savestate diverged");
+        println!("Replay from frame {} matches", frames / 2);
+    }
+
+    if let Some(path) = args.get(3)
+    {
+        match write_ppm(Path::new(path), gb.get_image_data())
+        {
+            Ok(()) => println!("Wrote {}", path),
+            Err(e) => eprintln!("Unable to write {}: {}", path, e)
+        }
+    }
+}
+
+/// Write RGBA image data of the screen size as a binary PPM image
+fn write_ppm(path: &Path, image: &[u8]) -> IoResult< () >
+{
+    let mut out = BufWriter::new(File::create(path)?);
+    write!(out, "P6\n{} {}\n255\n", DISPLAY_WIDTH, DISPLAY_HEIGHT)?;
+    for pixel in image.chunks_exact(4)
+    {
+        out.write_all(&pixel[..3])?;
+    }
+    out.flush()
+}
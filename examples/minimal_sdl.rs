@@ -0,0 +1,181 @@
+//! A complete frontend in one file using SDL2: video, audio, keyboard input
+//! and a savestate slot. Needs the SDL2 development libraries installed.
+//! Run with:
+//!
+//! ```text
+//! cargo run --release --features sdl-example --example minimal_sdl -- <rom>
+//! ```
+//!
+//! Arrow keys are the D-pad, Z and X are B and A, Enter is Start and
+//! Backspace is Select. F5 saves the state and F7 loads it back.
+
+use rustboy::prelude::*;
+use rustboy::SAMPLE_RATE;
+use sdl2::audio::{ AudioCallback, AudioSpecDesired };
+use sdl2::event::Event;
+use sdl2::keyboard::Keycode;
+use sdl2::pixels::PixelFormatEnum;
+use std::collections::VecDeque;
+use std::env;
+use std::path::Path;
+use std::process;
+use std::sync::{ Arc, Mutex };
+use std::sync::atomic::{ AtomicU64, Ordering };
+
+/// Window scale
+const SCALE: u32 = 3;
+
+/// The most audio buffered between the emulator and the device, a tenth of
+/// a second of stereo samples. Older samples are dropped when the emulator
+/// gets ahead, which keeps latency down.
+const MAX_BUFFERED: usize = SAMPLE_RATE as usize / 5;
+
+/// Plays the samples the emulator's audio callback queues up
+struct Speaker
+{
+    buffer: Arc< Mutex< VecDeque< i16 > > >,
+
+    /// Stereo samples handed to the device, including silence played when
+    /// the buffer ran dry
+    played: Arc< AtomicU64 >
+}
+
+impl AudioCallback for Speaker
+{
+    type Channel = i16;
+
+    fn callback(&mut self, out: &mut [i16])
+    {
+        let mut buffer = self.buffer.lock().unwrap();
+        for sample in out.iter_mut()
+        {
+            *sample = buffer.pop_front().unwrap_or(0);
+        }
+        self.played.fetch_add(out.len() as u64 / 2, Ordering::Relaxed);
+    }
+}
+
+fn main()
+{
+    let args: Vec< String > = env::args().collect();
+    if args.len() != 2
+    {
+        eprintln!("Usage: {} <rom>", args[0]);
+        process::exit(1);
+    }
+
+    if let Err(e) = run(Path::new(&args[1]))
+    {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+}
+
+fn run(rom: &Path) -> Result< (), String >
+{
+    let sdl = sdl2::init()?;
+    let video = sdl.video()?;
+    let window = video.window("rustboy", DISPLAY_WIDTH as u32 * SCALE, DISPLAY_HEIGHT as u32 * SCALE)
+        .position_centered()
+        .build()
+        .map_err(|e| e.to_string())?;
+
+    // Presenting with vsync paces the game to the display, assuming it runs
+    // at about 60 Hz like the GameBoy
+    let mut canvas = window.into_canvas().present_vsync().build().map_err(|e| e.to_string())?;
+    let textures = canvas.texture_creator();
+
+    // ABGR8888 is RGBA byte order on little endian hosts, which matches the
+    // image data
+    let mut screen = textures.create_texture_streaming(PixelFormatEnum::ABGR8888,
+        DISPLAY_WIDTH as u32, DISPLAY_HEIGHT as u32).map_err(|e| e.to_string())?;
+
+    let mut gb = Gameboy::new(rom);
+
+    // The emulator pushes audio into a buffer that the SDL audio thread
+    // pulls from
+    let buffer = Arc::new(Mutex::new(VecDeque::new()));
+    let queue = buffer.clone();
+    gb.set_audio_callback(move |samples| {
+        let mut queue = queue.lock().unwrap();
+        queue.extend(samples.iter().copied());
+        let excess = queue.len().saturating_sub(MAX_BUFFERED);
+        queue.drain(..excess);
+    });
+
+    let audio = sdl.audio()?;
+    let spec = AudioSpecDesired {
+        freq: Some(SAMPLE_RATE as i32),
+        channels: Some(2),
+        samples: None
+    };
+    let played = Arc::new(AtomicU64::new(0));
+    let counter = played.clone();
+    let device = audio.open_playback(None, &spec, |_| Speaker { buffer, played: counter })?;
+    device.resume();
+
+    let mut state = None;
+    let mut reported = 0;
+    let mut events = sdl.event_pump()?;
+    'running: loop
+    {
+        for event in events.poll_iter()
+        {
+            match event
+            {
+                Event::Quit { .. } | Event::KeyDown { keycode: Some(Keycode::Escape), .. } => break 'running,
+                Event::KeyDown { keycode: Some(Keycode::F5), .. } => state = Some(gb.save_state()),
+                Event::KeyDown { keycode: Some(Keycode::F7), .. } => {
+                    if let Some(state) = state.as_ref()
+                    {
+                        gb.load_state(state).map_err(|e| e.to_string())?;
+                    }
+                },
+                Event::KeyDown { keycode: Some(key), repeat: false, .. } => {
+                    if let Some(button) = button(key) { gb.key_down(button) }
+                },
+                Event::KeyUp { keycode: Some(key), .. } => {
+                    if let Some(button) = button(key) { gb.key_up(button) }
+                },
+                _ => {}
+            }
+        }
+
+        gb.run_frame();
+
+        screen.update(None, gb.get_image_data(), DISPLAY_WIDTH * 4).map_err(|e| e.to_string())?;
+        canvas.copy(&screen, None, None)?;
+        canvas.present();
+
+        // Feed the A/V stats. A growing audio backlog means the display runs
+        // faster than 60 Hz, a negative one that audio is running dry.
+        let total = played.load(Ordering::Relaxed);
+        gb.report_samples_played(total - reported);
+        reported = total;
+        gb.report_frame_presented();
+        if gb.frame_count() % 600 == 0
+        {
+            let av = gb.av_stats();
+            println!("{} frames skipped, {:.3}s of audio queued", av.frames_behind(), av.audio_backlog_secs());
+        }
+    }
+
+    gb.save().map_err(|e| e.to_string())
+}
+
+/// Get the GameBoy button a key is bound to
+fn button(key: Keycode) -> Option< Button >
+{
+    match key
+    {
+        Keycode::Up         => Some(Button::Up),
+        Keycode::Down       => Some(Button::Down),
+        Keycode::Left       => Some(Button::Left),
+        Keycode::Right      => Some(Button::Right),
+        Keycode::Z          => Some(Button::B),
+        Keycode::X          => Some(Button::A),
+        Keycode::Return     => Some(Button::Start),
+        Keycode::Backspace  => Some(Button::Select),
+        _ => None
+    }
+}
@@ -0,0 +1,173 @@
+//! The intended architecture for frontends that keep emulation off the UI
+//! thread. The emulator lives on its own thread and is only touched there:
+//! the UI sends it commands over a channel and gets frames and audio back
+//! over another. Commands are applied between frames, so a run is
+//! deterministic given the frames the inputs changed on, however the
+//! threads are scheduled.
+//!
+//! This example has no window. The "render" thread just reports what it
+//! receives, and the main thread plays a short script of inputs and
+//! savestate requests before shutting everything down. Run with:
+//!
+//! ```text
+//! cargo run --release --example threaded -- <rom>
+//! ```
+
+use rustboy::prelude::*;
+use std::env;
+use std::mem;
+use std::path::Path;
+use std::process;
+use std::sync::mpsc::{ channel, sync_channel, Receiver, Sender, SyncSender, TryRecvError };
+use std::thread;
+use std::time::{ Duration, Instant };
+
+/// Sent from the UI to the emulation thread
+enum Command
+{
+    /// Hold exactly these buttons from the next frame on
+    Inputs(ButtonState),
+
+    /// Save the state and send it back on the given channel
+    SaveState(Sender< Vec< u8 > >),
+
+    /// Restore a state saved with SaveState
+    LoadState(Vec< u8 >),
+
+    /// Stop emulating. The emulation thread drops its end of the frame
+    /// channel on the way out, which stops the render thread in turn.
+    Quit
+}
+
+/// A completed frame and the audio produced while running it
+struct Frame
+{
+    number: u64,
+    image: Vec< u8 >,
+    audio: Vec< i16 >
+}
+
+/// Frames the emulation thread may get ahead of the render thread. When the
+/// renderer falls behind, frames are dropped rather than slowing emulation.
+const FRAME_QUEUE: usize = 2;
+
+fn main()
+{
+    let args: Vec< String > = env::args().collect();
+    if args.len() != 2
+    {
+        eprintln!("Usage: {} <rom>", args[0]);
+        process::exit(1);
+    }
+
+    let gb = Gameboy::new(Path::new(&args[1]));
+    let (commands, command_rx) = channel();
+    let (frame_tx, frames) = sync_channel(FRAME_QUEUE);
+
+    let emulation = thread::spawn(move || emulate(gb, command_rx, frame_tx));
+    let render = thread::spawn(move || render(frames));
+
+    // The UI thread. A real frontend would translate window events into
+    // commands here instead of following a script.
+    thread::sleep(Duration::from_secs(1));
+    commands.send(Command::Inputs(ButtonState::START)).unwrap();
+    thread::sleep(Duration::from_millis(100));
+    commands.send(Command::Inputs(ButtonState::empty())).unwrap();
+
+    // Requests that need an answer carry a channel to send it back on
+    let (reply, state) = channel();
+    commands.send(Command::SaveState(reply)).unwrap();
+    let state = state.recv().unwrap();
+    println!("Saved a {} byte state", state.len());
+
+    thread::sleep(Duration::from_secs(1));
+    commands.send(Command::LoadState(state)).unwrap();
+    thread::sleep(Duration::from_secs(1));
+
+    // Shut down in order: the emulation thread finishes its frame and
+    // returns the GameBoy, then the render thread drains what is left
+    commands.send(Command::Quit).unwrap();
+    let mut gb = emulation.join().unwrap();
+    render.join().unwrap();
+
+    if let Err(e) = gb.save()
+    {
+        eprintln!("Unable to write save file: {}", e);
+    }
+}
+
+/// Run the emulator in real time, applying commands between frames, until
+/// told to quit or the UI goes away. Returns the GameBoy so the caller can
+/// save the game.
+fn emulate(mut gb: Gameboy, commands: Receiver< Command >, frames: SyncSender< Frame >) -> Gameboy
+{
+    let frame_time = Duration::from_secs_f64(1.0 / gb.frame_rate());
+    let start = Instant::now();
+    let mut audio = Vec::new();
+    let mut emulated = 0;
+
+    loop
+    {
+        loop
+        {
+            match commands.try_recv()
+            {
+                Ok(Command::Inputs(state)) => gb.set_inputs(state),
+                Ok(Command::SaveState(reply)) => {
+                    // The UI may have stopped waiting for the answer, in
+                    // which case there is nobody to tell
+                    let _ = reply.send(gb.save_state());
+                },
+                Ok(Command::LoadState(state)) => {
+                    if let Err(e) = gb.load_state(&state)
+                    {
+                        eprintln!("Unable to load state: {}", e);
+                    }
+                },
+                Ok(Command::Quit) | Err(TryRecvError::Disconnected) => return gb,
+                Err(TryRecvError::Empty) => break
+            }
+        }
+
+        let number = gb.frame_count() + 1;
+        let chunk = gb.next_av_chunk();
+        audio.extend_from_slice(chunk.audio);
+        if let Some(image) = chunk.frame
+        {
+            let frame = Frame {
+                number,
+                image: image.to_vec(),
+                audio: mem::take(&mut audio)
+            };
+            // A full queue means the renderer is behind, so skip the frame
+            let _ = frames.try_send(frame);
+
+            // Sleep until the frame is due so emulation runs in real time
+            emulated += 1;
+            if let Some(wait) = (frame_time * emulated).checked_sub(start.elapsed())
+            {
+                thread::sleep(wait);
+            }
+        }
+    }
+}
+
+/// Consume frames until the emulation thread hangs up. A real frontend
+/// would upload the image to a texture and queue the audio here.
+fn render(frames: Receiver< Frame >)
+{
+    let mut received = 0;
+    let mut samples = 0;
+    for frame in frames
+    {
+        received += 1;
+        samples += frame.audio.len() / 2;
+        if frame.number % 60 == 0
+        {
+            let lit = frame.image.chunks_exact(4).filter(|p| p[0] > 0x80).count();
+            println!("Frame {}: {} light pixels, {} frames and {} samples received",
+                frame.number, lit, received, samples);
+        }
+    }
+    println!("Emulation stopped after {} frames", received);
+}
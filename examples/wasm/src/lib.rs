@@ -0,0 +1,106 @@
+//! rustboy in the browser, using nothing but the core and plain exported
+//! functions, so no bindings generator is needed. index.html shows how to
+//! call them. Build with:
+//!
+//! ```text
+//! cargo build --release --target wasm32-unknown-unknown
+//! cp target/wasm32-unknown-unknown/release/rustboy_wasm.wasm .
+//! python3 -m http.server
+//! ```
+//!
+//! Buffers are passed as pointers into the module's memory. The page asks
+//! for a buffer with `alloc`, copies data into it and hands it back, and
+//! reads data the module returns straight out of its memory.
+
+use rustboy::{ ButtonState, Gameboy, DISPLAY_HEIGHT, DISPLAY_WIDTH };
+use std::cell::RefCell;
+use std::mem;
+
+thread_local!
+{
+    /// The loaded game. The page is single threaded, so there is only ever
+    /// one.
+    static GAMEBOY: RefCell< Option< Gameboy > > = const { RefCell::new(None) };
+
+    /// The last savestate taken
+    static STATE: RefCell< Vec< u8 > > = const { RefCell::new(Vec::new()) };
+}
+
+/// Run f on the loaded game, if there is one
+fn with_gameboy< T, F: FnOnce(&mut Gameboy) -> T >(f: F) -> Option< T >
+{
+    GAMEBOY.with(|gb| gb.borrow_mut().as_mut().map(f))
+}
+
+/// Allocate a buffer of len bytes for the page to fill in
+#[no_mangle]
+pub extern "C" fn alloc(len: usize) -> *mut u8
+{
+    let mut buf = Vec::< u8 >::with_capacity(len);
+    let ptr = buf.as_mut_ptr();
+    mem::forget(buf);
+    ptr
+}
+
+/// Load a ROM from a buffer created by `alloc`, which is taken over.
+/// Returns false if the ROM can't be run.
+///
+/// # Safety
+///
+/// ptr must come from `alloc(len)` and not be used again.
+#[no_mangle]
+pub unsafe extern "C" fn load_rom(ptr: *mut u8, len: usize) -> bool
+{
+    let rom = Vec::from_raw_parts(ptr, len, len);
+    let gb = Gameboy::try_from_rom(rom).ok();
+    let loaded = gb.is_some();
+    GAMEBOY.with(|cell| *cell.borrow_mut() = gb);
+    loaded
+}
+
+/// Set the buttons held, as the bits of `ButtonState`
+#[no_mangle]
+pub extern "C" fn set_inputs(bits: u8)
+{
+    with_gameboy(|gb| gb.set_inputs(ButtonState::from_bits_truncate(bits)));
+}
+
+/// Run a frame and return a pointer to its DISPLAY_WIDTH x DISPLAY_HEIGHT
+/// RGBA image data, which stays valid until the next call. Returns null if
+/// no game is loaded.
+#[no_mangle]
+pub extern "C" fn run_frame() -> *const u8
+{
+    with_gameboy(|gb| {
+        gb.run_frame();
+        gb.get_image_data().as_ptr()
+    }).unwrap_or(std::ptr::null())
+}
+
+/// Get the size of the screen image in bytes
+#[no_mangle]
+pub extern "C" fn frame_len() -> usize
+{
+    DISPLAY_WIDTH * DISPLAY_HEIGHT * 4
+}
+
+/// Save the state of the game, replacing the previous savestate
+#[no_mangle]
+pub extern "C" fn save_state()
+{
+    if let Some(state) = with_gameboy(|gb| gb.save_state())
+    {
+        STATE.with(|s| *s.borrow_mut() = state);
+    }
+}
+
+/// Load the savestate taken last. Returns false if there isn't one or it
+/// can't be loaded.
+#[no_mangle]
+pub extern "C" fn load_state() -> bool
+{
+    STATE.with(|state| {
+        let state = state.borrow();
+        !state.is_empty() && with_gameboy(|gb| gb.load_state(&state).is_ok()).unwrap_or(false)
+    })
+}
@@ -0,0 +1,26 @@
+#version 330 core
+
+// Darkens the edges of every GameBoy pixel so the screen looks like an LCD
+// grid when drawn at a large scale
+
+in vec2 tex_coords;
+out vec4 out_col;
+
+uniform sampler2D tex;
+uniform vec2 input_size;
+uniform vec2 output_size;
+
+void main()
+{
+    vec4 color = texture(tex, tex_coords);
+
+    // Position inside the current GameBoy pixel, from 0 to 1
+    vec2 cell = fract(tex_coords * input_size);
+
+    // Width of the grid lines as a fraction of a pixel, about one window
+    // pixel wide
+    vec2 line = input_size / output_size;
+    vec2 edge = step(line, cell);
+
+    out_col = vec4(color.rgb * mix(0.75, 1.0, edge.x * edge.y), 1.0);
+}
@@ -0,0 +1,182 @@
+use alto::{ Alto, AltoResult, Context, Source, SourceState, Stereo, StreamingSource };
+use rustboy::{ Gameboy, SAMPLE_RATE };
+use std::collections::VecDeque;
+use std::ffi::CString;
+use std::sync::{ Arc, Mutex };
+
+/// Loudest master volume, in percent
+pub const MAX_VOLUME: u32 = 100;
+
+/// How much the volume hotkeys change the master volume by, in percent
+pub const VOLUME_STEP: u32 = 10;
+
+/// Stereo samples per buffer queued on the source, about a frame's worth
+const BUFFER_FRAMES: usize = SAMPLE_RATE as usize / 60;
+
+/// Stereo samples audio pacing keeps buffered ahead of the device, about
+/// 50ms. Less risks the device running dry when the host is busy, more adds
+/// latency.
+pub const TARGET_FILL: usize = BUFFER_FRAMES * 3;
+
+/// The most buffers queued on the source at once
+const MAX_QUEUED: usize = 4;
+
+/// The most samples waiting to be queued, a tenth of a second of stereo
+/// audio. Older samples are dropped when the emulator gets ahead, which
+/// keeps latency down.
+const MAX_PENDING: usize = SAMPLE_RATE as usize / 5;
+
+/// The frontend's mixer: streams the samples the core produces to an
+/// OpenAL output device with the master volume applied on top of the
+/// game's own volume.
+pub struct Audio
+{
+    alto: Alto,
+    ctx: Context,
+    src: StreamingSource,
+
+    /// Name of the device playing, None for the system default
+    device: Option< CString >,
+
+    /// Master volume in percent
+    volume: u32,
+
+    /// Interleaved samples from the core that haven't been queued yet
+    pending: Arc< Mutex< VecDeque< i16 > > >,
+
+    /// Stereo samples in each buffer queued on the source, oldest first
+    queued: VecDeque< usize >,
+
+    /// Samples with the master volume applied, reused for every buffer
+    mix: Vec< i16 >
+}
+
+impl Audio
+{
+    /// Open the output device with the given name, or the system default
+    pub fn open(alto: Alto, device: Option< &str >, volume: u32) -> AltoResult< Self >
+    {
+        let device = device.map(|name| CString::new(name).unwrap_or_default());
+        let (ctx, src) = open_device(&alto, device.as_ref())?;
+        Ok(Audio {
+            alto,
+            ctx,
+            src,
+            device,
+            volume: volume.min(MAX_VOLUME),
+            pending: Arc::new(Mutex::new(VecDeque::new())),
+            queued: VecDeque::new(),
+            mix: Vec::with_capacity(BUFFER_FRAMES * 2)
+        })
+    }
+
+    /// Get the name of the device playing
+    pub fn device_name(&self) -> String
+    {
+        match self.device.clone().or_else(|| self.alto.default_output())
+        {
+            Some(name) => name.to_string_lossy().into_owned(),
+            None => "default".to_string()
+        }
+    }
+
+    /// Switch to the next output device, wrapping around to the first. The
+    /// current device keeps playing if the next one can't be opened.
+    pub fn next_device(&mut self) -> AltoResult< () >
+    {
+        let devices = self.alto.enumerate_outputs();
+        let current = self.device.clone().or_else(|| self.alto.default_output());
+        let next = match current.and_then(|c| devices.iter().position(|d| *d == c))
+        {
+            Some(i) => devices[(i + 1) % devices.len()].clone(),
+            None => match devices.first() {
+                Some(first) => first.clone(),
+                None => return Ok(())
+            }
+        };
+
+        let (ctx, src) = open_device(&self.alto, Some(&next))?;
+        self.ctx = ctx;
+        self.src = src;
+        self.device = Some(next);
+        self.queued.clear();
+        Ok(())
+    }
+
+    /// Get the master volume in percent
+    pub fn volume(&self) -> u32
+    {
+        self.volume
+    }
+
+    /// Set the master volume in percent, clamped to MAX_VOLUME
+    pub fn set_volume(&mut self, volume: u32)
+    {
+        self.volume = volume.min(MAX_VOLUME);
+    }
+
+    /// Get the number of stereo samples waiting to be played, both queued on
+    /// the source and waiting to be queued
+    pub fn buffered(&self) -> usize
+    {
+        self.pending.lock().unwrap().len() / 2 + self.queued.iter().sum::< usize >()
+    }
+
+    /// Have the GameBoy pass the samples it produces to the mixer
+    pub fn attach(&self, gb: &mut Gameboy)
+    {
+        let pending = self.pending.clone();
+        gb.set_audio_callback(move |samples| {
+            let mut pending = pending.lock().unwrap();
+            pending.extend(samples.iter().copied());
+            let excess = pending.len().saturating_sub(MAX_PENDING);
+            pending.drain(..excess);
+        });
+    }
+
+    /// Queue the samples produced since the last update on the source and
+    /// get the number of stereo samples the device finished playing since
+    /// then, for `Gameboy::report_samples_played`
+    pub fn update(&mut self) -> u64
+    {
+        let mut played = 0;
+        for _ in 0..self.src.buffers_processed()
+        {
+            if self.src.unqueue_buffer().is_err() { break }
+            played += self.queued.pop_front().unwrap_or(0) as u64;
+        }
+
+        let mut pending = self.pending.lock().unwrap();
+        let gain = self.volume as i32;
+        while self.queued.len() < MAX_QUEUED && pending.len() >= BUFFER_FRAMES * 2
+        {
+            self.mix.clear();
+            self.mix.extend(pending.drain(..BUFFER_FRAMES * 2)
+                .map(|s| (s as i32 * gain / MAX_VOLUME as i32) as i16));
+
+            let buf = match self.ctx.new_buffer::< Stereo< i16 >, _ >(&self.mix[..], SAMPLE_RATE as i32)
+            {
+                Ok(buf) => buf,
+                Err(_) => break
+            };
+            if self.src.queue_buffer(buf).is_err() { break }
+            self.queued.push_back(BUFFER_FRAMES);
+        }
+
+        // The source stops whenever it runs dry
+        if !self.queued.is_empty() && self.src.state() != SourceState::Playing
+        {
+            self.src.play();
+        }
+        played
+    }
+}
+
+/// Open an output device, or the default one, with a source to stream to
+fn open_device(alto: &Alto, name: Option< &CString >) -> AltoResult< (Context, StreamingSource) >
+{
+    let dev = alto.open(name.map(|n| n.as_c_str()))?;
+    let ctx = dev.new_context(None)?;
+    let src = ctx.new_streaming_source()?;
+    Ok((ctx, src))
+}
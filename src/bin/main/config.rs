@@ -0,0 +1,308 @@
+use crate::audio::MAX_VOLUME;
+use crate::controls::Bindings;
+use rustboy::{ BankAddress, Cheat, Gameboy, OppositeDirections, RamFill, Revision, Target };
+use std::fs::File;
+use std::io::{ Error, ErrorKind, Read };
+use std::io::Result as IoResult;
+use std::path::Path;
+
+/// Settings that can be overridden for a specific game
+#[derive(Debug, Clone, Default)]
+pub struct GameConfig
+{
+    /// Emulation speed multiplier
+    pub speed: Option< f32 >,
+
+    /// How many times faster than stock the CPU runs
+    pub overclock: Option< u32 >,
+
+    /// Master volume in percent
+    pub volume: Option< u32 >,
+
+    /// Name of the user shader to draw the screen with
+    pub shader: Option< String >,
+
+    /// Pattern RAM is filled with on power on
+    pub ram_fill: Option< RamFill >,
+
+    /// System to run the game on, e.g. a Super GameBoy for its border
+    pub target: Option< Target >,
+
+    /// Hardware revision to run the game on, which implies the target
+    pub revision: Option< Revision >,
+
+    /// Soft reset when A+B+Start+Select are pressed together
+    pub reset_combo: Option< bool >,
+
+    /// What the game sees when opposite directions are held at once
+    pub opposite_directions: Option< OppositeDirections >,
+
+    /// Cheats to enable, either as codes or by their name in the cheat
+    /// database
+    pub cheats: Vec< String >,
+
+    /// Addresses to pause at when the CPU reaches them
+    pub breakpoints: Vec< BankAddress >,
+
+    /// Key bindings as (action, key) pairs, applied over the global controls
+    pub controls: Vec< (String, String) >
+}
+
+impl GameConfig
+{
+    /// Load the overrides for the given game from a per-game config file.
+    ///
+    /// The file is split into sections headed by either a game title, e.g.
+    /// `[TETRIS]`, or a ROM checksum, e.g. `[0x16BF]`. Each section holds
+    /// `<setting> = <value>` lines: `speed` takes a multiplier, `overclock`
+    /// takes a CPU clock multiplier from 1 to 4, `volume` takes a master
+    /// volume in percent from 0 to 100, `shader` takes the name of a shader
+    /// in the shader directory, `ram_fill` takes `zero`, `ones`,
+    /// `pattern:<hex byte>` or `random:<seed>`, `target` takes `dmg`, `sgb`
+    /// or `cgb`, `revision` takes `dmg0`, `dmg`, `mgb`, `sgb`, `sgb2`, `cgb`
+    /// or `agb`, `reset_combo` takes `true` or `false`, `opposite_directions`
+    /// takes `block` or `allow`, `cheat` takes cheat codes separated by '+'
+    /// (or, with the cheat-db feature, a cheat's name) and may be repeated,
+    /// `breakpoint` takes a `bank:address` like `05:4000` and may be
+    /// repeated, and anything else is treated as a key binding like in the
+    /// controls file.
+    ///
+    /// Sections matching the checksum take priority over ones matching the
+    /// title.
+    pub fn load(path: &Path, gb: &Gameboy) -> IoResult< Self >
+    {
+        let mut src = File::open(path)?;
+        let mut text = String::new();
+        src.read_to_string(&mut text)?;
+        GameConfig::parse(&text, &gb.title(), gb.checksum())
+    }
+
+    /// Parse the overrides for the game with the given title and checksum
+    /// from the text of a per-game config file
+    pub fn parse(text: &str, title: &str, checksum: u16) -> IoResult< Self >
+    {
+        let mut by_title = GameConfig::default();
+        let mut by_checksum = GameConfig::default();
+        let mut section: Option< &mut GameConfig > = None;
+
+        for (n, line) in text.lines().enumerate()
+        {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') { continue }
+
+            let invalid = |msg: &str| Error::new(ErrorKind::InvalidData,
+                format!("{} on line {}: {}", msg, n + 1, line));
+
+            if line.starts_with('[') && line.ends_with(']')
+            {
+                let name = line[1..line.len() - 1].trim();
+                section = match parse_checksum(name) {
+                    Some(c) if c == checksum => Some(&mut by_checksum),
+                    Some(_) => None,
+                    None if name.eq_ignore_ascii_case(title) => Some(&mut by_title),
+                    None => None
+                };
+                continue
+            }
+
+            let mut parts = line.splitn(2, '=');
+            let name = parts.next().unwrap_or("").trim();
+            let value = parts.next().ok_or_else(|| invalid("Expected '='"))?.trim();
+
+            // Settings for other games are still checked for mistakes
+            let mut scratch = GameConfig::default();
+            let config = match section {
+                Some(ref mut c) => &mut **c,
+                None => &mut scratch
+            };
+            match name
+            {
+                "speed" => {
+                    let speed = value.parse().map_err(|_| invalid("Invalid speed"))?;
+                    config.speed = Some(speed);
+                },
+                "overclock" => {
+                    let factor = value.parse().map_err(|_| invalid("Invalid overclock"))?;
+                    config.overclock = Some(factor);
+                },
+                "volume" => {
+                    let volume = value.parse().ok().filter(|&v| v <= MAX_VOLUME)
+                        .ok_or_else(|| invalid("Invalid volume"))?;
+                    config.volume = Some(volume);
+                },
+                "shader" => config.shader = Some(value.to_string()),
+                "ram_fill" => {
+                    let fill = parse_ram_fill(value).ok_or_else(|| invalid("Invalid RAM fill"))?;
+                    config.ram_fill = Some(fill);
+                },
+                "target" => {
+                    let target = parse_target(value).ok_or_else(|| invalid("Invalid target"))?;
+                    config.target = Some(target);
+                },
+                "revision" => {
+                    let revision = Revision::from_name(value).ok_or_else(|| invalid("Invalid revision"))?;
+                    config.revision = Some(revision);
+                },
+                "reset_combo" => {
+                    let enabled = value.parse().map_err(|_| invalid("Expected true or false"))?;
+                    config.reset_combo = Some(enabled);
+                },
+                "opposite_directions" => {
+                    let policy = parse_opposite_directions(value)
+                        .ok_or_else(|| invalid("Expected block or allow"))?;
+                    config.opposite_directions = Some(policy);
+                },
+                "cheat" => {
+                    let codes = value.split('+').all(|code| Cheat::parse(code).is_ok());
+                    if !codes && !cfg!(feature = "cheat-db")
+                    {
+                        return Err(invalid("Invalid cheat code"))
+                    }
+                    config.cheats.push(value.to_string());
+                },
+                "breakpoint" => {
+                    let addr = value.parse().map_err(|_| invalid("Invalid breakpoint"))?;
+                    config.breakpoints.push(addr);
+                },
+                _ => {
+                    Bindings::new().bind_named(name, value).map_err(invalid)?;
+                    config.controls.push((name.to_string(), value.to_string()));
+                }
+            }
+        }
+
+        by_title.merge(by_checksum);
+        Ok(by_title)
+    }
+
+    /// Apply every override set in other on top of this config
+    fn merge(&mut self, other: GameConfig)
+    {
+        self.speed = other.speed.or(self.speed);
+        self.overclock = other.overclock.or(self.overclock);
+        self.volume = other.volume.or(self.volume);
+        self.shader = other.shader.or(self.shader.take());
+        self.ram_fill = other.ram_fill.or(self.ram_fill);
+        self.target = other.target.or(self.target);
+        self.revision = other.revision.or(self.revision);
+        self.reset_combo = other.reset_combo.or(self.reset_combo);
+        self.opposite_directions = other.opposite_directions.or(self.opposite_directions);
+        self.cheats.extend(other.cheats);
+        self.breakpoints.extend(other.breakpoints);
+        self.controls.extend(other.controls);
+    }
+
+    /// Apply the overrides to the GameBoy and key bindings. Must be called
+    /// right after the GameBoy is created since RAM is overwritten.
+    pub fn apply(&self, gb: &mut Gameboy, bindings: &mut Bindings)
+    {
+        // Restarting resets RAM so it has to come before the RAM fill
+        match (self.revision, self.target)
+        {
+            (Some(revision), _) => gb.restart_as_revision(revision),
+            (None, Some(target)) => gb.restart_as(target),
+            (None, None) => {}
+        }
+        if let Some(speed) = self.speed
+        {
+            gb.set_emulation_speed(speed);
+        }
+        if let Some(factor) = self.overclock
+        {
+            gb.set_overclock(factor);
+        }
+        if let Some(fill) = self.ram_fill
+        {
+            gb.set_ram_fill(fill);
+        }
+        if let Some(enabled) = self.reset_combo
+        {
+            gb.set_reset_combo(enabled);
+        }
+        if let Some(policy) = self.opposite_directions
+        {
+            gb.set_opposite_directions(policy);
+        }
+        for cheat in &self.cheats
+        {
+            if let Err(e) = gb.add_cheat(&lookup_cheat(gb, cheat))
+            {
+                eprintln!("Unable to enable cheat {}: {}", cheat, e);
+            }
+        }
+        for &addr in &self.breakpoints
+        {
+            gb.add_breakpoint(addr);
+        }
+        for (action, key) in &self.controls
+        {
+            // Already validated when parsed
+            let _ = bindings.bind_named(action, key);
+        }
+    }
+}
+
+/// Get the codes of a cheat given by its name in the cheat database, or the
+/// cheat itself if it isn't a name
+#[cfg(feature = "cheat-db")]
+fn lookup_cheat(gb: &Gameboy, cheat: &str) -> String
+{
+    match gb.known_cheats().iter().find(|c| c.name.eq_ignore_ascii_case(cheat))
+    {
+        Some(known) => known.codes.to_string(),
+        None => cheat.to_string()
+    }
+}
+
+/// Without the cheat database every cheat is given as codes
+#[cfg(not(feature = "cheat-db"))]
+fn lookup_cheat(_gb: &Gameboy, cheat: &str) -> String
+{
+    cheat.to_string()
+}
+
+/// Parse a checksum section name such as "0x16BF"
+fn parse_checksum(name: &str) -> Option< u16 >
+{
+    let hex = name.strip_prefix("0x").or_else(|| name.strip_prefix("0X"))?;
+    u16::from_str_radix(hex, 16).ok()
+}
+
+/// Parse a RAM fill setting such as "zero" or "random:1234"
+fn parse_ram_fill(value: &str) -> Option< RamFill >
+{
+    let mut parts = value.splitn(2, ':');
+    let kind = parts.next().unwrap_or("");
+    let arg = parts.next();
+    match (kind.to_ascii_lowercase().as_str(), arg)
+    {
+        ("zero", None) => Some(RamFill::Zero),
+        ("ones", None) => Some(RamFill::Ones),
+        ("pattern", Some(b)) => u8::from_str_radix(b.trim(), 16).ok().map(RamFill::Pattern),
+        ("random", Some(seed)) => seed.trim().parse().ok().map(RamFill::Random),
+        _ => None
+    }
+}
+
+/// Parse a target setting such as "sgb"
+fn parse_target(value: &str) -> Option< Target >
+{
+    match value.to_ascii_lowercase().as_str()
+    {
+        "dmg" | "gb" => Some(Target::GameBoy),
+        "sgb" => Some(Target::SuperGameBoy),
+        "cgb" | "gbc" => Some(Target::GameBoyColor),
+        _ => None
+    }
+}
+
+/// Parse an opposite direction setting such as "allow"
+fn parse_opposite_directions(value: &str) -> Option< OppositeDirections >
+{
+    match value.to_ascii_lowercase().as_str()
+    {
+        "block" => Some(OppositeDirections::Block),
+        "allow" => Some(OppositeDirections::Allow),
+        _ => None
+    }
+}
@@ -0,0 +1,305 @@
+use glium::glutin::VirtualKeyCode;
+use rustboy::Button;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{ Error, ErrorKind, Read };
+use std::io::Result as IoResult;
+use std::path::Path;
+
+/// Something that can be bound to a key
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action
+{
+    /// Hold down a GameBoy button while the key is held
+    Press(Button),
+
+    /// Pause or resume emulation
+    Pause,
+
+    /// Advance a single frame while paused
+    FrameAdvance,
+
+    /// Show or hide the input display
+    ToggleInputs,
+
+    /// Cycle through the debug panel's pages and hide it after the last
+    ToggleDebug,
+
+    /// Scroll the debug panel's memory page up
+    MemoryUp,
+
+    /// Scroll the debug panel's memory page down
+    MemoryDown,
+
+    /// Show or hide the CGB BG attribute overlay
+    ToggleAttributes,
+
+    /// Show or hide the SGB border
+    ToggleBorder,
+
+    /// Open or close the tile and BG map viewer window
+    ToggleViewer,
+
+    /// Stop playing back a movie and record from the current frame
+    TakeOver,
+
+    /// Turn the master volume up
+    VolumeUp,
+
+    /// Turn the master volume down
+    VolumeDown,
+
+    /// Switch to the next audio output device
+    NextAudioDevice,
+
+    /// Show or hide the BG layer
+    ToggleBgLayer,
+
+    /// Show or hide the window layer
+    ToggleWindowLayer,
+
+    /// Show or hide the sprite layer
+    ToggleSpriteLayer,
+
+    /// Save the state of the game to the savestate slot
+    SaveState,
+
+    /// Load the state of the game from the savestate slot
+    LoadState,
+
+    /// Run backwards while the key is held
+    Rewind,
+
+    /// Run at the fastest emulation speed while the key is held
+    FastForward,
+
+    /// Write the screen to an image file
+    Screenshot,
+
+    /// Reset the game, keeping the cartridge RAM
+    Reset,
+
+    /// Draw the screen with or without the user shader
+    ToggleFilter
+}
+
+/// Every action along with its name in the controls file and default key
+const ACTIONS: [(&str, Action, VirtualKeyCode); 31] = [
+    ("up",                Action::Press(Button::Up),      VirtualKeyCode::Up),
+    ("down",              Action::Press(Button::Down),    VirtualKeyCode::Down),
+    ("left",              Action::Press(Button::Left),    VirtualKeyCode::Left),
+    ("right",             Action::Press(Button::Right),   VirtualKeyCode::Right),
+    ("a",                 Action::Press(Button::A),       VirtualKeyCode::Z),
+    ("b",                 Action::Press(Button::B),       VirtualKeyCode::X),
+    ("start",             Action::Press(Button::Start),   VirtualKeyCode::O),
+    ("select",            Action::Press(Button::Select),  VirtualKeyCode::P),
+    ("pause",             Action::Pause,                  VirtualKeyCode::Space),
+    ("frame_advance",     Action::FrameAdvance,           VirtualKeyCode::N),
+    ("toggle_inputs",     Action::ToggleInputs,           VirtualKeyCode::I),
+    ("toggle_debug",      Action::ToggleDebug,            VirtualKeyCode::F1),
+    ("memory_up",         Action::MemoryUp,               VirtualKeyCode::PageUp),
+    ("memory_down",       Action::MemoryDown,             VirtualKeyCode::PageDown),
+    ("toggle_attributes", Action::ToggleAttributes,       VirtualKeyCode::F2),
+    ("toggle_border",     Action::ToggleBorder,           VirtualKeyCode::F3),
+    ("toggle_viewer",     Action::ToggleViewer,           VirtualKeyCode::F4),
+    ("take_over",         Action::TakeOver,               VirtualKeyCode::R),
+    ("volume_up",         Action::VolumeUp,               VirtualKeyCode::Equals),
+    ("volume_down",       Action::VolumeDown,             VirtualKeyCode::Minus),
+    ("next_audio_device", Action::NextAudioDevice,        VirtualKeyCode::F6),
+    ("toggle_bg",         Action::ToggleBgLayer,          VirtualKeyCode::F7),
+    ("toggle_window",     Action::ToggleWindowLayer,      VirtualKeyCode::F8),
+    ("toggle_sprites",    Action::ToggleSpriteLayer,      VirtualKeyCode::F9),
+    ("save_state",        Action::SaveState,              VirtualKeyCode::F5),
+    ("load_state",        Action::LoadState,              VirtualKeyCode::F10),
+    ("rewind",            Action::Rewind,                 VirtualKeyCode::Back),
+    ("fast_forward",      Action::FastForward,            VirtualKeyCode::Tab),
+    ("screenshot",        Action::Screenshot,             VirtualKeyCode::F12),
+    ("reset",             Action::Reset,                  VirtualKeyCode::F11),
+    ("toggle_filter",     Action::ToggleFilter,           VirtualKeyCode::C)
+];
+
+/// The default keys for the second player's buttons in link play, chosen to
+/// not clash with the first player's default keys
+const PLAYER_TWO_KEYS: [(Button, VirtualKeyCode); 8] = [
+    (Button::Up,        VirtualKeyCode::W),
+    (Button::Down,      VirtualKeyCode::S),
+    (Button::Left,      VirtualKeyCode::A),
+    (Button::Right,     VirtualKeyCode::D),
+    (Button::A,         VirtualKeyCode::H),
+    (Button::B,         VirtualKeyCode::G),
+    (Button::Start,     VirtualKeyCode::T),
+    (Button::Select,    VirtualKeyCode::Y)
+];
+
+/// Maps keys to the actions they perform.
+///
+/// Bindings can be changed with a controls file containing lines of the form
+/// `<action> = <key>`, e.g. `a = Space` or `pause = P`. Actions that aren't
+/// listed keep their default key. Empty lines and lines starting with '#' are
+/// ignored. Binding a key that another action has leaves that action with
+/// no key, which is reported as a conflict unless the action is given a new
+/// key as well.
+pub struct Bindings
+{
+    keys: HashMap< VirtualKeyCode, Action >,
+
+    /// Actions that lost their key, along with the key and the action it
+    /// went to
+    conflicts: Vec< (Action, VirtualKeyCode, Action) >
+}
+
+impl Bindings
+{
+    /// Create and return the default key bindings
+    pub fn new() -> Self
+    {
+        let keys = ACTIONS.iter().map(|&(_, action, key)| (key, action)).collect();
+        Bindings { keys, conflicts: Vec::new() }
+    }
+
+    /// Create and return the default key bindings of the second player in
+    /// link play, which only has keys bound to the GameBoy buttons
+    pub fn player_two() -> Self
+    {
+        let keys = PLAYER_TWO_KEYS.iter().map(|&(button, key)| (key, Action::Press(button))).collect();
+        Bindings { keys, conflicts: Vec::new() }
+    }
+
+    /// Load key bindings from the given controls file
+    pub fn load(path: &Path) -> IoResult< Self >
+    {
+        Bindings::parse(&read_file(path)?)
+    }
+
+    /// Load the second player's key bindings from the given controls file,
+    /// starting from `player_two` rather than the default bindings
+    pub fn load_player_two(path: &Path) -> IoResult< Self >
+    {
+        Bindings::player_two().parse_over(&read_file(path)?)
+    }
+
+    /// Parse key bindings from the text of a controls file
+    pub fn parse(text: &str) -> IoResult< Self >
+    {
+        Bindings::new().parse_over(text)
+    }
+
+    /// Apply the key bindings in the text of a controls file on top of these
+    /// bindings. Fails if any action is left without a key because the file
+    /// gave it to another action.
+    fn parse_over(mut self, text: &str) -> IoResult< Self >
+    {
+        for (n, line) in text.lines().enumerate()
+        {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') { continue }
+
+            let invalid = |msg: &str| Error::new(ErrorKind::InvalidData,
+                format!("{} on line {}: {}", msg, n + 1, line));
+
+            let mut parts = line.splitn(2, '=');
+            let name = parts.next().unwrap_or("").trim();
+            let key = parts.next().ok_or_else(|| invalid("Expected '='"))?.trim();
+
+            self.bind_named(name, key).map_err(invalid)?;
+        }
+
+        match self.conflicts().first()
+        {
+            Some(conflict) => Err(Error::new(ErrorKind::InvalidData, conflict.clone())),
+            None => Ok(self)
+        }
+    }
+
+    /// Bind a key to an action by their names as written in a controls file
+    pub fn bind_named(&mut self, action: &str, key: &str) -> Result< (), &'static str >
+    {
+        let action = ACTIONS.iter()
+            .find(|&&(n, _, _)| n.eq_ignore_ascii_case(action))
+            .map(|&(_, action, _)| action)
+            .ok_or("Unknown action")?;
+        let key = parse_key(key).ok_or("Unknown key")?;
+
+        self.bind(key, action);
+        Ok(())
+    }
+
+    /// Bind a key to an action, replacing the key previously bound to it.
+    /// Returns the action the key was taken from, if it had one.
+    pub fn bind(&mut self, key: VirtualKeyCode, action: Action) -> Option< Action >
+    {
+        self.keys.retain(|_, &mut a| a != action);
+        self.conflicts.retain(|&(lost, _, _)| lost != action);
+        let taken = self.keys.insert(key, action).filter(|&a| a != action);
+        if let Some(lost) = taken
+        {
+            self.conflicts.push((lost, key, action));
+        }
+        taken
+    }
+
+    /// Describe every action that was left without a key because its key was
+    /// bound to another action, e.g. "Space is bound to a, so pause has no
+    /// key"
+    pub fn conflicts(&self) -> Vec< String >
+    {
+        self.conflicts.iter()
+            .map(|&(lost, key, action)| format!("{:?} is bound to {}, so {} has no key",
+                key, action_name(action), action_name(lost)))
+            .collect()
+    }
+
+    /// Get the action bound to a key
+    pub fn action(&self, key: VirtualKeyCode) -> Option< Action >
+    {
+        self.keys.get(&key).cloned()
+    }
+}
+
+/// Get the name of an action as written in a controls file
+fn action_name(action: Action) -> &'static str
+{
+    ACTIONS.iter()
+        .find(|&&(_, a, _)| a == action)
+        .map(|&(name, _, _)| name)
+        .unwrap_or("?")
+}
+
+/// Read the whole of a controls file
+fn read_file(path: &Path) -> IoResult< String >
+{
+    let mut src = File::open(path)?;
+    let mut text = String::new();
+    src.read_to_string(&mut text)?;
+    Ok(text)
+}
+
+/// Get the key with the given name, e.g. "Z", "5", "Space" or "F1"
+fn parse_key(name: &str) -> Option< VirtualKeyCode >
+{
+    use glium::glutin::VirtualKeyCode::*;
+
+    let key = match name.to_ascii_lowercase().as_str()
+    {
+        "a" => A, "b" => B, "c" => C, "d" => D, "e" => E, "f" => F, "g" => G,
+        "h" => H, "i" => I, "j" => J, "k" => K, "l" => L, "m" => M, "n" => N,
+        "o" => O, "p" => P, "q" => Q, "r" => R, "s" => S, "t" => T, "u" => U,
+        "v" => V, "w" => W, "x" => X, "y" => Y, "z" => Z,
+        "0" => Key0, "1" => Key1, "2" => Key2, "3" => Key3, "4" => Key4,
+        "5" => Key5, "6" => Key6, "7" => Key7, "8" => Key8, "9" => Key9,
+        "f1" => F1, "f2" => F2, "f3" => F3, "f4" => F4, "f5" => F5,
+        "f6" => F6, "f7" => F7, "f8" => F8, "f9" => F9, "f10" => F10,
+        "f11" => F11, "f12" => F12,
+        "up" => Up, "down" => Down, "left" => Left, "right" => Right,
+        "space" => Space, "return" | "enter" => Return, "escape" => Escape,
+        "tab" => Tab, "back" | "backspace" => Back,
+        "pageup" => PageUp, "pagedown" => PageDown,
+        "lshift" => LShift, "rshift" => RShift,
+        "lcontrol" => LControl, "rcontrol" => RControl,
+        "lalt" => LAlt, "ralt" => RAlt,
+        "comma" => Comma, "period" => Period, "slash" => Slash,
+        "semicolon" => Semicolon, "minus" => Minus, "equals" => Equals,
+        _ => return None
+    };
+    Some(key)
+}
@@ -0,0 +1,162 @@
+use crate::frametime::FrameTimes;
+use crate::osd::draw_text;
+use rustboy::{ Gameboy, DISPLAY_HEIGHT, DISPLAY_WIDTH };
+
+/// Number of lines of text in the debug panel
+const PANEL_LINES: usize = 10;
+
+/// Vertical space taken by a line of text in the panel
+const LINE_HEIGHT: usize = 7;
+
+/// The color each of the 8 CGB BG palettes is tinted with in the attribute
+/// overlay
+const PALETTE_TINTS: [[u8; 3]; 8] = [
+    [255, 0, 0], [255, 160, 0], [255, 255, 0], [0, 255, 0],
+    [0, 255, 255], [0, 0, 255], [160, 0, 255], [255, 0, 255]
+];
+
+/// Bytes shown on each line of the memory page
+const MEMORY_ROW: u16 = 8;
+
+/// Number of bytes the memory page scrolls by at a time
+pub const MEMORY_PAGE: u16 = MEMORY_ROW * PANEL_LINES as u16;
+
+/// What the debug panel shows
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DebugPage
+{
+    /// CPU, GPU, mapper and frame timing state
+    Registers,
+
+    /// The instructions from PC onwards
+    Disassembly,
+
+    /// A hexdump of memory from a chosen address
+    Memory
+}
+
+impl DebugPage
+{
+    /// Get the page after this one, or None after the last page to hide the
+    /// panel
+    pub fn next(self) -> Option< DebugPage >
+    {
+        match self
+        {
+            DebugPage::Registers => Some(DebugPage::Disassembly),
+            DebugPage::Disassembly => Some(DebugPage::Memory),
+            DebugPage::Memory => None
+        }
+    }
+}
+
+/// Draw a panel with the given page along the bottom of the RGBA image. The
+/// memory page starts at `memory_addr`. The panel is drawn with the OSD font
+/// directly into the screen image so it needs no extra windows or GUI
+/// libraries.
+pub fn draw_debug_panel(image: &mut [u8], gb: &Gameboy, frame_times: &FrameTimes, page: DebugPage, memory_addr: u16)
+{
+    let lines = match page
+    {
+        DebugPage::Registers => register_lines(gb, frame_times),
+        DebugPage::Disassembly => disassembly_lines(gb),
+        DebugPage::Memory => memory_lines(gb, memory_addr)
+    };
+
+    let y0 = DISPLAY_HEIGHT - PANEL_LINES * LINE_HEIGHT;
+    for (i, line) in lines.iter().enumerate()
+    {
+        draw_text(image, 0, y0 + i * LINE_HEIGHT, line);
+    }
+}
+
+/// Get the lines of the registers page
+fn register_lines(gb: &Gameboy, frame_times: &FrameTimes) -> Vec< String >
+{
+    let r = gb.registers();
+    let m = gb.mapper_state();
+    let av = gb.av_stats();
+    vec![
+        format!("AF:{:02X}{:02X} BC:{:02X}{:02X}", r.a, r.f, r.b, r.c),
+        format!("DE:{:02X}{:02X} HL:{:02X}{:02X}", r.d, r.e, r.h, r.l),
+        format!("SP:{:04X} PC:{}", r.sp, gb.bank_address(r.pc)),
+        format!("IME:{} HALT:{} STOP:{}", r.ime, r.halt, r.stop),
+        format!("LY:{:3} MODE:{:?}", gb.ly(), gb.ppu_mode()),
+        format!("{} ROM:{:02X} RAM:{:X}{}", m.mapper, m.rom_bank, m.ram_bank,
+            if m.ram_enabled { "" } else { " OFF" }),
+        format!("SPEED:{:.2}X", gb.emulation_speed()),
+        format!("SHOWN:{} SKIPPED:{}", av.frames_presented, av.frames_behind()),
+        format!("STUTTER EMU:{} HOST:{}", frame_times.slow_frames(), frame_times.host_stalls()),
+        format!("FRAME:{:016X}", gb.frame_hash())
+    ]
+}
+
+/// Get the lines of the disassembly page, starting at PC
+fn disassembly_lines(gb: &Gameboy) -> Vec< String >
+{
+    let mut addr = gb.registers().pc;
+    (0..PANEL_LINES).map(|_| {
+        let (text, len) = gb.disassemble(addr);
+        let line = format!("{:04X} {}", addr, text);
+        addr = addr.wrapping_add(len);
+        line
+    }).collect()
+}
+
+/// Get the lines of the memory page, starting at the given address
+fn memory_lines(gb: &Gameboy, addr: u16) -> Vec< String >
+{
+    (0..PANEL_LINES as u16).map(|row| {
+        let start = addr.wrapping_add(row * MEMORY_ROW);
+        let bytes: Vec< String > = (0..MEMORY_ROW)
+            .map(|i| format!("{:02X}", gb.peek(start.wrapping_add(i))))
+            .collect();
+        format!("{:04X}: {}", start, bytes.join(" "))
+    }).collect()
+}
+
+/// Draw the CGB attributes of the BG tiles over the screen. Each tile is
+/// tinted with a color for its palette, tiles using VRAM bank 1 have a black
+/// diagonal, tiles drawn over sprites have a white border along the top and
+/// left and flipped tiles have a black edge on the right (X flip) or bottom
+/// (Y flip). Returns false without drawing anything when the game isn't
+/// running in CGB mode.
+pub fn draw_attribute_overlay(image: &mut [u8], gb: &Gameboy) -> bool
+{
+    let map = match gb.bg_attribute_map() {
+        Some(map) => map,
+        None => return false
+    };
+    let (scx, scy) = gb.scroll();
+
+    for y in 0..DISPLAY_HEIGHT
+    {
+        let my = (y + scy as usize) % 256;
+        for x in 0..DISPLAY_WIDTH
+        {
+            let mx = (x + scx as usize) % 256;
+            let attrs = map[(my >> 3) * 32 + (mx >> 3)];
+            let (tx, ty) = (mx & 7, my & 7);
+
+            let i = (y * DISPLAY_WIDTH + x) * 4;
+            let pixel = &mut image[i..i + 3];
+            if attrs.bank == 1 && tx == ty || attrs.x_flip && tx == 7 || attrs.y_flip && ty == 7
+            {
+                pixel.copy_from_slice(&[0, 0, 0]);
+            }
+            else if attrs.priority && (tx == 0 || ty == 0)
+            {
+                pixel.copy_from_slice(&[255, 255, 255]);
+            }
+            else
+            {
+                let tint = PALETTE_TINTS[attrs.palette as usize];
+                for (c, t) in pixel.iter_mut().zip(tint.iter())
+                {
+                    *c = ((*c as u16 + *t as u16) / 2) as u8;
+                }
+            }
+        }
+    }
+    true
+}
@@ -0,0 +1,151 @@
+use std::fmt;
+use std::time::{ Duration, Instant };
+
+/// Width of a histogram bucket
+const BUCKET_WIDTH: Duration = Duration::from_millis(1);
+
+/// Number of histogram buckets. The last one counts every slower frame.
+const BUCKETS: usize = 50;
+
+/// How far over its budget a frame can run before it counts as a stutter
+const STUTTER_FACTOR: f64 = 1.5;
+
+/// Widest bar drawn in the histogram report
+const BAR_WIDTH: u64 = 40;
+
+/// Host time taken by each frame the frontend shows, for telling stutter
+/// caused by the emulator being too slow from stutter caused by the OS not
+/// scheduling the frontend in time
+pub struct FrameTimes
+{
+    /// Frames in each BUCKET_WIDTH of frame time
+    histogram: [u64; BUCKETS],
+
+    /// When the last frame was shown, None before the first one
+    last_frame: Option< Instant >,
+
+    /// Time spent emulating since the last frame was shown
+    emulation: Duration,
+
+    /// Stutters caused by emulation taking longer than the frame budget
+    slow_frames: u64,
+
+    /// Stutters where the host held the frontend up
+    host_stalls: u64
+}
+
+/// A frame that took longer than its budget to show
+pub struct Stutter
+{
+    /// Host time since the frame before
+    pub elapsed: Duration,
+
+    /// The time the frame should have taken at the emulation speed
+    pub budget: Duration,
+
+    /// How much of the elapsed time was spent emulating
+    pub emulation: Duration
+}
+
+impl Stutter
+{
+    /// Was the emulator too slow, rather than the host holding it up?
+    pub fn is_slow_emulation(&self) -> bool
+    {
+        self.emulation > self.budget
+    }
+}
+
+impl fmt::Display for Stutter
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        write!(f, "Frame took {:.1}ms of a {:.1}ms budget, {:.1}ms emulating: {}",
+            ms(self.elapsed), ms(self.budget), ms(self.emulation),
+            if self.is_slow_emulation() { "emulation is too slow" } else { "the host held it up" })
+    }
+}
+
+impl FrameTimes
+{
+    /// Create and return an empty histogram
+    pub fn new() -> Self
+    {
+        FrameTimes {
+            histogram: [0; BUCKETS],
+            last_frame: None,
+            emulation: Duration::ZERO,
+            slow_frames: 0,
+            host_stalls: 0
+        }
+    }
+
+    /// Count time spent running the emulator towards the next frame
+    pub fn add_emulation(&mut self, time: Duration)
+    {
+        self.emulation += time;
+    }
+
+    /// Record a frame being shown now. Returns the stutter if the frame took
+    /// longer than the given budget allows.
+    pub fn frame_shown(&mut self, budget: Duration) -> Option< Stutter >
+    {
+        let now = Instant::now();
+        let emulation = std::mem::take(&mut self.emulation);
+        let elapsed = now - self.last_frame.replace(now)?;
+
+        let bucket = (elapsed.as_nanos() / BUCKET_WIDTH.as_nanos()) as usize;
+        self.histogram[bucket.min(BUCKETS - 1)] += 1;
+
+        if elapsed.as_secs_f64() <= budget.as_secs_f64() * STUTTER_FACTOR
+        {
+            return None
+        }
+        let stutter = Stutter { elapsed, budget, emulation };
+        if stutter.is_slow_emulation() { self.slow_frames += 1 } else { self.host_stalls += 1 }
+        Some(stutter)
+    }
+
+    /// Forget the last frame, so time spent paused or in a menu isn't
+    /// counted against the next one
+    pub fn restart(&mut self)
+    {
+        self.last_frame = None;
+        self.emulation = Duration::ZERO;
+    }
+
+    /// Get the number of stutters caused by slow emulation
+    pub fn slow_frames(&self) -> u64
+    {
+        self.slow_frames
+    }
+
+    /// Get the number of stutters caused by the host
+    pub fn host_stalls(&self) -> u64
+    {
+        self.host_stalls
+    }
+
+    /// Get a text report of the histogram, one line per non-empty bucket
+    pub fn report(&self) -> String
+    {
+        let frames: u64 = self.histogram.iter().sum();
+        let largest = self.histogram.iter().copied().max().unwrap_or(0).max(1);
+        let mut report = format!("{} frames, {} stutters from slow emulation, {} from the host\n",
+            frames, self.slow_frames, self.host_stalls);
+        for (i, &count) in self.histogram.iter().enumerate().filter(|(_, &c)| c > 0)
+        {
+            let start = i as u128 * BUCKET_WIDTH.as_millis();
+            let label = if i == BUCKETS - 1 { format!("{:>3}+ms", start) } else { format!("{:>4}ms", start) };
+            let bar = "#".repeat(((count * BAR_WIDTH).div_ceil(largest)) as usize);
+            report += &format!("{} {:>7} {}\n", label, count, bar);
+        }
+        report
+    }
+}
+
+/// Get a duration in milliseconds
+fn ms(time: Duration) -> f64
+{
+    time.as_secs_f64() * 1000.0
+}
@@ -0,0 +1,220 @@
+/*
+    Split screen link play. Two games run side by side in one window with
+    their serial ports connected by an in-process link cable, which is the
+    easiest way to try out two player games locally.
+*/
+
+use crate::controls::{ Action, Bindings };
+use crate::osd::Osd;
+use crate::{ create_program, empty_texture, open_display, quad, show_error, upload, CONTROLS_PATH };
+use glium::{ glutin, uniform, Surface };
+use glium::index::{ IndexBuffer, PrimitiveType };
+use rustboy::{ Gameboy, LinkPort, MemoryStorage, RunExit, DISPLAY_HEIGHT, DISPLAY_WIDTH };
+use std::io::Result as IoResult;
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+
+/// File the second player's key bindings are loaded from, if it exists
+const CONTROLS2_PATH: &str = "controls2.cfg";
+
+/// Run two games side by side in one window with their serial ports
+/// connected by a link cable. Player one uses the normal controls and
+/// player two has their own bindings for the GameBoy buttons. Pausing and
+/// frame advance apply to both GameBoys.
+pub fn run_linked(rom_a: &Path, rom_b: &Path)
+{
+    // Each screen gets the window size of the single screen mode
+    let ratio = 1 + (DISPLAY_WIDTH / 10);
+    let width = DISPLAY_WIDTH + 10 * ratio;
+    let height = DISPLAY_HEIGHT + 9 * ratio;
+
+    let mut event_loop = glutin::EventsLoop::new();
+    let wb = glutin::WindowBuilder::new()
+        .with_dimensions(glutin::dpi::LogicalSize::new(2.0 * width as f64, height as f64))
+        .with_resizable(false)
+        .with_title("Rustboy - Link Play");
+    let cb = glutin::ContextBuilder::new()
+        .with_gl(glutin::GlRequest::Specific(glutin::Api::OpenGl, (3, 3)))
+        .with_gl_profile(glutin::GlProfile::Core)
+        .with_vsync(true);
+    let display = open_display(wb, cb, &event_loop);
+
+    // Player one on the left half of the window, player two on the right
+    let vertex_bufs = [
+        quad(&display, -1.0, 1.0, 0.0, -1.0),
+        quad(&display, 0.0, 1.0, 1.0, -1.0)
+    ];
+    let index_buf = IndexBuffer::new(&display, PrimitiveType::TriangleStrip,
+        &[1 as u16, 2, 0, 3]).unwrap();
+    let screen_texs = [
+        empty_texture(&display, DISPLAY_WIDTH, DISPLAY_HEIGHT),
+        empty_texture(&display, DISPLAY_WIDTH, DISPLAY_HEIGHT)
+    ];
+    let mut overlay = vec![0; DISPLAY_WIDTH * DISPLAY_HEIGHT * 4];
+    let program = create_program(&display);
+
+    let load = |path: &Path| Gameboy::try_new(path).map_err(|e| format!("Unable to load {}: {}", path.display(), e));
+    let mut gbs = match (load(rom_a), load(rom_b))
+    {
+        (Ok(a), Ok(b)) => [a, b],
+        (Err(e), _) | (_, Err(e)) => show_error(&display, &mut event_loop, &e)
+    };
+
+    // Both GameBoys would write the same save file when running the same
+    // game, so only player one's save is kept
+    if rom_a == rom_b
+    {
+        if let Err(e) = gbs[1].set_storage(MemoryStorage::new())
+        {
+            eprintln!("Unable to set up player two's storage: {}", e);
+        }
+    }
+
+    let (port_a, port_b) = LinkPort::pair();
+    gbs[0].connect_link(port_a);
+    gbs[1].connect_link(port_b);
+    for gb in gbs.iter_mut()
+    {
+        gb.set_watchdog(true);
+    }
+
+    let mut osds = [Osd::new(), Osd::new()];
+    let bindings = [
+        load_bindings(CONTROLS_PATH, Bindings::load, Bindings::new, &mut osds[0]),
+        load_bindings(CONTROLS2_PATH, Bindings::load_player_two, Bindings::player_two, &mut osds[1])
+    ];
+
+    let mut paused = false;
+    let mut advance = false;
+
+    let mut closed = false;
+    while !closed
+    {
+        event_loop.poll_events(|e|
+        {
+            if let glutin::Event::WindowEvent { event, .. } = e
+            {
+                match event
+                {
+                    glutin::WindowEvent::CloseRequested => closed = true,
+                    glutin::WindowEvent::KeyboardInput { input, .. } =>
+                    {
+                        let key = match input.virtual_keycode {
+                            Some(key) => key,
+                            None => return
+                        };
+                        let pressed = input.state == glutin::ElementState::Pressed;
+
+                        // Player one's bindings take priority over player two's
+                        let bound = bindings.iter().enumerate()
+                            .find_map(|(player, b)| b.action(key).map(|action| (player, action)));
+                        match bound
+                        {
+                            Some((player, Action::Press(button))) => {
+                                let gb = &mut gbs[player];
+                                if pressed { gb.key_down(button) } else { gb.key_up(button) }
+                            },
+                            Some((_, Action::Pause)) if pressed => {
+                                paused = !paused;
+                                for osd in osds.iter_mut()
+                                {
+                                    osd.notify(if paused { "Paused" } else { "Resumed" });
+                                }
+                            },
+                            Some((_, Action::FrameAdvance)) if pressed => advance = true,
+                            _ => {}
+                        }
+                    },
+                    _ => ()
+                }
+            }
+        });
+
+        // Run both GameBoys for the same amount of time. Bytes sent over the
+        // link are picked up by the other side the next time it runs.
+        for (gb, osd) in gbs.iter_mut().zip(osds.iter_mut())
+        {
+            let exit = if !paused
+            {
+                gb.run()
+            }
+            else if advance
+            {
+                gb.run_frame()
+            }
+            else
+            {
+                RunExit::Completed
+            };
+
+            match exit
+            {
+                RunExit::Completed => {},
+                RunExit::Breakpoint(addr) => {
+                    paused = true;
+                    osd.notify(format!("Breakpoint at {}", addr));
+                },
+                RunExit::Hung(hang) => {
+                    paused = true;
+                    eprintln!("Game hung: {}", hang);
+                    osd.notify(format!("Game hung at {}", hang.start));
+                }
+            }
+        }
+        advance = false;
+
+        let mut target = display.draw();
+        target.clear_color(0.0, 0.0, 1.0, 1.0);
+        let screens = gbs.iter().zip(osds.iter_mut()).zip(screen_texs.iter().zip(vertex_bufs.iter()));
+        for ((gb, osd), (tex, vertex_buf)) in screens
+        {
+            let image_data = if osd.is_empty()
+            {
+                gb.get_image_data()
+            }
+            else
+            {
+                overlay.copy_from_slice(gb.get_image_data());
+                osd.draw(&mut overlay);
+                &overlay[..]
+            };
+            upload(tex, image_data);
+
+            let uniforms = uniform! { tex: tex };
+            target.draw(vertex_buf, &index_buf, &program, &uniforms, &Default::default()).unwrap();
+        }
+        target.finish().unwrap();
+
+        thread::sleep(Duration::from_millis(10));
+    }
+
+    for gb in gbs.iter_mut()
+    {
+        if let Err(e) = gb.save()
+        {
+            eprintln!("Unable to write save file: {}", e);
+        }
+    }
+}
+
+/// Load key bindings from the given controls file if it exists, falling back
+/// to the defaults
+fn load_bindings(path: &str, load: fn(&Path) -> IoResult< Bindings >,
+    defaults: fn() -> Bindings, osd: &mut Osd) -> Bindings
+{
+    if !Path::new(path).exists()
+    {
+        return defaults()
+    }
+
+    match load(Path::new(path))
+    {
+        Ok(b) => b,
+        Err(e) => {
+            eprintln!("Unable to load {}: {}", path, e);
+            osd.notify("Invalid controls file");
+            defaults()
+        }
+    }
+}
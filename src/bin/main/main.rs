@@ -0,0 +1,1208 @@
+mod audio;
+mod config;
+mod controls;
+mod debug;
+mod frametime;
+mod link;
+mod osd;
+mod rewind;
+mod shader;
+mod spectate;
+mod viewer;
+
+use alto::*;
+use glium::{ glutin, implement_vertex, uniform, Surface, VertexBuffer };
+use glium::index::{ IndexBuffer, PrimitiveType };
+use glium::texture::{ ClientFormat, MipmapsOption, RawImage2d, Texture2d, UncompressedFloatFormat };
+use audio::{ Audio, MAX_VOLUME, TARGET_FILL, VOLUME_STEP };
+use config::GameConfig;
+use controls::{ Action, Bindings };
+use debug::{ draw_attribute_overlay, draw_debug_panel, DebugPage, MEMORY_PAGE };
+use frametime::FrameTimes;
+use osd::{ draw_text, draw_wrapped_text, fill_rect, Osd };
+use rustboy::prelude::*;
+use rustboy::{ BORDER_WIDTH, BORDER_HEIGHT, MAX_EMULATION_SPEED, CompatDb, FrameRecorder, HashLog, PixelLayer, SpectateHost };
+use rewind::Rewind;
+use viewer::Viewer;
+use std::borrow::Cow;
+use std::env;
+use std::fs::{ self, File };
+use std::io::BufWriter;
+use std::io::Result as IoResult;
+use std::path::Path;
+use std::process;
+use std::thread;
+use std::time::{ Duration, Instant, SystemTime, UNIX_EPOCH };
+use std::sync::{ Arc, Mutex };
+
+/// File the key bindings are loaded from, if it exists
+const CONTROLS_PATH: &str = "controls.cfg";
+
+/// File per-game overrides are loaded from, if it exists
+const GAMES_PATH: &str = "games.cfg";
+
+/// How often battery backed RAM is written out while the game runs, if it
+/// changed
+const AUTOSAVE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The most frames audio pacing runs at once to catch up, so a stalled
+/// audio device can't hold up input and drawing for long
+const MAX_PACED_FRAMES: u32 = 4;
+
+/// The savestate slot the save and load state keys use
+const STATE_SLOT: u8 = 0;
+
+/// How long `compat` runs each game to see if it boots, 30 seconds
+const COMPAT_FRAMES: u32 = 60 * 30;
+
+/// How emulation is kept running at the right speed
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Pacing
+{
+    /// Run a slice of emulation, then sleep
+    Sleep,
+
+    /// Run as many frames as it takes to keep the audio buffer filled, so
+    /// the audio device's clock sets the speed
+    Audio
+}
+
+/// Position of the game screen inside the SGB border in pixels
+const BORDER_SCREEN_X: usize = 48;
+const BORDER_SCREEN_Y: usize = 40;
+
+#[derive(Clone, Copy)]
+struct Vertex
+{
+    pos: [f32; 2],  // Position
+    col: [f32; 4],  // Color
+    tex: [f32; 2]   // Texture Coords
+}
+implement_vertex!(Vertex, pos, tex, col);
+
+fn main()
+{
+    // Audio and shader options can be given before or after the mode
+    let mut args: Vec< String > = env::args().collect();
+    let volume = match take_option(&mut args, "--volume").map(|v| v.parse::< u32 >())
+    {
+        Some(Ok(v)) if v <= MAX_VOLUME => Some(v),
+        Some(_) => {
+            eprintln!("Invalid volume (expected 0 - {})", MAX_VOLUME);
+            process::exit(1);
+        },
+        None => None
+    };
+    let audio_device = take_option(&mut args, "--audio-device");
+    let mut shader_name = take_option(&mut args, "--shader");
+    let log_frame_times = take_flag(&mut args, "--frame-times");
+    let render_worker = take_flag(&mut args, "--render-worker");
+    let pacing = match take_option(&mut args, "--pacing").as_deref()
+    {
+        None | Some("sleep") => Pacing::Sleep,
+        Some("audio") => Pacing::Audio,
+        Some(p) => {
+            eprintln!("Unknown pacing: {} (expected sleep or audio)", p);
+            process::exit(1);
+        }
+    };
+
+    // Headless batch modes
+    if args.len() > 1 && args[1] == "movie2video"
+    {
+        if args.len() != 5
+        {
+            eprintln!("Usage: {} movie2video [--render-worker] <rom> <movie> <output>", args[0]);
+            process::exit(1);
+        }
+        movie_to_video(Path::new(&args[2]), Path::new(&args[3]), Path::new(&args[4]), render_worker);
+        return
+    }
+    if args.len() > 1 && args[1] == "export-save"
+    {
+        if args.len() != 4 && args.len() != 5
+        {
+            eprintln!("Usage: {} export-save <rom> <output> [raw|rtc]", args[0]);
+            process::exit(1);
+        }
+        let format = match args.get(4).map(|f| f.as_str()) {
+            None | Some("raw") => SaveFormat::Raw,
+            Some("rtc") => SaveFormat::Rtc { timestamp: unix_time() },
+            Some(_) => {
+                eprintln!("Unknown save format: {} (expected raw or rtc)", args[4]);
+                process::exit(1);
+            }
+        };
+        export_save(Path::new(&args[2]), Path::new(&args[3]), format);
+        return
+    }
+    if args.len() > 1 && args[1] == "import-save"
+    {
+        if args.len() != 4
+        {
+            eprintln!("Usage: {} import-save <rom> <save>", args[0]);
+            process::exit(1);
+        }
+        import_save(Path::new(&args[2]), Path::new(&args[3]));
+        return
+    }
+    if args.len() > 1 && args[1] == "bench"
+    {
+        if args.len() != 3 && args.len() != 4
+        {
+            eprintln!("Usage: {} bench <rom> [frames]", args[0]);
+            process::exit(1);
+        }
+        let frames = match args.get(3).map(|f| f.parse::< u32 >()) {
+            Some(Ok(f)) => f,
+            Some(Err(_)) => {
+                eprintln!("Invalid frame count: {}", args[3]);
+                process::exit(1);
+            },
+            None => 3600
+        };
+        bench(Path::new(&args[2]), frames);
+        return
+    }
+
+    if args.len() > 1 && (args[1] == "verify" || args[1] == "hash-movie")
+    {
+        if args.len() != 5
+        {
+            eprintln!("Usage: {} {} <rom> <movie> <hashes>", args[0], args[1]);
+            process::exit(1);
+        }
+        let (rom, movie, hashes) = (Path::new(&args[2]), Path::new(&args[3]), Path::new(&args[4]));
+        if args[1] == "verify" { verify(rom, movie, hashes) } else { hash_movie(rom, movie, hashes) }
+        return
+    }
+
+    if args.len() > 1 && args[1] == "compat"
+    {
+        if args.len() < 4
+        {
+            eprintln!("Usage: {} compat <database> <rom>...", args[0]);
+            process::exit(1);
+        }
+        compat(Path::new(&args[2]), &args[3..]);
+        return
+    }
+    if args.len() > 1 && args[1] == "compat-report"
+    {
+        if args.len() != 4
+        {
+            eprintln!("Usage: {} compat-report <database> <output>", args[0]);
+            process::exit(1);
+        }
+        compat_report(Path::new(&args[2]), Path::new(&args[3]));
+        return
+    }
+
+    if args.len() > 1 && args[1] == "audio-devices"
+    {
+        list_audio_devices();
+        return
+    }
+
+    if args.len() > 1 && args[1] == "link"
+    {
+        if args.len() != 4
+        {
+            eprintln!("Usage: {} link <rom> <rom>", args[0]);
+            process::exit(1);
+        }
+        link::run_linked(Path::new(&args[2]), Path::new(&args[3]));
+        return
+    }
+
+    if args.len() > 1 && args[1] == "spectate"
+    {
+        if args.len() != 4
+        {
+            eprintln!("Usage: {} spectate <rom> <address>", args[0]);
+            process::exit(1);
+        }
+        spectate::run_spectator(Path::new(&args[2]), &args[3]);
+        return
+    }
+
+    // Port to stream the game to spectators on, when hosting
+    let host_port = if args.len() > 1 && args[1] == "host"
+    {
+        match args.get(2).map(|p| p.parse::< u16 >())
+        {
+            Some(Ok(port)) if args.len() == 3 => Some(port),
+            _ => {
+                eprintln!("Usage: {} host <port>", args[0]);
+                process::exit(1);
+            }
+        }
+    }
+    else
+    {
+        None
+    };
+
+    // Movie to play back in the window, which the player can take over
+    let movie_path = if args.len() > 1 && args[1] == "play"
+    {
+        if args.len() != 3
+        {
+            eprintln!("Usage: {} play <movie>", args[0]);
+            process::exit(1);
+        }
+        Some(Path::new(&args[2]))
+    }
+    else
+    {
+        None
+    };
+
+    // Display scaling stuff
+    let ratio = 1 + (DISPLAY_WIDTH / 10);
+    let width = DISPLAY_WIDTH + 10 * ratio;
+    let height = DISPLAY_HEIGHT + 9 * ratio;
+
+    // Create event loop
+    let mut event_loop = glutin::EventsLoop::new();
+
+    // Create window builder
+    let wb = glium::glutin::WindowBuilder::new()
+        .with_dimensions(glutin::dpi::LogicalSize::new(width as f64, height as f64))
+        .with_resizable(false)
+        .with_title("Rustboy - GameBoy Emulator");
+
+    // Create context builder. We're using OpenGL 3.3 Core Profile
+    let cb = glium::glutin::ContextBuilder::new()
+        .with_gl(glutin::GlRequest::Specific(glutin::Api::OpenGl, (3, 3)))
+        .with_gl_profile(glutin::GlProfile::Core)
+        .with_vsync(true);
+
+    // Create the display
+    let display = open_display(wb, cb, &event_loop);
+
+    // Create vertex and index buffers. The game screen fills the window
+    // unless there is an SGB border, in which case the border fills the
+    // window and the screen is drawn in its middle.
+    let vertex_buf = quad(&display, -1.0, 1.0, 1.0, -1.0);
+    let bordered_buf = {
+        let left = BORDER_SCREEN_X as f32 / BORDER_WIDTH as f32 * 2.0 - 1.0;
+        let top = 1.0 - BORDER_SCREEN_Y as f32 / BORDER_HEIGHT as f32 * 2.0;
+        quad(&display, left, top, -left, -top)
+    };
+    let index_buf = IndexBuffer::new(&display, PrimitiveType::TriangleStrip, 
+        &[1 as u16, 2, 0, 3]).unwrap();
+
+    // Textures the screen and border are uploaded into every frame
+    let screen_tex = empty_texture(&display, DISPLAY_WIDTH, DISPLAY_HEIGHT);
+    let border_tex = empty_texture(&display, BORDER_WIDTH, BORDER_HEIGHT);
+
+    // Copy of the screen that overlays are drawn into. When nothing is drawn
+    // over the screen the core's frame buffer is uploaded directly instead.
+    let mut overlay = vec![0; DISPLAY_WIDTH * DISPLAY_HEIGHT * 4];
+
+    // Create the shader program
+    let program = create_program(&display);
+
+    // Initialize OpenAL with alto. From here on failures are shown in the
+    // window rather than only on the console.
+    let alto = Alto::load_default().unwrap_or_else(|e| {
+        show_error(&display, &mut event_loop, &format!("No OpenAL implementation present: {}", e))
+    });
+    let mut audio = match Audio::open(alto, audio_device.as_deref(), volume.unwrap_or(MAX_VOLUME))
+    {
+        Ok(audio) => audio,
+        Err(e) => show_error(&display, &mut event_loop, &format!("Unable to open audio device: {}", e))
+    };
+
+    // Create GameBoy instance
+    let rom_path = Path::new("ROMs/Tetris.gb");
+    let mut gb = Gameboy::try_new(rom_path).unwrap_or_else(|e| {
+        show_error(&display, &mut event_loop, &format!("Unable to load {}: {}", rom_path.display(), e))
+    });
+
+    // Pause and report where the game is stuck if it crashes
+    gb.set_watchdog(true);
+
+    // Play the game's audio
+    audio.attach(&mut gb);
+
+    // On-screen notifications
+    let mut osd = Osd::new();
+
+    // Warn about anything the cartridge needs that isn't emulated
+    for feature in gb.unsupported_features()
+    {
+        eprintln!("Warning: {}", feature);
+        osd.notify(feature.to_string());
+    }
+
+    // Key bindings, loaded from the controls file if there is one
+    let mut bindings = if Path::new(CONTROLS_PATH).exists()
+    {
+        match Bindings::load(Path::new(CONTROLS_PATH))
+        {
+            Ok(b) => b,
+            Err(e) => {
+                eprintln!("Unable to load {}: {}", CONTROLS_PATH, e);
+                osd.notify("Invalid controls file");
+                Bindings::new()
+            }
+        }
+    }
+    else
+    {
+        Bindings::new()
+    };
+
+    // Per-game overrides, loaded from the game config file if there is one
+    if Path::new(GAMES_PATH).exists()
+    {
+        match GameConfig::load(Path::new(GAMES_PATH), &gb)
+        {
+            Ok(config) => {
+                config.apply(&mut gb, &mut bindings);
+
+                // The volume and shader given on the command line win
+                if let (Some(v), None) = (config.volume, volume)
+                {
+                    audio.set_volume(v);
+                }
+                if shader_name.is_none()
+                {
+                    shader_name = config.shader;
+                }
+            },
+            Err(e) => {
+                eprintln!("Unable to load {}: {}", GAMES_PATH, e);
+                osd.notify("Invalid game config file");
+            }
+        }
+    }
+
+    // Per-game controls can take keys from other actions
+    for conflict in bindings.conflicts()
+    {
+        eprintln!("Warning: {}", conflict);
+        osd.notify(conflict);
+    }
+
+    // User shader the game screen is drawn with instead of the default one,
+    // and whether it is currently used
+    let mut use_shader = true;
+    let screen_program = shader_name.and_then(|name| match shader::load_program(&display, &name)
+    {
+        Ok(program) => Some(program),
+        Err(e) => {
+            eprintln!("{}", e);
+            osd.notify("Invalid shader, using the default");
+            None
+        }
+    });
+
+    if let Some(path) = movie_path
+    {
+        match Movie::load(path)
+        {
+            Ok(movie) => {
+                gb.play_movie(movie);
+                osd.notify("Playing movie - press a button to take over");
+            },
+            Err(e) => show_error(&display, &mut event_loop, &format!("Unable to load movie file: {}", e))
+        }
+    }
+
+    // Spectators watching the game, when hosting
+    let mut host = host_port.map(|port| match SpectateHost::bind(("0.0.0.0", port))
+    {
+        Ok(host) => {
+            osd.notify(format!("Hosting on port {}", port));
+            host
+        },
+        Err(e) => show_error(&display, &mut event_loop, &format!("Unable to host on port {}: {}", port, e))
+    });
+
+    // Frame advance debugging. Advances a single frame while paused.
+    let mut advance = false;
+
+    // Draws the current keypad state over the screen
+    let mut show_inputs = false;
+
+    // Draws the debug panel's current page over the screen, and where its
+    // memory page starts
+    let mut debug_page: Option< DebugPage > = None;
+    let mut memory_addr: u16 = 0xC000;
+
+    // Draws the CGB BG tile attributes over the screen
+    let mut show_attributes = false;
+
+    // Draws the SGB border around the screen when the game sends one. The
+    // window is resized to fit whenever the border appears or disappears.
+    let mut show_border = true;
+    let mut bordered = false;
+
+    // Savestates of the last few seconds, loaded back while rewinding
+    let mut rewind = Rewind::new();
+    let mut rewinding = false;
+
+    // The emulation speed to go back to when fast forward is released
+    let mut fast_forward: Option< f32 > = None;
+
+    // Tile and BG map viewer window, while it is open
+    let mut viewer: Option< Viewer > = None;
+    let mut toggle_viewer = false;
+
+    // Host time taken by each frame, to tell stutter from slow emulation
+    // apart from stutter caused by the OS
+    let mut frame_times = FrameTimes::new();
+
+    // Battery saves are flushed periodically so a crash loses little
+    let mut last_autosave = Instant::now();
+
+    // Primary application loop
+    let mut closed = false;
+    while !closed
+    {
+        // Event loop
+        let viewer_id = viewer.as_ref().map(|v| v.id());
+        event_loop.poll_events(|e| 
+        {
+            match e
+            {
+                glutin::Event::WindowEvent { event, window_id } => 
+                {
+                    match event 
+                    {
+                        // Window close event. Closing the viewer leaves the
+                        // emulator running.
+                        glutin::WindowEvent::CloseRequested if Some(window_id) == viewer_id => toggle_viewer = true,
+                        glutin::WindowEvent::CloseRequested => closed = true,
+
+                        // Keyboard input event
+                        glutin::WindowEvent::KeyboardInput { input, .. } => 
+                        {
+                            let action = match input.virtual_keycode {
+                                Some(key) => bindings.action(key),
+                                None => None
+                            };
+                            let pressed = input.state == glutin::ElementState::Pressed;
+
+                            match action
+                            {
+                                Some(Action::Press(button)) => {
+                                    if pressed && gb.playback().is_some()
+                                    {
+                                        gb.take_over(TakeOver::FreePlay);
+                                        osd.notify("Movie stopped");
+                                    }
+                                    if pressed { gb.key_down(button) } else { gb.key_up(button) }
+                                },
+                                Some(Action::TakeOver) if pressed && gb.playback().is_some() => {
+                                    gb.take_over(TakeOver::Record);
+                                    osd.notify("Recording from here");
+                                },
+                                Some(Action::Pause) if pressed => {
+                                    if gb.is_paused() { gb.resume() } else { gb.pause() }
+                                    osd.notify(if gb.is_paused() { "Paused" } else { "Resumed" });
+                                },
+                                Some(Action::FrameAdvance) if pressed => advance = true,
+                                Some(Action::ToggleInputs) if pressed => {
+                                    show_inputs = !show_inputs;
+                                    osd.notify(if show_inputs { "Input display on" } else { "Input display off" });
+                                },
+                                Some(Action::ToggleDebug) if pressed => debug_page = match debug_page {
+                                    Some(page) => page.next(),
+                                    None => Some(DebugPage::Registers)
+                                },
+                                Some(Action::MemoryUp) if pressed => memory_addr = memory_addr.wrapping_sub(MEMORY_PAGE),
+                                Some(Action::MemoryDown) if pressed => memory_addr = memory_addr.wrapping_add(MEMORY_PAGE),
+                                Some(Action::ToggleAttributes) if pressed => show_attributes = !show_attributes,
+                                Some(Action::ToggleBorder) if pressed => {
+                                    show_border = !show_border;
+                                    osd.notify(if show_border { "SGB border on" } else { "SGB border off" });
+                                },
+                                Some(Action::ToggleViewer) if pressed => toggle_viewer = true,
+                                Some(Action::VolumeUp) if pressed => {
+                                    audio.set_volume(audio.volume() + VOLUME_STEP);
+                                    osd.notify(format!("Volume {}%", audio.volume()));
+                                },
+                                Some(Action::VolumeDown) if pressed => {
+                                    audio.set_volume(audio.volume().saturating_sub(VOLUME_STEP));
+                                    osd.notify(format!("Volume {}%", audio.volume()));
+                                },
+                                Some(Action::ToggleBgLayer) if pressed => toggle_layer(&mut gb, &mut osd, PixelLayer::Background),
+                                Some(Action::ToggleWindowLayer) if pressed => toggle_layer(&mut gb, &mut osd, PixelLayer::Window),
+                                Some(Action::ToggleSpriteLayer) if pressed => toggle_layer(&mut gb, &mut osd, PixelLayer::Sprite),
+                                Some(Action::SaveState) if pressed => match gb.store_state(STATE_SLOT) {
+                                    Ok(()) => osd.notify("State saved"),
+                                    Err(e) => osd.notify(format!("Unable to save state: {}", e))
+                                },
+                                Some(Action::LoadState) if pressed => match gb.load_stored_state(STATE_SLOT) {
+                                    Ok(()) => osd.notify("State loaded"),
+                                    Err(e) => osd.notify(format!("Unable to load state: {}", e))
+                                },
+                                Some(Action::Rewind) => rewinding = pressed,
+                                Some(Action::FastForward) => {
+                                    if pressed && fast_forward.is_none()
+                                    {
+                                        fast_forward = Some(gb.emulation_speed());
+                                        gb.set_emulation_speed(MAX_EMULATION_SPEED);
+                                    }
+                                    else if let (false, Some(speed)) = (pressed, fast_forward.take())
+                                    {
+                                        gb.set_emulation_speed(speed);
+                                    }
+                                },
+                                Some(Action::Screenshot) if pressed => match save_screenshot(gb.get_image_data()) {
+                                    Ok(name) => osd.notify(format!("Saved {}", name)),
+                                    Err(e) => osd.notify(format!("Unable to save screenshot: {}", e))
+                                },
+                                Some(Action::Reset) if pressed => {
+                                    gb.soft_reset();
+                                    osd.notify("Reset");
+                                },
+                                Some(Action::ToggleFilter) if pressed => {
+                                    if screen_program.is_some()
+                                    {
+                                        use_shader = !use_shader;
+                                        osd.notify(if use_shader { "Shader on" } else { "Shader off" });
+                                    }
+                                    else
+                                    {
+                                        osd.notify("No shader loaded");
+                                    }
+                                },
+                                Some(Action::NextAudioDevice) if pressed => match audio.next_device() {
+                                    Ok(()) => osd.notify(format!("Audio: {}", audio.device_name())),
+                                    Err(e) => {
+                                        eprintln!("Unable to switch audio device: {}", e);
+                                        osd.notify("Unable to switch audio device");
+                                    }
+                                },
+                                _ => {}
+                            }
+                        },
+                        _ => ()
+                    }
+                },
+
+                _ => ()
+            }
+        });
+
+        if toggle_viewer
+        {
+            viewer = match viewer {
+                Some(_) => None,
+                None => Some(Viewer::new(&event_loop))
+            };
+            toggle_viewer = false;
+        }
+
+        // Send spectators what happened since the last run, along with the
+        // inputs for this one
+        if let Some(host) = host.as_mut()
+        {
+            if let Err(e) = host.update(&gb)
+            {
+                eprintln!("Unable to stream to spectators: {}", e);
+            }
+        }
+
+        // Execute GameBoy cycle
+        let frame = gb.frame_count();
+        let started = Instant::now();
+        let exit = if rewinding && !gb.is_paused()
+        {
+            if !rewind.step_back(&mut gb)
+            {
+                rewinding = false;
+                osd.notify("Nothing left to rewind");
+            }
+            RunExit::Completed
+        }
+        else if gb.is_paused() && advance
+        {
+            gb.run_frame()
+        }
+        else if pacing == Pacing::Audio && !gb.is_paused()
+        {
+            run_paced(&mut gb, &mut audio)
+        }
+        else
+        {
+            gb.run()
+        };
+        advance = false;
+        frame_times.add_emulation(started.elapsed());
+        if !rewinding
+        {
+            rewind.record(&gb);
+        }
+
+        // Pause at breakpoints and hangs. Frame advance or resuming carries
+        // on from there.
+        match exit
+        {
+            RunExit::Completed => {},
+            RunExit::Breakpoint(addr) => {
+                gb.pause();
+                osd.notify(format!("Breakpoint at {}", addr));
+            },
+            RunExit::Hung(hang) => {
+                gb.pause();
+                eprintln!("Game hung: {}", hang);
+                osd.notify(format!("Game hung at {}", hang.start));
+            }
+        }
+
+        // Upload the GameBoy GPU image data, with any overlays drawn over it
+        let stopped = gb.status().stopped;
+        let image_data = if show_inputs || show_attributes || debug_page.is_some() || stopped || !osd.is_empty()
+        {
+            overlay.copy_from_slice(gb.get_image_data());
+            if show_inputs
+            {
+                draw_input_overlay(&mut overlay, gb.inputs());
+            }
+            if show_attributes && !draw_attribute_overlay(&mut overlay, &gb)
+            {
+                show_attributes = false;
+                osd.notify("No BG attributes outside CGB mode");
+            }
+            if let Some(page) = debug_page
+            {
+                draw_debug_panel(&mut overlay, &gb, &frame_times, page, memory_addr);
+            }
+            if stopped
+            {
+                osd::draw_text(&mut overlay, 2, DISPLAY_HEIGHT - 9, "CPU STOPPED - PRESS A BUTTON");
+            }
+            osd.draw(&mut overlay);
+            &overlay[..]
+        }
+        else
+        {
+            gb.get_image_data()
+        };
+        upload(&screen_tex, image_data);
+
+        // Switch layouts when the border appears or disappears
+        let border = if show_border { gb.get_border_image() } else { None };
+        if border.is_some() != bordered
+        {
+            bordered = border.is_some();
+            let scale = width as f64 / DISPLAY_WIDTH as f64;
+            let size = if bordered {
+                (BORDER_WIDTH as f64 * scale, BORDER_HEIGHT as f64 * scale)
+            } else {
+                (width as f64, height as f64)
+            };
+            display.gl_window().window().set_inner_size(glutin::dpi::LogicalSize::new(size.0, size.1));
+        }
+
+        // Draw. The border is always drawn as is; only the screen goes
+        // through the user shader.
+        let mut target = display.draw();
+        target.clear_color(0.0, 0.0, 1.0, 1.0);
+        let (target_width, target_height) = target.get_dimensions();
+        let screen_shader = screen_program.as_ref().filter(|_| use_shader).unwrap_or(&program);
+        let screen_buf = match border
+        {
+            Some(border) => {
+                upload(&border_tex, border);
+                let uniforms = uniform! { tex: &border_tex };
+                target.draw(&vertex_buf, &index_buf, &program, &uniforms, &Default::default()).unwrap();
+                &bordered_buf
+            },
+            None => &vertex_buf
+        };
+        let output_size = if bordered {
+            [target_width as f32 * DISPLAY_WIDTH as f32 / BORDER_WIDTH as f32,
+             target_height as f32 * DISPLAY_HEIGHT as f32 / BORDER_HEIGHT as f32]
+        } else {
+            [target_width as f32, target_height as f32]
+        };
+        let uniforms = uniform! {
+            tex: &screen_tex,
+            input_size: [DISPLAY_WIDTH as f32, DISPLAY_HEIGHT as f32],
+            output_size: output_size,
+            frame_count: gb.frame_count() as i32
+        };
+        target.draw(screen_buf, &index_buf, screen_shader, &uniforms, &Default::default()).unwrap();
+        target.finish().unwrap();
+
+        // Only count new frames as presented, so frames emulated without
+        // ever reaching the screen show up in the A/V stats
+        if gb.frame_count() != frame
+        {
+            gb.report_frame_presented();
+            let budget = Duration::from_secs_f64(1.0 / (gb.frame_rate() * gb.emulation_speed() as f64));
+            match frame_times.frame_shown(budget)
+            {
+                Some(stutter) if log_frame_times => eprintln!("{}", stutter),
+                _ => {}
+            }
+        }
+
+        // Time spent paused isn't a stutter
+        if gb.is_paused()
+        {
+            frame_times.restart();
+        }
+        gb.report_samples_played(audio.update());
+
+        if let Some(viewer) = viewer.as_mut()
+        {
+            viewer.draw(&gb);
+        }
+
+        if last_autosave.elapsed() >= AUTOSAVE_INTERVAL
+        {
+            last_autosave = Instant::now();
+            if let Err(e) = gb.save()
+            {
+                osd.notify(format!("Unable to write save file: {}", e));
+            }
+        }
+
+        // Sleep main thread to avoid overloading CPU. Audio pacing only waits
+        // for the device to play some of the buffer.
+        thread::sleep(Duration::from_millis(if pacing == Pacing::Audio { 1 } else { 10 }));
+    }
+
+    if log_frame_times
+    {
+        print!("{}", frame_times.report());
+    }
+
+    // Persist battery backed RAM on exit
+    if let Err(e) = gb.save()
+    {
+        eprintln!("Unable to write save file: {}", e);
+    }
+
+    // Keep the movie recorded after taking over next to the original
+    if let (Some(path), Some(movie)) = (movie_path, gb.stop_recording())
+    {
+        let out = format!("{}.takeover", path.display());
+        match movie.save(Path::new(&out))
+        {
+            Ok(()) => println!("Wrote {} frames to {}", movie.len(), out),
+            Err(e) => eprintln!("Unable to write movie file: {}", e)
+        }
+    }
+}
+
+/// Write the RGBA screen image to `screenshot-<unix time>.ppm` in the working
+/// directory and return the file name
+fn save_screenshot(image: &[u8]) -> IoResult< String >
+{
+    let name = format!("screenshot-{}.ppm", unix_time());
+    let mut data = format!("P6\n{} {}\n255\n", DISPLAY_WIDTH, DISPLAY_HEIGHT).into_bytes();
+    for pixel in image.chunks(4)
+    {
+        data.extend_from_slice(&pixel[..3]);
+    }
+    fs::write(&name, data)?;
+    Ok(name)
+}
+
+/// Show or hide a layer of the screen and say which way it went
+fn toggle_layer(gb: &mut Gameboy, osd: &mut Osd, layer: PixelLayer)
+{
+    let shown = !gb.layer_shown(layer);
+    gb.set_layer_shown(layer, shown);
+    let name = match layer {
+        PixelLayer::Background => "BG",
+        PixelLayer::Window => "Window",
+        _ => "Sprites"
+    };
+    osd.notify(format!("{} {}", name, if shown { "shown" } else { "hidden" }));
+}
+
+/// Open the window. There is nowhere to show an error yet if that fails, so
+/// it is printed before exiting.
+fn open_display(wb: glutin::WindowBuilder, cb: glutin::ContextBuilder< glutin::NotCurrent >,
+    event_loop: &glutin::EventsLoop) -> glium::Display
+{
+    glium::Display::new(wb, cb, event_loop).unwrap_or_else(|e| {
+        eprintln!("Unable to open a window: {}", e);
+        process::exit(1);
+    })
+}
+
+/// Show an error in the window until it is closed, then exit with status 1.
+/// The error is printed as well, for when the window can't be seen.
+fn show_error(display: &glium::Display, event_loop: &mut glutin::EventsLoop, msg: &str) -> !
+{
+    eprintln!("{}", msg);
+
+    let mut image = vec![0; DISPLAY_WIDTH * DISPLAY_HEIGHT * 4];
+    fill_rect(&mut image, 0, 0, DISPLAY_WIDTH, DISPLAY_HEIGHT, [0, 0, 0, 255]);
+    draw_text(&mut image, 2, 2, "ERROR");
+    let lines = draw_wrapped_text(&mut image, 2, 12, msg);
+    draw_wrapped_text(&mut image, 2, 20 + lines * 8, "Close the window to exit");
+
+    let tex = empty_texture(display, DISPLAY_WIDTH, DISPLAY_HEIGHT);
+    upload(&tex, &image);
+    let vertex_buf = quad(display, -1.0, 1.0, 1.0, -1.0);
+    let index_buf = IndexBuffer::new(display, PrimitiveType::TriangleStrip, &[1 as u16, 2, 0, 3]).unwrap();
+    let program = create_program(display);
+
+    let mut closed = false;
+    while !closed
+    {
+        event_loop.poll_events(|e| {
+            if let glutin::Event::WindowEvent { event: glutin::WindowEvent::CloseRequested, .. } = e
+            {
+                closed = true;
+            }
+        });
+
+        let mut target = display.draw();
+        target.clear_color(0.0, 0.0, 0.0, 1.0);
+        target.draw(&vertex_buf, &index_buf, &program, &uniform! { tex: &tex }, &Default::default()).unwrap();
+        target.finish().unwrap();
+        thread::sleep(Duration::from_millis(16));
+    }
+    process::exit(1)
+}
+
+/// Create the shader program that draws a textured quad
+fn create_program(display: &glium::Display) -> glium::Program
+{
+    glium::Program::from_source(display, shader::VERTEX_SHADER, shader::FRAGMENT_SHADER, None).unwrap()
+}
+
+/// Create an RGBA texture of the given size to upload images into
+fn empty_texture(display: &glium::Display, width: usize, height: usize) -> Texture2d
+{
+    Texture2d::empty_with_format(display, UncompressedFloatFormat::U8U8U8U8,
+        MipmapsOption::NoMipmap, width as u32, height as u32).unwrap()
+}
+
+/// Replace the contents of a texture with RGBA image data of the same size.
+/// The data is read straight from the slice without being copied first.
+fn upload(tex: &Texture2d, data: &[u8])
+{
+    let (width, height) = tex.dimensions();
+    let image = RawImage2d {
+        data: Cow::Borrowed(data),
+        width,
+        height,
+        format: ClientFormat::U8U8U8U8
+    };
+    tex.write(glium::Rect { left: 0, bottom: 0, width, height }, image);
+}
+
+/// Create a textured quad covering the given part of the window, in OpenGL
+/// coordinates
+fn quad(display: &glium::Display, left: f32, top: f32, right: f32, bottom: f32) -> VertexBuffer< Vertex >
+{
+    let vertices = vec![
+        Vertex { pos: [left, top], col: [1.0, 0.0, 0.0, 1.0], tex: [0.0, 0.0] },          // Top-Left
+        Vertex { pos: [right, top], col: [0.0, 1.0, 0.0, 1.0], tex: [1.0, 0.0] },         // Top-Right
+        Vertex { pos: [right, bottom], col: [0.0, 0.0, 1.0, 1.0], tex: [1.0, 1.0] },      // Bottom-Right
+        Vertex { pos: [left, bottom], col: [1.0, 1.0, 1.0, 1.0], tex: [0.0, 1.0] },       // Bottom-Left
+    ];
+    VertexBuffer::new(display, &vertices).unwrap()
+}
+
+/// Draw the state of every button in the bottom left corner of the image.
+/// Held buttons are drawn in red, released buttons in dark gray.
+fn draw_input_overlay(image: &mut [u8], state: ButtonState)
+{
+    // Size of a single button indicator in pixels
+    const SIZE: usize = 4;
+
+    // Top-left corner of the overlay
+    let x0 = 4;
+    let y0 = DISPLAY_HEIGHT - 4 - SIZE * 3;
+
+    // (column, row, held) for each button. The D-pad is drawn as a cross and
+    // Select, Start, B & A are drawn in a row to the right of it.
+    let buttons = [
+        (1, 0, state.contains(ButtonState::UP)),
+        (0, 1, state.contains(ButtonState::LEFT)),
+        (2, 1, state.contains(ButtonState::RIGHT)),
+        (1, 2, state.contains(ButtonState::DOWN)),
+        (4, 2, state.contains(ButtonState::SELECT)),
+        (6, 2, state.contains(ButtonState::START)),
+        (8, 1, state.contains(ButtonState::B)),
+        (10, 1, state.contains(ButtonState::A))
+    ];
+
+    for &(col, row, held) in buttons.iter()
+    {
+        let color = if held { [255, 0, 0, 255] } else { [64, 64, 64, 255] };
+        for y in 0..SIZE - 1
+        {
+            for x in 0..SIZE - 1
+            {
+                let px = x0 + col * SIZE + x;
+                let py = y0 + row * SIZE + y;
+                let i = (py * DISPLAY_WIDTH + px) * 4;
+                image[i..i + 4].copy_from_slice(&color);
+            }
+        }
+    }
+}
+
+/// Play back a movie without a window and write every frame to the output file
+/// as raw RGBA video. The output can be encoded with e.g.
+/// `ffmpeg -f rawvideo -pixel_format rgba -video_size 160x144 -framerate 59.73 -i <output> out.mp4`.
+/// With `render_worker` lines are drawn on another thread, see
+/// `Gameboy::set_render_worker`.
+fn movie_to_video(rom_path: &Path, movie_path: &Path, out_path: &Path, render_worker: bool)
+{
+    let movie = Movie::load(movie_path).unwrap_or_else(|e| {
+        eprintln!("Unable to load {}: {}", movie_path.display(), e);
+        process::exit(1);
+    });
+    let out = File::create(out_path).unwrap_or_else(|e| {
+        eprintln!("Unable to create {}: {}", out_path.display(), e);
+        process::exit(1);
+    });
+    let recorder = Arc::new(Mutex::new(FrameRecorder::new(BufWriter::new(out))));
+
+    let mut gb = load_game(rom_path);
+    gb.add_frame_sink(recorder.clone());
+
+    gb.set_render_worker(render_worker);
+    for &state in movie.frames()
+    {
+        gb.set_inputs(state);
+        gb.run_frame();
+    }
+    recorder.lock().unwrap().finish().expect("Unable to write frame");
+
+    println!("Wrote {} frames to {}", movie.len(), out_path.display());
+}
+
+/// Load a ROM for a mode without a window, with its battery save. Exits on
+/// failure.
+fn load_game(rom_path: &Path) -> Gameboy
+{
+    Gameboy::try_new(rom_path).unwrap_or_else(|e| {
+        eprintln!("Unable to load {}: {}", rom_path.display(), e);
+        process::exit(1);
+    })
+}
+
+/// Load a ROM and movie for replaying headlessly. The GameBoy has no storage
+/// so a save file can't change the replay. Exits on failure.
+fn load_replay(rom_path: &Path, movie_path: &Path) -> (Gameboy, Movie)
+{
+    let gb = fs::read(rom_path).and_then(Gameboy::try_from_rom).unwrap_or_else(|e| {
+        eprintln!("Unable to load {}: {}", rom_path.display(), e);
+        process::exit(1);
+    });
+    let movie = Movie::load(movie_path).unwrap_or_else(|e| {
+        eprintln!("Unable to load {}: {}", movie_path.display(), e);
+        process::exit(1);
+    });
+    (gb, movie)
+}
+
+/// Replay a movie and write the frame hashes taken along the way, for
+/// `verify` to check later replays against
+fn hash_movie(rom_path: &Path, movie_path: &Path, out_path: &Path)
+{
+    let (mut gb, movie) = load_replay(rom_path, movie_path);
+    let log = HashLog::record(&mut gb, &movie);
+    if let Err(e) = log.save(out_path)
+    {
+        eprintln!("Unable to write {}: {}", out_path.display(), e);
+        process::exit(1);
+    }
+    println!("Wrote {} hashes to {}", log.hashes().len(), out_path.display());
+}
+
+/// Replay a movie and check it hashes the same as it did when the hashes
+/// were written with `hash-movie`. Exits with status 1 if it doesn't, so it
+/// can be used as a regression check.
+fn verify(rom_path: &Path, movie_path: &Path, hashes_path: &Path)
+{
+    let (mut gb, movie) = load_replay(rom_path, movie_path);
+    let expected = HashLog::load(hashes_path).unwrap_or_else(|e| {
+        eprintln!("Unable to load {}: {}", hashes_path.display(), e);
+        process::exit(1);
+    });
+    match expected.verify(&mut gb, &movie)
+    {
+        Ok(()) => println!("{} frames match", movie.len()),
+        Err(divergence) => {
+            eprintln!("{}", divergence);
+            process::exit(1);
+        }
+    }
+}
+
+/// Load a compatibility database, or start an empty one if the file doesn't
+/// exist yet. Exits on failure.
+fn load_compat_db(path: &Path) -> CompatDb
+{
+    if !path.exists()
+    {
+        return CompatDb::default()
+    }
+    CompatDb::load(path).unwrap_or_else(|e| {
+        eprintln!("Unable to load {}: {}", path.display(), e);
+        process::exit(1);
+    })
+}
+
+/// Check whether each ROM boots and record the results in the compatibility
+/// database
+fn compat(db_path: &Path, roms: &[String])
+{
+    let mut db = load_compat_db(db_path);
+    for rom_path in roms
+    {
+        match fs::read(rom_path).and_then(|rom| db.test(rom, COMPAT_FRAMES))
+        {
+            Ok(status) => println!("{}: {}", rom_path, status),
+            Err(e) => eprintln!("Unable to load {}: {}", rom_path, e)
+        }
+    }
+    if let Err(e) = db.save(db_path)
+    {
+        eprintln!("Unable to write {}: {}", db_path.display(), e);
+        process::exit(1);
+    }
+}
+
+/// Write a Markdown compatibility report from the compatibility database
+fn compat_report(db_path: &Path, out_path: &Path)
+{
+    let db = load_compat_db(db_path);
+    if let Err(e) = fs::write(out_path, db.to_markdown())
+    {
+        eprintln!("Unable to write {}: {}", out_path.display(), e);
+        process::exit(1);
+    }
+    println!("Wrote {} games to {}", db.entries().len(), out_path.display());
+}
+
+/// Write the game's battery save out in a format other emulators can load
+fn export_save(rom_path: &Path, out_path: &Path, format: SaveFormat)
+{
+    let gb = load_game(rom_path);
+    let data = match gb.export_save(format) {
+        Some(d) => d,
+        None => {
+            eprintln!("{} has no battery backed RAM", rom_path.display());
+            process::exit(1);
+        }
+    };
+    if let Err(e) = fs::write(out_path, &data)
+    {
+        eprintln!("Unable to write {}: {}", out_path.display(), e);
+        process::exit(1);
+    }
+    println!("Exported {} bytes to {}", data.len(), out_path.display());
+}
+
+/// Replace the game's battery save with a save file from another emulator
+fn import_save(rom_path: &Path, save_path: &Path)
+{
+    let mut gb = load_game(rom_path);
+    let result = fs::read(save_path)
+        .and_then(|data| gb.import_save(&data))
+        .and_then(|_| gb.save());
+    if let Err(e) = result
+    {
+        eprintln!("Unable to import {}: {}", save_path.display(), e);
+        process::exit(1);
+    }
+    println!("Imported {}", save_path.display());
+}
+
+/// Remove an option and the value after it from the command line arguments,
+/// returning the value. Exits if the option has no value.
+fn take_option(args: &mut Vec< String >, name: &str) -> Option< String >
+{
+    let i = args.iter().position(|a| a == name)?;
+    if i + 1 >= args.len()
+    {
+        eprintln!("Missing value for {}", name);
+        process::exit(1);
+    }
+    let value = args.remove(i + 1);
+    args.remove(i);
+    Some(value)
+}
+
+/// Run just enough whole frames to top the audio buffer up to its target
+/// fill, which ties the emulation speed to the audio device's clock
+fn run_paced(gb: &mut Gameboy, audio: &mut Audio) -> RunExit
+{
+    for _ in 0..MAX_PACED_FRAMES
+    {
+        if audio.buffered() >= TARGET_FILL { break }
+        let exit = gb.run_frame();
+        gb.report_samples_played(audio.update());
+        if exit != RunExit::Completed
+        {
+            return exit
+        }
+    }
+    RunExit::Completed
+}
+
+/// Remove a flag from the arguments, returning whether it was given
+fn take_flag(args: &mut Vec< String >, name: &str) -> bool
+{
+    match args.iter().position(|a| a == name)
+    {
+        Some(i) => {
+            args.remove(i);
+            true
+        },
+        None => false
+    }
+}
+
+/// Print the name of every audio output device, for `--audio-device`
+fn list_audio_devices()
+{
+    let alto = match Alto::load_default() {
+        Ok(alto) => alto,
+        Err(e) => {
+            eprintln!("No OpenAL implementation present: {}", e);
+            process::exit(1);
+        }
+    };
+    let default = alto.default_output();
+    for device in alto.enumerate_outputs()
+    {
+        let marker = if Some(&device) == default.as_ref() { " (default)" } else { "" };
+        println!("{}{}", device.to_string_lossy(), marker);
+    }
+}
+
+/// Get the current time as seconds since the UNIX epoch
+fn unix_time() -> u64
+{
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+/// Run the given number of frames as fast as possible without a window and
+/// report how long it took
+fn bench(rom_path: &Path, frames: u32)
+{
+    let mut gb = load_game(rom_path);
+
+    let start = Instant::now();
+    for _ in 0..frames
+    {
+        gb.run_frame();
+    }
+    let elapsed = start.elapsed();
+
+    let secs = elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 * 1e-9;
+    let fps = frames as f64 / secs;
+    println!("Ran {} frames in {:.3}s", frames, secs);
+    println!("{:.1} frames per second ({:.1}x real time)", fps, fps / gb.frame_rate());
+}
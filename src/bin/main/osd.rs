@@ -0,0 +1,214 @@
+use rustboy::{ DISPLAY_WIDTH, DISPLAY_HEIGHT };
+use std::time::{ Duration, Instant };
+
+/// How long a notification stays on screen
+const MESSAGE_DURATION: Duration = Duration::from_secs(2);
+
+/// Width of a glyph in pixels
+const GLYPH_WIDTH: usize = 3;
+
+/// Height of a glyph in pixels
+const GLYPH_HEIGHT: usize = 5;
+
+/// Horizontal space taken by a glyph, including spacing
+const ADVANCE: usize = GLYPH_WIDTH + 1;
+
+/// Vertical space taken by a line of text, including padding
+const LINE_HEIGHT: usize = GLYPH_HEIGHT + 3;
+
+/// The color text is drawn in
+const TEXT_COLOR: [u8; 4] = [255, 255, 255, 255];
+
+/// The color drawn behind text so it is readable over any image
+const BACKGROUND_COLOR: [u8; 4] = [0, 0, 0, 255];
+
+/// On-screen display of short lived notifications drawn over the GameBoy
+/// screen, e.g. "PAUSED" or "STATE SAVED"
+pub struct Osd
+{
+    messages: Vec< (String, Instant) >
+}
+
+impl Osd
+{
+    /// Create and return a new, empty OSD
+    pub fn new() -> Self
+    {
+        Osd { messages: Vec::new() }
+    }
+
+    /// Show a notification for a couple of seconds
+    pub fn notify< S: Into< String > >(&mut self, msg: S)
+    {
+        self.messages.push((msg.into(), Instant::now()));
+    }
+
+    /// Are there no notifications to draw? Expired notifications count
+    /// until the next call to draw.
+    pub fn is_empty(&self) -> bool
+    {
+        self.messages.is_empty()
+    }
+
+    /// Draw every notification that hasn't expired yet into the top left
+    /// corner of the RGBA image. Expired notifications are removed.
+    pub fn draw(&mut self, image: &mut [u8])
+    {
+        let now = Instant::now();
+        self.messages.retain(|&(_, shown)| now.duration_since(shown) < MESSAGE_DURATION);
+
+        // Only draw as many of the most recent messages as fit on screen
+        let max_lines = DISPLAY_HEIGHT / LINE_HEIGHT;
+        let skip = self.messages.len().saturating_sub(max_lines);
+        for (line, (msg, _)) in self.messages.iter().skip(skip).enumerate()
+        {
+            draw_text(image, 2, 2 + line * LINE_HEIGHT, msg);
+        }
+    }
+}
+
+/// Draw a line of text with its top left corner at (x, y). Text that runs
+/// off the right edge of the screen is cut off.
+pub fn draw_text(image: &mut [u8], x: usize, y: usize, text: &str)
+{
+    let chars = text.chars().take((DISPLAY_WIDTH - x) / ADVANCE);
+    let width = chars.clone().count() * ADVANCE + 1;
+    fill_rect(image, x, y, width, GLYPH_HEIGHT + 2, BACKGROUND_COLOR);
+
+    for (i, c) in chars.enumerate()
+    {
+        let glyph = glyph(c);
+        for (row, bits) in glyph.iter().enumerate()
+        {
+            for col in 0..GLYPH_WIDTH
+            {
+                if bits & (0x4 >> col) != 0
+                {
+                    let px = x + 1 + i * ADVANCE + col;
+                    let py = y + 1 + row;
+                    set_pixel(image, px, py, TEXT_COLOR);
+                }
+            }
+        }
+    }
+}
+
+/// Draw text with its top left corner at (x, y), wrapped at spaces to fit the
+/// screen. Words too long for a line are split. Returns the number of lines
+/// drawn.
+pub fn draw_wrapped_text(image: &mut [u8], x: usize, y: usize, text: &str) -> usize
+{
+    let width = (DISPLAY_WIDTH - x) / ADVANCE;
+    let mut lines: Vec< String > = Vec::new();
+    let mut line = String::new();
+    for word in text.split_whitespace()
+    {
+        let len = line.chars().count();
+        if len > 0 && len + 1 + word.chars().count() > width
+        {
+            lines.push(std::mem::take(&mut line));
+        }
+        if !line.is_empty()
+        {
+            line.push(' ');
+        }
+        for c in word.chars()
+        {
+            if line.chars().count() == width
+            {
+                lines.push(std::mem::take(&mut line));
+            }
+            line.push(c);
+        }
+    }
+    if !line.is_empty()
+    {
+        lines.push(line);
+    }
+
+    for (i, line) in lines.iter().enumerate()
+    {
+        draw_text(image, x, y + i * LINE_HEIGHT, line);
+    }
+    lines.len()
+}
+
+/// Fill a rectangle of the image with a color, clipped to the screen
+pub fn fill_rect(image: &mut [u8], x: usize, y: usize, w: usize, h: usize, color: [u8; 4])
+{
+    for py in y..(y + h).min(DISPLAY_HEIGHT)
+    {
+        for px in x..(x + w).min(DISPLAY_WIDTH)
+        {
+            set_pixel(image, px, py, color);
+        }
+    }
+}
+
+fn set_pixel(image: &mut [u8], x: usize, y: usize, color: [u8; 4])
+{
+    if x >= DISPLAY_WIDTH || y >= DISPLAY_HEIGHT { return }
+    let i = (y * DISPLAY_WIDTH + x) * 4;
+    image[i..i + 4].copy_from_slice(&color);
+}
+
+/// Get the 3x5 glyph for a character. Each row is 3 bits with the leftmost
+/// pixel in bit 2. Lowercase letters are drawn as uppercase and unknown
+/// characters are drawn as '?'.
+fn glyph(c: char) -> [u8; GLYPH_HEIGHT]
+{
+    match c.to_ascii_uppercase()
+    {
+        'A' => [0b010, 0b101, 0b111, 0b101, 0b101],
+        'B' => [0b110, 0b101, 0b110, 0b101, 0b110],
+        'C' => [0b011, 0b100, 0b100, 0b100, 0b011],
+        'D' => [0b110, 0b101, 0b101, 0b101, 0b110],
+        'E' => [0b111, 0b100, 0b110, 0b100, 0b111],
+        'F' => [0b111, 0b100, 0b110, 0b100, 0b100],
+        'G' => [0b011, 0b100, 0b101, 0b101, 0b011],
+        'H' => [0b101, 0b101, 0b111, 0b101, 0b101],
+        'I' => [0b111, 0b010, 0b010, 0b010, 0b111],
+        'J' => [0b001, 0b001, 0b001, 0b101, 0b010],
+        'K' => [0b101, 0b101, 0b110, 0b101, 0b101],
+        'L' => [0b100, 0b100, 0b100, 0b100, 0b111],
+        'M' => [0b101, 0b111, 0b111, 0b101, 0b101],
+        'N' => [0b110, 0b101, 0b101, 0b101, 0b101],
+        'O' => [0b010, 0b101, 0b101, 0b101, 0b010],
+        'P' => [0b110, 0b101, 0b110, 0b100, 0b100],
+        'Q' => [0b010, 0b101, 0b101, 0b110, 0b011],
+        'R' => [0b110, 0b101, 0b110, 0b101, 0b101],
+        'S' => [0b011, 0b100, 0b010, 0b001, 0b110],
+        'T' => [0b111, 0b010, 0b010, 0b010, 0b010],
+        'U' => [0b101, 0b101, 0b101, 0b101, 0b111],
+        'V' => [0b101, 0b101, 0b101, 0b101, 0b010],
+        'W' => [0b101, 0b101, 0b111, 0b111, 0b101],
+        'X' => [0b101, 0b101, 0b010, 0b101, 0b101],
+        'Y' => [0b101, 0b101, 0b010, 0b010, 0b010],
+        'Z' => [0b111, 0b001, 0b010, 0b100, 0b111],
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b110, 0b001, 0b010, 0b100, 0b111],
+        '3' => [0b110, 0b001, 0b010, 0b001, 0b110],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b110, 0b001, 0b110],
+        '6' => [0b011, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b010, 0b010, 0b010],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b110],
+        ' ' => [0b000, 0b000, 0b000, 0b000, 0b000],
+        '.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+        ',' => [0b000, 0b000, 0b000, 0b010, 0b100],
+        ':' => [0b000, 0b010, 0b000, 0b010, 0b000],
+        '-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+        '+' => [0b000, 0b010, 0b111, 0b010, 0b000],
+        '=' => [0b000, 0b111, 0b000, 0b111, 0b000],
+        '_' => [0b000, 0b000, 0b000, 0b000, 0b111],
+        '/' => [0b001, 0b001, 0b010, 0b100, 0b100],
+        '%' => [0b101, 0b001, 0b010, 0b100, 0b101],
+        '(' => [0b010, 0b100, 0b100, 0b100, 0b010],
+        ')' => [0b010, 0b001, 0b001, 0b001, 0b010],
+        '!' => [0b010, 0b010, 0b010, 0b000, 0b010],
+        '$' => [0b011, 0b110, 0b010, 0b011, 0b110],
+        _   => [0b110, 0b001, 0b010, 0b000, 0b010]
+    }
+}
@@ -0,0 +1,65 @@
+/*
+    Rewinding keeps a savestate every few frames while the game runs and
+    loads them back, newest first, while the rewind key is held.
+*/
+
+use rustboy::Gameboy;
+use std::collections::VecDeque;
+
+/// Frames between snapshots
+const SNAPSHOT_INTERVAL: u64 = 10;
+
+/// The most snapshots kept, 20 seconds at 60 frames a second
+const MAX_SNAPSHOTS: usize = 120;
+
+/// The snapshots of the last few seconds of a game
+pub struct Rewind
+{
+    snapshots: VecDeque< Vec< u8 > >,
+
+    /// Frame count of the newest snapshot
+    last_frame: u64
+}
+
+impl Rewind
+{
+    /// Create and return an empty rewind buffer
+    pub fn new() -> Self
+    {
+        Rewind { snapshots: VecDeque::new(), last_frame: 0 }
+    }
+
+    /// Take a snapshot if SNAPSHOT_INTERVAL frames have passed since the
+    /// last one, dropping the oldest when the buffer is full
+    pub fn record(&mut self, gb: &Gameboy)
+    {
+        let frame = gb.frame_count();
+        if !self.snapshots.is_empty() && frame >= self.last_frame && frame - self.last_frame < SNAPSHOT_INTERVAL
+        {
+            return
+        }
+
+        if self.snapshots.len() == MAX_SNAPSHOTS
+        {
+            self.snapshots.pop_front();
+        }
+        self.snapshots.push_back(gb.save_state());
+        self.last_frame = frame;
+    }
+
+    /// Load the newest snapshot and drop it from the buffer. Returns false
+    /// if there is nothing left to go back to.
+    pub fn step_back(&mut self, gb: &mut Gameboy) -> bool
+    {
+        match self.snapshots.pop_back()
+        {
+            Some(state) => {
+                // The states were saved by this same game so they always load
+                let _ = gb.load_state(&state);
+                self.last_frame = gb.frame_count();
+                true
+            },
+            None => false
+        }
+    }
+}
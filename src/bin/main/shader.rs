@@ -0,0 +1,58 @@
+use glium::{ Display, Program };
+use std::fs;
+use std::path::Path;
+
+/// Directory user shaders are loaded from
+pub const SHADER_DIR: &str = "shaders";
+
+/// Vertex shader every program is built with. Passes the texture coordinates
+/// of the quad on to the fragment shader as `tex_coords`.
+pub const VERTEX_SHADER: &str = "
+    #version 330 core
+
+    in vec2 pos;
+    in vec4 col;
+    in vec2 tex;
+    out vec4 frag_col;
+    out vec2 tex_coords;
+
+    void main()
+    {
+        frag_col = col;
+        tex_coords = tex;
+        gl_Position = vec4(pos, 0.0, 1.0);
+    }
+";
+
+/// Fragment shader that draws the texture as it is
+pub const FRAGMENT_SHADER: &str = "
+    #version 330 core
+
+    in vec4 frag_col;
+    in vec2 tex_coords;
+    out vec4 out_col;
+    uniform sampler2D tex;
+
+    void main()
+    {
+        out_col = texture(tex, tex_coords);
+    }
+";
+
+/// Build a program from the fragment shader `<name>.glsl` in SHADER_DIR.
+///
+/// The shader gets `tex_coords` from the vertex shader, writes the color
+/// to `out_col` and can use these uniforms:
+///
+/// - `sampler2D tex`: the game screen
+/// - `vec2 input_size`: size of the game screen in pixels, 160x144
+/// - `vec2 output_size`: size the screen is drawn at in window pixels
+/// - `int frame_count`: frames completed since power on
+pub fn load_program(display: &Display, name: &str) -> Result< Program, String >
+{
+    let path = Path::new(SHADER_DIR).join(format!("{}.glsl", name));
+    let fragment = fs::read_to_string(&path)
+        .map_err(|e| format!("Unable to read {}: {}", path.display(), e))?;
+    Program::from_source(display, VERTEX_SHADER, &fragment, None)
+        .map_err(|e| format!("Unable to compile {}: {}", path.display(), e))
+}
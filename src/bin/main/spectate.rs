@@ -0,0 +1,118 @@
+/*
+    Spectator mode. Mirrors a game hosted by another rustboy with
+    `rustboy host <port>`, which streams its inputs and occasional states.
+    The spectator needs the same ROM as the host.
+*/
+
+use crate::osd::Osd;
+use crate::{ create_program, empty_texture, open_display, quad, show_error, upload };
+use glium::{ glutin, uniform, Surface };
+use glium::index::{ IndexBuffer, PrimitiveType };
+use rustboy::{ Gameboy, MemoryStorage, SpectateClient, DISPLAY_HEIGHT, DISPLAY_WIDTH };
+use std::path::Path;
+use std::process;
+use std::thread;
+use std::time::Duration;
+
+/// Connect to a host and show its game in a window until either side
+/// closes. Nothing is saved, since the host owns the game.
+pub fn run_spectator(rom: &Path, addr: &str)
+{
+    let mut client = match SpectateClient::connect(addr)
+    {
+        Ok(client) => client,
+        Err(e) => {
+            eprintln!("Unable to connect to {}: {}", addr, e);
+            process::exit(1);
+        }
+    };
+
+    let ratio = 1 + (DISPLAY_WIDTH / 10);
+    let width = DISPLAY_WIDTH + 10 * ratio;
+    let height = DISPLAY_HEIGHT + 9 * ratio;
+
+    let mut event_loop = glutin::EventsLoop::new();
+    let wb = glutin::WindowBuilder::new()
+        .with_dimensions(glutin::dpi::LogicalSize::new(width as f64, height as f64))
+        .with_resizable(false)
+        .with_title("Rustboy - Spectating");
+    let cb = glutin::ContextBuilder::new()
+        .with_gl(glutin::GlRequest::Specific(glutin::Api::OpenGl, (3, 3)))
+        .with_gl_profile(glutin::GlProfile::Core)
+        .with_vsync(true);
+    let display = open_display(wb, cb, &event_loop);
+
+    let vertex_buf = quad(&display, -1.0, 1.0, 1.0, -1.0);
+    let index_buf = IndexBuffer::new(&display, PrimitiveType::TriangleStrip,
+        &[1 as u16, 2, 0, 3]).unwrap();
+    let screen_tex = empty_texture(&display, DISPLAY_WIDTH, DISPLAY_HEIGHT);
+    let mut overlay = vec![0; DISPLAY_WIDTH * DISPLAY_HEIGHT * 4];
+    let program = create_program(&display);
+
+    let mut gb = Gameboy::try_new(rom).unwrap_or_else(|e| {
+        show_error(&display, &mut event_loop, &format!("Unable to load {}: {}", rom.display(), e))
+    });
+    if let Err(e) = gb.set_storage(MemoryStorage::new())
+    {
+        eprintln!("Unable to set up storage: {}", e);
+    }
+
+    let mut osd = Osd::new();
+    osd.notify(format!("Waiting for {}", addr));
+    let mut connected = true;
+
+    let mut closed = false;
+    while !closed
+    {
+        event_loop.poll_events(|e|
+        {
+            if let glutin::Event::WindowEvent { event: glutin::WindowEvent::CloseRequested, .. } = e
+            {
+                closed = true;
+            }
+        });
+
+        if connected
+        {
+            let synced = client.is_synced();
+            match client.update(&mut gb)
+            {
+                Ok(true) => {
+                    if client.is_synced() && !synced
+                    {
+                        osd.notify("Spectating");
+                    }
+                },
+                Ok(false) => {
+                    connected = false;
+                    osd.notify("Host disconnected");
+                },
+                Err(e) => {
+                    connected = false;
+                    eprintln!("Lost the host: {}", e);
+                    osd.notify("Lost the host");
+                }
+            }
+        }
+
+        let image_data = if osd.is_empty()
+        {
+            gb.get_image_data()
+        }
+        else
+        {
+            overlay.copy_from_slice(gb.get_image_data());
+            osd.draw(&mut overlay);
+            &overlay[..]
+        };
+        upload(&screen_tex, image_data);
+
+        let mut target = display.draw();
+        target.clear_color(0.0, 0.0, 1.0, 1.0);
+        let uniforms = uniform! { tex: &screen_tex };
+        target.draw(&vertex_buf, &index_buf, &program, &uniforms, &Default::default()).unwrap();
+        target.finish().unwrap();
+
+        thread::sleep(Duration::from_millis(10));
+    }
+}
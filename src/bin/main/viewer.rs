@@ -0,0 +1,131 @@
+/*
+    A second window showing the tile data in both VRAM banks, the sprites in
+    OAM and the whole BG tile map, drawn larger than would fit over the game
+    screen. The outline on the map marks the part of it that is currently on
+    screen.
+*/
+
+use crate::{ create_program, empty_texture, quad, upload, Vertex };
+use glium::{ glutin, uniform, Surface, VertexBuffer };
+use glium::index::{ IndexBuffer, PrimitiveType };
+use glium::texture::Texture2d;
+use rustboy::{ Gameboy, DISPLAY_HEIGHT, DISPLAY_WIDTH, MAP_VIEW_SIZE, OAM_VIEW_WIDTH, TILE_VIEW_HEIGHT, TILE_VIEW_WIDTH };
+
+/// How many times larger than the GameBoy's pixels the viewer is drawn
+const SCALE: usize = 2;
+
+/// Size of the viewer image: the two tile banks side by side followed by the
+/// BG map, with the sprites under the first bank
+const WIDTH: usize = TILE_VIEW_WIDTH * 2 + MAP_VIEW_SIZE;
+const HEIGHT: usize = MAP_VIEW_SIZE;
+
+/// The color of the space around the tile data
+const BACKGROUND_COLOR: [u8; 4] = [48, 48, 48, 255];
+
+/// The color the visible part of the BG map is outlined in
+const OUTLINE_COLOR: [u8; 4] = [255, 0, 0, 255];
+
+/// The tile and map viewer window
+pub struct Viewer
+{
+    display: glium::Display,
+    program: glium::Program,
+    vertex_buf: VertexBuffer< Vertex >,
+    index_buf: IndexBuffer< u16 >,
+    texture: Texture2d,
+    image: Vec< u8 >
+}
+
+impl Viewer
+{
+    /// Open the viewer window
+    pub fn new(event_loop: &glutin::EventsLoop) -> Self
+    {
+        let wb = glutin::WindowBuilder::new()
+            .with_dimensions(glutin::dpi::LogicalSize::new((WIDTH * SCALE) as f64, (HEIGHT * SCALE) as f64))
+            .with_resizable(false)
+            .with_title("Rustboy - Tiles & BG Map");
+        let cb = glutin::ContextBuilder::new()
+            .with_gl(glutin::GlRequest::Specific(glutin::Api::OpenGl, (3, 3)))
+            .with_gl_profile(glutin::GlProfile::Core);
+        let display = glium::Display::new(wb, cb, event_loop).unwrap();
+
+        Viewer {
+            program: create_program(&display),
+            vertex_buf: quad(&display, -1.0, 1.0, 1.0, -1.0),
+            index_buf: IndexBuffer::new(&display, PrimitiveType::TriangleStrip, &[1, 2, 0, 3]).unwrap(),
+            texture: empty_texture(&display, WIDTH, HEIGHT),
+            image: vec![0; WIDTH * HEIGHT * 4],
+            display
+        }
+    }
+
+    /// Get the ID of the viewer window, to tell its events apart
+    pub fn id(&self) -> glutin::WindowId
+    {
+        self.display.gl_window().window().id()
+    }
+
+    /// Redraw the viewer with the current contents of VRAM
+    pub fn draw(&mut self, gb: &Gameboy)
+    {
+        for pixel in self.image.chunks_mut(4)
+        {
+            pixel.copy_from_slice(&BACKGROUND_COLOR);
+        }
+        self.blit(&gb.tile_data_image(0), TILE_VIEW_WIDTH, 0, 0);
+        self.blit(&gb.tile_data_image(1), TILE_VIEW_WIDTH, TILE_VIEW_WIDTH, 0);
+        self.blit(&gb.bg_map_image(), MAP_VIEW_SIZE, TILE_VIEW_WIDTH * 2, 0);
+        self.blit(&gb.oam_image(), OAM_VIEW_WIDTH, 0, TILE_VIEW_HEIGHT);
+        self.outline_screen(gb.scroll());
+
+        upload(&self.texture, &self.image);
+        let mut target = self.display.draw();
+        target.clear_color(0.0, 0.0, 0.0, 1.0);
+        let uniforms = uniform! { tex: &self.texture };
+        target.draw(&self.vertex_buf, &self.index_buf, &self.program, &uniforms, &Default::default()).unwrap();
+        target.finish().unwrap();
+    }
+
+    /// Copy an RGBA image of the given width into the viewer image with its
+    /// top left corner at (x, y). Transparent pixels are skipped.
+    fn blit(&mut self, src: &[u8], width: usize, x: usize, y: usize)
+    {
+        for (i, row) in src.chunks(width * 4).enumerate()
+        {
+            let offset = ((y + i) * WIDTH + x) * 4;
+            for (dst, pixel) in self.image[offset..offset + row.len()].chunks_mut(4).zip(row.chunks(4))
+            {
+                if pixel[3] != 0
+                {
+                    dst.copy_from_slice(pixel);
+                }
+            }
+        }
+    }
+
+    /// Outline the part of the BG map that is on screen, wrapping around the
+    /// edges of the map like the GPU does
+    fn outline_screen(&mut self, (scx, scy): (u8, u8))
+    {
+        let (scx, scy) = (scx as usize, scy as usize);
+        for i in 0..DISPLAY_WIDTH
+        {
+            self.map_pixel(scx + i, scy);
+            self.map_pixel(scx + i, scy + DISPLAY_HEIGHT - 1);
+        }
+        for i in 0..DISPLAY_HEIGHT
+        {
+            self.map_pixel(scx, scy + i);
+            self.map_pixel(scx + DISPLAY_WIDTH - 1, scy + i);
+        }
+    }
+
+    /// Draw an outline pixel at (x, y) on the BG map
+    fn map_pixel(&mut self, x: usize, y: usize)
+    {
+        let x = TILE_VIEW_WIDTH * 2 + x % MAP_VIEW_SIZE;
+        let offset = ((y % MAP_VIEW_SIZE) * WIDTH + x) * 4;
+        self.image[offset..offset + 4].copy_from_slice(&OUTLINE_COLOR);
+    }
+}
@@ -0,0 +1,302 @@
+use crate::{ Accuracy, Gameboy, Revision, Target, MIN_EMULATION_SPEED, MAX_EMULATION_SPEED, MAX_OVERCLOCK };
+use crate::keypad::OppositeDirections;
+use crate::mem::ram::RamFill;
+use crate::invalid;
+use crate::storage::{ FileStorage, StorageBackend };
+use std::io::Result as IoResult;
+use std::path::{ Path, PathBuf };
+
+/// Creates a GameBoy with everything set up front, checking that the
+/// options make sense together before anything is loaded:
+///
+/// ```no_run
+/// # use rustboy::{ GameboyBuilder, MemoryStorage, Target };
+/// let gb = GameboyBuilder::new()
+///     .rom_path("game.gbc")
+///     .target(Target::GameBoyColor)
+///     .storage(MemoryStorage::new())
+///     .build()?;
+/// # Ok::< (), std::io::Error >(())
+/// ```
+pub struct GameboyBuilder
+{
+    rom: Option< Vec< u8 > >,
+    rom_path: Option< PathBuf >,
+    target: Option< Target >,
+    revision: Option< Revision >,
+    storage: Option< Box< dyn StorageBackend > >,
+    ram_fill: Option< RamFill >,
+    boot_rom: Option< Vec< u8 > >,
+    fast_boot: bool,
+    accuracy: Accuracy,
+    sgb_border: Option< bool >,
+    emulation_speed: f32,
+    overclock: u32,
+    opposite_directions: OppositeDirections,
+    watchdog: bool,
+    reset_combo: bool,
+    strict: bool
+}
+
+impl GameboyBuilder
+{
+    /// Start building a GameBoy with no ROM, no storage and every option at its
+    /// default
+    pub fn new() -> Self
+    {
+        GameboyBuilder {
+            rom: None,
+            rom_path: None,
+            target: None,
+            revision: None,
+            storage: None,
+            ram_fill: None,
+            boot_rom: None,
+            fast_boot: false,
+            accuracy: Accuracy::Relaxed,
+            sgb_border: None,
+            emulation_speed: 1.0,
+            overclock: 1,
+            opposite_directions: OppositeDirections::Block,
+            watchdog: false,
+            reset_combo: false,
+            strict: false
+        }
+    }
+
+    /// Run a ROM image that has already been loaded into memory
+    pub fn rom_bytes(mut self, rom: Vec< u8 >) -> Self
+    {
+        self.rom = Some(rom);
+        self
+    }
+
+    /// Run the ROM in the given file. Unless other storage is given, battery
+    /// saves are kept next to the file with the same name, like
+    /// `Gameboy::new`.
+    pub fn rom_path< P: AsRef< Path > >(mut self, path: P) -> Self
+    {
+        self.rom_path = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Set the system the game runs on. Defaults to the CGB for games that
+    /// support it and the DMG for the rest.
+    pub fn target(mut self, target: Target) -> Self
+    {
+        self.target = Some(target);
+        self
+    }
+
+    /// Set the hardware revision of the system the game runs on, which
+    /// implies the target. Defaults to the usual revision of the target.
+    pub fn revision(mut self, revision: Revision) -> Self
+    {
+        self.revision = Some(revision);
+        self
+    }
+
+    /// Set the storage backend battery saves and savestates are kept in. The
+    /// game's save is loaded from it when the GameBoy is built.
+    pub fn storage< S: StorageBackend + 'static >(mut self, storage: S) -> Self
+    {
+        self.storage = Some(Box::new(storage));
+        self
+    }
+
+    /// Set the pattern RAM is filled with at power on
+    pub fn ram_fill(mut self, fill: RamFill) -> Self
+    {
+        self.ram_fill = Some(fill);
+        self
+    }
+
+    /// Run the given boot ROM before the game, see `Gameboy::set_boot_rom`.
+    /// A boot ROM of the wrong size for the target is an error.
+    pub fn boot_rom(mut self, boot_rom: Vec< u8 >) -> Self
+    {
+        self.boot_rom = Some(boot_rom);
+        self
+    }
+
+    /// Run the boot ROM straight through without drawing it, see
+    /// `Gameboy::set_fast_boot`
+    pub fn fast_boot(mut self, enabled: bool) -> Self
+    {
+        self.fast_boot = enabled;
+        self
+    }
+
+    /// Set how closely the hardware's restrictions on memory access are
+    /// followed, see `Accuracy`
+    pub fn accuracy(mut self, accuracy: Accuracy) -> Self
+    {
+        self.accuracy = accuracy;
+        self
+    }
+
+    /// Show the SGB border, see `Gameboy::set_sgb_border`. Asking for the
+    /// border runs the game on the SGB unless another target is given, and
+    /// giving a target without a border along with it is an error.
+    pub fn sgb_border(mut self, enabled: bool) -> Self
+    {
+        self.sgb_border = Some(enabled);
+        self
+    }
+
+    /// Set the emulation speed multiplier, see `Gameboy::set_emulation_speed`.
+    /// Unlike the setter, speeds out of range are an error rather than
+    /// clamped.
+    pub fn emulation_speed(mut self, speed: f32) -> Self
+    {
+        self.emulation_speed = speed;
+        self
+    }
+
+    /// Overclock the CPU, see `Gameboy::set_overclock`. Factors out of
+    /// range are an error rather than clamped.
+    pub fn overclock(mut self, factor: u32) -> Self
+    {
+        self.overclock = factor;
+        self
+    }
+
+    /// Set how opposite directions on the D-pad are handled
+    pub fn opposite_directions(mut self, policy: OppositeDirections) -> Self
+    {
+        self.opposite_directions = policy;
+        self
+    }
+
+    /// Enable the watchdog, see `Gameboy::set_watchdog`
+    pub fn watchdog(mut self, enabled: bool) -> Self
+    {
+        self.watchdog = enabled;
+        self
+    }
+
+    /// Enable the soft reset combo, see `Gameboy::set_reset_combo`
+    pub fn reset_combo(mut self, enabled: bool) -> Self
+    {
+        self.reset_combo = enabled;
+        self
+    }
+
+    /// Refuse ROMs that are unlikely to run, like `Gameboy::try_from_rom`:
+    /// cartridges with mappers that aren't emulated, and CGB only games on
+    /// other targets. Off by default, in which case the closest supported
+    /// hardware is used.
+    pub fn strict(mut self, enabled: bool) -> Self
+    {
+        self.strict = enabled;
+        self
+    }
+
+    /// Create the GameBoy. Fails if the options conflict, the ROM can't be
+    /// read or, in strict mode, is unlikely to run, or the save can't be
+    /// loaded from storage.
+    pub fn build(self) -> IoResult< Gameboy >
+    {
+        if !(MIN_EMULATION_SPEED..=MAX_EMULATION_SPEED).contains(&self.emulation_speed)
+        {
+            return Err(invalid(&format!("Emulation speed {} is outside of {} - {}",
+                self.emulation_speed, MIN_EMULATION_SPEED, MAX_EMULATION_SPEED)))
+        }
+        if !(1..=MAX_OVERCLOCK).contains(&self.overclock)
+        {
+            return Err(invalid(&format!("Overclock factor {} is outside of 1 - {}",
+                self.overclock, MAX_OVERCLOCK)))
+        }
+
+        let rom = match (self.rom, self.rom_path.as_ref())
+        {
+            (Some(rom), None) => {
+                Gameboy::check_rom(&rom)?;
+                rom
+            },
+            (None, Some(path)) => Gameboy::load_rom(path)?,
+            (None, None) => return Err(invalid("No ROM was given")),
+            (Some(_), Some(_)) => return Err(invalid("Both ROM data and a ROM path were given"))
+        };
+
+        let revision = match (self.target, self.revision)
+        {
+            (Some(target), Some(revision)) if revision.target() != target => {
+                return Err(invalid(&format!("{:?} is not a revision of the {:?}", revision, target)))
+            },
+            (_, Some(revision)) => revision,
+            (Some(target), None) => Revision::for_target(target),
+            (None, None) if self.sgb_border == Some(true) => Revision::Sgb,
+
+            // Games that support the CGB set bit 7 of the CGB flag
+            (None, None) if rom[0x0143] & 0x80 != 0 => Revision::Cgb,
+            (None, None) => Revision::Dmg
+        };
+
+        if self.sgb_border == Some(true) && revision.target() != Target::SuperGameBoy
+        {
+            return Err(invalid(&format!("Only the Super GameBoy has a border, not the {:?}", revision.target())))
+        }
+
+        // Only games that also run on older systems leave bit 6 clear. The
+        // default target is always one the game runs on.
+        if self.strict && rom[0x0143] == 0xC0 && revision.target() != Target::GameBoyColor
+        {
+            return Err(invalid("The game only runs on a GameBoy Color"))
+        }
+
+        let mut gb = Gameboy::from_rom_revision(rom, revision);
+        if self.strict
+        {
+            gb.check_supported()?;
+        }
+        gb.set_fast_boot(self.fast_boot);
+        if let Some(boot_rom) = self.boot_rom
+        {
+            gb.set_boot_rom(boot_rom)?;
+        }
+
+        if let Some(fill) = self.ram_fill
+        {
+            gb.set_ram_fill(fill);
+        }
+        gb.set_accuracy(self.accuracy);
+        if let Some(enabled) = self.sgb_border
+        {
+            gb.set_sgb_border(enabled);
+        }
+        gb.set_emulation_speed(self.emulation_speed);
+        gb.set_overclock(self.overclock);
+        gb.set_opposite_directions(self.opposite_directions);
+        gb.set_watchdog(self.watchdog);
+        gb.set_reset_combo(self.reset_combo);
+
+        let storage = match (self.storage, self.rom_path.as_ref())
+        {
+            (Some(storage), _) => Some(storage),
+            (None, Some(path)) => {
+                let dir = path.parent().unwrap_or_else(|| Path::new("."));
+                Some(Box::new(FileStorage::new(dir)) as Box< dyn StorageBackend >)
+            },
+            (None, None) => None
+        };
+        if let Some(name) = self.rom_path.as_ref().and_then(|path| path.file_stem())
+        {
+            gb.save_name = name.to_string_lossy().into_owned();
+        }
+        if let Some(storage) = storage
+        {
+            gb.set_boxed_storage(storage)?;
+        }
+
+        Ok(gb)
+    }
+}
+
+impl Default for GameboyBuilder
+{
+    fn default() -> Self
+    {
+        GameboyBuilder::new()
+    }
+}
@@ -0,0 +1,145 @@
+/*
+    Cheat Code Formats:
+    -----------------------------------------------
+    Game Genie      ABC-DEF-GHI or ABC-DEF
+                    AB  New value
+                    FCDE Address, with F XORed with 0xF
+                    GI  Value to compare against, XORed with 0xBA and
+                        rotated left by 2. Without it the patch always
+                        applies. H is a check digit and is ignored.
+    GameShark       TTVVLLHH
+                    TT  Type, only 01 (write every frame) is supported
+                    VV  Value
+                    HHLL Address
+    -----------------------------------------------
+    Game Genie codes patch ROM as it is read, GameShark codes write RAM at
+    the end of every frame.
+*/
+
+use crate::invalid;
+use std::io::Result as IoResult;
+#[cfg(feature = "cheat-db")]
+use std::sync::OnceLock;
+
+/// A single cheat code
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cheat
+{
+    /// Replaces a byte of ROM when it is read, if it has the compare value
+    GameGenie { addr: u16, value: u8, compare: Option< u8 > },
+
+    /// Writes a byte of memory at the end of every frame
+    GameShark { addr: u16, value: u8 }
+}
+
+impl Cheat
+{
+    /// Parse a Game Genie or GameShark code. Dashes and spaces are optional.
+    pub fn parse(code: &str) -> IoResult< Self >
+    {
+        let digits: Vec< u8 > = code.chars()
+            .filter(|&c| c != '-' && !c.is_whitespace())
+            .map(|c| c.to_digit(16).map(|d| d as u8))
+            .collect::< Option< _ > >()
+            .ok_or_else(|| invalid("Cheat codes must be hexadecimal"))?;
+        let byte = |i: usize| (digits[i] << 4) | digits[i + 1];
+
+        match digits.len()
+        {
+            6 | 9 => {
+                let addr = ((digits[5] ^ 0xF) as u16) << 12 | (digits[2] as u16) << 8 |
+                    (digits[3] as u16) << 4 | digits[4] as u16;
+                if addr > 0x7FFF
+                {
+                    return Err(invalid("Game Genie codes can only patch ROM"))
+                }
+                let compare = if digits.len() == 9
+                {
+                    Some(((digits[6] << 4) | digits[8]).rotate_right(2) ^ 0xBA)
+                }
+                else
+                {
+                    None
+                };
+                Ok(Cheat::GameGenie { addr, value: byte(0), compare })
+            },
+            8 => {
+                if byte(0) != 0x01
+                {
+                    return Err(invalid("Only type 01 GameShark codes are supported"))
+                }
+                Ok(Cheat::GameShark { addr: (byte(6) as u16) << 8 | byte(4) as u16, value: byte(2) })
+            },
+            _ => Err(invalid("Cheat codes must be Game Genie (ABC-DEF-GHI) or GameShark (01VVAAAA) codes"))
+        }
+    }
+
+    /// Apply the cheat to a byte read from ROM, if it is a Game Genie code
+    /// for that address
+    pub fn patch_rom(&self, addr: u16, value: u8) -> u8
+    {
+        match *self
+        {
+            Cheat::GameGenie { addr: a, value: v, compare } if a == addr => {
+                match compare
+                {
+                    Some(c) if c != value => value,
+                    _ => v
+                }
+            },
+            _ => value
+        }
+    }
+}
+
+/// A named set of cheat codes for a game from the cheat database
+#[cfg(feature = "cheat-db")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NamedCheat
+{
+    /// What the cheat does, e.g. "Infinite lives"
+    pub name: &'static str,
+
+    /// The codes to enter, separated by '+'
+    pub codes: &'static str
+}
+
+/// The cheat database, compressed with deflate. Each game starts with a
+/// `[checksum] title` line, where the checksum is the global checksum from
+/// the cartridge header in hex, followed by its cheats as
+/// `name = code+code` lines.
+#[cfg(feature = "cheat-db")]
+const DATABASE: &[u8] = include_bytes!("../data/cheats.txt.deflate");
+
+/// Get the text of the cheat database, decompressing it the first time
+#[cfg(feature = "cheat-db")]
+fn database() -> &'static str
+{
+    static TEXT: OnceLock< String > = OnceLock::new();
+    TEXT.get_or_init(|| {
+        let text = miniz_oxide::inflate::decompress_to_vec(DATABASE).expect("Cheat database is corrupt");
+        String::from_utf8(text).expect("Cheat database is corrupt")
+    })
+}
+
+/// Look up the named cheats for the game with the given header checksum
+#[cfg(feature = "cheat-db")]
+pub fn lookup(checksum: u16) -> Vec< NamedCheat >
+{
+    let header = format!("[{:04X}]", checksum);
+    database().lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .skip_while(|line| !line.starts_with(&header))
+        .skip(1)
+        .take_while(|line| !line.starts_with('['))
+        .filter_map(|line| {
+            let mut parts = line.splitn(2, '=');
+            match (parts.next(), parts.next())
+            {
+                (Some(name), Some(codes)) => Some(NamedCheat { name: name.trim(), codes: codes.trim() }),
+                _ => None
+            }
+        })
+        .collect()
+}
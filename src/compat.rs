@@ -0,0 +1,249 @@
+use crate::{ Gameboy, Revision, RunExit };
+use std::fmt;
+use std::fs;
+use std::io::{ Error, ErrorKind };
+use std::io::Result as IoResult;
+use std::path::Path;
+
+/// How well a game runs, from worst to best
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Compatibility
+{
+    /// Crashes, hangs or never shows anything
+    Broken,
+
+    /// Gets to a picture, but hasn't been played or has problems that stop
+    /// it from being played through
+    Boots,
+
+    /// Can be played through with minor glitches
+    Playable,
+
+    /// No known problems
+    Perfect
+}
+
+impl Compatibility
+{
+    /// Every rating, from worst to best
+    pub const ALL: [Compatibility; 4] = [
+        Compatibility::Broken,
+        Compatibility::Boots,
+        Compatibility::Playable,
+        Compatibility::Perfect
+    ];
+
+    /// Get the rating with the given name, ignoring case
+    pub fn from_name(name: &str) -> Option< Self >
+    {
+        Compatibility::ALL.into_iter().find(|c| c.to_string().eq_ignore_ascii_case(name))
+    }
+}
+
+impl fmt::Display for Compatibility
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        f.write_str(match self
+        {
+            Compatibility::Broken => "broken",
+            Compatibility::Boots => "boots",
+            Compatibility::Playable => "playable",
+            Compatibility::Perfect => "perfect"
+        })
+    }
+}
+
+/// What is known about how one game runs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompatEntry
+{
+    /// The title from the cartridge header
+    pub title: String,
+
+    /// The global checksum from the cartridge header, which together with
+    /// the title tells games apart
+    pub checksum: u16,
+
+    /// How well the game runs
+    pub status: Compatibility,
+
+    /// The revision the game runs best as, None for the usual one for the
+    /// cartridge
+    pub revision: Option< Revision >,
+
+    /// Free form notes, e.g. which glitches there are
+    pub notes: String
+}
+
+/// A database of how well each game runs, for keeping track of which games
+/// need accuracy work. `test` updates the database from a run of the game,
+/// which is enough to tell games that are broken from ones that boot.
+/// Playable and perfect ratings come from playing the game, and are edited
+/// into the file by hand.
+///
+/// Database files have one line per game, with the checksum in hex, rating,
+/// revision ("-" for the default), title and notes separated by tabs. Empty
+/// lines and lines starting with '#' are ignored.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CompatDb
+{
+    entries: Vec< CompatEntry >
+}
+
+impl CompatDb
+{
+    /// Get every game in the database, in the order they were added
+    pub fn entries(&self) -> &[CompatEntry]
+    {
+        &self.entries
+    }
+
+    /// Get what is known about the game with the given title and checksum
+    pub fn get(&self, title: &str, checksum: u16) -> Option< &CompatEntry >
+    {
+        self.entries.iter().find(|e| e.title == title && e.checksum == checksum)
+    }
+
+    /// Add a game to the database, replacing what was known about it
+    pub fn insert(&mut self, entry: CompatEntry)
+    {
+        match self.entries.iter_mut().find(|e| e.title == entry.title && e.checksum == entry.checksum)
+        {
+            Some(e) => *e = entry,
+            None => self.entries.push(entry)
+        }
+    }
+
+    /// Run a ROM for the given number of frames, with the revision the
+    /// database has for it, and record whether it boots. A game that hangs
+    /// or never draws anything is rated broken. A game that boots keeps a
+    /// playable or perfect rating it already had. Returns the new rating.
+    pub fn test(&mut self, rom: Vec< u8 >, frames: u32) -> IoResult< Compatibility >
+    {
+        let mut gb = Gameboy::try_from_rom(rom)?;
+        let (title, checksum) = (gb.title(), gb.checksum());
+        let known = self.get(&title, checksum).cloned();
+        if let Some(revision) = known.as_ref().and_then(|e| e.revision)
+        {
+            gb.restart_as_revision(revision);
+        }
+
+        let mut drew = false;
+        let mut hung = false;
+        for _ in 0..frames
+        {
+            if let RunExit::Hung(_) = gb.run_frame()
+            {
+                hung = true;
+                break
+            }
+            drew |= !is_blank(gb.get_image_data());
+        }
+
+        let status = match known.as_ref().map(|e| e.status)
+        {
+            _ if hung || !drew => Compatibility::Broken,
+            Some(status) if status > Compatibility::Boots => status,
+            _ => Compatibility::Boots
+        };
+        let (revision, notes) = known.map_or((None, String::new()), |e| (e.revision, e.notes));
+        self.insert(CompatEntry { title, checksum, status, revision, notes });
+        Ok(status)
+    }
+
+    /// Get a compatibility report in Markdown: the number of games with each
+    /// rating, then a table of every game sorted by title
+    pub fn to_markdown(&self) -> String
+    {
+        let mut report = String::from("# Compatibility\n\n| Rating | Games |\n|:--|--:|\n");
+        for status in Compatibility::ALL.into_iter().rev()
+        {
+            let count = self.entries.iter().filter(|e| e.status == status).count();
+            report += &format!("| {} | {} |\n", status, count);
+        }
+
+        let mut entries: Vec< &CompatEntry > = self.entries.iter().collect();
+        entries.sort_by(|a, b| a.title.cmp(&b.title).then(a.checksum.cmp(&b.checksum)));
+        report += "\n| Game | Checksum | Rating | Revision | Notes |\n|:--|:--|:--|:--|:--|\n";
+        for e in entries
+        {
+            report += &format!("| {} | {:04X} | {} | {} | {} |\n",
+                escape(&e.title), e.checksum, e.status,
+                e.revision.map_or("", |r| r.name()), escape(&e.notes));
+        }
+        report
+    }
+
+    /// Load a database from the given file
+    pub fn load(path: &Path) -> IoResult< Self >
+    {
+        CompatDb::parse(&fs::read_to_string(path)?)
+    }
+
+    /// Save the database to the given file
+    pub fn save(&self, path: &Path) -> IoResult< () >
+    {
+        fs::write(path, self.to_text())
+    }
+
+    /// Parse a database from its text representation
+    pub fn parse(text: &str) -> IoResult< Self >
+    {
+        let mut db = CompatDb::default();
+        for (n, line) in text.lines().enumerate()
+        {
+            if line.trim().is_empty() || line.starts_with('#') { continue }
+
+            let invalid = || Error::new(ErrorKind::InvalidData,
+                format!("Invalid game on line {}: {}", n + 1, line));
+            let fields: Vec< &str > = line.split('\t').collect();
+            let (checksum, status, revision, title) = match fields[..]
+            {
+                [checksum, status, revision, title] | [checksum, status, revision, title, _] =>
+                    (checksum, status, revision, title),
+                _ => return Err(invalid())
+            };
+            let revision = match revision
+            {
+                "-" => None,
+                r => Some(Revision::from_name(r).ok_or_else(invalid)?)
+            };
+            db.insert(CompatEntry {
+                title: title.to_string(),
+                checksum: u16::from_str_radix(checksum, 16).map_err(|_| invalid())?,
+                status: Compatibility::from_name(status).ok_or_else(invalid)?,
+                revision,
+                notes: fields.get(4).unwrap_or(&"").to_string()
+            });
+        }
+        Ok(db)
+    }
+
+    /// Get the text representation of the database
+    pub fn to_text(&self) -> String
+    {
+        self.entries.iter().map(|e| {
+            format!("{:04x}\t{}\t{}\t{}\t{}\n", e.checksum, e.status,
+                e.revision.map_or("-", |r| r.name()), clean(&e.title), clean(&e.notes))
+        }).collect()
+    }
+}
+
+/// Is every pixel of the image the same color?
+fn is_blank(image: &[u8]) -> bool
+{
+    image.chunks_exact(4).all(|p| p == &image[..4])
+}
+
+/// Replace the characters that would break a line of a database file
+fn clean(text: &str) -> String
+{
+    text.replace(['\t', '\n', '\r'], " ")
+}
+
+/// Escape text for a Markdown table cell
+fn escape(text: &str) -> String
+{
+    text.replace('|', "\\|")
+}
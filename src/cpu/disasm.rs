@@ -0,0 +1,118 @@
+/*
+    Opcodes are decoded from their octal digits, xx yyy zzz, where x picks
+    one of four blocks, y is usually a destination register, condition or
+    operation and z a source register. For y, p is the top two bits and q
+    the lowest.
+*/
+
+/// 8-bit registers by their index in an opcode
+const R8: [&str; 8] = ["B", "C", "D", "E", "H", "L", "(HL)", "A"];
+
+/// 16-bit register pairs for loads and arithmetic
+const R16: [&str; 4] = ["BC", "DE", "HL", "SP"];
+
+/// 16-bit register pairs for PUSH and POP
+const R16_STACK: [&str; 4] = ["BC", "DE", "HL", "AF"];
+
+/// 16-bit register pairs addressing memory for loads to and from A
+const R16_MEM: [&str; 4] = ["(BC)", "(DE)", "(HL+)", "(HL-)"];
+
+/// Jump conditions
+const CONDITIONS: [&str; 4] = ["NZ", "Z", "NC", "C"];
+
+/// Arithmetic and logic operations on A
+const ALU: [&str; 8] = ["ADD A,", "ADC A,", "SUB", "SBC A,", "AND", "XOR", "OR", "CP"];
+
+/// Rotates, shifts and SWAP from the CB prefixed opcodes
+const ROTATES: [&str; 8] = ["RLC", "RRC", "RL", "RR", "SLA", "SRA", "SWAP", "SRL"];
+
+/// Operations on A and the flags with no operands
+const ACCUMULATOR: [&str; 8] = ["RLCA", "RRCA", "RLA", "RRA", "DAA", "CPL", "SCF", "CCF"];
+
+/// Disassemble the instruction at the given address, reading memory with
+/// `read`. Returns the instruction in the usual assembly syntax with hex
+/// operands, e.g. "LD A, $42", and its length in bytes. Relative jumps show
+/// the address they jump to. Opcodes that don't exist are shown as "DB".
+pub fn disassemble< F: Fn(u16) -> u8 >(read: F, addr: u16) -> (String, u16)
+{
+    let op = read(addr);
+    let n = read(addr.wrapping_add(1));
+    let nn = u16::from_le_bytes([n, read(addr.wrapping_add(2))]);
+    let jr = addr.wrapping_add(2).wrapping_add(n as i8 as u16);
+    let (x, y, z) = ((op >> 6) as usize, ((op >> 3) & 7) as usize, (op & 7) as usize);
+    let (p, q) = (y >> 1, y & 1);
+
+    let (text, len) = match (x, z)
+    {
+        (0, 0) => match y
+        {
+            0 => ("NOP".to_string(), 1),
+            1 => (format!("LD (${:04X}), SP", nn), 3),
+            2 => ("STOP".to_string(), 2),
+            3 => (format!("JR ${:04X}", jr), 2),
+            _ => (format!("JR {}, ${:04X}", CONDITIONS[y - 4], jr), 2)
+        },
+        (0, 1) if q == 0 => (format!("LD {}, ${:04X}", R16[p], nn), 3),
+        (0, 1) => (format!("ADD HL, {}", R16[p]), 1),
+        (0, 2) if q == 0 => (format!("LD {}, A", R16_MEM[p]), 1),
+        (0, 2) => (format!("LD A, {}", R16_MEM[p]), 1),
+        (0, 3) if q == 0 => (format!("INC {}", R16[p]), 1),
+        (0, 3) => (format!("DEC {}", R16[p]), 1),
+        (0, 4) => (format!("INC {}", R8[y]), 1),
+        (0, 5) => (format!("DEC {}", R8[y]), 1),
+        (0, 6) => (format!("LD {}, ${:02X}", R8[y], n), 2),
+        (0, _) => (ACCUMULATOR[y].to_string(), 1),
+
+        (1, 6) if y == 6 => ("HALT".to_string(), 1),
+        (1, _) => (format!("LD {}, {}", R8[y], R8[z]), 1),
+
+        (2, _) => (format!("{} {}", ALU[y], R8[z]), 1),
+
+        (3, 0) => match y
+        {
+            0..=3 => (format!("RET {}", CONDITIONS[y]), 1),
+            4 => (format!("LDH (${:02X}), A", n), 2),
+            5 => (format!("ADD SP, {}", n as i8), 2),
+            6 => (format!("LDH A, (${:02X})", n), 2),
+            _ => (format!("LD HL, SP{:+}", n as i8), 2)
+        },
+        (3, 1) if q == 0 => (format!("POP {}", R16_STACK[p]), 1),
+        (3, 1) => (["RET", "RETI", "JP HL", "LD SP, HL"][p].to_string(), 1),
+        (3, 2) => match y
+        {
+            0..=3 => (format!("JP {}, ${:04X}", CONDITIONS[y], nn), 3),
+            4 => ("LD ($FF00+C), A".to_string(), 1),
+            5 => (format!("LD (${:04X}), A", nn), 3),
+            6 => ("LD A, ($FF00+C)".to_string(), 1),
+            _ => (format!("LD A, (${:04X})", nn), 3)
+        },
+        (3, 3) => match y
+        {
+            0 => (format!("JP ${:04X}", nn), 3),
+            1 => (disassemble_cb(n), 2),
+            6 => ("DI".to_string(), 1),
+            7 => ("EI".to_string(), 1),
+            _ => (format!("DB ${:02X}", op), 1)
+        },
+        (3, 4) if y < 4 => (format!("CALL {}, ${:04X}", CONDITIONS[y], nn), 3),
+        (3, 5) if q == 0 => (format!("PUSH {}", R16_STACK[p]), 1),
+        (3, 5) if p == 0 => (format!("CALL ${:04X}", nn), 3),
+        (3, 6) => (format!("{} ${:02X}", ALU[y], n), 2),
+        (3, 7) => (format!("RST ${:02X}", y * 8), 1),
+        _ => (format!("DB ${:02X}", op), 1)
+    };
+    (text, len)
+}
+
+/// Disassemble the opcode following a CB prefix
+fn disassemble_cb(op: u8) -> String
+{
+    let (x, y, z) = ((op >> 6) as usize, ((op >> 3) & 7) as usize, (op & 7) as usize);
+    match x
+    {
+        0 => format!("{} {}", ROTATES[y], R8[z]),
+        1 => format!("BIT {}, {}", y, R8[z]),
+        2 => format!("RES {}, {}", y, R8[z]),
+        _ => format!("SET {}, {}", y, R8[z])
+    }
+}
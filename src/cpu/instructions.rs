@@ -115,7 +115,7 @@ pub fn exec(op: u8, regs: &mut Registers, mem: &mut Memory) -> u32
                 N | 
                 if a < n + c { C } else { 0x0 } | 
                 if (a & 0xF) < (n & 0xF) + c { H } else { 0x0 };
-            regs.a = (a.overflowing_sub(n).0 - c) as u8;
+            regs.a = a.wrapping_sub(n).wrapping_sub(c) as u8;
             regs.f |= if regs.a == 0 { Z } else { 0x0 };
             1
         });
@@ -282,10 +282,14 @@ pub fn exec(op: u8, regs: &mut Registers, mem: &mut Memory) -> u32
         });
     }
 
-    // Halt CPU & LCD display until a button is pressed
+    // Halt CPU & LCD display until a button is pressed. Entering STOP mode
+    // resets the DIV register. Only buttons pressed after this point wake the
+    // CPU up.
     macro_rules! stop {
         () => ({
             regs.stop = 1;
+            mem.reset_div();
+            mem.keypad.take_wakeup();
             1   
         });
     }
@@ -301,7 +305,7 @@ pub fn exec(op: u8, regs: &mut Registers, mem: &mut Memory) -> u32
     // Enables interrupts after next instruction
     macro_rules! ei {
         () => ({
-            regs.ei(mem);
+            regs.ei();
             1   
         });
     }
@@ -358,7 +362,7 @@ pub fn exec(op: u8, regs: &mut Registers, mem: &mut Memory) -> u32
         // Jump to address of the two byte immediate value (LS byte first)
         () => ({
             regs.pc = mem.read_word(regs.adv());
-            3
+            4
         });
         
         // Jump to address of the two byte immediate value (LS byte first)
@@ -412,7 +416,7 @@ pub fn exec(op: u8, regs: &mut Registers, mem: &mut Memory) -> u32
     macro_rules! rst {
         ($n:expr) => ({
             regs.rst($n, mem);
-            8
+            4
         });
     }
 
@@ -433,7 +437,8 @@ pub fn exec(op: u8, regs: &mut Registers, mem: &mut Memory) -> u32
     macro_rules! reti {
         () => ({
             regs.ret(mem);
-            regs.ei(mem);
+            regs.ime = 1;
+            regs.delay = 0;
             4
         });
     }
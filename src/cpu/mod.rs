@@ -1,8 +1,14 @@
-mod registers;
+pub mod registers;
 mod instructions;
+#[cfg(feature = "debugger")]
+pub mod disasm;
 
-use crate::Target;
+use crate::Revision;
 use crate::mem::{ Memory, Speed };
+#[cfg(feature = "savestates")]
+use crate::state::{ Reader, Writer };
+#[cfg(feature = "savestates")]
+use std::io::Result as IoResult;
 use registers::Registers;
 
 /// The different types of GB interrupts
@@ -24,9 +30,96 @@ pub struct CPU
 impl CPU
 {
     /// Create and return a new instance of the Gameboy CPU
-    pub fn new(_target: Target) -> Self
+    pub fn new(revision: Revision) -> Self
     {
-        CPU { regs: Registers::new() }
+        let mut regs = Registers::new();
+
+        // Each boot ROM leaves different values behind. Games check for
+        // A = 0x11 to detect a CGB and A = 0xFF for a Pocket or SGB2, and B
+        // bit 0 for a GameBoy Advance.
+        match revision
+        {
+            Revision::Dmg0 => {
+                regs.f = 0x00;
+                regs.b = 0xFF;
+                regs.e = 0xC1;
+                regs.h = 0x84;
+                regs.l = 0x03;
+            },
+            Revision::Dmg => {},
+            Revision::Mgb => regs.a = 0xFF,
+            Revision::Sgb | Revision::Sgb2 => {
+                if revision == Revision::Sgb2 { regs.a = 0xFF; }
+                regs.f = 0x00;
+                regs.c = 0x14;
+                regs.e = 0x00;
+                regs.h = 0xC0;
+                regs.l = 0x60;
+            },
+            Revision::Cgb | Revision::Agb => {
+                regs.a = 0x11;
+                regs.f = 0x80;
+                regs.c = 0x00;
+                regs.d = 0xFF;
+                regs.e = 0x56;
+                regs.h = 0x00;
+                regs.l = 0x0D;
+
+                // The AGB boot ROM increments B as its last step
+                if revision == Revision::Agb
+                {
+                    regs.b = 0x01;
+                    regs.f = 0x00;
+                }
+            }
+        }
+
+        CPU { regs }
+    }
+
+    /// Create a CPU in the state it powers up in, with every register
+    /// cleared so it starts running from the beginning of the boot ROM
+    pub fn power_up() -> Self
+    {
+        let regs = Registers {
+            a: 0, b: 0, c: 0, d: 0, e: 0, f: 0, h: 0, l: 0,
+            sp: 0,
+            pc: 0,
+            ..Registers::new()
+        };
+        CPU { regs }
+    }
+
+    /// Write the CPU registers to a savestate chunk
+    #[cfg(feature = "savestates")]
+    pub fn save_state(&self, w: &mut Writer)
+    {
+        let r = &self.regs;
+        w.bytes(&[r.a, r.b, r.c, r.d, r.e, r.f, r.h, r.l]);
+        w.u16(r.sp);
+        w.u16(r.pc);
+        w.u32(r.ime);
+        w.u32(r.halt);
+        w.u32(r.stop);
+        w.u32(r.delay);
+    }
+
+    /// Restore the CPU registers from a savestate chunk
+    #[cfg(feature = "savestates")]
+    pub fn load_state(&mut self, r: &mut Reader) -> IoResult< () >
+    {
+        let regs = &mut self.regs;
+        let mut b = [0; 8];
+        r.bytes(&mut b)?;
+        regs.a = b[0]; regs.b = b[1]; regs.c = b[2]; regs.d = b[3];
+        regs.e = b[4]; regs.f = b[5]; regs.h = b[6]; regs.l = b[7];
+        regs.sp = r.u16()?;
+        regs.pc = r.u16()?;
+        regs.ime = r.u32()?;
+        regs.halt = r.u32()?;
+        regs.stop = r.u32()?;
+        regs.delay = r.u32()?;
+        Ok(())
     }
 
     /// Execute a CPU cycle
@@ -35,8 +128,23 @@ impl CPU
         // Step the interrupts forward
         self.regs.interrupt_step();
 
-        // Execute next instruction & get the number of ticks it took
-        let mut ticks = if self.regs.halt == 0 && self.regs.stop == 0 
+        // Interrupts are checked between instructions, once the rest of the
+        // system has caught up with the previous instruction. Checking after
+        // an instruction instead would miss anything it caused until another
+        // instruction had run.
+        let mut ticks = if self.regs.stop != 0 && mem.speed_switch
+        {
+            // A speed switch happens before any pending interrupt is
+            // serviced, even one that is already pending
+            mem.switch_speed();
+            self.regs.stop = 0;
+            1
+        }
+        else if let Some(ticks) = self.handle_interrupts(mem)
+        {
+            ticks
+        }
+        else if self.regs.halt == 0 && self.regs.stop == 0 
         {
             let pc = self.regs.adv();
             let opcode = mem.read_byte(pc);
@@ -44,44 +152,30 @@ impl CPU
         } 
         else 
         {
-            if self.regs.stop != 0 && mem.speed_switch
+            if self.regs.stop != 0 && mem.keypad.take_wakeup()
             {
-                mem.switch_speed();
+                // A selected joypad line going low ends STOP even when the
+                // joypad interrupt is disabled
                 self.regs.stop = 0;
+                1
             }
-
-            1
-        };
-
-        // Handle interrupts
-        if self.regs.ime != 0 || self.regs.halt != 0
-        {
-            let ints = mem.intf & mem.inte;
-            if ints != 0
+            else if self.regs.halt != 0
             {
-                let i = ints.trailing_zeros();
-                if self.regs.ime != 0
+                // Nothing can wake the CPU up before the next Timer, Serial
+                // or GPU event, so skip straight to it instead of idling one
+                // cycle at a time
+                let ticks_per_cycle = match mem.speed
                 {
-                    mem.intf &= !(1 << (i as u32));
-                }
-
-                self.regs.ime = 0;
-                self.regs.halt = 0;
-                self.regs.stop = 0;
-                
-                match i
-                {
-                    0 => { self.regs.rst(0x40, mem); },
-                    1 => { self.regs.rst(0x48, mem); },
-                    2 => { self.regs.rst(0x50, mem); },
-                    3 => { self.regs.rst(0x58, mem); },
-                    4 => { self.regs.rst(0x60, mem); },
-                    _ => {},
-                }
-
-                ticks += 1;
+                    Speed::Normal => 4,
+                    Speed::Double => 2
+                };
+                (mem.ticks_until_event() / ticks_per_cycle).max(1)
             }
-        }
+            else
+            {
+                1
+            }
+        };
 
         // Multiply ticks based on current speed
         match mem.speed
@@ -92,4 +186,44 @@ impl CPU
         
         ticks
     }
+
+    /// Wake the CPU up if an interrupt is pending and dispatch it if
+    /// interrupts are enabled. Returns the number of machine cycles taken if
+    /// an interrupt was dispatched.
+    fn handle_interrupts(&mut self, mem: &mut Memory) -> Option< u32 >
+    {
+        // Only the low 5 bits of IF and IE are interrupts
+        let ints = mem.intf & mem.inte & 0x1F;
+        if ints == 0
+        {
+            return None
+        }
+
+        // The joypad is the only interrupt that ends STOP, the rest stay
+        // pending until it ends
+        if self.regs.stop != 0
+        {
+            if ints & Interrupts::Joypad as u8 == 0
+            {
+                return None
+            }
+            self.regs.stop = 0;
+        }
+
+        // A pending interrupt ends HALT even while interrupts are disabled
+        self.regs.halt = 0;
+        if self.regs.ime == 0
+        {
+            return None
+        }
+
+        let i = ints.trailing_zeros();
+        mem.intf &= !(1 << i);
+        self.regs.ime = 0;
+        self.regs.delay = 0;
+        self.regs.rst(0x40 + (i as u16) * 8, mem);
+
+        // Dispatching an interrupt takes 5 machine cycles
+        Some(5)
+    }
 }
\ No newline at end of file
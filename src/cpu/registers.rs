@@ -2,6 +2,7 @@ use crate::mem::Memory;
 
 /// Represents all of the GB CPU registers
 #[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Registers
 {
     /// 8-bit 'A' register
@@ -129,13 +130,10 @@ impl Registers
     }
 
     /// Schedule enabling of interrupts
-    pub fn ei(&mut self, m: &mut Memory)
+    pub fn ei(&mut self)
     {
-        if self.delay == 2 || m.read_byte(self.pc) == 0x76
-        {
-            self.delay = 1;
-        }
-        else
+        // IME is set once the instruction after EI has run
+        if self.ime == 0 && self.delay == 0
         {
             self.delay = 2;
         }
@@ -158,4 +156,4 @@ impl Registers
             _ => {}
         }
     }
-}
\ No newline at end of file
+}
@@ -1,17 +1,48 @@
 use crate::Target;
 use crate::cpu::Interrupts;
-use crate::mem::Memory;
+use crate::mem::{ Memory, Speed };
+use crate::sgb::{ Border, Transfer, TRANSFER_SIZE };
+use crate::mem::map::{ in_range, OAM_START, OAM_END, VRAM_START, VRAM_END };
+use crate::render::{ LineState, PixelLayer, PixelSource, RenderWorker };
+use crate::sink::{ Frame, FrameSink };
+#[cfg(feature = "savestates")]
+use crate::invalid;
+#[cfg(feature = "savestates")]
+use crate::state::{ Reader, Writer };
+#[cfg(feature = "savestates")]
+use std::io::Result as IoResult;
+use std::sync::Arc;
 
 const VRAM_SIZE: usize = 8 << 10;
 const OAM_SIZE: usize = 0xA0;
-const NUM_TILES: usize = 384;
+
+/// Ticks an OAM DMA takes at normal speed, one M-cycle per byte
+const OAM_DMA_TICKS: u32 = OAM_SIZE as u32 * 4;
+pub const NUM_TILES: usize = 384;
 const CGB_BP_SIZE: usize = 64;
 
 pub const WIDTH: usize = 160;
 pub const HEIGHT: usize = 144;
 
+/// Size of the tile data debug image of one VRAM bank: 16 x 24 tiles
+#[cfg(feature = "debugger")]
+pub const TILE_VIEW_WIDTH: usize = 16 * 8;
+#[cfg(feature = "debugger")]
+pub const TILE_VIEW_HEIGHT: usize = 24 * 8;
+
+/// Size of the BG map debug image: 32 x 32 tiles
+#[cfg(feature = "debugger")]
+pub const MAP_VIEW_SIZE: usize = 32 * 8;
+
+/// Size of the OAM debug image: the 40 sprites in 4 rows of 10, each given
+/// room for an 8 x 16 sprite
+#[cfg(feature = "debugger")]
+pub const OAM_VIEW_WIDTH: usize = 10 * 8;
+#[cfg(feature = "debugger")]
+pub const OAM_VIEW_HEIGHT: usize = 4 * 16;
+
 /// A color is simply 4 bytes that represent RGBA values
-type Color = [u8; 4];
+pub type Color = [u8; 4];
 
 /// The default GameBoy color palette
 const PALETTE: [Color; 4] = [
@@ -21,8 +52,36 @@ const PALETTE: [Color; 4] = [
     [0, 0, 0, 255]          // BLACK
 ];
 
+/// Every byte with its bits spread out into the bytes of a u64, most
+/// significant bit first, so a row of tile data decodes to color indices with
+/// `lo | hi << 1`
+#[cfg(feature = "tile-lut")]
+const TILE_ROW_LUT: [u64; 256] = {
+    let mut lut = [0; 256];
+    let mut b = 0;
+    while b < 256
+    {
+        let mut k = 0;
+        while k < 8
+        {
+            lut[b] |= (((b >> (7 - k)) & 1) as u64) << (k * 8);
+            k += 1;
+        }
+        b += 1;
+    }
+    lut
+};
+
+/// A callback invoked after every line is rendered with the line number and
+/// the RGBA image data of that line
+pub type ScanlineCallback = Box< dyn FnMut(u8, &[u8]) + Send >;
+
+/// Receives every completed frame
+pub type BoxedFrameSink = Box< dyn FrameSink + Send >;
+
+/// The modes the GPU cycles through while drawing a frame
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-enum Mode
+pub enum Mode
 {
     HBlank = 0x00,
     VBlank = 0x01,
@@ -30,20 +89,73 @@ enum Mode
     RdVRAM = 0x03
 }
 
-struct Palette
+#[derive(Clone)]
+pub struct Palette
 {
-    bg: [Color; 4],
-    obp0: [Color; 4],
-    obp1: [Color; 4]
+    pub bg: [Color; 4],
+    pub obp0: [Color; 4],
+    pub obp1: [Color; 4]
 }
 
-struct Tiles
+#[derive(Clone)]
+pub struct Tiles
 {
-    data: [[[u8; 8]; 8]; NUM_TILES * 2],
+    pub data: [[[u8; 8]; 8]; NUM_TILES * 2],
     need_update: bool,
     to_update: [bool; NUM_TILES * 2]
 }
 
+/// The memory lines are drawn from. Shared with the render worker, which
+/// holds on to a snapshot of it for every line it has yet to draw.
+#[derive(Clone)]
+pub struct Video
+{
+    /// VRAM banks - CGB supports 2 banks of VRAM
+    pub vram: [[u8; VRAM_SIZE]; 2],
+
+    /// OAM memory
+    pub oam: [u8; OAM_SIZE],
+
+    /// Compiled tiles
+    pub tiles: Tiles
+}
+
+/// The CGB attributes of a BG or window tile, stored in VRAM bank 1 at the
+/// same address as the tile's index in bank 0
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct BgAttributes
+{
+    /// Which of the 8 BG palettes the tile uses
+    pub palette: u8,
+
+    /// The VRAM bank the tile data is in
+    pub bank: u8,
+
+    /// Is the tile flipped horizontally?
+    pub x_flip: bool,
+
+    /// Is the tile flipped vertically?
+    pub y_flip: bool,
+
+    /// Is the tile drawn over sprites?
+    pub priority: bool
+}
+
+impl BgAttributes
+{
+    /// Decode a tile attribute byte
+    pub fn from_byte(attrs: u8) -> Self
+    {
+        BgAttributes {
+            palette: attrs & 0x7,
+            bank: (attrs >> 3) & 1,
+            x_flip: attrs & 0x20 != 0,
+            y_flip: attrs & 0x40 != 0,
+            priority: attrs & 0x80 != 0
+        }
+    }
+}
+
 struct CGB
 {
     bgp: [u8; CGB_BP_SIZE],
@@ -57,7 +169,11 @@ struct CGB
 struct SGB
 {
     atf: [u8; 20 * 18],
-    pal: [[Color; 4]; 4]
+    pal: [[Color; 4]; 4],
+    border: Border,
+
+    /// VRAM transfer to read from the screen at the start of the next VBlank
+    transfer: Option< Transfer >
 }
 
 pub struct GPU
@@ -65,39 +181,45 @@ pub struct GPU
     /// Image data to be drawn to the screen
     pub image_data: Box< [u8; WIDTH * HEIGHT * 4] >,
 
+    /// Where every pixel of image_data came from, not kept in savestates
+    pub pixel_sources: Box< [PixelSource; WIDTH * HEIGHT] >,
+
     /// Should CGB functionality be used?
     pub is_cgb: bool,
 
     /// Should SGB functionality be used?
     pub is_sgb: bool,
 
-    cgb: Box< CGB >,
-    sgb: Box< SGB >,
+    /// OPRI bit 0: overlapping sprites are prioritized by X coordinate like
+    /// on the DMG, rather than by OAM index. Set for DMG games by the CGB
+    /// boot ROM, which locks it before handing over.
+    pub obj_x_priority: bool,
+
+    cgb: CGB,
+    sgb: SGB,
 
     /// Target GB system
-    _target: Target,
+    target: Target,
 
     /// Internal GPU clock
     internal_clock: u32,
 
-    /// VRAM banks - CGB supports 2 banks of VRAM
-    vram: Box< [[u8; VRAM_SIZE]; 2] >,
+    /// VRAM, OAM and the tiles compiled from VRAM
+    video: Arc< Video >,
 
     /// Selected VRAM bank
     vram_bank: u8,
 
-    /// OAM memory
-    oam: [u8; OAM_SIZE],
-
     /// Current mode
     mode: Mode,
 
     /// Compiled Palettes. Updated when BGP/OBP0/OBP1 are written to. Meant for
     /// non-CGB use only.
-    pal: Box< Palette >,
+    pal: Palette,
 
-    /// Compiled tiles
-    tiles: Box< Tiles >,
+    /// Ticks left of the OAM DMA in progress. The CPU can't access OAM
+    /// until it finishes.
+    oam_dma: u32,
 
     /// CGB VRAM DMA transfer
     hdma_src: u16,
@@ -135,6 +257,18 @@ pub struct GPU
     /// LYC = LY coincidence interrupt (1 = Enabled)
     lycly: bool,
 
+    /// LYC = LY coincidence flag. Updated whenever LY or LYC changes.
+    coincidence: bool,
+
+    /// The STAT interrupt line: set while any enabled STAT interrupt source
+    /// is active. The interrupt is only requested when it goes from low to
+    /// high, so one source can block another.
+    stat_line: bool,
+
+    /// A STAT interrupt requested by a register write, waiting to be
+    /// collected by `take_stat_interrupt`
+    stat_irq: bool,
+
     /// Mode 2 OAM Interrupt (1 = Enable)
     mode2_int: bool,
 
@@ -171,47 +305,83 @@ pub struct GPU
     wy: u8,
 
     /// 0xFF4B - Window X Position (minus 7) Register (WX)
-    wx: u8
+    wx: u8,
+
+    /// Internal window line counter. Only advances on lines where the window
+    /// is actually drawn.
+    win_line: u8,
+
+    /// Has LY matched WY yet this frame? The window is only drawn once it has.
+    win_triggered: bool,
+
+    /// Number of frames completed, incremented every time VBlank is entered
+    frames: u64,
+
+    /// Optional callback invoked after every line is rendered
+    scanline_callback: Option< ScanlineCallback >,
+
+    /// Sinks passed every frame at the start of VBlank
+    frame_sinks: Vec< BoxedFrameSink >,
+
+    /// Draws lines on another thread, when enabled
+    worker: Option< RenderWorker >,
+
+    /// Are lines drawn and finished frames handed to the frame sinks? Off
+    /// while the boot ROM is fast forwarded.
+    output: bool,
+
+    /// Are the BG, window and sprites drawn? Hiding a layer only changes
+    /// what ends up on the screen, never what the game sees.
+    show_bg: bool,
+    show_window: bool,
+    show_obj: bool
 }
 
 impl GPU
 {
     /// Create and return a new instance of the GameBoy GPU
-    pub fn new(_target: Target) -> Self
+    pub fn new(target: Target) -> Self
     {
         GPU {
             image_data: Box::new([0xFF; HEIGHT * WIDTH * 4]),
+            pixel_sources: Box::new([PixelSource::default(); WIDTH * HEIGHT]),
             is_cgb: false,
+            obj_x_priority: true,
             is_sgb: false,
-            cgb: Box::new(CGB {
+            cgb: CGB {
                 bgp: [255; CGB_BP_SIZE],
                 obp: [0; CGB_BP_SIZE],
                 bgpi: 0,
                 obpi: 0,
                 cbgp: [[[255, 255, 255, 255]; 4]; 8],
                 cobp: [[[0, 0, 0, 255]; 4]; 8]
-            }),
-            sgb: Box::new(SGB {
+            },
+            sgb: SGB {
                 atf: [0; 20 * 18],
-                pal: [[[0, 0, 0, 255]; 4]; 4]
-            }),
-            _target: _target,
+                pal: [PALETTE; 4],
+                border: Border::new(),
+                transfer: None
+            },
+            target,
             internal_clock: 0,
-            vram: Box::new([[0x0; VRAM_SIZE]; 2]),
+            video: Arc::new(Video {
+                vram: [[0x0; VRAM_SIZE]; 2],
+                oam: [0x0; OAM_SIZE],
+                tiles: Tiles {
+                    data: [[[0x0; 8]; 8]; NUM_TILES * 2],
+                    to_update: [false; NUM_TILES * 2],
+                    need_update: false
+                }
+            }),
             vram_bank: 0,
-            oam: [0x0; OAM_SIZE],
             mode: Mode::RdOAM,
-            pal: Box::new(Palette {
+            pal: Palette {
                 bg: [[0x0; 4]; 4],
                 obp0: [[0x0; 4]; 4],
                 obp1: [[0x0; 4]; 4]
-            }),
-            tiles: Box::new(Tiles {
-                data: [[[0x0; 8]; 8]; NUM_TILES * 2],
-                to_update: [false; NUM_TILES * 2],
-                need_update: false
-            }),
+            },
 
+            oam_dma: 0,
             hdma_src: 0,
             hdma_dst: 0,
             hdma5: 0,
@@ -225,6 +395,9 @@ impl GPU
             obj_enabled: false,
             bg_enabled: false,
             lycly: false,
+            coincidence: false,
+            stat_line: false,
+            stat_irq: false,
             mode2_int: false,
             mode1_int: false,
             mode0_int: false,
@@ -236,20 +409,58 @@ impl GPU
             obp0: 0x0,
             obp1: 0x0,
             wy: 0x0,
-            wx: 0x0
+            wx: 0x0,
+            win_line: 0,
+            win_triggered: false,
+            frames: 0,
+            scanline_callback: None,
+            frame_sinks: Vec::new(),
+            worker: None,
+            output: true,
+            show_bg: true,
+            show_window: true,
+            show_obj: true
         }
     }
 
-    /// Triggers a DMA transfer into OAM
+    /// Triggers a DMA transfer into OAM from 0xXX00 - 0xXX9F. Any source
+    /// can be used, see Memory::dma_read for what is read from each region.
+    /// The data is copied straight away, but OAM stays out of the CPU's
+    /// reach for the 160 M-cycles the transfer takes.
     pub fn oam_dma_transfer(mem: &mut Memory, val: u8)
     {
-        let or_val = (val as u16) << 8;
-        if or_val > 0xF100 { return }
-
+        let src = (val as u16) << 8;
         for i in 0..OAM_SIZE as u16
         {
-            mem.gpu.oam[i as usize] = mem.read_byte(or_val | i);
+            let val = mem.dma_read(src | i);
+            Arc::make_mut(&mut mem.gpu.video).oam[i as usize] = val;
         }
+        mem.gpu.oam_dma = match mem.speed
+        {
+            Speed::Normal => OAM_DMA_TICKS,
+            Speed::Double => OAM_DMA_TICKS / 2
+        };
+    }
+
+    /// Is an OAM DMA in progress? The CPU reads 0xFF from OAM and its writes
+    /// are ignored while one is.
+    pub fn oam_dma_active(&self) -> bool
+    {
+        self.oam_dma > 0
+    }
+
+    /// Is the GPU reading VRAM to draw the current line? Nothing else can
+    /// read VRAM while it is.
+    pub fn vram_busy(&self) -> bool
+    {
+        self.lcd_enabled && self.mode == Mode::RdVRAM
+    }
+
+    /// Is the GPU reading OAM, to find the sprites on the current line or
+    /// to draw them? Nothing else can access OAM while it is.
+    pub fn oam_busy(&self) -> bool
+    {
+        self.lcd_enabled && matches!(self.mode, Mode::RdOAM | Mode::RdVRAM)
     }
 
     /// Triggers a DMA transfer into VRAM when in CGB mode
@@ -257,7 +468,7 @@ impl GPU
     {
         let src = mem.gpu.hdma_src & 0xFFF0;
         let dst = mem.gpu.hdma_dst & 0x1FF0;
-        if (src > 0x7FFF && src < 0xA000) || src > 0xDFF0 || dst < 0x8000 || dst > 0x9FF0
+        if in_range(src, VRAM_START, VRAM_END) || src > 0xDFF0 || dst < 0x8000 || dst > 0x9FF0
         {
             return
         }
@@ -266,16 +477,16 @@ impl GPU
     /// Clears the screen to blank white
     pub fn clear(&mut self)
     {
-        for i in self.image_data.iter_mut()
-        {
-            *i = 0xFF;
-        }
+        self.finish_lines();
+        self.image_data.fill(0xFF);
+        self.pixel_sources.fill(PixelSource::default());
     }
 
     /// Step the GPU a given number of ticks forward. The GPU screen is
     /// synchronized with the CPU clock.
     pub fn step(&mut self, ticks: u32, intf: &mut u8)
     {
+        self.oam_dma = self.oam_dma.saturating_sub(ticks);
         self.internal_clock += ticks;
 
         // If clock >= 456 an entire line has been completed
@@ -283,14 +494,19 @@ impl GPU
         {
             self.internal_clock -= 456;
             self.ly = (self.ly + 1) % 154;
+            self.update_coincidence();
 
             if self.ly >= 144 && self.mode != Mode::VBlank
             {
                 self.switch_mode(Mode::VBlank, intf);
+                if let Some(transfer) = self.sgb.transfer.take()
+                {
+                    let data = self.sgb_transfer_data();
+                    self.sgb.border.transfer(transfer, &data, self.sgb.pal[0][0]);
+                }
             }
 
-            // Trigger an LCD Status Interrupt if necessary
-            if self.ly == self.lyc && self.lycly
+            if self.update_stat()
             {
                 *intf |= Interrupts::LCDStat as u8;
             }
@@ -314,16 +530,261 @@ impl GPU
         }
     }
 
+    /// Get the current GPU mode
+    pub fn mode(&self) -> Mode
+    {
+        self.mode
+    }
+
+    /// Get the line currently being drawn (LY)
+    pub fn ly(&self) -> u8
+    {
+        self.ly
+    }
+
+    /// Get the BG scroll position (SCX, SCY)
+    pub fn scroll(&self) -> (u8, u8)
+    {
+        (self.scx, self.scy)
+    }
+
+    /// Decode the CGB attributes of every tile in the BG tile map selected
+    /// by LCDC, row by row. Returns None when not in CGB mode since there
+    /// are no attributes.
+    #[cfg(feature = "debugger")]
+    pub fn bg_attribute_map(&self) -> Option< Vec< BgAttributes > >
+    {
+        if !self.is_cgb { return None }
+
+        let base = self.bg_base();
+        let map = self.video.vram[1][base..base + 32 * 32].iter()
+            .map(|&attrs| BgAttributes::from_byte(attrs))
+            .collect();
+        Some(map)
+    }
+
+    /// Draw every tile in a VRAM bank as RGBA image data, TILE_VIEW_WIDTH x
+    /// TILE_VIEW_HEIGHT, in the default DMG shades. Bank 1 only has data in
+    /// CGB mode.
+    #[cfg(feature = "debugger")]
+    pub fn tile_data_image(&self, bank: usize) -> Vec< u8 >
+    {
+        let mut image = vec![0; TILE_VIEW_WIDTH * TILE_VIEW_HEIGHT * 4];
+        for tile_i in 0..NUM_TILES
+        {
+            let (tx, ty) = ((tile_i % 16) * 8, (tile_i / 16) * 8);
+            for y in 0..8
+            {
+                for x in 0..8
+                {
+                    let color = PALETTE[self.vram_pixel(bank, tile_i, x, y) as usize];
+                    let offset = ((ty + y) * TILE_VIEW_WIDTH + tx + x) * 4;
+                    image[offset..offset + 4].copy_from_slice(&color);
+                }
+            }
+        }
+        image
+    }
+
+    /// Draw the whole BG tile map selected by LCDC as RGBA image data,
+    /// MAP_VIEW_SIZE x MAP_VIEW_SIZE, with the current BG palettes and CGB
+    /// attributes. The SGB palettes are not applied.
+    #[cfg(feature = "debugger")]
+    pub fn bg_map_image(&self) -> Vec< u8 >
+    {
+        let mut image = vec![0; MAP_VIEW_SIZE * MAP_VIEW_SIZE * 4];
+        let tile_base = if !self.tile_data { 256 } else { 0 };
+        let map_base = self.bg_base();
+        for map_i in 0..32 * 32
+        {
+            let tile_i = self.add_tile_i(tile_base, self.video.vram[0][map_base + map_i]);
+            let (attrs, palette) = if self.is_cgb
+            {
+                let attrs = BgAttributes::from_byte(self.video.vram[1][map_base + map_i]);
+                (attrs, self.cgb.cbgp[attrs.palette as usize])
+            }
+            else
+            {
+                (BgAttributes::default(), self.pal.bg)
+            };
+
+            let (tx, ty) = ((map_i % 32) * 8, (map_i / 32) * 8);
+            for y in 0..8
+            {
+                for x in 0..8
+                {
+                    let px = if attrs.x_flip { 7 - x } else { x };
+                    let py = if attrs.y_flip { 7 - y } else { y };
+                    let color = palette[self.vram_pixel(attrs.bank as usize, tile_i, px, py) as usize];
+                    let offset = ((ty + y) * MAP_VIEW_SIZE + tx + x) * 4;
+                    image[offset..offset + 4].copy_from_slice(&color);
+                }
+            }
+        }
+        image
+    }
+
+    /// Draw every sprite in OAM as RGBA image data, OAM_VIEW_WIDTH x
+    /// OAM_VIEW_HEIGHT, in OAM order with the current sprite palettes and
+    /// flips. Transparent pixels, and the bottom half of 8 x 8 sprites, are
+    /// left fully transparent.
+    #[cfg(feature = "debugger")]
+    pub fn oam_image(&self) -> Vec< u8 >
+    {
+        let mut image = vec![0; OAM_VIEW_WIDTH * OAM_VIEW_HEIGHT * 4];
+        let height = if self.obj_size { 16 } else { 8 };
+        for (sprite_i, entry) in self.video.oam.chunks(4).enumerate()
+        {
+            let flags = entry[3];
+            let tile_i = if self.obj_size { entry[2] & 0xFE } else { entry[2] } as usize;
+            let (bank, palette) = if self.is_cgb
+            {
+                (((flags >> 3) & 1) as usize, self.cgb.cobp[(flags & 7) as usize])
+            }
+            else
+            {
+                (0, if flags & 0x10 != 0 { self.pal.obp1 } else { self.pal.obp0 })
+            };
+
+            let (sx, sy) = ((sprite_i % 10) * 8, (sprite_i / 10) * 16);
+            for y in 0..height
+            {
+                for x in 0..8
+                {
+                    let px = if flags & 0x20 != 0 { 7 - x } else { x };
+                    let py = if flags & 0x40 != 0 { height - 1 - y } else { y };
+                    let color_i = self.vram_pixel(bank, tile_i + py / 8, px, py % 8);
+                    if color_i == 0 { continue }
+
+                    let offset = ((sy + y) * OAM_VIEW_WIDTH + sx + x) * 4;
+                    image[offset..offset + 4].copy_from_slice(&palette[color_i as usize]);
+                }
+            }
+        }
+        image
+    }
+
+    /// Decode the color index of pixel (x, y) of a tile straight from VRAM
+    #[cfg(feature = "debugger")]
+    fn vram_pixel(&self, bank: usize, tile_i: usize, x: usize, y: usize) -> u8
+    {
+        let addr = tile_i * 16 + y * 2;
+        let (lo, hi) = (self.video.vram[bank][addr], self.video.vram[bank][addr + 1]);
+        let bit = 7 - x;
+        ((lo >> bit) & 1) | (((hi >> bit) & 1) << 1)
+    }
+
+    /// Force the GPU into the given mode. The internal clock is moved to the
+    /// start of the mode on the current line, or to the start of line 144 for
+    /// VBlank. No interrupts are requested and no line is rendered.
+    #[cfg(feature = "debugger")]
+    pub fn force_mode(&mut self, mode: Mode)
+    {
+        match mode
+        {
+            Mode::VBlank => {
+                if self.ly < 144 { self.ly = 144; }
+                self.internal_clock = 0;
+            },
+            _ => {
+                if self.ly >= 144 { self.ly = 0; }
+                self.internal_clock = match mode
+                {
+                    Mode::RdOAM => 0,
+                    Mode::RdVRAM => 81,
+                    _ => 253
+                };
+            }
+        }
+        self.mode = mode;
+        self.update_coincidence();
+        self.stat_line = self.stat_sources();
+    }
+
+    /// Force LY to the given line (0 - 153). The internal clock is moved to
+    /// the start of the line. No interrupts are requested.
+    #[cfg(feature = "debugger")]
+    pub fn force_ly(&mut self, ly: u8)
+    {
+        self.ly = ly % 154;
+        self.internal_clock = 0;
+        self.mode = if self.ly >= 144 { Mode::VBlank } else { Mode::RdOAM };
+        self.update_coincidence();
+        self.stat_line = self.stat_sources();
+    }
+
+    /// Compare LY and LYC, setting the coincidence flag in STAT
+    fn update_coincidence(&mut self)
+    {
+        self.coincidence = self.ly == self.lyc;
+    }
+
+    /// Set or clear the callback invoked after every line is rendered
+    pub fn set_scanline_callback(&mut self, callback: Option< ScanlineCallback >)
+    {
+        self.scanline_callback = callback;
+    }
+
+    /// Remove and return the scanline callback, if one is set
+    pub fn take_scanline_callback(&mut self) -> Option< ScanlineCallback >
+    {
+        self.scanline_callback.take()
+    }
+
+    /// Add a sink to pass every completed frame to
+    pub fn add_frame_sink(&mut self, sink: BoxedFrameSink)
+    {
+        self.frame_sinks.push(sink);
+    }
+
+    /// Replace the frame sinks
+    pub fn set_frame_sinks(&mut self, sinks: Vec< BoxedFrameSink >)
+    {
+        self.frame_sinks = sinks;
+    }
+
+    /// Remove and return every frame sink
+    pub fn take_frame_sinks(&mut self) -> Vec< BoxedFrameSink >
+    {
+        ::std::mem::take(&mut self.frame_sinks)
+    }
+
+    /// Get the number of frames the GPU has completed
+    pub fn frames(&self) -> u64
+    {
+        self.frames
+    }
+
+    /// Get the number of ticks until the GPU next switches mode or line. No
+    /// GPU interrupt can be requested before then.
+    pub fn ticks_until_event(&self) -> u32
+    {
+        let next: u32 = if self.ly >= 144 || self.internal_clock > 252
+        {
+            456
+        }
+        else if self.internal_clock > 80
+        {
+            253
+        }
+        else
+        {
+            81
+        };
+        next.saturating_sub(self.internal_clock).max(1)
+    }
+
     /// Read a byte from GPU memory
     pub fn read_byte(&self, addr: u16) -> u8
     {
         match addr
         {
             // VRAM
-            0x8000...0x9FFF => self.vram[self.vram_bank as usize][(addr & 0x1FFF) as usize],
+            VRAM_START..=VRAM_END => self.video.vram[self.vram_bank as usize][(addr & 0x1FFF) as usize],
 
             // OAM
-            0xFE00...0xFE9F => self.oam[(addr & 0xFF) as usize],
+            OAM_START..=OAM_END if self.oam_dma_active() => 0xFF,
+            OAM_START..=OAM_END => self.video.oam[(addr & 0xFF) as usize],
 
             // LCDC Register
             0xFF40 => { 
@@ -343,7 +804,7 @@ impl GPU
                 ((self.mode2_int as u8) << 5) |
                 ((self.mode1_int as u8) << 4) |
                 ((self.mode0_int as u8) << 3) |
-                ((if self.lycly as u8 == self.ly { 1 } else { 0 } as u8) << 2) |
+                ((self.coincidence as u8) << 2) |
                 ((self.mode as u8) << 0)
             },
 
@@ -390,6 +851,9 @@ impl GPU
             0xFF6A => self.cgb.obpi,
             0xFF6B => self.cgb.obp[(self.cgb.obpi & 0x3F) as usize],
 
+            // Object priority mode
+            0xFF6C => 0xFE | self.obj_x_priority as u8,
+
             _ => 0xFF
         }
     }
@@ -400,14 +864,15 @@ impl GPU
         match addr
         {
             // VRAM
-            0x8000...0x9FFF => 
+            VRAM_START..=VRAM_END => 
             {
-                self.vram[self.vram_bank as usize][(addr & 0x1FFF) as usize] = val;
+                Arc::make_mut(&mut self.video).vram[self.vram_bank as usize][(addr & 0x1FFF) as usize] = val;
                 if addr < 0x9800 { self.update_tile(addr); }
             },
 
             // OAM
-            0xFE00...0xFE9F => self.oam[(addr & 0xFF) as usize] = val,
+            OAM_START..=OAM_END if self.oam_dma_active() => {},
+            OAM_START..=OAM_END => Arc::make_mut(&mut self.video).oam[(addr & 0xFF) as usize] = val,
 
             // LCDC Register
             0xFF40 => 
@@ -427,17 +892,32 @@ impl GPU
                 {
                     self.internal_clock = 4;
                     self.ly = 0;
+                    self.update_coincidence();
                 }
+                self.stat_irq |= self.update_stat();
             },
 
             // LCD STAT Register
             0xFF41 => 
             {
+                // On DMG hardware every source is briefly enabled while STAT
+                // is written, which requests an interrupt during HBlank,
+                // VBlank or when LY = LYC. Some games rely on this.
+                if self.target != Target::GameBoyColor && self.lcd_enabled
+                {
+                    let glitch = self.mode == Mode::HBlank || 
+                        self.mode == Mode::VBlank || self.coincidence;
+                    self.stat_irq |= glitch && !self.stat_line;
+                    self.stat_line |= glitch;
+                }
+
                 self.lycly          = (val >> 6) & 1 != 0;
                 self.mode2_int      = (val >> 5) & 1 != 0;
                 self.mode1_int      = (val >> 4) & 1 != 0;
                 self.mode0_int      = (val >> 3) & 1 != 0;
                 // Other bits are read-only
+
+                self.stat_irq |= self.update_stat();
             },
 
             // SCY
@@ -449,7 +929,12 @@ impl GPU
             // 0xFF44 LY is Read Only
 
             // LYC
-            0xFF45 => self.lyc = val,
+            0xFF45 => 
+            {
+                self.lyc = val;
+                self.update_coincidence();
+                self.stat_irq |= self.update_stat();
+            },
 
             // BGP
             0xFF47 => 
@@ -500,7 +985,7 @@ impl GPU
 
             0xFF69 => 
             {
-                let cgb = &mut *self.cgb;
+                let cgb = &mut self.cgb;
                 cgb.bgp[(cgb.bgpi & 0x3F) as usize] = val;
                 update_cgb_palette(&mut cgb.cbgp, &cgb.bgp, cgb.bgpi);
                 if cgb.bgpi & 0x80 != 0 { cgb.bgpi = (cgb.bgpi + 1) & 0xBF; }
@@ -510,7 +995,7 @@ impl GPU
 
             0xFF6B => 
             {
-                let cgb = &mut *self.cgb;
+                let cgb = &mut self.cgb;
                 cgb.obp[(cgb.obpi & 0x3F) as usize] = val;
                 update_cgb_palette(&mut cgb.cobp, &cgb.obp, cgb.obpi);
                 if cgb.obpi & 0x80 != 0 { cgb.obpi = (cgb.obpi + 1) & 0xBF; }
@@ -520,368 +1005,470 @@ impl GPU
         }
     }
 
-    /// Register that a tile needs to be updated
-    fn update_tile(&mut self, addr: u16)
-    {
-        let tile_i = (addr & 0x1FFF) / 16;
-        let tile_i = tile_i + (self.vram_bank as u16) * (NUM_TILES as u16);
-        self.tiles.need_update = true;
-        self.tiles.to_update[tile_i as usize] = true;
-    }
-
-    /// Switch the current GPU mode
-    fn switch_mode(&mut self, mode: Mode, intf: &mut u8)
+    /// Write the GPU state to a savestate chunk
+    #[cfg(feature = "savestates")]
+    pub fn save_state(&self, w: &mut Writer)
     {
-        self.mode = mode;
-        match mode
+        w.bytes(&self.image_data[..]);
+        w.bytes(&self.video.vram[0]);
+        w.bytes(&self.video.vram[1]);
+        w.bytes(&self.video.oam);
+        w.bytes(&self.cgb.bgp);
+        w.bytes(&self.cgb.obp);
+        w.bytes(&[self.cgb.bgpi, self.cgb.obpi]);
+        w.bytes(&self.sgb.atf);
+        for color in self.sgb.pal.iter().flat_map(|p| p.iter())
         {
-            Mode::HBlank => {
-                self.render_line();
-                if self.mode0_int { *intf |= Interrupts::LCDStat as u8; }
-            },
-            Mode::VBlank => {
-                *intf |= Interrupts::VBlank as u8;
-                if self.mode1_int { *intf |= Interrupts::LCDStat as u8; }
-            },
-            Mode::RdOAM => {
-                if self.mode2_int { *intf |= Interrupts::LCDStat as u8; }
-            },
-            Mode::RdVRAM => {}
+            w.bytes(color);
         }
+        w.u32(self.internal_clock);
+        w.bytes(&[self.vram_bank, self.mode as u8]);
+        w.u16(self.hdma_src);
+        w.u16(self.hdma_dst);
+        w.u8(self.hdma5);
+        w.bytes(&[
+            self.read_byte(0xFF40), self.read_byte(0xFF41),
+            self.scy, self.scx, self.ly, self.lyc,
+            self.bgp, self.obp0, self.obp1, self.wy, self.wx
+        ]);
+        w.u8(self.win_line);
+        w.bool(self.win_triggered);
+        w.u64(self.frames);
+        w.u32(self.oam_dma);
     }
 
-    /// Render a line to the screen. Performed when the GPU is HBlanking.
-    fn render_line(&mut self)
+    /// Restore the GPU state from a savestate chunk. Palettes and tiles are
+    /// recompiled from the restored registers and VRAM.
+    #[cfg(feature = "savestates")]
+    pub fn load_state(&mut self, r: &mut Reader) -> IoResult< () >
     {
-        // We can't render if the LCD isn't on
-        if !self.lcd_enabled { return }
-
-        // Line to draw
-        let mut scanline = [0u8; WIDTH];
-
-        // Update compiled tiles if necessary 
-        if self.tiles.need_update
+        self.finish_lines();
+        r.bytes(&mut self.image_data[..])?;
+        self.pixel_sources.fill(PixelSource::default());
+        let video = Arc::make_mut(&mut self.video);
+        r.bytes(&mut video.vram[0])?;
+        r.bytes(&mut video.vram[1])?;
+        r.bytes(&mut video.oam)?;
+        r.bytes(&mut self.cgb.bgp)?;
+        r.bytes(&mut self.cgb.obp)?;
+        self.cgb.bgpi = r.u8()?;
+        self.cgb.obpi = r.u8()?;
+        r.bytes(&mut self.sgb.atf)?;
+        for color in self.sgb.pal.iter_mut().flat_map(|p| p.iter_mut())
         {
-            self.update_tileset();
-            self.tiles.need_update = false;
+            r.bytes(color)?;
+        }
+        self.internal_clock = r.u32()?;
+        self.vram_bank = r.u8()? & 1;
+        self.mode = match r.u8()? {
+            0x00 => Mode::HBlank,
+            0x01 => Mode::VBlank,
+            0x02 => Mode::RdOAM,
+            0x03 => Mode::RdVRAM,
+            _ => return Err(invalid("Invalid GPU mode in savestate"))
+        };
+        self.hdma_src = r.u16()?;
+        self.hdma_dst = r.u16()?;
+        self.hdma5 = r.u8()?;
+
+        let mut regs = [0; 11];
+        r.bytes(&mut regs)?;
+        // Restore LCDC without the side effects of turning the LCD on
+        let (ly, clock) = (regs[4], self.internal_clock);
+        self.write_byte(0xFF40, regs[0]);
+        self.write_byte(0xFF41, regs[1]);
+        self.scy = regs[2];
+        self.scx = regs[3];
+        self.ly = ly;
+        self.internal_clock = clock;
+        self.lyc = regs[5];
+        self.update_coincidence();
+        self.stat_line = self.stat_sources();
+        self.stat_irq = false;
+        self.write_byte(0xFF47, regs[6]);
+        self.write_byte(0xFF48, regs[7]);
+        self.write_byte(0xFF49, regs[8]);
+        self.wy = regs[9];
+        self.wx = regs[10];
+        self.win_line = r.u8()?;
+        self.win_triggered = r.bool()?;
+        self.frames = r.u64()?;
+        self.oam_dma = r.u32()?;
+
+        // Recompile CGB palettes, one write per color
+        let cgb = &mut self.cgb;
+        for addr in (0..CGB_BP_SIZE as u8).step_by(2)
+        {
+            update_cgb_palette(&mut cgb.cbgp, &cgb.bgp, addr);
+            update_cgb_palette(&mut cgb.cobp, &cgb.obp, addr);
         }
 
-        // Render BG
-        if self.bg_enabled  { self.render_background(&mut scanline); }
-
-        // Render Window
-        if self.win_enabled { self.render_window(&mut scanline); }
+        // Recompile every tile
+        let tiles = &mut Arc::make_mut(&mut self.video).tiles;
+        tiles.need_update = true;
+        for t in tiles.to_update.iter_mut()
+        {
+            *t = true;
+        }
+        Ok(())
+    }
 
-        // Render Sprites
-        if self.obj_enabled { self.render_obj(&mut scanline); }
+    /// Start an SGB VRAM transfer. The data is read from the screen at the
+    /// end of the current frame.
+    pub fn start_sgb_transfer(&mut self, transfer: Transfer)
+    {
+        self.sgb.transfer = Some(transfer);
     }
 
-    fn update_tileset(&mut self)
+    /// Get the data the game is showing for an SGB VRAM transfer. The game
+    /// fills the BG map with consecutive tiles so the data is read back from
+    /// the tiles of the first 13 rows of the map in order.
+    fn sgb_transfer_data(&self) -> Vec< u8 >
     {
-        let tiles = &mut *self.tiles;
-        let iter = tiles.to_update.iter_mut();
-        for (i, t) in iter.enumerate().filter(|&(_, &mut i)| i)
+        let map_base = self.bg_base();
+        let tile_base = if !self.tile_data { 256 } else { 0 };
+        let mut data = Vec::with_capacity(20 * 13 * 16);
+        for row in 0..13
         {
-            *t = false;
-            for j in 0..8
+            for col in 0..20
             {
-                let addr = ((i % NUM_TILES) * 16) + j * 2;
-                let (mut lsb, mut msb) = if i < NUM_TILES
-                {
-                    (self.vram[0][addr], self.vram[0][addr + 1])
-                }
-                else
-                {
-                    (self.vram[1][addr], self.vram[1][addr + 1])
-                };
-
-                for k in (0..8).rev()
-                {
-                    tiles.data[i][j][k] = ((msb & 1) << 1) | (lsb & 1);
-                    lsb >>= 1;
-                    msb >>= 1;
-                }
+                let tile_i = self.add_tile_i(tile_base, self.video.vram[0][map_base + row * 32 + col]);
+                let addr = tile_i * 16;
+                data.extend_from_slice(&self.video.vram[0][addr..addr + 16]);
             }
         }
+        data.truncate(TRANSFER_SIZE);
+        data
     }
 
-    fn render_background(&mut self, scanline: &mut [u8; WIDTH])
+    /// Get the SGB border image, if the game has sent a border
+    pub fn border_image(&self) -> Option< &[u8] >
     {
-        let map_base = self.bg_base();
-        let line = self.ly as usize + self.scy as usize;
-
-        let map_base = map_base + ((line % 256) >> 3) * 32;
-
-        // X and Y location inside tile to paint
-        let y = (self.ly + self.scy) % 8;
-        let mut x = self.scx % 8;
-
-        // Canvas offset
-        let mut canvas_offset = (self.ly as usize) * WIDTH * 4;
+        self.sgb.border.image()
+    }
 
-        let mut i = 0;
-        let tile_base = if !self.tile_data { 256 } else { 0 };
+    /// Write the SGB border state to a savestate chunk
+    #[cfg(feature = "savestates")]
+    pub fn save_border_state(&self, w: &mut Writer)
+    {
+        self.sgb.border.save_state(w);
+        w.u8(match self.sgb.transfer {
+            None => 0,
+            Some(Transfer::Chr(false)) => 1,
+            Some(Transfer::Chr(true)) => 2,
+            Some(Transfer::Pct) => 3
+        });
+    }
 
-        loop
+    /// Restore the SGB border state from a savestate chunk
+    #[cfg(feature = "savestates")]
+    pub fn load_border_state(&mut self, r: &mut Reader) -> IoResult< () >
+    {
+        self.sgb.border.load_state(r, self.sgb.pal[0][0])?;
+        self.sgb.transfer = match r.u8()?
         {
-            let map_offset = ((i as usize + self.scx as usize) % 256) >> 3;
-            let tile_i = self.vram[0][map_base + map_offset];
-
-            let tile_base = self.add_tile_i(tile_base, tile_i);
+            0 => None,
+            1 => Some(Transfer::Chr(false)),
+            2 => Some(Transfer::Chr(true)),
+            3 => Some(Transfer::Pct),
+            _ => return Err(invalid("Invalid SGB transfer in savestate"))
+        };
+        Ok(())
+    }
 
-            let row;
-            let bgpri;
-            let hflip;
-            let bgp;
-            if self.is_cgb
-            {
-                let attrs = self.vram[1][map_base + map_offset as usize] as usize;
-                let tile = self.tiles.data[tile_base + ((attrs >> 3) & 1) * NUM_TILES];
+    /// Set two of the SGB palettes from the data of a PAL01, PAL23, PAL03 or
+    /// PAL12 command. Color 0 is shared by all four palettes.
+    pub fn set_sgb_palettes(&mut self, a: usize, b: usize, data: &[u8])
+    {
+        let color_0 = rgb555(data[1], data[2]);
+        for pal in self.sgb.pal.iter_mut()
+        {
+            pal[0] = color_0;
+        }
+        for i in 0..3
+        {
+            self.sgb.pal[a][i + 1] = rgb555(data[3 + i * 2], data[4 + i * 2]);
+            self.sgb.pal[b][i + 1] = rgb555(data[9 + i * 2], data[10 + i * 2]);
+        }
+    }
 
-                bgpri = attrs & 0x80 != 0;
-                hflip = attrs & 0x20 != 0;
-                row = tile[if attrs & 0x40 != 0 { 7 - y } else { y } as usize];
-                bgp = self.cgb.cbgp[attrs & 0x7];
-            }
-            else
-            {
-                row = self.tiles.data[tile_base as usize][y as usize];
-                bgpri = false;
-                hflip = false;
-                bgp = self.pal.bg;
-            }
+    /// Register that a tile needs to be updated
+    fn update_tile(&mut self, addr: u16)
+    {
+        let tile_i = (addr & 0x1FFF) / 16;
+        let tile_i = tile_i + (self.vram_bank as u16) * (NUM_TILES as u16);
+        let tiles = &mut Arc::make_mut(&mut self.video).tiles;
+        tiles.need_update = true;
+        tiles.to_update[tile_i as usize] = true;
+    }
 
-            while x < 8 && i < WIDTH as u8
-            {
-                let color_i = row[if hflip { 7 - x } else { x } as usize];
-                let color;
-                if self.is_sgb && !self.is_cgb
+    /// Switch the current GPU mode
+    fn switch_mode(&mut self, mode: Mode, intf: &mut u8)
+    {
+        self.mode = mode;
+        match mode
+        {
+            Mode::HBlank => self.render_line(),
+            Mode::VBlank => {
+                self.finish_lines();
+                self.frames = self.frames.wrapping_add(1);
+                let frame = Frame {
+                    number: self.frames,
+                    image: &self.image_data[..],
+                    sources: &self.pixel_sources[..]
+                };
+                if self.output
                 {
-                    let sgb_addr = (i >> 3) as usize + (self.ly as usize >> 3) * 20;
-                    let mapped = self.sgb.atf[sgb_addr] as usize;
-                    match bgp[color_i as usize][0]
+                    for sink in self.frame_sinks.iter_mut()
                     {
-                        0 => color = self.sgb.pal[mapped][3],
-                        96 => color = self.sgb.pal[mapped][2],
-                        192 => color = self.sgb.pal[mapped][1],
-                        255 => color = self.sgb.pal[mapped][0],
-                        _ => color = [0, 0, 0, 0]
+                        sink.push_frame(&frame);
                     }
                 }
-                else
-                {
-                    color = bgp[color_i as usize];
-                }
-
-                scanline[i as usize] = if bgpri { 4 } else { color_i };
-
-                self.image_data[canvas_offset]      = color[0];
-                self.image_data[canvas_offset + 1]  = color[1];
-                self.image_data[canvas_offset + 2]  = color[2];
-                self.image_data[canvas_offset + 3]  = color[3];
-
-                x += 1;
-                i += 1;
-                canvas_offset += 4;
-            }
+                self.win_line = 0;
+                self.win_triggered = false;
+                *intf |= Interrupts::VBlank as u8;
+            },
+            Mode::RdOAM | Mode::RdVRAM => {}
+        }
 
-            x = 0;
-            if i >= WIDTH as u8 { break }
+        if self.update_stat()
+        {
+            *intf |= Interrupts::LCDStat as u8;
         }
     }
 
-    fn render_window(&mut self, scanline: &mut [u8; WIDTH])
+    /// Is any enabled STAT interrupt source active?
+    fn stat_sources(&self) -> bool
     {
-        if self.ly < self.wy { return }
-
-        if self.wx >= WIDTH as u8 + 7 { return }
+        if !self.lcd_enabled { return false }
 
-        let map_base = if self.win_tmap { 0x1C00 } else { 0x1800 };
-        let map_base = map_base + ((self.ly as usize - self.wy as usize) >> 3) * 32;
+        (self.lycly && self.coincidence) ||
+        (self.mode0_int && self.mode == Mode::HBlank) ||
+        (self.mode1_int && self.mode == Mode::VBlank) ||
+        (self.mode2_int && self.mode == Mode::RdOAM)
+    }
 
-        let y = (self.ly - self.wy) % 8;
-        let (mut x, mut i) = if self.wx < 7 {
-            (7 - self.wx, 0)
-        } else {
-            ((self.wx - 7) % 8, self.wx - 7)
-        };
+    /// Re-evaluate the STAT interrupt line. Must be called after anything
+    /// that changes a STAT interrupt source. Returns true if the line went
+    /// high and a STAT interrupt should be requested.
+    fn update_stat(&mut self) -> bool
+    {
+        let line = self.stat_sources();
+        let rising = line && !self.stat_line;
+        self.stat_line = line;
+        rising
+    }
 
-        let mut canvas_offset = (self.ly as usize * WIDTH + i as usize) * 4;
+    /// Take the STAT interrupt requested by the last register write, if any
+    pub fn take_stat_interrupt(&mut self) -> bool
+    {
+        ::std::mem::take(&mut self.stat_irq)
+    }
 
-        let tile_base = if !self.tile_data { 256 } else { 0 };
+    /// Render a line to the screen. Performed when the GPU is HBlanking.
+    fn render_line(&mut self)
+    {
+        // We can't render if the LCD isn't on
+        if !self.lcd_enabled { return }
 
-        let mut map_offset = 0;
-        loop
+        // Update compiled tiles if necessary 
+        if self.video.tiles.need_update
         {
-            let tile_i = self.vram[0][map_base + map_offset as usize];
-            map_offset += 1;
-            let tile_base = self.add_tile_i(tile_base, tile_i);
-
-            let row;
-            let bgpri;
-            let hflip;
-            let bgp;
-            if self.is_cgb
-            {
-                let attrs = self.vram[1][map_base + map_offset as usize - 1] as usize;
-                let tile = self.tiles.data[tile_base + ((attrs >> 3) & 1) * NUM_TILES];
-
-                bgpri = attrs & 0x80 != 0;
-                hflip = attrs & 0x20 != 0;
-                row = tile[if attrs & 0x40 != 0 { 7 - y } else { y } as usize];
-                bgp = self.cgb.cbgp[attrs & 0x7];
-            }
-            else
-            {
-                row = self.tiles.data[tile_base as usize][y as usize];
-                bgpri = false;
-                hflip = false;
-                bgp = self.pal.bg;
-            }
+            Arc::make_mut(&mut self.video).update_tileset();
+        }
 
-            while x < 8 && i < WIDTH as u8
-            {
-                let color_i = row[if hflip { 7 - x } else { x } as usize];
-                let color;
-                if self.is_sgb && !self.is_cgb
-                {
-                    let sgb_addr = (i >> 3) + (self.ly >> 3) * 20;
-                    let mapped = self.sgb.atf[sgb_addr as usize] as usize;
-                    match bgp[color_i as usize][0]
-                    {
-                        0 => color = self.sgb.pal[mapped][3],
-                        96 => color = self.sgb.pal[mapped][2],
-                        192 => color = self.sgb.pal[mapped][1],
-                        255 => color = self.sgb.pal[mapped][0],
-                        _ => color = [0, 0, 0, 0]
-                    }
-                }
-                else
-                {
-                    color = bgp[color_i as usize];
-                }
+        // The window is only drawn once LY has matched WY this frame. WX =
+        // 166 still draws the last column of the screen, anything past it
+        // hides the window for this line.
+        if self.ly == self.wy { self.win_triggered = true; }
+        let window = self.win_enabled && self.win_triggered && self.wx <= WIDTH as u8 + 6;
+
+        let line = LineState {
+            ly: self.ly,
+            scx: self.scx,
+            scy: self.scy,
+            wx: self.wx,
+            win_line: self.win_line,
+            window,
+            bg_enabled: self.bg_enabled,
+            obj_enabled: self.obj_enabled,
+            obj_size: self.obj_size,
+            tile_data: self.tile_data,
+            bg_tmap: self.bg_tmap,
+            win_tmap: self.win_tmap,
+            is_cgb: self.is_cgb,
+            is_sgb: self.is_sgb,
+            obj_x_priority: self.obj_x_priority,
+            pal: self.pal.clone(),
+            cbgp: self.cgb.cbgp,
+            cobp: self.cgb.cobp,
+            sgb_atf: self.sgb.atf,
+            sgb_pal: self.sgb.pal,
+            show_bg: self.show_bg,
+            show_window: self.show_window,
+            show_obj: self.show_obj
+        };
 
-                scanline[i as usize] = if bgpri { 4 } else { color_i };
+        // The window line only advances on lines the window was drawn, so
+        // hiding it part way down the screen doesn't skip any of its rows
+        if window
+        {
+            self.win_line = self.win_line.wrapping_add(1);
+        }
+        if !self.output { return }
 
-                self.image_data[canvas_offset]      = color[0];
-                self.image_data[canvas_offset + 1]  = color[1];
-                self.image_data[canvas_offset + 2]  = color[2];
-                self.image_data[canvas_offset + 3]  = color[3];
+        // Colors of the line, copied to the frame buffer once it is drawn.
+        // The BG, or the blank line drawn when it is off, covers every pixel.
+        let mut pixels = [[0xFF; 4]; WIDTH];
+        let mut sources = [PixelSource::default(); WIDTH];
 
-                x += 1;
-                i += 1;
-                canvas_offset += 4;
+        match self.worker
+        {
+            Some(ref mut worker) => worker.draw(self.video.clone(), line, pixels, sources),
+            None => {
+                line.draw(&self.video, &mut pixels, &mut sources);
+                self.store_line(self.ly, &pixels, &sources);
             }
-
-            x = 0;
-            if i >= 160 { break }
         }
     }
 
-    fn render_obj(&mut self, scanline: &mut [u8; WIDTH])
+    /// Copy a drawn line into the frame buffer and hand it to the scanline
+    /// callback
+    fn store_line(&mut self, ly: u8, pixels: &[Color; WIDTH], sources: &[PixelSource; WIDTH])
     {
-        let line = self.ly as i32;
-        let y_size = if self.obj_size { 16 } else { 8 };
+        let line = ly as usize * WIDTH;
+        self.pixel_sources[line..line + WIDTH].copy_from_slice(sources);
 
-        for obj in self.oam.chunks(4)
+        let start = ly as usize * WIDTH * 4;
+        let row = &mut self.image_data[start..start + WIDTH * 4];
+        for (chunk, pixel) in row.chunks_exact_mut(4).zip(pixels.iter())
         {
-            let mut y_offset = (obj[0] as i32) - 16;
-            let x_offset = (obj[1] as i32) - 8;
-            let mut tile = obj[2] as usize;
-            let flags = obj[3];
+            chunk.copy_from_slice(pixel);
+        }
 
-            if y_offset > line || y_offset + y_size <= line || 
-                x_offset <= -8 || x_offset >= WIDTH as i32
-            {
-                continue
-            }
+        if let Some(ref mut callback) = self.scanline_callback
+        {
+            callback(ly, &self.image_data[start..start + WIDTH * 4]);
+        }
+    }
 
-            if y_size == 16
-            {
-                tile &= 0xFE;
-                if line - y_offset >= 8
-                {
-                    tile |= 1;
-                    y_offset += 8;
-                }
-            }
+    /// Wait for the render worker to draw every line it was given and copy
+    /// them into the frame buffer
+    fn finish_lines(&mut self)
+    {
+        if let Some(mut worker) = self.worker.take()
+        {
+            worker.finish(|ly, pixels, sources| self.store_line(ly, pixels, sources));
+            self.worker = Some(worker);
+        }
+    }
 
-            let mut canvas_offset = (WIDTH as i32 * line + x_offset) * 4;
+    /// Draw lines on a separate thread instead of while the CPU waits. The
+    /// frame buffer is only brought up to date at the start of VBlank, so
+    /// it holds the last frame while a frame is being drawn and scanline
+    /// callbacks are invoked for the whole frame at once.
+    pub fn set_render_worker(&mut self, enabled: bool)
+    {
+        self.finish_lines();
+        self.worker = if enabled { Some(RenderWorker::new()) } else { None };
+    }
 
-            let pal;
-            let tiled;
-            if self.is_cgb
-            {
-                pal = self.cgb.cobp[(flags & 0x3) as usize];
-                tiled = self.tiles.data[((flags as usize >> 3) & 1 * NUM_TILES) + tile as usize];
-            }
-            else
-            {
-                pal = if flags & 0x10 != 0 { self.pal.obp1 } else { self.pal.obp0 };
-                tiled = self.tiles.data[tile as usize];
-            }
+    /// Are lines drawn on a separate thread?
+    pub fn render_worker(&self) -> bool
+    {
+        self.worker.is_some()
+    }
 
-            let row = if flags & 0x40 != 0 { 
-                tiled[(7 - (line - y_offset)) as usize] 
-            } else { 
-                tiled[(line - y_offset) as usize] 
-            };
+    /// Set whether lines are drawn and finished frames are handed to the
+    /// frame sinks
+    pub fn set_output(&mut self, enabled: bool)
+    {
+        self.output = enabled;
+    }
 
-            for x in 0..8
-            {
-                canvas_offset += 4;
+    /// Show or hide a layer from the next line on. Blank can't be hidden.
+    pub fn set_layer_shown(&mut self, layer: PixelLayer, shown: bool)
+    {
+        match layer
+        {
+            PixelLayer::Background => self.show_bg = shown,
+            PixelLayer::Window => self.show_window = shown,
+            PixelLayer::Sprite => self.show_obj = shown,
+            PixelLayer::Blank => {}
+        }
+    }
 
-                if x_offset + x < 0 || x_offset + x >= WIDTH as i32 || 
-                    scanline[(x + x_offset) as usize] > 3
-                {
-                    continue
-                }
+    /// Is a layer drawn?
+    pub fn layer_shown(&self, layer: PixelLayer) -> bool
+    {
+        match layer
+        {
+            PixelLayer::Background => self.show_bg,
+            PixelLayer::Window => self.show_window,
+            PixelLayer::Sprite => self.show_obj,
+            PixelLayer::Blank => true
+        }
+    }
 
-                let color_i = row[if flags & 0x20 != 0 { 7 - x } else { x } as usize];
-                if color_i == 0 { continue }
+    fn add_tile_i(&self, base: usize, tile_i: u8) -> usize
+    {
+        if self.tile_data { base + tile_i as usize } else { (base as isize + (tile_i as i8 as isize)) as usize }
+    }
 
-                if flags & 0x80 != 0 && scanline[(x_offset + x) as usize] != 0
-                {
-                    continue
-                }
+    fn bg_base(&self) -> usize
+    {
+        if self.bg_tmap { 0x1C00 } else { 0x1800 }
+    }
+}
 
-                let color;
-                if self.is_sgb && !self.is_cgb
+impl Video
+{
+    /// Recompile every tile that has changed since it was last compiled
+    fn update_tileset(&mut self)
+    {
+        let tiles = &mut self.tiles;
+        let iter = tiles.to_update.iter_mut();
+        for (i, t) in iter.enumerate().filter(|&(_, &mut i)| i)
+        {
+            *t = false;
+            for j in 0..8
+            {
+                let addr = ((i % NUM_TILES) * 16) + j * 2;
+                let (lsb, msb) = if i < NUM_TILES
                 {
-                    let sgb_addr = ((x_offset as usize + x as usize) >> 3) + 
-                        (line as usize >> 3) * 20;
-                    let mapped = self.sgb.atf[sgb_addr as usize] as usize;
-                    match pal[color_i as usize][0]
-                    {
-                        0 => color = self.sgb.pal[mapped][3],
-                        96 => color = self.sgb.pal[mapped][2],
-                        192 => color = self.sgb.pal[mapped][1],
-                        255 => color = self.sgb.pal[mapped][0],
-                        _ => color = [0, 0, 0, 0]
-                    }
+                    (self.vram[0][addr], self.vram[0][addr + 1])
                 }
                 else
                 {
-                    color = pal[color_i as usize];
-                }
+                    (self.vram[1][addr], self.vram[1][addr + 1])
+                };
 
-                self.image_data[(canvas_offset - 4) as usize] = color[0];
-                self.image_data[(canvas_offset - 3) as usize] = color[1];
-                self.image_data[(canvas_offset - 2) as usize] = color[2];
-                self.image_data[(canvas_offset - 1) as usize] = color[3];
+                tiles.data[i][j] = decode_tile_row(lsb, msb);
             }
         }
+        tiles.need_update = false;
     }
+}
 
-    fn add_tile_i(&self, base: usize, tile_i: u8) -> usize
-    {
-        if self.tile_data { base + tile_i as usize } else { (base as isize + (tile_i as i8 as isize)) as usize }
-    }
+/// Decode a row of tile data into the color index of each of its 8 pixels
+#[cfg(feature = "tile-lut")]
+fn decode_tile_row(lsb: u8, msb: u8) -> [u8; 8]
+{
+    (TILE_ROW_LUT[lsb as usize] | (TILE_ROW_LUT[msb as usize] << 1)).to_le_bytes()
+}
 
-    fn bg_base(&self) -> usize
+/// Decode a row of tile data into the color index of each of its 8 pixels
+#[cfg(not(feature = "tile-lut"))]
+fn decode_tile_row(mut lsb: u8, mut msb: u8) -> [u8; 8]
+{
+    let mut row = [0; 8];
+    for k in (0..8).rev()
     {
-        if self.bg_tmap { 0x1C00 } else { 0x1800 }
+        row[k] = ((msb & 1) << 1) | (lsb & 1);
+        lsb >>= 1;
+        msb >>= 1;
     }
+    row
 }
 
 /// Update cached palettes for BG/OBP0/OBP1. Called whenever the registers
@@ -904,10 +1491,16 @@ fn update_cgb_palette(pal: &mut [[Color; 4]; 8], mem: &[u8; CGB_BP_SIZE], addr:
     let b_1 = mem[(addr & 0x3E) as usize];
     let b_2 = mem[((addr & 0x3E) + 1) as usize];
 
-    let color = &mut pal[pal_i as usize][col_i as usize];
+    pal[pal_i as usize][col_i as usize] = rgb555(b_1, b_2);
+}
 
-    color[0] = (b_1 & 0x1F) << 3;
-    color[1] = ((b_1 >> 5) | ((b_2 & 0x3) << 3)) << 3;
-    color[2] = ((b_2 >> 2) & 0x1F) << 3;
-    color[3] = 255;
+/// Convert a little endian RGB555 color used by the CGB and SGB to RGBA
+pub fn rgb555(lo: u8, hi: u8) -> Color
+{
+    [
+        (lo & 0x1F) << 3,
+        ((lo >> 5) | ((hi & 0x3) << 3)) << 3,
+        ((hi >> 2) & 0x1F) << 3,
+        255
+    ]
 }
\ No newline at end of file
@@ -1,7 +1,15 @@
 use crate::cpu::Interrupts;
-use crate::Button;
+use crate::sgb::PacketReceiver;
+#[cfg(feature = "savestates")]
+use crate::invalid;
+#[cfg(feature = "savestates")]
+use crate::state::{ Reader, Writer };
+use crate::{ Button, ButtonState };
+#[cfg(feature = "savestates")]
+use std::io::Result as IoResult;
 
 /// The type of button that was pressed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Selected
 {
     Button = 0x20,
@@ -9,13 +17,49 @@ pub enum Selected
     MltReq = 0x00
 }
 
+/// A callback that is sampled for the button state whenever the game reads
+/// the keypad register
+pub type InputProvider = Box< dyn Fn() -> ButtonState + Send >;
+
+/// What the game sees when opposite directions on the D-pad are held at once
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OppositeDirections
+{
+    /// Neither direction of the pair is pressed, like on a real D-pad which
+    /// can't press both. Some games misbehave when they see both.
+    Block,
+
+    /// Both directions are pressed, for tool-assisted runs that rely on it
+    Allow
+}
+
 /// Represents the GameBoy joypad
 pub struct Keypad
 {
-    buttons: u8,
-    directions: u8,
+    held: ButtonState,
+    opposite_directions: OppositeDirections,
     keypad_sel: u8,
-    col: Selected
+    col: Selected,
+
+    /// Optional input provider. When set it takes precedence over the
+    /// buttons registered with key_down/key_up/set_state.
+    provider: Option< InputProvider >,
+
+    /// Should SGB functionality be used?
+    pub is_sgb: bool,
+
+    /// Receives SGB command packets sent through the keypad register
+    packets: PacketReceiver,
+
+    /// The last SGB command that was received and not yet handled
+    command: Option< Vec< u8 > >,
+
+    /// The number of joypads enabled by the SGB MLT_REQ command (1, 2 or 4)
+    players: u8,
+
+    /// Has a selected line gone low since the last check? This wakes the
+    /// CPU from STOP mode.
+    wakeup: bool
 }
 
 impl Keypad
@@ -24,27 +68,54 @@ impl Keypad
     pub fn new() -> Self
     {
         Keypad {
-            buttons: 0xF,
-            directions: 0xF,
+            held: ButtonState::empty(),
+            opposite_directions: OppositeDirections::Block,
             keypad_sel: 0,
-            col: Selected::Direction
+            col: Selected::Direction,
+            provider: None,
+            is_sgb: false,
+            packets: PacketReceiver::new(),
+            command: None,
+            players: 1,
+            wakeup: false
         }
     }
 
     /// Read the GB keypad register
     pub fn read_byte(&self, _addr: u16) -> u8
     {
+        let held = match self.provider
+        {
+            Some(ref provider) => provider(),
+            None => self.held
+        };
+
         match self.col
         {
-            Selected::Button => self.buttons,
-            Selected::Direction => self.directions,
-            Selected::MltReq => 0xF - self.keypad_sel
+            Selected::MltReq => 0xF - self.keypad_sel,
+            _ => self.lines(held)
         }
     }
 
     /// Write to the GB keypad register
     pub fn write_byte(&mut self, _addr: u16, val: u8)
     {
+        if self.is_sgb
+        {
+            // In multiplayer mode the selected joypad advances every time
+            // P15 goes from low to high
+            if self.players > 1 && val & 0x30 == 0x30 && self.col == Selected::Button
+            {
+                self.keypad_sel = (self.keypad_sel + 1) % self.players;
+            }
+
+            if let Some(command) = self.packets.write(val)
+            {
+                self.command = Some(command);
+            }
+        }
+
+        let lines = self.lines(self.held);
         match !val & 0x30
         {
             0x20 => self.col = Selected::Button,
@@ -53,38 +124,163 @@ impl Keypad
 
             _ => {}
         }
+        self.check_wakeup(lines);
     }
 
     /// Called whenever a button is pressed
     pub fn key_down(&mut self, key: Button, intf: &mut u8)
     {
         *intf |= Interrupts::Joypad as u8;
-        match key
-        {
-            Button::Left        => self.directions &= 0xD,
-            Button::Right       => self.directions &= 0xE,
-            Button::Up          => self.directions &= 0xB,
-            Button::Down        => self.directions &= 0x7,
-            Button::A           => self.buttons &= 0xE,
-            Button::B           => self.buttons &= 0xD,
-            Button::Start       => self.buttons &= 0x7,
-            Button::Select      => self.buttons &= 0xB
-        }
+        let lines = self.lines(self.held);
+        self.held.insert(key.into());
+        self.check_wakeup(lines);
     }
 
     /// Called whenever a button is released
     pub fn key_up(&mut self, key: Button)
     {
-        match key
+        self.held.remove(key.into());
+    }
+
+    /// Replace the state of every button at once
+    pub fn set_state(&mut self, state: ButtonState, intf: &mut u8)
+    {
+        // A joypad interrupt is requested when any line goes from high to low
+        if !(state - self.held).is_empty()
+        {
+            *intf |= Interrupts::Joypad as u8;
+        }
+
+        let lines = self.lines(self.held);
+        self.held = state;
+        self.check_wakeup(lines);
+    }
+
+    /// Set what the game sees when opposite directions are held at once
+    pub fn set_opposite_directions(&mut self, policy: OppositeDirections)
+    {
+        self.opposite_directions = policy;
+    }
+
+    /// Get what the game sees when opposite directions are held at once
+    pub fn opposite_directions(&self) -> OppositeDirections
+    {
+        self.opposite_directions
+    }
+
+    /// Get the active-low state of the keypad lines for the selected group
+    /// of buttons when the given buttons are held. Selecting both groups at
+    /// once connects both to the same lines.
+    fn lines(&self, held: ButtonState) -> u8
+    {
+        let mut held = held;
+        if self.opposite_directions == OppositeDirections::Block
         {
-            Button::Left        => self.directions |= !0xD,
-            Button::Right       => self.directions |= !0xE,
-            Button::Up          => self.directions |= !0xB,
-            Button::Down        => self.directions |= !0x7,
-            Button::A           => self.buttons |= !0xE,
-            Button::B           => self.buttons |= !0xD,
-            Button::Start       => self.buttons |= !0x7,
-            Button::Select      => self.buttons |= !0xB
+            for &pair in [ButtonState::LEFT | ButtonState::RIGHT, ButtonState::UP | ButtonState::DOWN].iter()
+            {
+                if held.contains(pair)
+                {
+                    held.remove(pair);
+                }
+            }
+        }
+
+        let bits = held.bits();
+        let pressed = match self.col
+        {
+            Selected::Button => bits,
+            Selected::Direction => bits >> 4,
+            Selected::MltReq => bits | (bits >> 4)
+        };
+        !pressed & 0xF
+    }
+
+    /// Note whether any of the lines went low since they were last read
+    fn check_wakeup(&mut self, lines: u8)
+    {
+        if lines & !self.lines(self.held) & 0xF != 0
+        {
+            self.wakeup = true;
+        }
+    }
+
+    /// Check whether a selected line has gone low since the last call. Inputs
+    /// from an input provider are only sampled when the register is read, so
+    /// they can't wake the CPU.
+    pub fn take_wakeup(&mut self) -> bool
+    {
+        std::mem::replace(&mut self.wakeup, false)
+    }
+
+    /// Take the last SGB command that was received, if any
+    pub fn take_sgb_command(&mut self) -> Option< Vec< u8 > >
+    {
+        self.command.take()
+    }
+
+    /// Set the number of joypads enabled by the SGB MLT_REQ command. Reading
+    /// the keypad register with both P14 & P15 high returns the ID of the
+    /// currently selected joypad.
+    pub fn set_players(&mut self, players: u8)
+    {
+        self.players = players;
+        self.keypad_sel = 0;
+    }
+
+    /// Get the state of every button as held, before opposite directions are
+    /// blocked
+    pub fn state(&self) -> ButtonState
+    {
+        match self.provider
+        {
+            Some(ref provider) => provider(),
+            None => self.held
         }
     }
-}
\ No newline at end of file
+
+    /// Write the keypad state to a savestate chunk
+    #[cfg(feature = "savestates")]
+    pub fn save_state(&self, w: &mut Writer)
+    {
+        let bits = self.held.bits();
+        let (buttons, directions) = (!bits & 0xF, !(bits >> 4) & 0xF);
+        w.bytes(&[buttons, directions, self.keypad_sel, self.col as u8, self.players]);
+        self.packets.save_state(w);
+        w.bool(self.command.is_some());
+        w.vec(self.command.as_ref().map(|c| &c[..]).unwrap_or(&[]));
+    }
+
+    /// Restore the keypad state from a savestate chunk
+    #[cfg(feature = "savestates")]
+    pub fn load_state(&mut self, r: &mut Reader) -> IoResult< () >
+    {
+        let mut b = [0; 5];
+        r.bytes(&mut b)?;
+        self.held = ButtonState::from_bits_truncate(!((b[0] & 0xF) | (b[1] << 4)));
+        self.keypad_sel = b[2];
+        self.col = match b[3] {
+            0x20 => Selected::Button,
+            0x10 => Selected::Direction,
+            0x00 => Selected::MltReq,
+            _ => return Err(invalid("Invalid keypad selection in savestate"))
+        };
+        self.players = b[4];
+        self.packets.load_state(r)?;
+        let pending = r.bool()?;
+        let command = r.vec()?;
+        self.command = if pending { Some(command) } else { None };
+        Ok(())
+    }
+
+    /// Set or clear the callback used to sample the buttons at read time
+    pub fn set_provider(&mut self, provider: Option< InputProvider >)
+    {
+        self.provider = provider;
+    }
+
+    /// Remove and return the input provider, if one is set
+    pub fn take_provider(&mut self) -> Option< InputProvider >
+    {
+        self.provider.take()
+    }
+}
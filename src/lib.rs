@@ -1,23 +1,113 @@
+mod builder;
+#[cfg(feature = "cheats")]
+mod cheats;
+mod compat;
 mod cpu;
 mod mem;
 mod gpu;
+mod render;
 mod timer;
+mod serial;
 mod keypad;
+mod link;
 mod spu;
+mod sgb;
+mod sink;
+#[cfg(feature = "savestates")]
+mod state;
+mod movie;
+#[cfg(feature = "spectate")]
+mod spectate;
+mod storage;
+mod verify;
+mod watchdog;
 
-use crate::cpu::CPU;
+use crate::cpu::{ CPU, Interrupts };
 use crate::mem::Memory;
+#[cfg(feature = "savestates")]
+use crate::state::{ StateReader, StateWriter };
+use crate::watchdog::Watchdog;
+pub use crate::builder::GameboyBuilder;
+#[cfg(feature = "cheats")]
+pub use crate::cheats::Cheat;
+#[cfg(feature = "cheat-db")]
+pub use crate::cheats::NamedCheat;
+pub use crate::compat::{ CompatDb, CompatEntry, Compatibility };
+pub use crate::cpu::registers::Registers;
+pub use crate::gpu::BgAttributes;
+#[cfg(feature = "debugger")]
+pub use crate::gpu::{ TILE_VIEW_WIDTH, TILE_VIEW_HEIGHT, MAP_VIEW_SIZE, OAM_VIEW_WIDTH, OAM_VIEW_HEIGHT };
+pub use crate::gpu::Mode as PpuMode;
+pub use crate::keypad::OppositeDirections;
+pub use crate::link::LinkPort;
+pub use crate::mem::{ map, Accuracy, BankAddress, MapperState, Speed, Unsupported };
+pub use crate::mem::ram::RamFill;
+pub use crate::movie::Movie;
+pub use crate::render::{ PixelLayer, PixelSource };
+pub use crate::serial::MAX_SERIAL_OUTPUT;
+pub use crate::sgb::{ BORDER_WIDTH, BORDER_HEIGHT };
+pub use crate::sink::{ Frame, FrameSink, FrameBuffer, FrameRecorder, NullSink };
+#[cfg(feature = "spectate")]
+pub use crate::spectate::{ SpectateClient, SpectateHost };
+pub use crate::spu::SAMPLE_RATE;
+#[cfg(feature = "savestates")]
+pub use crate::state::{ StateChunks, STATE_VERSION };
+pub use crate::storage::{ StorageBackend, FileStorage, MemoryStorage, ChangeCallback, SaveFormat };
+use crate::storage::SavedClock;
+pub use crate::verify::{ Divergence, HashLog, HASH_INTERVAL };
+pub use crate::watchdog::{ Hang, RunExit };
 use std::fs::File;
+use std::io::{ Error, ErrorKind };
 use std::io::Read;
 use std::io::Result as IoResult;
 use std::path::Path;
 
+/// The types needed to run a game and show it, for frontends to glob import
+/// with `use rustboy::prelude::*`. Everything here is also exported from the
+/// crate root, where the less commonly used types live too.
+pub mod prelude
+{
+    pub use crate::{ Gameboy, GameboyBuilder, Target, Button, ButtonState, RunExit, Hang, EmuStatus, AvChunk, AvStats };
+    pub use crate::{ Movie, TakeOver, SaveFormat, RamFill, OppositeDirections, LinkPort };
+    pub use crate::{ StorageBackend, FileStorage, MemoryStorage };
+    pub use crate::{ Frame, FrameSink, FrameBuffer };
+    pub use crate::{ DISPLAY_WIDTH, DISPLAY_HEIGHT };
+}
+
 /// The width of the GameBoy screen in pixels
 pub const DISPLAY_WIDTH: usize = 160;
 
 /// The height of the GameBoy screen in pixels
 pub const DISPLAY_HEIGHT: usize = 144;
 
+/// The number of emulated cycles executed by a single call to `Gameboy::run`
+/// at normal emulation speed
+const CYCLES_PER_RUN: u32 = 0x10000;
+
+/// The clock rate of the DMG and CGB in Hz
+pub const DMG_CLOCK_RATE: u32 = 4_194_304;
+
+/// The clock rate of the SGB in Hz. The SGB derives its clock from the SNES,
+/// so it runs about 2.4% faster than a GameBoy.
+pub const SGB_CLOCK_RATE: u32 = 4_295_454;
+
+/// The number of ticks it takes to draw a frame
+const TICKS_PER_FRAME: u32 = 70224;
+
+/// The longest a boot ROM is fast forwarded for, in frames. Boot ROMs hand
+/// over to the cartridge after about 3 seconds, or lock up if its logo is
+/// wrong.
+const FAST_BOOT_FRAMES: u32 = 60 * 10;
+
+/// The slowest supported emulation speed multiplier
+pub const MIN_EMULATION_SPEED: f32 = 0.25;
+
+/// The fastest supported emulation speed multiplier
+pub const MAX_EMULATION_SPEED: f32 = 4.0;
+
+/// The most the CPU can be overclocked by, see `Gameboy::set_overclock`
+pub const MAX_OVERCLOCK: u32 = 4;
+
 /// The target GameBoy system that is running
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Target
@@ -27,6 +117,107 @@ pub enum Target
     SuperGameBoy
 }
 
+impl Target
+{
+    /// Get the size of the target's boot ROM in bytes
+    pub fn boot_rom_size(self) -> usize
+    {
+        match self
+        {
+            Target::GameBoyColor => 0x900,
+            Target::GameBoy | Target::SuperGameBoy => 0x100
+        }
+    }
+}
+
+/// A hardware revision of one of the target systems. The boot ROMs of each
+/// revision leave different values in the CPU registers, which some games
+/// and test ROMs use to tell them apart, e.g. A = 0xFF on a GameBoy Pocket.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Revision
+{
+    /// The first DMG, with the DMG0 CPU
+    Dmg0,
+
+    /// The DMG with any later CPU revision (A, B or C)
+    Dmg,
+
+    /// The GameBoy Pocket and GameBoy Light
+    Mgb,
+
+    /// The Super GameBoy
+    Sgb,
+
+    /// The Super GameBoy 2
+    Sgb2,
+
+    /// The GameBoy Color, any CPU revision (CGB-CPU 0 to E). The revisions
+    /// boot with the same register values.
+    Cgb,
+
+    /// The GameBoy Advance running a GameBoy Color game
+    Agb
+}
+
+impl Revision
+{
+    /// Get the revision a target system is emulated as by default
+    pub fn for_target(target: Target) -> Self
+    {
+        match target
+        {
+            Target::GameBoy => Revision::Dmg,
+            Target::GameBoyColor => Revision::Cgb,
+            Target::SuperGameBoy => Revision::Sgb
+        }
+    }
+
+    /// Get the system this is a revision of
+    pub fn target(self) -> Target
+    {
+        match self
+        {
+            Revision::Dmg0 | Revision::Dmg | Revision::Mgb => Target::GameBoy,
+            Revision::Sgb | Revision::Sgb2 => Target::SuperGameBoy,
+            Revision::Cgb | Revision::Agb => Target::GameBoyColor
+        }
+    }
+
+    /// Get the short name of the revision, e.g. "mgb"
+    pub fn name(self) -> &'static str
+    {
+        match self
+        {
+            Revision::Dmg0 => "dmg0",
+            Revision::Dmg => "dmg",
+            Revision::Mgb => "mgb",
+            Revision::Sgb => "sgb",
+            Revision::Sgb2 => "sgb2",
+            Revision::Cgb => "cgb",
+            Revision::Agb => "agb"
+        }
+    }
+
+    /// Get the revision with the given short name, ignoring case
+    pub fn from_name(name: &str) -> Option< Self >
+    {
+        [Revision::Dmg0, Revision::Dmg, Revision::Mgb, Revision::Sgb, Revision::Sgb2, Revision::Cgb, Revision::Agb]
+            .into_iter()
+            .find(|r| r.name().eq_ignore_ascii_case(name))
+    }
+
+    /// Get the value the boot ROM leaves in DIV, if it's known
+    fn boot_div(self) -> Option< u8 >
+    {
+        match self
+        {
+            Revision::Dmg0 => Some(0x18),
+            Revision::Dmg | Revision::Mgb => Some(0xAB),
+            _ => None
+        }
+    }
+}
+
 /// GameBoy buttons
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Button
@@ -41,14 +232,216 @@ pub enum Button
     Select
 }
 
-/// Represents an instance of the GameBoy system
+bitflags::bitflags!
+{
+    /// The set of GameBoy buttons held down at once. The buttons are in the
+    /// low 4 bits and the D-pad in the high 4 bits, in the same order as the
+    /// keypad register.
+    #[derive(Default)]
+    pub struct ButtonState: u8
+    {
+        const A         = 0x01;
+        const B         = 0x02;
+        const SELECT    = 0x04;
+        const START     = 0x08;
+        const RIGHT     = 0x10;
+        const LEFT      = 0x20;
+        const UP        = 0x40;
+        const DOWN      = 0x80;
+    }
+}
+
+/// Button states are serialized as the bits of the keypad register, like
+/// `ButtonState::bits`
+#[cfg(feature = "serde")]
+impl serde::Serialize for ButtonState
+{
+    fn serialize< S: serde::Serializer >(&self, serializer: S) -> Result< S::Ok, S::Error >
+    {
+        serializer.serialize_u8(self.bits())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl< 'de > serde::Deserialize< 'de > for ButtonState
+{
+    fn deserialize< D: serde::Deserializer< 'de > >(deserializer: D) -> Result< Self, D::Error >
+    {
+        u8::deserialize(deserializer).map(ButtonState::from_bits_truncate)
+    }
+}
+
+impl From< Button > for ButtonState
+{
+    fn from(button: Button) -> Self
+    {
+        match button
+        {
+            Button::Left    => ButtonState::LEFT,
+            Button::Right   => ButtonState::RIGHT,
+            Button::Up      => ButtonState::UP,
+            Button::Down    => ButtonState::DOWN,
+            Button::A       => ButtonState::A,
+            Button::B       => ButtonState::B,
+            Button::Start   => ButtonState::START,
+            Button::Select  => ButtonState::SELECT
+        }
+    }
+}
+
+/// A snapshot of the state of the emulated system, for frontends that want to
+/// show what the GameBoy is doing
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EmuStatus
+{
+    /// The speed the CPU is running at. Only CGB games can switch to double
+    /// speed.
+    pub speed: Speed,
+
+    /// Is the CPU halted waiting for an interrupt?
+    pub halted: bool,
+
+    /// Is the CPU stopped waiting for a button press?
+    pub stopped: bool,
+
+    /// Is the LCD turned on?
+    pub lcd_on: bool,
+
+    /// Number of frames completed since power on
+    pub frame_count: u64,
+
+    /// Number of ticks emulated since power on
+    pub cycle_count: u64
+}
+
+/// The audio and video produced since the previous call to `next_av_chunk`.
+/// Both slices borrow the emulator's own buffers, so they must be consumed
+/// (or copied) before the emulator is stepped again.
+#[derive(Debug, Clone, Copy)]
+pub struct AvChunk<'a>
+{
+    /// Interleaved left and right samples at SAMPLE_RATE Hz
+    pub audio: &'a [i16],
+
+    /// The frame completed during the chunk as DISPLAY_WIDTH x
+    /// DISPLAY_HEIGHT RGBA image data, if one was
+    pub frame: Option< &'a [u8] >
+}
+
+/// Audio and video throughput since the stats were last reset, for frontends
+/// that adjust their rate to keep audio and video in sync and for diagnosing
+/// stutter. The emulator counts what it produces; what is presented and
+/// played is only known if the frontend reports it with
+/// `report_frame_presented` and `report_samples_played`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AvStats
+{
+    /// Frames completed by the emulator
+    pub frames_produced: u64,
+
+    /// Frames the frontend reported showing
+    pub frames_presented: u64,
+
+    /// Stereo samples produced by the emulator
+    pub samples_produced: u64,
+
+    /// Stereo samples the frontend reported the audio device playing
+    pub samples_played: u64
+}
+
+impl AvStats
+{
+    /// Frames produced but never presented, which were either dropped or
+    /// are still queued
+    pub fn frames_behind(&self) -> i64
+    {
+        self.frames_produced as i64 - self.frames_presented as i64
+    }
+
+    /// Stereo samples produced but not played yet, i.e. queued between the
+    /// emulator and the audio device. Negative when the device has played
+    /// more than was produced, meaning it ran dry and played silence.
+    pub fn audio_backlog(&self) -> i64
+    {
+        self.samples_produced as i64 - self.samples_played as i64
+    }
+
+    /// The audio backlog in seconds
+    pub fn audio_backlog_secs(&self) -> f64
+    {
+        self.audio_backlog() as f64 / SAMPLE_RATE as f64
+    }
+
+    /// The number of samples played per sample produced. Below 1.0 the
+    /// emulator is getting ahead of the audio device and the backlog grows;
+    /// above 1.0 the device is catching up and will run dry. None until
+    /// anything has been produced.
+    pub fn audio_rate(&self) -> Option< f64 >
+    {
+        if self.samples_produced == 0
+        {
+            None
+        }
+        else
+        {
+            Some(self.samples_played as f64 / self.samples_produced as f64)
+        }
+    }
+}
+
+/// What to do with a movie that is being played back when the user takes
+/// control, see `Gameboy::take_over`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TakeOver
+{
+    /// Keep the movie up to the current frame and record from there
+    Record,
+
+    /// Drop the movie and play normally
+    FreePlay
+}
+
+/// A cartridge pulled out of a running GameBoy with
+/// `Gameboy::eject_cartridge`, or one to plug in with `insert_cartridge`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cartridge
+{
+    /// The ROM image
+    pub rom: Vec< u8 >,
+
+    /// The contents of battery backed cartridge RAM, None for a cartridge
+    /// without a battery or to start with blank RAM
+    pub save: Option< Vec< u8 > >
+}
+
+impl Cartridge
+{
+    /// A cartridge with the given ROM image and blank RAM
+    pub fn new(rom: Vec< u8 >) -> Self
+    {
+        Cartridge { rom, save: None }
+    }
+}
+
+/// Represents an instance of the GameBoy system.
+///
+/// The emulator core is fully deterministic: two instances created from the
+/// same ROM and fed the same inputs at the same points in emulated time will
+/// produce identical frames. Nothing in the core reads a random number
+/// source, and uninitialized RAM is filled with a fixed pattern (or a seeded
+/// pseudo-random one, see `set_ram_fill`). `frame_hash` can be used to
+/// compare the output of two runs. The host clock is only read to run the
+/// MBC3 clock of a battery save forward by the time since it was made.
 pub struct Gameboy
 {
     /// GameBoy CPU
     cpu: CPU,
 
-    /// GameBoy Memory Unit
-    mem: Memory,
+    /// GameBoy Memory Unit. Every component is stored inline in Memory so
+    /// it is too large to keep on the stack.
+    mem: Box< Memory >,
 
     /// FPS GameBoy is running at
     fps: u32,
@@ -57,48 +450,344 @@ pub struct Gameboy
     cycles: u32,
 
     /// Target system
-    target: Target
+    target: Target,
+
+    /// Hardware revision of the target system
+    revision: Revision,
+
+    /// Emulation speed multiplier. Scales the number of emulated cycles that
+    /// are executed per call to run()
+    emulation_speed: f32,
+
+    /// How many times faster than stock the CPU runs compared to the rest
+    /// of the system, and the CPU ticks that haven't added up to a tick of
+    /// system time yet
+    overclock: u32,
+    overclock_ticks: u32,
+
+    /// Is emulation paused? See `pause`.
+    paused: bool,
+
+    /// Where battery backed cartridge RAM is persisted
+    storage: Option< Box< dyn StorageBackend > >,
+
+    /// The MBC3 clock as of the last save to or load from the storage
+    /// backend, if the cartridge has one
+    clock_saved: Option< SavedClock >,
+
+    /// Name the game's save data is stored under
+    save_name: String,
+
+    /// Checksum of the game the save name belongs to, which stops being the
+    /// game inserted when cartridges are swapped
+    save_checksum: u16,
+
+    /// The movie the inputs of every frame are being recorded to, if any
+    recording: Option< Movie >,
+
+    /// The movie being played back and the frame it is on, if any
+    playback: Option< (Movie, usize) >,
+
+    /// Active cheat codes
+    #[cfg(feature = "cheats")]
+    cheats: Vec< Cheat >,
+
+    /// Should holding A+B+Start+Select soft reset the game?
+    reset_combo: bool,
+
+    /// Was the reset combo held at the end of the last frame? A reset only
+    /// happens when the combo is first pressed.
+    reset_combo_held: bool,
+
+    /// Is the SGB border shown?
+    sgb_border: bool,
+
+    /// Is the boot ROM run straight through without being drawn?
+    fast_boot: bool,
+
+    /// Addresses that stop emulation when the CPU is about to execute them
+    #[cfg(feature = "debugger")]
+    breakpoints: Vec< BankAddress >,
+
+    /// Watches for the game crashing, when enabled
+    watchdog: Option< Watchdog >,
+
+    /// Why emulation last stopped early, if it did
+    interrupted: Option< RunExit >,
+
+    /// A/V throughput since the stats were last reset. Samples produced are
+    /// counted by the SPU, offset by the count it had at the reset.
+    av_stats: AvStats,
+    av_sample_base: u64
 }
 
 impl Gameboy
 {
-    /// Create and return a new instance of a GameBoy running as the target system
+    /// Create and return a new instance of a GameBoy running the ROM file at
+    /// the given path, on a CGB if the game supports it and a DMG otherwise.
+    /// Panics if the ROM can't be loaded; see `try_new`.
     pub fn new(rom_path: &Path) -> Self
     {
-        // Load the ROM file
-        let rom = match Gameboy::load_rom(rom_path) {
-            Ok(r) => r,
-            Err(e) => panic!("Unable to load ROM file: {}", e)
-        };
-        
-        // Determine the target system
-        let target = Target::GameBoy;
+        match Gameboy::try_new(rom_path)
+        {
+            Ok(gb) => gb,
+            Err(e) => panic!("Unable to load {}: {}", rom_path.display(), e)
+        }
+    }
+
+    /// Create and return a new instance of a GameBoy running the ROM file at
+    /// the given path, or the error if it can't be loaded
+    pub fn try_new(rom_path: &Path) -> IoResult< Self >
+    {
+        // Battery saves live next to the ROM file with the same name
+        GameboyBuilder::new().rom_path(rom_path).build()
+    }
+
+    /// Create and return a new instance of a GameBoy running the given ROM
+    /// image that has already been loaded into memory
+    pub fn from_rom(rom: Vec< u8 >) -> Self
+    {
+        Gameboy::from_rom_as(rom, Target::GameBoy)
+    }
 
+    /// Create and return a new instance of the given target system running
+    /// the given ROM image
+    pub fn from_rom_as(rom: Vec< u8 >, target: Target) -> Self
+    {
+        Gameboy::from_rom_revision(rom, Revision::for_target(target))
+    }
+
+    /// Create and return a new instance of the given hardware revision
+    /// running the given ROM image
+    pub fn from_rom_revision(rom: Vec< u8 >, revision: Revision) -> Self
+    {
+        let target = revision.target();
         let mut gb = Gameboy { 
-            cpu: CPU::new(target),
-            mem: Memory::new(target),
+            cpu: CPU::new(revision),
+            mem: Box::new(Memory::new(target)),
             fps: 0, 
             cycles: 0,
-            target: target
+            target: target,
+            revision,
+            emulation_speed: 1.0,
+            overclock: 1,
+            overclock_ticks: 0,
+            paused: false,
+            storage: None,
+            clock_saved: None,
+            save_name: String::new(),
+            save_checksum: 0,
+            recording: None,
+            playback: None,
+            #[cfg(feature = "cheats")]
+            cheats: Vec::new(),
+            reset_combo: false,
+            reset_combo_held: false,
+            sgb_border: true,
+            fast_boot: false,
+            #[cfg(feature = "debugger")]
+            breakpoints: Vec::new(),
+            watchdog: None,
+            interrupted: None,
+            av_stats: AvStats::default(),
+            av_sample_base: 0
         };
         gb.power_on();
         gb.mem.load_cartridge(rom);
+        gb.save_name = gb.title();
+        gb.save_checksum = gb.checksum();
 
         gb
     }
 
+    /// Create and return a new instance of a GameBoy running the given ROM
+    /// image, on a CGB if the game supports it and a DMG otherwise. Fails if
+    /// the image isn't a GameBoy ROM or the cartridge uses a feature that
+    /// will likely stop the game from running. Unlike `from_rom`, which
+    /// carries on with the closest supported hardware.
+    pub fn try_from_rom(rom: Vec< u8 >) -> IoResult< Self >
+    {
+        GameboyBuilder::new().rom_bytes(rom).strict(true).build()
+    }
+
+    /// Fail if the cartridge uses a feature that will likely stop the game
+    /// from running
+    fn check_supported(&self) -> IoResult< () >
+    {
+        let critical: Vec< String > = self.unsupported_features().iter()
+            .filter(|f| f.is_critical())
+            .map(|f| f.to_string())
+            .collect();
+        if !critical.is_empty()
+        {
+            return Err(invalid(&critical.join(", ")))
+        }
+        Ok(())
+    }
+
+    /// Restart the loaded game as if it had been inserted into a different
+    /// target system, without reloading the ROM. Useful for comparing how a
+    /// dual-mode cartridge looks on a DMG and a CGB. Everything is reset to
+    /// its power on state except battery backed cartridge RAM, the MBC3
+    /// clock, the storage backend, any registered callbacks and the link
+    /// cable. RAM is filled with the default pattern.
+    pub fn restart_as(&mut self, target: Target)
+    {
+        self.restart_as_revision(Revision::for_target(target));
+    }
+
+    /// Restart the loaded game on a specific hardware revision, like
+    /// `restart_as`
+    pub fn restart_as_revision(&mut self, revision: Revision)
+    {
+        let target = revision.target();
+        let rom = self.mem.take_rom();
+        let save = self.mem.battery_ram().map(|ram| ram.to_vec());
+        let clock = self.mem.rtc_registers();
+
+        let mut mem = Memory::new(target);
+        mem.gpu.set_scanline_callback(self.mem.gpu.take_scanline_callback());
+        mem.gpu.set_frame_sinks(self.mem.gpu.take_frame_sinks());
+        mem.spu.set_callback(self.mem.spu.take_callback());
+        mem.spu.set_produced(self.mem.spu.produced());
+        mem.keypad.set_provider(self.mem.keypad.take_provider());
+        mem.keypad.set_opposite_directions(self.mem.keypad.opposite_directions());
+        mem.set_accuracy(self.mem.accuracy());
+        mem.serial.set_link(self.mem.serial.set_link(None));
+        mem.gpu.set_render_worker(self.mem.gpu.render_worker());
+        for layer in [PixelLayer::Background, PixelLayer::Window, PixelLayer::Sprite]
+        {
+            mem.gpu.set_layer_shown(layer, self.mem.gpu.layer_shown(layer));
+        }
+        #[cfg(feature = "cheats")]
+        mem.set_rom_patches(&self.cheats);
+        let boot_rom = self.mem.boot_rom().to_vec();
+        if boot_rom.len() == target.boot_rom_size()
+        {
+            mem.set_boot_rom(boot_rom);
+        }
+
+        self.cpu = CPU::new(revision);
+        *self.mem = mem;
+        self.target = target;
+        self.revision = revision;
+        self.cycles = 0;
+        self.power_on();
+        if rom.is_empty() { return }
+        self.mem.load_cartridge(rom);
+        if let Some(save) = save
+        {
+            self.mem.load_battery_ram(&save);
+        }
+        if let Some((regs, latched)) = clock
+        {
+            self.mem.restore_rtc(regs, latched, 0);
+        }
+        self.fast_forward_boot_rom();
+    }
+
+    /// Pull the cartridge out while the game keeps running, like the tricks
+    /// that swap cartridges mid game to carry RAM contents over. Until
+    /// another one is inserted, reads from the cartridge area return 0xFF
+    /// and writes are ignored, so the game will likely crash unless it is
+    /// running from RAM. Returns None if no cartridge is inserted.
+    ///
+    /// Battery backed RAM goes with the cartridge rather than being written
+    /// to the storage backend, so call `save` first to keep it.
+    pub fn eject_cartridge(&mut self) -> Option< Cartridge >
+    {
+        if !self.mem.has_cartridge()
+        {
+            return None
+        }
+        let (rom, save) = self.mem.eject_cartridge();
+        Some(Cartridge { rom, save })
+    }
+
+    /// Plug a cartridge in while the game is running. Its mapper starts in
+    /// its power on state, while the rest of the system carries on as it
+    /// was: in particular CGB and SGB functionality stay as the cartridge
+    /// that was booted chose. Battery saves and savestates of a different
+    /// game are stored under its title. Fails if a cartridge is already
+    /// inserted or the ROM isn't a GameBoy ROM.
+    pub fn insert_cartridge(&mut self, cart: Cartridge) -> IoResult< () >
+    {
+        if self.mem.has_cartridge()
+        {
+            return Err(invalid("A cartridge is already inserted"))
+        }
+        Gameboy::check_rom(&cart.rom)?;
+
+        // Swapping back to the game that was saved under keeps its name
+        let checksum = Memory::checksum_of(&cart.rom);
+        if checksum != self.save_checksum
+        {
+            self.save_name = self.mem.title_of(&cart.rom);
+            self.save_checksum = checksum;
+        }
+        self.mem.insert_cartridge(cart.rom);
+        if let Some(save) = cart.save
+        {
+            self.mem.load_battery_ram(&save);
+        }
+        Ok(())
+    }
+
+    /// Is a cartridge inserted? Only false after `eject_cartridge`.
+    pub fn has_cartridge(&self) -> bool
+    {
+        self.mem.has_cartridge()
+    }
+
+    /// Reset the game the way it resets itself: the CPU registers are set to
+    /// the values the boot ROM leaves behind and execution continues at the
+    /// cartridge entry point (0x0100). Unlike `restart_as` nothing else is
+    /// touched, so RAM keeps its contents and games can tell the warm boot
+    /// from a power on.
+    pub fn soft_reset(&mut self)
+    {
+        self.cpu = CPU::new(self.revision);
+    }
+
+    /// Soft reset the game whenever A, B, Start and Select are all pressed
+    /// at once, like many games do themselves. Off by default. The reset
+    /// happens at the end of the frame the last of the buttons is pressed
+    /// on.
+    pub fn set_reset_combo(&mut self, enabled: bool)
+    {
+        self.reset_combo = enabled;
+    }
+
     /// Load the ROM from file into a Vec< u8 >
     fn load_rom(rom_path: &Path) -> IoResult< Vec< u8 > >
     {
         let mut src = File::open(rom_path)?;
         let mut rom = Vec::new();
         (&mut src).read_to_end(&mut rom)?;
+        Gameboy::check_rom(&rom)?;
         Ok(rom)
     }
 
-    /// Execute the GameBoy power up sequence
+    /// Check that a ROM image looks like a GameBoy cartridge, so empty files
+    /// or files of the wrong type can be reported instead of loaded
+    pub fn check_rom(rom: &[u8]) -> IoResult< () >
+    {
+        Memory::check_rom(rom)
+    }
+
+    /// Execute the GameBoy power up sequence. With a boot ROM the CPU starts
+    /// at 0x0000 in the boot ROM. Without one the CPU registers and IO
+    /// registers are set to the values the boot ROM leaves behind and
+    /// execution starts at the cartridge entry point (0x0100).
     fn power_on(&mut self)
     {
+        // The boot ROM sets everything up itself
+        if self.mem.map_boot_rom()
+        {
+            self.cpu = CPU::power_up();
+            return
+        }
+
         // http://marc.rawer.de/Gameboy/Docs/GBCPUman.pdf - page 18
         
         self.mem.write_byte(0xFF05, 0x00);  // TIMA
@@ -141,41 +830,1207 @@ impl Gameboy
             }
             _ => {}
         }
+
+        // DIV has been counting since power on by the time the boot ROM
+        // hands over
+        if let Some(div) = self.revision.boot_div()
+        {
+            self.mem.set_div(div);
+        }
     }
 
-    /// Run a single cycle of the GameBoy
-    pub fn run(&mut self)
+    /// Run the boot ROM until it hands over to the cartridge if fast boot is
+    /// on, without drawing anything. The boot ROM still checks the
+    /// cartridge's logo, and one that locks up is left running after
+    /// FAST_BOOT_FRAMES.
+    fn fast_forward_boot_rom(&mut self)
     {
-        while self.cycles < 0x10000
+        if !self.fast_boot { return }
+
+        self.mem.gpu.set_output(false);
+        let mut ticks = 0;
+        while self.mem.boot_rom_mapped() && ticks < FAST_BOOT_FRAMES * TICKS_PER_FRAME
         {
-            let time = self.cpu.exec(&mut self.mem);
-            self.mem.step(time);
-            self.cycles += time;
+            ticks += self.step();
         }
-        self.cycles -= 0x10000;
+        self.mem.gpu.set_output(true);
+        self.interrupted = None;
     }
 
-    /// Get the image data currently being drawn by GPU
-    pub fn get_image_data(&self) -> &[u8]
+    /// Run a single cycle of the GameBoy. The SGB runs slightly more cycles
+    /// per call to match its faster clock. Stops early at a breakpoint or
+    /// when the watchdog goes off, in which case the next call finishes the
+    /// rest of the cycle.
+    pub fn run(&mut self) -> RunExit
     {
-        &*self.mem.gpu.image_data
+        if self.paused
+        {
+            return RunExit::Completed
+        }
+
+        // Only next_av_chunk hands out samples
+        self.mem.spu.stop_collecting();
+
+        let clock_scale = self.clock_rate() as f32 / DMG_CLOCK_RATE as f32;
+        let target_cycles = (CYCLES_PER_RUN as f32 * self.emulation_speed * clock_scale) as u32;
+        self.interrupted = None;
+        while self.cycles < target_cycles && self.interrupted.is_none()
+        {
+            self.cycles += self.step();
+        }
+        match self.interrupted
+        {
+            Some(exit) => exit,
+            None => {
+                self.cycles -= target_cycles;
+                RunExit::Completed
+            }
+        }
     }
 
-    /// Register that a key has been pressed down
-    pub fn key_down(&mut self, key: Button)
+    /// Run the GameBoy until the GPU completes the current frame and enters
+    /// VBlank. Useful for advancing emulation one frame at a time while
+    /// debugging. Stops early at a breakpoint or when the watchdog goes off.
+    pub fn run_frame(&mut self) -> RunExit
     {
-        self.mem.keypad.key_down(key, &mut self.mem.intf);
+        self.mem.spu.stop_collecting();
+        let frame = self.mem.gpu.frames();
+        self.interrupted = None;
+        while self.mem.gpu.frames() == frame && self.interrupted.is_none()
+        {
+            self.step();
+        }
+        self.interrupted.unwrap_or(RunExit::Completed)
     }
 
-    /// Register that a key has been released
-    pub fn key_up(&mut self, key: Button)
+    /// Run the GameBoy until a VBlank interrupt is requested, stopping right
+    /// before the CPU could service it. Usually that is the GPU entering
+    /// VBlank like `run_frame`, but a game can also request one itself by
+    /// writing IF, and turning the LCD off and on again moves the next one.
+    /// Useful for embedders that need to stay in step with a game's VBlank
+    /// handler. Stops early at a breakpoint or when the watchdog goes off.
+    pub fn run_until_vblank(&mut self) -> RunExit
     {
-        self.mem.keypad.key_up(key);
+        self.mem.spu.stop_collecting();
+        let vblank = Interrupts::VBlank as u8;
+        self.interrupted = None;
+        loop
+        {
+            let frame = self.mem.gpu.frames();
+            let requested = self.mem.intf & vblank;
+            self.step();
+
+            // A request made while one is already pending still counts
+            let raised = self.mem.intf & vblank & !requested != 0 || self.mem.gpu.frames() != frame;
+            if raised || self.interrupted.is_some()
+            {
+                return self.interrupted.unwrap_or(RunExit::Completed)
+            }
+        }
     }
 
-    /// Get the current FPS the GameBoy is running at
-    pub fn fps(&mut self) -> u32
+    /// Run the GameBoy until `cycle_count` reaches the given number of
+    /// ticks, finishing the instruction that passes it. Running two linked
+    /// GameBoys to the same count a few hundred ticks at a time keeps them
+    /// in lockstep, for link protocols that expect the partner to answer
+    /// each byte before the next. Stops early at a breakpoint or when the
+    /// watchdog goes off.
+    pub fn run_until_cycle(&mut self, cycle: u64) -> RunExit
     {
-        ::std::mem::replace(&mut self.fps, 0)
+        self.mem.spu.stop_collecting();
+        self.interrupted = None;
+        while self.mem.cycles < cycle && self.interrupted.is_none()
+        {
+            self.step();
+        }
+        self.interrupted.unwrap_or(RunExit::Completed)
     }
-}
\ No newline at end of file
+
+    /// Run the emulator until the next frame is completed and return it
+    /// along with the audio samples produced on the way. Intended for
+    /// feeding encoders and other pull-based consumers without copying
+    /// through an intermediate buffer. At most one frame's worth of ticks
+    /// is run per call, and running stops early at a breakpoint. The sound
+    /// channels are not emulated yet so the audio is silent.
+    pub fn next_av_chunk(&mut self) -> AvChunk<'_>
+    {
+        self.mem.spu.collect_samples();
+        if self.paused
+        {
+            return AvChunk { audio: self.mem.spu.samples(), frame: None }
+        }
+
+        let frame = self.mem.gpu.frames();
+        let mut ticks = 0;
+        self.interrupted = None;
+        while self.mem.gpu.frames() == frame && ticks < TICKS_PER_FRAME && self.interrupted.is_none()
+        {
+            ticks += self.step();
+        }
+
+        AvChunk {
+            audio: self.mem.spu.samples(),
+            frame: if self.mem.gpu.frames() != frame
+            {
+                Some(&*self.mem.gpu.image_data)
+            }
+            else
+            {
+                None
+            }
+        }
+    }
+
+    /// Execute a single CPU instruction and step the rest of the system to
+    /// match. Returns the number of ticks that passed.
+    fn step(&mut self) -> u32
+    {
+        let frame = self.mem.gpu.frames();
+        #[cfg(feature = "debugger")]
+        let pc = self.cpu.regs.pc;
+        let mut time = self.cpu.exec(&mut self.mem);
+
+        // An overclocked CPU gets through its instructions in a fraction of
+        // the time, while everything else keeps its stock timing. A halted
+        // CPU skips ahead in system time, so that isn't scaled.
+        if self.overclock > 1 && self.cpu.regs.halt == 0
+        {
+            self.overclock_ticks += time;
+            time = self.overclock_ticks / self.overclock;
+            self.overclock_ticks %= self.overclock;
+        }
+        self.mem.step(time, self.cpu.regs.stop != 0);
+
+        // Only moving onto a breakpoint stops emulation, so it can carry on
+        // from one and doesn't stop over and over while halted on one
+        #[cfg(feature = "debugger")]
+        if !self.breakpoints.is_empty() && self.cpu.regs.pc != pc
+        {
+            let addr = self.mem.bank_address(self.cpu.regs.pc);
+            if self.breakpoints.contains(&addr)
+            {
+                self.interrupted = Some(RunExit::Breakpoint(addr));
+            }
+        }
+
+        // STOP is how games deliberately sleep with the LCD off, so it
+        // doesn't count as hanging
+        if let Some(watchdog) = self.watchdog.as_mut()
+        {
+            let pc = self.mem.bank_address(self.cpu.regs.pc);
+            let awake = self.mem.gpu.lcd_enabled || self.cpu.regs.stop != 0;
+            if let Some(hang) = watchdog.step(pc, time, awake)
+            {
+                self.interrupted = Some(RunExit::Hung(hang));
+            }
+        }
+
+        if self.mem.gpu.frames() != frame
+        {
+            self.av_stats.frames_produced += 1;
+
+            #[cfg(feature = "cheats")]
+            for cheat in self.cheats.iter()
+            {
+                if let Cheat::GameShark { addr, value } = *cheat
+                {
+                    self.mem.write_byte(addr, value);
+                }
+            }
+
+            let inputs = self.inputs();
+            let combo = inputs.contains(ButtonState::A | ButtonState::B | ButtonState::START | ButtonState::SELECT);
+            if self.reset_combo && combo && !self.reset_combo_held
+            {
+                self.soft_reset();
+            }
+            self.reset_combo_held = combo;
+
+            if let Some(movie) = self.recording.as_mut()
+            {
+                movie.push(inputs);
+            }
+            if let Some((movie, frame)) = self.playback.as_mut()
+            {
+                *frame += 1;
+                match movie.frame(*frame)
+                {
+                    Some(state) => self.mem.keypad.set_state(state, &mut self.mem.intf),
+                    None => {
+                        self.playback = None;
+                        self.mem.keypad.set_state(ButtonState::default(), &mut self.mem.intf);
+                    }
+                }
+            }
+        }
+        time
+    }
+
+    /// Enable a cheat. Several codes can be given at once separated by '+',
+    /// in which case none are enabled if any of them is invalid. Game Genie
+    /// codes patch ROM as it is read and GameShark codes write memory at the
+    /// end of every frame.
+    #[cfg(feature = "cheats")]
+    pub fn add_cheat(&mut self, codes: &str) -> IoResult< () >
+    {
+        let parsed = codes.split('+')
+            .map(Cheat::parse)
+            .collect::< IoResult< Vec< _ > > >()?;
+        self.cheats.extend(parsed);
+        self.mem.set_rom_patches(&self.cheats);
+        Ok(())
+    }
+
+    /// Disable every cheat
+    #[cfg(feature = "cheats")]
+    pub fn clear_cheats(&mut self)
+    {
+        self.cheats.clear();
+        self.mem.set_rom_patches(&self.cheats);
+    }
+
+    /// Get the cheats that are enabled
+    #[cfg(feature = "cheats")]
+    pub fn cheats(&self) -> &[Cheat]
+    {
+        &self.cheats
+    }
+
+    /// Look up the cheats for the loaded game in the built in cheat
+    /// database. The codes of a cheat can be passed to `add_cheat` as is.
+    #[cfg(feature = "cheat-db")]
+    pub fn known_cheats(&self) -> Vec< NamedCheat >
+    {
+        cheats::lookup(self.checksum())
+    }
+
+    /// Start recording the inputs of every frame to a new movie, replacing
+    /// any recording in progress. A frame's inputs are the buttons held when
+    /// it is completed.
+    ///
+    /// While recording, savestates hold the movie up to that point and
+    /// loading one rewinds the recording to it and counts a rerecord.
+    pub fn start_recording(&mut self)
+    {
+        self.playback = None;
+        self.recording = Some(Movie::new());
+    }
+
+    /// Stop recording and return the recorded movie, if there was one
+    pub fn stop_recording(&mut self) -> Option< Movie >
+    {
+        self.recording.take()
+    }
+
+    /// Get the movie being recorded, if any
+    pub fn recording(&self) -> Option< &Movie >
+    {
+        self.recording.as_ref()
+    }
+
+    /// Play back a movie from the next frame on, stopping any recording. The
+    /// movie's inputs replace the buttons set with key_down/key_up/set_inputs
+    /// until playback ends, at which point every button is released. Use
+    /// `take_over` to stop playback early and continue from the current
+    /// frame.
+    ///
+    /// Savestates made during playback hold the movie up to that frame, and
+    /// loading one moves playback to it without changing the movie.
+    pub fn play_movie(&mut self, movie: Movie)
+    {
+        self.recording = None;
+        match movie.frame(0)
+        {
+            Some(state) => {
+                self.mem.keypad.set_state(state, &mut self.mem.intf);
+                self.playback = Some((movie, 0));
+            },
+            None => self.playback = None
+        }
+    }
+
+    /// Get the movie being played back and the frame it is on, if any
+    pub fn playback(&self) -> Option< (&Movie, usize) >
+    {
+        self.playback.as_ref().map(|(movie, frame)| (movie, *frame))
+    }
+
+    /// Stop playing back a movie and hand control to the user. The buttons
+    /// held by the movie stay held until changed. Does nothing if no movie
+    /// is being played.
+    pub fn take_over(&mut self, mode: TakeOver)
+    {
+        if let Some((mut movie, frame)) = self.playback.take()
+        {
+            if mode == TakeOver::Record
+            {
+                movie.truncate(frame);
+                self.recording = Some(movie);
+            }
+        }
+    }
+
+    /// Get the title of the game from the cartridge header
+    pub fn title(&self) -> String
+    {
+        self.mem.title()
+    }
+
+    /// Get the features of the loaded cartridge that aren't emulated. The
+    /// game may misbehave if it relies on any of them.
+    pub fn unsupported_features(&self) -> &[Unsupported]
+    {
+        self.mem.unsupported()
+    }
+
+    /// Get the global checksum from the cartridge header. Together with the
+    /// title this identifies the game that is loaded.
+    pub fn checksum(&self) -> u16
+    {
+        self.mem.checksum()
+    }
+
+    /// Save the entire state of the system. The state can be restored with
+    /// `load_state` on any GameBoy running the same game, including ones
+    /// created by later versions of rustboy.
+    #[cfg(feature = "savestates")]
+    pub fn save_state(&self) -> Vec< u8 >
+    {
+        let mut state = StateWriter::new();
+        state.chunk(b"INFO", |w| {
+            w.u16(self.checksum());
+            w.u32(self.cycles);
+        });
+        state.chunk(b"CPU ", |w| self.cpu.save_state(w));
+        self.mem.save_state(&mut state);
+        if let Some(movie) = self.recording.as_ref()
+        {
+            state.chunk(b"MOVI", |w| movie.save_state(w));
+        }
+        if let Some((movie, frame)) = self.playback.as_ref()
+        {
+            let mut played = movie.clone();
+            played.truncate(*frame);
+            state.chunk(b"MOVI", |w| played.save_state(w));
+        }
+        state.finish()
+    }
+
+    /// Restore the state of the system from a savestate created by
+    /// `save_state`. States from older versions of the format are migrated
+    /// to the current one. Fails if the state is malformed or was saved by a
+    /// different game, in which case nothing is changed.
+    ///
+    /// While recording a movie the recording is rewound to the state, and
+    /// while playing one back playback moves to the state's frame. Either
+    /// way the state must have been saved during a movie too.
+    #[cfg(feature = "savestates")]
+    pub fn load_state(&mut self, data: &[u8]) -> IoResult< () >
+    {
+        let state = StateReader::parse(data)?;
+
+        let mut info = state.chunk(b"INFO")?;
+        if info.u16()? != self.checksum()
+        {
+            return Err(invalid("Savestate was saved by a different game"))
+        }
+        let cycles = info.u32()?;
+
+        // Every chunk is parsed and checked before anything is changed, so a
+        // malformed state leaves the system as it was
+        let mut played = Movie::new();
+        if self.recording.is_some() || self.playback.is_some()
+        {
+            if !state.has_chunk(b"MOVI")
+            {
+                return Err(invalid("Savestate wasn't saved during a movie"))
+            }
+            played.load_state(&mut state.chunk(b"MOVI")?)?;
+        }
+        if let Some((movie, _)) = self.playback.as_ref()
+        {
+            if played.len() >= movie.len()
+            {
+                return Err(invalid("Savestate is past the end of the movie"))
+            }
+        }
+        let mut cpu = CPU { regs: self.cpu.regs };
+        cpu.load_state(&mut state.chunk(b"CPU ")?)?;
+        self.mem.check_state(&state)?;
+
+        self.cpu = cpu;
+        self.mem.load_state(&state)?;
+        self.cycles = cycles;
+
+        let played_len = played.len();
+        if let Some(movie) = self.recording.as_mut()
+        {
+            movie.rewind_to(played);
+        }
+        if let Some((movie, frame)) = self.playback.as_mut()
+        {
+            *frame = played_len;
+            self.mem.keypad.set_state(movie.frame(*frame).unwrap_or_default(), &mut self.mem.intf);
+        }
+        Ok(())
+    }
+
+    /// Set the storage backend battery backed cartridge RAM is persisted to
+    /// and load the game's save data from it, if there is any. GameBoys
+    /// created with `new` use a FileStorage in the ROM's directory, while
+    /// those created with `from_rom` have no storage until one is set.
+    pub fn set_storage< S: StorageBackend + 'static >(&mut self, storage: S) -> IoResult< () >
+    {
+        self.set_boxed_storage(Box::new(storage))
+    }
+
+    fn set_boxed_storage(&mut self, mut storage: Box< dyn StorageBackend >) -> IoResult< () >
+    {
+        if let Some(data) = storage.load(&self.save_key())?
+        {
+            match self.mem.battery_ram().map(|ram| storage::decode_save(&data, ram.len()))
+            {
+                Some(Ok((ram, clock))) => self.load_save(ram, clock),
+                _ => self.mem.load_battery_ram(&data)
+            }
+            self.mem.mark_ram_saved();
+        }
+        self.clock_saved = self.saved_clock(storage::unix_time());
+        self.storage = Some(storage);
+        Ok(())
+    }
+
+    /// Load battery backed cartridge RAM and the clock from a save. The
+    /// clock is run forward by the wall clock time since the save was made.
+    fn load_save(&mut self, ram: &[u8], clock: Option< SavedClock >)
+    {
+        self.mem.load_battery_ram(ram);
+        if let Some(clock) = clock
+        {
+            let seconds = storage::unix_time().saturating_sub(clock.timestamp);
+            self.mem.restore_rtc(clock.regs, clock.latched, seconds);
+        }
+    }
+
+    /// Get the MBC3 clock as of the given UNIX timestamp, if the cartridge
+    /// has one
+    fn saved_clock(&self, timestamp: u64) -> Option< SavedClock >
+    {
+        self.mem.rtc_registers().map(|(regs, latched)| SavedClock { regs, latched, timestamp })
+    }
+
+    /// Write battery backed cartridge RAM to the storage backend, with the
+    /// VBA/BGB RTC footer for cartridges with a clock. Does nothing if the
+    /// cartridge has no battery, no storage has been set or nothing has
+    /// changed since the last save, which makes it cheap enough to call
+    /// every second or so as an autosave.
+    pub fn save(&mut self) -> IoResult< () >
+    {
+        if !self.has_unsaved_changes()
+        {
+            return Ok(())
+        }
+        let now = storage::unix_time();
+        let format = match self.mem.rtc_registers()
+        {
+            Some(_) => SaveFormat::Rtc { timestamp: now },
+            None => SaveFormat::Raw
+        };
+        let key = self.save_key();
+        if let (Some(data), Some(storage)) = (self.export_save(format), self.storage.as_mut())
+        {
+            storage.store(&key, &data)?;
+            self.mem.mark_ram_saved();
+            self.clock_saved = self.saved_clock(now);
+        }
+        Ok(())
+    }
+
+    /// Has battery backed cartridge RAM changed since it was last saved to
+    /// or loaded from the storage backend? The MBC3 clock counts as changed
+    /// once it drifts from the wall clock time since then, e.g. while
+    /// emulation is paused, since loading the save runs it forward by that.
+    pub fn has_unsaved_changes(&self) -> bool
+    {
+        self.mem.battery_ram_dirty() || self.clock_saved.is_some_and(|clock| {
+            let seconds = storage::unix_time().saturating_sub(clock.timestamp);
+            self.mem.rtc_drifted(&clock.regs, seconds)
+        })
+    }
+
+    /// Export battery backed cartridge RAM as a save file that other
+    /// emulators can load. Returns None if the cartridge has no battery.
+    pub fn export_save(&self, format: SaveFormat) -> Option< Vec< u8 > >
+    {
+        let ram = self.mem.battery_ram()?;
+        Some(match format
+        {
+            SaveFormat::Raw => storage::encode_save(ram, None),
+            SaveFormat::Rtc { timestamp } =>
+            {
+                let clock = self.saved_clock(timestamp)
+                    .unwrap_or(SavedClock { regs: [0; 5], latched: [0; 5], timestamp });
+                storage::encode_save(ram, Some(&clock))
+            }
+        })
+    }
+
+    /// Replace battery backed cartridge RAM with a save file from this or
+    /// another emulator, either raw or with a VBA/BGB RTC footer, or with
+    /// the cartridge RAM of a SameBoy (BESS) or BGB savestate. The MBC3
+    /// clock is restored from saves that have one and run forward by the
+    /// wall clock time since the save was made. Fails without changing
+    /// anything if the cartridge has no battery or the save is the wrong
+    /// size for it.
+    pub fn import_save(&mut self, data: &[u8]) -> IoResult< () >
+    {
+        let size = match self.mem.battery_ram() {
+            Some(ram) => ram.len(),
+            None => return Err(invalid("The cartridge has no battery backed RAM"))
+        };
+        let (ram, clock) = storage::decode_save(data, size)?;
+        self.load_save(ram, clock);
+        Ok(())
+    }
+
+    /// Save the state of the system to a numbered slot in the storage
+    /// backend, replacing the state previously in the slot. Fails if no
+    /// storage has been set.
+    #[cfg(feature = "savestates")]
+    pub fn store_state(&mut self, slot: u8) -> IoResult< () >
+    {
+        let key = self.state_key(slot);
+        let state = self.save_state();
+        match self.storage.as_mut()
+        {
+            Some(storage) => storage.store(&key, &state),
+            None => Err(invalid("No storage has been set"))
+        }
+    }
+
+    /// Load the state of the system from a numbered slot in the storage
+    /// backend. Fails if the slot is empty or no storage has been set.
+    #[cfg(feature = "savestates")]
+    pub fn load_stored_state(&mut self, slot: u8) -> IoResult< () >
+    {
+        let key = self.state_key(slot);
+        let state = match self.storage.as_mut()
+        {
+            Some(storage) => storage.load(&key)?,
+            None => return Err(invalid("No storage has been set"))
+        };
+        match state
+        {
+            Some(state) => self.load_state(&state),
+            None => Err(Error::new(ErrorKind::NotFound, format!("No savestate in slot {}", slot)))
+        }
+    }
+
+    /// The key the game's battery save is stored under
+    fn save_key(&self) -> String
+    {
+        format!("{}.sav", self.save_name)
+    }
+
+    /// The key a savestate slot is stored under
+    #[cfg(feature = "savestates")]
+    fn state_key(&self, slot: u8) -> String
+    {
+        format!("{}.ss{}", self.save_name, slot)
+    }
+
+    /// Set the emulation speed multiplier. A value of 1.0 is normal speed,
+    /// 0.5 is half speed and 2.0 is double speed. The value is clamped
+    /// between MIN_EMULATION_SPEED and MAX_EMULATION_SPEED. Since the speed
+    /// only changes how much emulated time passes per host frame, any audio
+    /// produced must be resampled by the frontend to keep its pitch.
+    pub fn set_emulation_speed(&mut self, speed: f32)
+    {
+        self.emulation_speed = if speed.is_nan() { 1.0 } else {
+            speed.clamp(MIN_EMULATION_SPEED, MAX_EMULATION_SPEED)
+        };
+    }
+
+    /// Pause emulation: `run` and `next_av_chunk` do nothing until `resume`
+    /// is called, so no time passes for the game and no audio is produced.
+    /// Everything the game can see, including its timers, runs off emulated
+    /// time, so a pause is invisible to it. `run_frame` and
+    /// `run_until_vblank` still work, for advancing frame by frame.
+    pub fn pause(&mut self)
+    {
+        self.paused = true;
+
+        // Samples nobody collected would otherwise be heard on resume
+        self.mem.spu.stop_collecting();
+    }
+
+    /// Carry on from where `pause` left off
+    pub fn resume(&mut self)
+    {
+        self.paused = false;
+    }
+
+    /// Is emulation paused?
+    pub fn is_paused(&self) -> bool
+    {
+        self.paused
+    }
+
+    /// Run the CPU the given number of times faster than stock, like
+    /// overclocking hardware mods do, which cuts down on slowdown in games
+    /// that drop frames when busy. Only the CPU is sped up: the GPU, sound,
+    /// timer and serial port keep their stock timing, so games still run at
+    /// the normal frame rate. The factor is clamped between 1 (stock) and
+    /// MAX_OVERCLOCK. Games that rely on exact CPU timing may break.
+    pub fn set_overclock(&mut self, factor: u32)
+    {
+        self.overclock = factor.clamp(1, MAX_OVERCLOCK);
+        self.overclock_ticks = 0;
+    }
+
+    /// Get how many times faster than stock the CPU runs
+    pub fn overclock(&self) -> u32
+    {
+        self.overclock
+    }
+
+    /// Get the target system that is running
+    pub fn target(&self) -> Target
+    {
+        self.target
+    }
+
+    /// Get the hardware revision of the target system that is running
+    pub fn revision(&self) -> Revision
+    {
+        self.revision
+    }
+
+    /// Get the clock rate of the target system in Hz
+    pub fn clock_rate(&self) -> u32
+    {
+        match self.target
+        {
+            Target::SuperGameBoy => SGB_CLOCK_RATE,
+            _ => DMG_CLOCK_RATE
+        }
+    }
+
+    /// Get the number of frames the target system draws per second
+    pub fn frame_rate(&self) -> f64
+    {
+        self.clock_rate() as f64 / TICKS_PER_FRAME as f64
+    }
+
+    /// Get the current emulation speed multiplier
+    pub fn emulation_speed(&self) -> f32
+    {
+        self.emulation_speed
+    }
+
+    /// Plug one end of a link cable into the serial port, replacing any
+    /// cable already plugged in. See `LinkPort::pair`.
+    pub fn connect_link(&mut self, port: LinkPort)
+    {
+        self.mem.serial.set_link(Some(port));
+    }
+
+    /// Unplug the link cable from the serial port and return it
+    pub fn disconnect_link(&mut self) -> Option< LinkPort >
+    {
+        self.mem.serial.set_link(None)
+    }
+
+    /// Get the bytes the game has sent over the serial port. Test ROMs
+    /// commonly report their results this way. Only the last
+    /// MAX_SERIAL_OUTPUT bytes are kept.
+    pub fn serial_output(&self) -> &[u8]
+    {
+        self.mem.serial.output()
+    }
+
+    /// Take the bytes the game has sent over the serial port since they were
+    /// last taken, for tools that read the output as it arrives
+    pub fn take_serial_output(&mut self) -> Vec< u8 >
+    {
+        self.mem.serial.take_output()
+    }
+
+    /// Get a snapshot of the current state of the system
+    pub fn status(&self) -> EmuStatus
+    {
+        EmuStatus {
+            speed: self.mem.speed,
+            halted: self.cpu.regs.halt != 0,
+            stopped: self.cpu.regs.stop != 0,
+            lcd_on: self.mem.gpu.lcd_enabled,
+            frame_count: self.frame_count(),
+            cycle_count: self.cycle_count()
+        }
+    }
+
+    /// Get the audio and video throughput since power on or the last
+    /// `reset_av_stats`. Comparing what was produced against what the
+    /// frontend reported presenting and playing shows whether emulation is
+    /// running ahead of or behind the display and audio device.
+    pub fn av_stats(&self) -> AvStats
+    {
+        AvStats {
+            samples_produced: self.mem.spu.produced() - self.av_sample_base,
+            ..self.av_stats
+        }
+    }
+
+    /// Tell the GameBoy the frontend has shown a frame
+    pub fn report_frame_presented(&mut self)
+    {
+        self.av_stats.frames_presented += 1;
+    }
+
+    /// Tell the GameBoy the audio device has played the given number of
+    /// stereo samples
+    pub fn report_samples_played(&mut self, samples: u64)
+    {
+        self.av_stats.samples_played += samples;
+    }
+
+    /// Zero every A/V counter, e.g. after a pause or seek so the stats only
+    /// describe the run since
+    pub fn reset_av_stats(&mut self)
+    {
+        self.av_stats = AvStats::default();
+        self.av_sample_base = self.mem.spu.produced();
+    }
+
+    /// Get the number of frames the GPU has completed since power on. A frame
+    /// is counted every time VBlank is entered.
+    pub fn frame_count(&self) -> u64
+    {
+        self.mem.gpu.frames()
+    }
+
+    /// Get the number of ticks emulated since power on. Ticks are counted at
+    /// the 4.19MHz clock rate, so in CGB double speed mode each CPU cycle
+    /// counts as half as many ticks.
+    pub fn cycle_count(&self) -> u64
+    {
+        self.mem.cycles
+    }
+
+    /// Get a copy of the current CPU registers
+    pub fn registers(&self) -> Registers
+    {
+        self.cpu.regs
+    }
+
+    /// Get the current banking state of the cartridge mapper
+    pub fn mapper_state(&self) -> MapperState
+    {
+        self.mem.mapper_state()
+    }
+
+    /// Get the number of ROM banks on the cartridge
+    pub fn rom_bank_count(&self) -> usize
+    {
+        self.mem.rom_bank_count()
+    }
+
+    /// Resolve a CPU address to the ROM bank currently mapped there, for
+    /// showing addresses as `bank:address`
+    pub fn bank_address(&self, addr: u16) -> BankAddress
+    {
+        self.mem.bank_address(addr)
+    }
+
+    /// Stop emulation when the CPU is about to execute the given address with
+    /// the given ROM bank mapped in. `run`, `run_frame` and `next_av_chunk`
+    /// return early when a breakpoint is reached, see `breakpoint_hit`.
+    #[cfg(feature = "debugger")]
+    pub fn add_breakpoint(&mut self, addr: BankAddress)
+    {
+        if !self.breakpoints.contains(&addr)
+        {
+            self.breakpoints.push(addr);
+        }
+    }
+
+    /// Remove a breakpoint added with `add_breakpoint`
+    #[cfg(feature = "debugger")]
+    pub fn remove_breakpoint(&mut self, addr: BankAddress)
+    {
+        self.breakpoints.retain(|&b| b != addr);
+    }
+
+    /// Remove every breakpoint
+    #[cfg(feature = "debugger")]
+    pub fn clear_breakpoints(&mut self)
+    {
+        self.breakpoints.clear();
+    }
+
+    /// Get the active breakpoints
+    #[cfg(feature = "debugger")]
+    pub fn breakpoints(&self) -> &[BankAddress]
+    {
+        &self.breakpoints
+    }
+
+    /// Get the breakpoint the last call to `run`, `run_frame` or
+    /// `next_av_chunk` stopped at, if it stopped at one
+    #[cfg(feature = "debugger")]
+    pub fn breakpoint_hit(&self) -> Option< BankAddress >
+    {
+        match self.interrupted
+        {
+            Some(RunExit::Breakpoint(addr)) => Some(addr),
+            _ => None
+        }
+    }
+
+    /// Enable or disable the watchdog, which stops emulation with
+    /// `RunExit::Hung` when the CPU spends a couple of seconds looping over
+    /// a few bytes of code with the LCD off, as crashed games tend to do
+    pub fn set_watchdog(&mut self, enabled: bool)
+    {
+        self.watchdog = if enabled { Some(Watchdog::new()) } else { None };
+    }
+
+    /// Draw lines on a separate thread while the CPU carries on, for batch
+    /// jobs like dumping movies to video on hosts with cores to spare. Off
+    /// by default. The output is identical, but the image data is only
+    /// updated when a frame completes, so it holds the previous frame in
+    /// the middle of one, and the scanline callback is invoked for every
+    /// line of a frame at once when it completes.
+    pub fn set_render_worker(&mut self, enabled: bool)
+    {
+        self.mem.gpu.set_render_worker(enabled);
+    }
+
+    /// Get the mode the GPU is currently in
+    pub fn ppu_mode(&self) -> PpuMode
+    {
+        self.mem.gpu.mode()
+    }
+
+    /// Get the line the GPU is currently drawing (LY)
+    pub fn ly(&self) -> u8
+    {
+        self.mem.gpu.ly()
+    }
+
+    /// Get the BG scroll position (SCX, SCY)
+    pub fn scroll(&self) -> (u8, u8)
+    {
+        self.mem.gpu.scroll()
+    }
+
+    /// Get the CGB attributes of every tile in the current BG tile map, as
+    /// 32 rows of 32 tiles. Returns None when the game isn't running in CGB
+    /// mode.
+    #[cfg(feature = "debugger")]
+    pub fn bg_attribute_map(&self) -> Option< Vec< BgAttributes > >
+    {
+        self.mem.gpu.bg_attribute_map()
+    }
+
+    /// Draw every tile in a VRAM bank (0 or 1) as TILE_VIEW_WIDTH x
+    /// TILE_VIEW_HEIGHT RGBA image data for a tile viewer. Bank 1 is only
+    /// used in CGB mode.
+    #[cfg(feature = "debugger")]
+    pub fn tile_data_image(&self, bank: usize) -> Vec< u8 >
+    {
+        self.mem.gpu.tile_data_image(bank & 1)
+    }
+
+    /// Draw the whole current BG tile map as MAP_VIEW_SIZE x MAP_VIEW_SIZE
+    /// RGBA image data for a map viewer. The visible part of the map starts
+    /// at `scroll()` and wraps around.
+    #[cfg(feature = "debugger")]
+    pub fn bg_map_image(&self) -> Vec< u8 >
+    {
+        self.mem.gpu.bg_map_image()
+    }
+
+    /// Draw every sprite in OAM as OAM_VIEW_WIDTH x OAM_VIEW_HEIGHT RGBA
+    /// image data for an OAM viewer, in rows of 10 sprites. Transparent
+    /// pixels have an alpha of 0.
+    #[cfg(feature = "debugger")]
+    pub fn oam_image(&self) -> Vec< u8 >
+    {
+        self.mem.gpu.oam_image()
+    }
+
+    /// Read a byte of memory as the CPU would see it right now, for memory
+    /// viewers. Reading has no effect on the system.
+    #[cfg(feature = "debugger")]
+    pub fn peek(&self, addr: u16) -> u8
+    {
+        self.mem.read_byte(addr)
+    }
+
+    /// Disassemble the instruction at the given address. Returns the
+    /// instruction, e.g. "LD A, $42", and its length in bytes.
+    #[cfg(feature = "debugger")]
+    pub fn disassemble(&self, addr: u16) -> (String, u16)
+    {
+        cpu::disasm::disassemble(|addr| self.mem.read_byte(addr), addr)
+    }
+
+    /// Force the GPU into the given mode. Meant for debugging and testing
+    /// timing sensitive code; no interrupts are requested by the switch.
+    #[cfg(feature = "debugger")]
+    pub fn force_ppu_mode(&mut self, mode: PpuMode)
+    {
+        self.mem.gpu.force_mode(mode);
+    }
+
+    /// Force the GPU to the start of the given line. Meant for debugging and
+    /// testing timing sensitive code; no interrupts are requested.
+    #[cfg(feature = "debugger")]
+    pub fn force_ly(&mut self, ly: u8)
+    {
+        self.mem.gpu.force_ly(ly);
+    }
+
+    /// Register a callback that is invoked every time the GPU finishes
+    /// rendering a line, with the line number and the RGBA image data of that
+    /// line. Useful for integrations that need to observe raster effects such
+    /// as mid-frame scroll or palette changes.
+    pub fn set_scanline_callback< F >(&mut self, callback: F)
+        where F: FnMut(u8, &[u8]) + Send + 'static
+    {
+        self.mem.gpu.set_scanline_callback(Some(Box::new(callback)));
+    }
+
+    /// Remove a previously registered scanline callback
+    pub fn clear_scanline_callback(&mut self)
+    {
+        self.mem.gpu.set_scanline_callback(None);
+    }
+
+    /// Add a sink that is passed every frame the GPU completes, as it enters
+    /// VBlank. Any number of sinks can be added and are called in order.
+    pub fn add_frame_sink< S >(&mut self, sink: S)
+        where S: FrameSink + Send + 'static
+    {
+        self.mem.gpu.add_frame_sink(Box::new(sink));
+    }
+
+    /// Remove every frame sink
+    pub fn clear_frame_sinks(&mut self)
+    {
+        self.mem.gpu.take_frame_sinks();
+    }
+
+    /// Register a callback that is passed audio as it is produced, in
+    /// batches of interleaved left and right samples at SAMPLE_RATE Hz. For
+    /// frontends that push audio to a device rather than pulling it with
+    /// `next_av_chunk`, which still returns every sample. Nothing is
+    /// produced without the audio feature.
+    pub fn set_audio_callback< F >(&mut self, callback: F)
+        where F: FnMut(&[i16]) + Send + 'static
+    {
+        self.mem.spu.set_callback(Some(Box::new(callback)));
+    }
+
+    /// Remove a previously registered audio callback
+    pub fn clear_audio_callback(&mut self)
+    {
+        self.mem.spu.set_callback(None);
+    }
+
+    /// Get the image data currently being drawn by GPU. The image is
+    /// DISPLAY_WIDTH x DISPLAY_HEIGHT tightly packed RGBA pixels, top row
+    /// first, so it can be handed to a texture upload or encoder as is.
+    pub fn get_image_data(&self) -> &[u8]
+    {
+        &*self.mem.gpu.image_data
+    }
+
+    /// Get where every pixel of the image data came from: the layer, its
+    /// color index before the palette was applied and the palette number.
+    /// In the same order as `get_image_data` and updated along with it, so
+    /// frontends can recolor the screen themselves. Blank after loading a
+    /// savestate until each line is drawn again.
+    pub fn pixel_sources(&self) -> &[PixelSource]
+    {
+        &self.mem.gpu.pixel_sources[..]
+    }
+
+    /// Show or hide the BG, window or sprites, for debugging. Only what is
+    /// drawn changes: the game runs exactly the same. A hidden BG leaves the
+    /// screen white under the other layers. Takes effect from the next line.
+    #[cfg(feature = "debugger")]
+    pub fn set_layer_shown(&mut self, layer: PixelLayer, shown: bool)
+    {
+        self.mem.gpu.set_layer_shown(layer, shown);
+    }
+
+    /// Is a layer drawn? Every layer is unless hidden with `set_layer_shown`.
+    #[cfg(feature = "debugger")]
+    pub fn layer_shown(&self, layer: PixelLayer) -> bool
+    {
+        self.mem.gpu.layer_shown(layer)
+    }
+
+    /// Get the SGB border as BORDER_WIDTH x BORDER_HEIGHT RGBA image data.
+    /// The game screen belongs in the middle of the border. Returns None
+    /// unless the game has sent a border to the SGB, or if the border is
+    /// turned off.
+    pub fn get_border_image(&self) -> Option< &[u8] >
+    {
+        if !self.sgb_border { return None }
+        self.mem.gpu.border_image()
+    }
+
+    /// Set whether the SGB border is shown. On by default. The game still
+    /// sends the border when it is off, so it comes back when turned on.
+    pub fn set_sgb_border(&mut self, enabled: bool)
+    {
+        self.sgb_border = enabled;
+    }
+
+    /// Is the SGB border shown?
+    pub fn sgb_border(&self) -> bool
+    {
+        self.sgb_border
+    }
+
+    /// Run the given boot ROM before the cartridge, which restarts the
+    /// system. DMG and SGB boot ROMs are 256 bytes and CGB boot ROMs are
+    /// 2304 bytes, with the cartridge header showing through at 0x0100 thru
+    /// 0x01FF. An empty boot ROM goes back to starting in the state the boot
+    /// ROM leaves the system in. The CGB boot ROM's colorization of DMG games
+    /// isn't applied, since DMG games always run in DMG mode.
+    pub fn set_boot_rom(&mut self, boot_rom: Vec< u8 >) -> IoResult< () >
+    {
+        if !boot_rom.is_empty() && boot_rom.len() != self.target.boot_rom_size()
+        {
+            return Err(invalid(&format!("{:?} boot ROMs are {} bytes, not {}",
+                self.target, self.target.boot_rom_size(), boot_rom.len())))
+        }
+        self.mem.set_boot_rom(boot_rom);
+        self.restart_as_revision(self.revision);
+        Ok(())
+    }
+
+    /// Set how closely the hardware's restrictions on memory access are
+    /// followed, see `Accuracy`. Defaults to `Accuracy::Relaxed`.
+    pub fn set_accuracy(&mut self, accuracy: Accuracy)
+    {
+        self.mem.set_accuracy(accuracy);
+    }
+
+    /// Get how closely the hardware's restrictions on memory access are
+    /// followed
+    pub fn accuracy(&self) -> Accuracy
+    {
+        self.mem.accuracy()
+    }
+
+    /// Run the boot ROM straight through when the system is next restarted,
+    /// e.g. by `set_boot_rom` or `restart_as`, instead of showing the logo
+    /// scroll. Nothing is drawn until it hands over to the cartridge, which
+    /// happens before the restart returns. The boot ROM still checks the
+    /// cartridge's logo and locks up if it is wrong. Off by default.
+    pub fn set_fast_boot(&mut self, enabled: bool)
+    {
+        self.fast_boot = enabled;
+    }
+
+    /// Is the boot ROM run straight through?
+    pub fn fast_boot(&self) -> bool
+    {
+        self.fast_boot
+    }
+
+    /// Is the boot ROM still running? False once it has handed over to the
+    /// cartridge, or if there is no boot ROM.
+    pub fn in_boot_rom(&self) -> bool
+    {
+        self.mem.boot_rom_mapped()
+    }
+
+    /// Re-initialize WRAM and HRAM with the given fill pattern. Real hardware
+    /// powers up with semi-random RAM contents and some games use this as a
+    /// source of randomness. This is meant to be called right after the
+    /// GameBoy is created since it overwrites anything the game has stored.
+    pub fn set_ram_fill(&mut self, fill: RamFill)
+    {
+        self.mem.fill_ram(fill);
+    }
+
+    /// Get a hash of the image data currently being drawn by the GPU. The
+    /// hash is stable across platforms and builds so it can be used to check
+    /// that two runs produced the same frame.
+    pub fn frame_hash(&self) -> u64
+    {
+        // 64-bit FNV-1a
+        self.get_image_data().iter().fold(0xCBF2_9CE4_8422_2325, |hash, &b| {
+            (hash ^ b as u64).wrapping_mul(0x0000_0100_0000_01B3)
+        })
+    }
+
+    /// Register that a key has been pressed down
+    pub fn key_down(&mut self, key: Button)
+    {
+        self.mem.keypad.key_down(key, &mut self.mem.intf);
+    }
+
+    /// Register that a key has been released
+    pub fn key_up(&mut self, key: Button)
+    {
+        self.mem.keypad.key_up(key);
+    }
+
+    /// Set the state of every button at once. The new state replaces the
+    /// previous one entirely and is seen by the game from the next call to
+    /// `run()` onwards.
+    pub fn set_inputs(&mut self, state: ButtonState)
+    {
+        self.mem.keypad.set_state(state, &mut self.mem.intf);
+    }
+
+    /// Get the state of every button as currently seen by the game
+    pub fn inputs(&self) -> ButtonState
+    {
+        self.mem.keypad.state()
+    }
+
+    /// Set what the game sees when Left+Right or Up+Down are held at once.
+    /// Defaults to `OppositeDirections::Block`, which reports neither
+    /// direction of the pair like a real D-pad.
+    pub fn set_opposite_directions(&mut self, policy: OppositeDirections)
+    {
+        self.mem.keypad.set_opposite_directions(policy);
+    }
+
+    /// Register a callback that is invoked every time the game reads the
+    /// keypad register (0xFF00). This samples input at the last possible
+    /// moment instead of once per host frame. While a provider is set the
+    /// button state passed to key_down/key_up/set_inputs is ignored when the
+    /// register is read.
+    pub fn set_input_provider< F >(&mut self, provider: F)
+        where F: Fn() -> ButtonState + Send + 'static
+    {
+        self.mem.keypad.set_provider(Some(Box::new(provider)));
+    }
+
+    /// Remove a previously registered input provider
+    pub fn clear_input_provider(&mut self)
+    {
+        self.mem.keypad.set_provider(None);
+    }
+
+    /// Get the current FPS the GameBoy is running at
+    pub fn fps(&mut self) -> u32
+    {
+        ::std::mem::replace(&mut self.fps, 0)
+    }
+}
+
+/// Create an error for a malformed savestate, ROM or other input
+pub(crate) fn invalid(msg: &str) -> Error
+{
+    Error::new(ErrorKind::InvalidData, msg)
+}
@@ -0,0 +1,187 @@
+use std::collections::VecDeque;
+use std::io::{ Read, Write };
+use std::io::Result as IoResult;
+use std::net::{ Shutdown, TcpStream, ToSocketAddrs };
+use std::sync::{ Arc, Mutex };
+use std::thread;
+
+/// Message sent over a TCP link cable when a side starts waiting for a
+/// transfer, followed by its byte and the bits shifted so far
+const MSG_LISTEN: u8 = 0x01;
+
+/// Message sent over a TCP link cable when a side stops waiting
+const MSG_IDLE: u8 = 0x02;
+
+/// Message sent over a TCP link cable for a clock edge, followed by the
+/// bit shifted out
+const MSG_EDGE: u8 = 0x03;
+
+/// The state of the cable shared by both ends, indexed by side
+#[derive(Debug, Default)]
+struct Cable
+{
+    /// The byte each side is shifting out while it waits for the other
+    /// side to clock a transfer, as of the clock edges sent so far, and
+    /// the number of bits shifted
+    waiting: [Option< (u8, u8) >; 2],
+
+    /// The bits each side received from the other side's clock edges that
+    /// it hasn't shifted in yet, oldest first
+    incoming: [VecDeque< u8 >; 2]
+}
+
+impl Cable
+{
+    /// Set whether a side is waiting for a transfer, with the byte and the
+    /// number of bits it has shifted so far
+    fn listen(&mut self, side: usize, waiting: Option< (u8, u8) >)
+    {
+        self.waiting[side] = waiting;
+        self.incoming[side].clear();
+    }
+
+    /// Send a clock edge from a side shifting out `bit` and get the bit the
+    /// other side shifts out, or 1 if it isn't waiting for a transfer. The
+    /// other side stops waiting after 8 edges.
+    fn edge(&mut self, side: usize, bit: u8) -> u8
+    {
+        let other = 1 - side;
+        match self.waiting[other]
+        {
+            Some((byte, bits)) =>
+            {
+                self.waiting[other] = if bits < 7 { Some((byte << 1 | bit, bits + 1)) } else { None };
+                self.incoming[other].push_back(bit);
+                byte >> 7
+            },
+            None => 1
+        }
+    }
+}
+
+/// One end of a link cable connecting the serial ports of two GameBoys,
+/// either in the same process or over TCP. Whichever side starts a
+/// transfer with its internal clock shifts a bit to and from the other
+/// side on every clock edge, provided the other side is waiting for a
+/// transfer with its external clock. Otherwise 1's are shifted in, as if
+/// nothing were connected.
+///
+/// The waiting side shifts the bits in when it next steps, so both
+/// GameBoys of an in-process cable can be run one after another on the
+/// same thread. Over TCP each end keeps its own copy of the cable and
+/// tells the other end about every change, so a partner that hasn't heard
+/// about a transfer yet receives 1's.
+#[derive(Debug)]
+pub struct LinkPort
+{
+    cable: Arc< Mutex< Cable > >,
+    side: usize,
+
+    /// The connection to the other end of a TCP link cable
+    remote: Option< TcpStream >
+}
+
+impl LinkPort
+{
+    /// Create both ends of a new link cable
+    pub fn pair() -> (LinkPort, LinkPort)
+    {
+        let cable = Arc::new(Mutex::new(Cable::default()));
+        (
+            LinkPort { cable: cable.clone(), side: 0, remote: None },
+            LinkPort { cable, side: 1, remote: None }
+        )
+    }
+
+    /// Connect to a link cable at the given address, which another
+    /// emulator accepted with `from_tcp`
+    pub fn connect_tcp< A: ToSocketAddrs >(addr: A) -> IoResult< LinkPort >
+    {
+        LinkPort::from_tcp(TcpStream::connect(addr)?)
+    }
+
+    /// Use a TCP connection to another emulator as a link cable, e.g. one
+    /// accepted from a TcpListener. The other end is unplugged when the
+    /// connection closes.
+    pub fn from_tcp(stream: TcpStream) -> IoResult< LinkPort >
+    {
+        stream.set_nodelay(true)?;
+        let cable = Arc::new(Mutex::new(Cable::default()));
+        let mut reader = stream.try_clone()?;
+        let remote_cable = cable.clone();
+        thread::spawn(move || {
+            let mut msg = [0; 3];
+            while reader.read_exact(&mut msg[..1]).is_ok()
+            {
+                let operands = match msg[0] { MSG_LISTEN => 2, MSG_EDGE => 1, _ => 0 };
+                if reader.read_exact(&mut msg[1..1 + operands]).is_err() { break }
+
+                let mut cable = remote_cable.lock().unwrap();
+                match msg[0]
+                {
+                    MSG_LISTEN => cable.listen(1, Some((msg[1], msg[2]))),
+                    MSG_EDGE => { cable.edge(1, msg[1]); },
+                    _ => cable.listen(1, None)
+                }
+            }
+            remote_cable.lock().unwrap().listen(1, None);
+        });
+
+        Ok(LinkPort { cable, side: 0, remote: Some(stream) })
+    }
+
+    /// Set the byte this side shifts out and the bits shifted so far when
+    /// the other side clocks a transfer, or None when this side isn't
+    /// waiting for one
+    pub(crate) fn listen(&self, waiting: Option< (u8, u8) >)
+    {
+        self.cable.lock().unwrap().listen(self.side, waiting);
+        match waiting
+        {
+            Some((byte, bits)) => self.send(&[MSG_LISTEN, byte, bits]),
+            None => self.send(&[MSG_IDLE])
+        }
+    }
+
+    /// Take the next bit the other side shifted out with its clock, if any
+    pub(crate) fn receive(&self) -> Option< u8 >
+    {
+        self.cable.lock().unwrap().incoming[self.side].pop_front()
+    }
+
+    /// Shift a bit out with this side's clock and get the bit shifted in
+    pub(crate) fn shift(&self, bit: u8) -> u8
+    {
+        let mut cable = self.cable.lock().unwrap();
+        let received = cable.edge(self.side, bit);
+        if self.remote.is_some()
+        {
+            // The other end shifts the bits in from its own copy
+            cable.incoming[1 - self.side].clear();
+        }
+        self.send(&[MSG_EDGE, bit]);
+        received
+    }
+
+    /// Tell the other end of a TCP link cable about a change. A connection
+    /// that fails is left for the reader to notice it closed.
+    fn send(&self, msg: &[u8])
+    {
+        if let Some(mut stream) = self.remote.as_ref()
+        {
+            let _ = stream.write_all(msg);
+        }
+    }
+}
+
+impl Drop for LinkPort
+{
+    /// Close a TCP link cable, which unplugs it at the other end too
+    fn drop(&mut self)
+    {
+        if let Some(ref stream) = self.remote
+        {
+            let _ = stream.shutdown(Shutdown::Both);
+        }
+    }
+}
@@ -0,0 +1,171 @@
+/*
+    Addresses of the regions in the GameBoy memory map. Each region is given
+    as a pair of inclusive _START and _END constants so they can be used
+    directly in match patterns, e.g. map::VRAM_START..=map::VRAM_END.
+*/
+
+/// 16K ROM bank 0, fixed for most mappers
+pub const ROM_START: u16 = 0x0000;
+pub const ROM_BANK0_END: u16 = 0x3FFF;
+
+/// 16K switchable ROM bank
+pub const ROMX_START: u16 = 0x4000;
+pub const ROM_END: u16 = 0x7FFF;
+
+/// 8K video RAM, banked on CGB
+pub const VRAM_START: u16 = 0x8000;
+pub const VRAM_END: u16 = 0x9FFF;
+
+/// 8K switchable external cartridge RAM
+pub const EXT_RAM_START: u16 = 0xA000;
+pub const EXT_RAM_END: u16 = 0xBFFF;
+
+/// 4K WRAM bank 0
+pub const WRAM_START: u16 = 0xC000;
+pub const WRAM_BANK0_END: u16 = 0xCFFF;
+
+/// 4K WRAM bank 1, switchable between banks 1 thru 7 on CGB
+pub const WRAMX_START: u16 = 0xD000;
+pub const WRAM_END: u16 = 0xDFFF;
+
+/// Mirror of 0xC000 thru 0xDDFF
+pub const ECHO_START: u16 = 0xE000;
+pub const ECHO_BANK0_END: u16 = 0xEFFF;
+pub const ECHOX_START: u16 = 0xF000;
+pub const ECHO_END: u16 = 0xFDFF;
+
+/// Sprite attribute table
+pub const OAM_START: u16 = 0xFE00;
+pub const OAM_END: u16 = 0xFE9F;
+
+/// Not usable, reads back as 0xFF
+pub const UNUSABLE_START: u16 = 0xFEA0;
+pub const UNUSABLE_END: u16 = 0xFEFF;
+
+/// I/O registers
+pub const IO_START: u16 = 0xFF00;
+pub const IO_END: u16 = 0xFF7F;
+
+/// High RAM
+pub const HRAM_START: u16 = 0xFF80;
+pub const HRAM_END: u16 = 0xFFFE;
+
+/// Interrupt enable register
+pub const IE: u16 = 0xFFFF;
+
+/// Is the address between start and end, inclusive?
+pub fn in_range(addr: u16, start: u16, end: u16) -> bool
+{
+    start <= addr && addr <= end
+}
+
+/// A region of the memory map
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Region
+{
+    Rom0,
+    RomX,
+    Vram,
+    ExtRam,
+    Wram0,
+    WramX,
+    Echo,
+    Oam,
+    Unusable,
+    Io,
+    Hram,
+    Ie
+}
+
+impl Region
+{
+    /// Every region, in address order
+    pub const ALL: [Region; 12] = [
+        Region::Rom0, Region::RomX, Region::Vram, Region::ExtRam,
+        Region::Wram0, Region::WramX, Region::Echo, Region::Oam,
+        Region::Unusable, Region::Io, Region::Hram, Region::Ie
+    ];
+
+    /// Get the region an address falls in
+    pub fn of(addr: u16) -> Region
+    {
+        match addr
+        {
+            ROM_START..=ROM_BANK0_END => Region::Rom0,
+            ROMX_START..=ROM_END => Region::RomX,
+            VRAM_START..=VRAM_END => Region::Vram,
+            EXT_RAM_START..=EXT_RAM_END => Region::ExtRam,
+            WRAM_START..=WRAM_BANK0_END => Region::Wram0,
+            WRAMX_START..=WRAM_END => Region::WramX,
+            ECHO_START..=ECHO_END => Region::Echo,
+            OAM_START..=OAM_END => Region::Oam,
+            UNUSABLE_START..=UNUSABLE_END => Region::Unusable,
+            IO_START..=IO_END => Region::Io,
+            HRAM_START..=HRAM_END => Region::Hram,
+            IE => Region::Ie
+        }
+    }
+
+    /// Get the first address of the region
+    pub fn start(self) -> u16
+    {
+        self.bounds().0
+    }
+
+    /// Get the last address of the region
+    pub fn end(self) -> u16
+    {
+        self.bounds().1
+    }
+
+    /// Get the size of the region in bytes
+    pub fn size(self) -> usize
+    {
+        (self.end() - self.start()) as usize + 1
+    }
+
+    /// Does the region contain the address?
+    pub fn contains(self, addr: u16) -> bool
+    {
+        in_range(addr, self.start(), self.end())
+    }
+
+    /// Get a short name for the region, e.g. for labelling a memory viewer
+    pub fn name(self) -> &'static str
+    {
+        match self
+        {
+            Region::Rom0 => "ROM0",
+            Region::RomX => "ROMX",
+            Region::Vram => "VRAM",
+            Region::ExtRam => "SRAM",
+            Region::Wram0 => "WRAM0",
+            Region::WramX => "WRAMX",
+            Region::Echo => "ECHO",
+            Region::Oam => "OAM",
+            Region::Unusable => "----",
+            Region::Io => "IO",
+            Region::Hram => "HRAM",
+            Region::Ie => "IE"
+        }
+    }
+
+    fn bounds(self) -> (u16, u16)
+    {
+        match self
+        {
+            Region::Rom0 => (ROM_START, ROM_BANK0_END),
+            Region::RomX => (ROMX_START, ROM_END),
+            Region::Vram => (VRAM_START, VRAM_END),
+            Region::ExtRam => (EXT_RAM_START, EXT_RAM_END),
+            Region::Wram0 => (WRAM_START, WRAM_BANK0_END),
+            Region::WramX => (WRAMX_START, WRAM_END),
+            Region::Echo => (ECHO_START, ECHO_END),
+            Region::Oam => (OAM_START, OAM_END),
+            Region::Unusable => (UNUSABLE_START, UNUSABLE_END),
+            Region::Io => (IO_START, IO_END),
+            Region::Hram => (HRAM_START, HRAM_END),
+            Region::Ie => (IE, IE)
+        }
+    }
+}
@@ -0,0 +1,271 @@
+/*
+    MBC6 Memory Map:
+    -----------------------------------------------
+    $0000-$3FFF: 16KB ROM bank 00
+    $4000-$5FFF: 8KB ROM/Flash bank A
+    $6000-$7FFF: 8KB ROM/Flash bank B
+    $A000-$AFFF: 4KB RAM bank A
+    $B000-$BFFF: 4KB RAM bank B
+    -----------------------------------------------
+    Registers:
+    $0000-$03FF: RAM enable (0x0A enables)
+    $0400-$07FF: RAM bank A number
+    $0800-$0BFF: RAM bank B number
+    $0C00-$0FFF: Flash enable (bit 0)
+    $1000:       Flash write enable (bit 0)
+    $2000-$27FF: ROM/Flash bank A number
+    $2800-$2FFF: ROM/Flash bank A select (0x08 selects flash)
+    $3000-$37FF: ROM/Flash bank B number
+    $3800-$3FFF: ROM/Flash bank B select (0x08 selects flash)
+    -----------------------------------------------
+    Used by Net de Get: Minigame @ 100. The flash chip is a 1MB Macronix
+    MX29F008 programmed with the usual JEDEC command sequences.
+    https://gbdev.io/pandocs/MBC6.html
+*/
+
+#[cfg(feature = "savestates")]
+use crate::state::{ Reader, Writer };
+#[cfg(feature = "savestates")]
+use std::io::Result as IoResult;
+
+/// Size of the cartridge SRAM
+pub const SRAM_SIZE: usize = 32 << 10;
+
+/// Size of the flash chip
+pub const FLASH_SIZE: usize = 1 << 20;
+
+/// Size of a flash sector cleared by a sector erase command
+const SECTOR_SIZE: usize = 128 << 10;
+
+/// Flash ID returned in ID mode: Macronix, MX29F008
+const FLASH_ID: [u8; 2] = [0xC2, 0x81];
+
+/// Progress through a flash command sequence
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FlashState
+{
+    Read,
+    Unlock1,
+    Unlock2,
+    Program,
+    EraseUnlock0,
+    EraseUnlock1,
+    EraseUnlock2,
+    Id
+}
+
+/// The state of the MBC6 registers and flash chip. Cartridge RAM is laid out
+/// as SRAM_SIZE bytes of SRAM followed by FLASH_SIZE bytes of flash.
+pub struct Mbc6
+{
+    ram_enabled: bool,
+    ram_bank: [u8; 2],
+    rom_bank: [u8; 2],
+    flash_selected: [bool; 2],
+    flash_enabled: bool,
+    flash_write_enabled: bool,
+    flash_state: FlashState
+}
+
+impl Mbc6
+{
+    pub fn new() -> Self
+    {
+        Mbc6 {
+            ram_enabled: false,
+            ram_bank: [0; 2],
+            rom_bank: [0; 2],
+            flash_selected: [false; 2],
+            flash_enabled: false,
+            flash_write_enabled: false,
+            flash_state: FlashState::Read
+        }
+    }
+
+    /// Create the contents of cartridge RAM for a fresh cartridge
+    pub fn new_ram() -> Vec< u8 >
+    {
+        let mut ram = vec![0; SRAM_SIZE + FLASH_SIZE];
+        for b in ram[SRAM_SIZE..].iter_mut()
+        {
+            *b = 0xFF;
+        }
+        ram
+    }
+
+    /// Get the ROM and RAM banks mapped into the first window of each area.
+    /// ROM banks are 8KB and RAM banks are 4KB.
+    pub fn banks(&self) -> (u16, u8)
+    {
+        (self.rom_bank[0] as u16, self.ram_bank[0])
+    }
+
+    /// Get the 8KB ROM bank mapped into one of the two windows at 0x4000
+    /// and 0x6000
+    pub fn rom_bank(&self, window: usize) -> u16
+    {
+        self.rom_bank[window] as u16
+    }
+
+    /// Is cartridge RAM enabled?
+    pub fn ram_enabled(&self) -> bool
+    {
+        self.ram_enabled
+    }
+
+    /// Read from one of the switchable ROM/Flash banks (0x4000 - 0x7FFF)
+    pub fn read_rom(&self, rom: &[u8], ram: &[u8], addr: u16) -> u8
+    {
+        let window = ((addr >> 13) & 1) as usize;
+        if self.flash_selected[window]
+        {
+            if !self.flash_enabled { return 0xFF }
+            if self.flash_state == FlashState::Id
+            {
+                return FLASH_ID[(addr & 1) as usize]
+            }
+            ram[SRAM_SIZE + self.flash_addr(window, addr)]
+        }
+        else
+        {
+            let i = ((self.rom_bank[window] as usize) << 13) | (addr & 0x1FFF) as usize;
+            rom[i % rom.len()]
+        }
+    }
+
+    /// Read from one of the switchable RAM banks (0xA000 - 0xBFFF)
+    pub fn read_ram(&self, ram: &[u8], addr: u16) -> u8
+    {
+        if !self.ram_enabled { return 0xFF }
+        ram[self.ram_addr(addr)]
+    }
+
+    /// Write to one of the MBC6 registers or a flash bank (0x0000 - 0x7FFF).
+    /// Returns true if the write went to the flash chip, which may have
+    /// changed it.
+    pub fn write_rom(&mut self, ram: &mut [u8], addr: u16, val: u8) -> bool
+    {
+        match addr
+        {
+            0x0000..=0x03FF => self.ram_enabled = val & 0xF == 0xA,
+            0x0400..=0x07FF => self.ram_bank[0] = val & 0x7,
+            0x0800..=0x0BFF => self.ram_bank[1] = val & 0x7,
+            0x0C00..=0x0FFF => self.flash_enabled = val & 1 != 0,
+            0x1000 => self.flash_write_enabled = val & 1 != 0,
+            0x2000..=0x27FF => self.rom_bank[0] = val & 0x7F,
+            0x2800..=0x2FFF => self.flash_selected[0] = val == 0x08,
+            0x3000..=0x37FF => self.rom_bank[1] = val & 0x7F,
+            0x3800..=0x3FFF => self.flash_selected[1] = val == 0x08,
+            0x4000..=0x7FFF => {
+                let window = ((addr >> 13) & 1) as usize;
+                if self.flash_selected[window] && self.flash_enabled
+                {
+                    let flash_addr = self.flash_addr(window, addr);
+                    self.write_flash(&mut ram[SRAM_SIZE..], flash_addr, val);
+                    return true
+                }
+            },
+            _ => {}
+        }
+        false
+    }
+
+    /// Write to one of the switchable RAM banks (0xA000 - 0xBFFF). Returns
+    /// the offset into SRAM written, if RAM is enabled.
+    pub fn write_ram(&mut self, ram: &mut [u8], addr: u16, val: u8) -> Option< usize >
+    {
+        if !self.ram_enabled { return None }
+        let i = self.ram_addr(addr);
+        ram[i] = val;
+        Some(i)
+    }
+
+    /// Handle a write to the flash chip, stepping through its command
+    /// sequences
+    fn write_flash(&mut self, flash: &mut [u8], addr: usize, val: u8)
+    {
+        use self::FlashState::*;
+
+        // Commands are recognized by the low 15 bits of the address
+        let cmd_addr = addr & 0x7FFF;
+
+        self.flash_state = match (self.flash_state, cmd_addr, val)
+        {
+            (Program, _, _) => {
+                // Programming can only clear bits, erasing sets them again
+                if self.flash_write_enabled { flash[addr] &= val; }
+                Read
+            },
+            (_, _, 0xF0) => Read,
+            (Read, 0x5555, 0xAA) | (Id, 0x5555, 0xAA) => Unlock1,
+            (Unlock1, 0x2AAA, 0x55) => Unlock2,
+            (Unlock2, 0x5555, 0xA0) => Program,
+            (Unlock2, 0x5555, 0x80) => EraseUnlock0,
+            (Unlock2, 0x5555, 0x90) => Id,
+            (EraseUnlock0, 0x5555, 0xAA) => EraseUnlock1,
+            (EraseUnlock1, 0x2AAA, 0x55) => EraseUnlock2,
+            (EraseUnlock2, _, 0x30) => {
+                if self.flash_write_enabled
+                {
+                    let start = addr & !(SECTOR_SIZE - 1);
+                    for b in flash[start..start + SECTOR_SIZE].iter_mut() { *b = 0xFF; }
+                }
+                Read
+            },
+            (EraseUnlock2, 0x5555, 0x10) => {
+                if self.flash_write_enabled
+                {
+                    for b in flash.iter_mut() { *b = 0xFF; }
+                }
+                Read
+            },
+            (Id, _, _) => Id,
+            _ => Read
+        };
+    }
+
+    /// Get the offset into flash of an address in one of the flash windows
+    fn flash_addr(&self, window: usize, addr: u16) -> usize
+    {
+        (((self.rom_bank[window] as usize) << 13) | (addr & 0x1FFF) as usize) % FLASH_SIZE
+    }
+
+    /// Get the offset into SRAM of an address in one of the RAM windows
+    fn ram_addr(&self, addr: u16) -> usize
+    {
+        let window = ((addr >> 12) & 1) as usize;
+        ((self.ram_bank[window] as usize) << 12) | (addr & 0xFFF) as usize
+    }
+
+    /// Write the MBC6 registers to a savestate chunk
+    #[cfg(feature = "savestates")]
+    pub fn save_state(&self, w: &mut Writer)
+    {
+        w.bool(self.ram_enabled);
+        w.bytes(&self.ram_bank);
+        w.bytes(&self.rom_bank);
+        w.bool(self.flash_selected[0]);
+        w.bool(self.flash_selected[1]);
+        w.bool(self.flash_enabled);
+        w.bool(self.flash_write_enabled);
+        w.u8(self.flash_state as u8);
+    }
+
+    /// Restore the MBC6 registers from a savestate chunk
+    #[cfg(feature = "savestates")]
+    pub fn load_state(&mut self, r: &mut Reader) -> IoResult< () >
+    {
+        use self::FlashState::*;
+
+        self.ram_enabled = r.bool()?;
+        r.bytes(&mut self.ram_bank)?;
+        self.ram_bank = [self.ram_bank[0] & 0x7, self.ram_bank[1] & 0x7];
+        r.bytes(&mut self.rom_bank)?;
+        self.flash_selected = [r.bool()?, r.bool()?];
+        self.flash_enabled = r.bool()?;
+        self.flash_write_enabled = r.bool()?;
+        self.flash_state = [Read, Unlock1, Unlock2, Program, EraseUnlock0, EraseUnlock1, EraseUnlock2, Id]
+            .get(r.u8()? as usize).cloned().unwrap_or(Read);
+        Ok(())
+    }
+}
@@ -17,29 +17,176 @@
     http://gbdev.gg8.se/wiki/articles/Memory_Map
 */
 
+pub mod map;
 pub mod ram;
-
-use crate::Target;
+mod mbc6;
+mod rtc;
+mod tama5;
+mod unlicensed;
+
+use crate::{ Target, DMG_CLOCK_RATE, SGB_CLOCK_RATE };
+#[cfg(feature = "cheats")]
+use crate::cheats::Cheat;
+use crate::cpu::Interrupts;
 use crate::gpu::GPU;
 use crate::timer::Timer;
+use crate::serial::Serial;
 use crate::keypad::Keypad;
-use ram::RAM;
+use crate::sgb::{ command, Transfer };
+use crate::spu::SPU;
+use crate::invalid;
+#[cfg(feature = "savestates")]
+use crate::state::{ Reader, StateReader, StateWriter, Writer };
+use map::*;
+use mbc6::Mbc6;
+use ram::{ RAM, RamFill };
+use rtc::{ Rtc, RTC_SECONDS, RTC_DAY_HIGH };
+use tama5::Tama5;
+use unlicensed::{ Sachen, NINTENDO_LOGO };
+use std::fmt;
+use std::io::Result as IoResult;
 use std::iter::repeat;
+use std::str::FromStr;
 
 /// GB has 8K of WRAM, CGB has 32K of WRAM
 const WRAM_SIZE: usize = 32 << 10;
 
 /// HRAM is from 0xFF80 to 0xFFFE
-const HRAM_SIZE: usize = 0x7F;
+const HRAM_SIZE: usize = (HRAM_END - HRAM_START + 1) as usize;
+
+/// MBC2 has 512 half bytes of RAM built in, whatever the header says
+const MBC2_RAM_SIZE: usize = 512;
+
+/// Cartridge RAM is tracked for changes in blocks of this size, the size of
+/// a RAM bank
+const DIRTY_BANK_SIZE: usize = 8 << 10;
+
+/// The cartridge header ends at 0x014F
+const HEADER_END: usize = 0x0150;
+
+/// Bits of each IO register (0xFF00 thru 0xFF7F) that are unused or
+/// unmapped and always read back as 1. Registers that don't exist at all
+/// read back as 0xFF.
+/// http://gbdev.gg8.se/wiki/articles/Power_Up_Sequence
+const IO_UNUSED_BITS: [u8; 0x80] = [
+    // 0xFF00 - 0xFF0F: P1, SB, SC, -, DIV, TIMA, TMA, TAC, -, ..., IF
+    0xC0, 0x00, 0x7C, 0xFF, 0x00, 0x00, 0x00, 0xF8,
+    0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xE0,
+
+    // 0xFF10 - 0xFF1F: NR10 - NR34
+    0x80, 0x3F, 0x00, 0xFF, 0xBF, 0xFF, 0x3F, 0x00,
+    0xFF, 0xBF, 0x7F, 0xFF, 0x9F, 0xFF, 0xBF, 0xFF,
+
+    // 0xFF20 - 0xFF2F: NR41 - NR52
+    0xFF, 0x00, 0x00, 0xBF, 0x00, 0x00, 0x70, 0xFF,
+    0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+
+    // 0xFF30 - 0xFF3F: Wave pattern RAM
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+
+    // 0xFF40 - 0xFF4F: LCDC, STAT, SCY, SCX, LY, LYC, DMA, BGP, OBP0, OBP1,
+    // WY, WX, -, KEY1, -, VBK
+    0x00, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0xFF, 0x7E, 0xFF, 0xFE,
+
+    // 0xFF50 - 0xFF5F: BOOT, HDMA1 - HDMA5, RP
+    0xFF, 0x00, 0x00, 0x00, 0x00, 0x00, 0x3C, 0xFF,
+    0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+
+    // 0xFF60 - 0xFF6F: BCPS, BCPD, OCPS, OCPD, OPRI
+    0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+    0x40, 0x00, 0x40, 0x00, 0xFE, 0xFF, 0xFF, 0xFF,
+
+    // 0xFF70 - 0xFF7F: SVBK, -, undocumented FF72 - FF75, PCM12, PCM34
+    0xF8, 0xFF, 0x00, 0x00, 0x00, 0x8F, 0x00, 0x00,
+    0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+];
 
 /// The speed at which the GameBoy is running
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Speed
 {
     Normal,
     Double
 }
 
+/// How closely the restrictions the hardware puts on the CPU's memory
+/// accesses are followed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Accuracy
+{
+    /// The CPU can access VRAM and OAM at any time. Games with timing bugs
+    /// that real hardware hides run without glitches.
+    #[default]
+    Relaxed,
+
+    /// VRAM can't be accessed while the GPU draws a line and OAM can't be
+    /// accessed while it searches or draws. Reads return 0xFF and writes
+    /// are ignored, like on real hardware.
+    Hardware
+}
+
+/// A cartridge feature that isn't emulated
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Unsupported
+{
+    /// The cartridge uses a memory bank controller that isn't emulated. A
+    /// similar controller is emulated in its place, which may be enough for
+    /// the game to boot.
+    Mapper(&'static str),
+
+    /// The cartridge type in the header (0x0147) isn't recognized
+    CartridgeType(u8),
+
+    /// The RAM size in the header (0x0149) isn't recognized. The cartridge
+    /// is given no RAM.
+    RamSize(u8),
+
+    /// The cartridge has a real time clock, which never advances
+    Rtc,
+
+    /// The cartridge has a rumble motor, which isn't connected to anything
+    Rumble,
+
+    /// The cartridge has an infrared port, which never receives a signal
+    Infrared,
+
+    /// The cartridge has a camera sensor, which only sees black
+    Camera,
+
+    /// The cartridge has an accelerometer, which never detects movement
+    Accelerometer
+}
+
+impl Unsupported
+{
+    /// Is the feature likely to stop the game from running at all?
+    pub fn is_critical(&self) -> bool
+    {
+        matches!(*self, Unsupported::Mapper(_) | Unsupported::CartridgeType(_))
+    }
+}
+
+impl fmt::Display for Unsupported
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        match *self
+        {
+            Unsupported::Mapper(name) => write!(f, "{} mapper is not supported", name),
+            Unsupported::CartridgeType(n) => write!(f, "Unknown cartridge type {:#04X}", n),
+            Unsupported::RamSize(n) => write!(f, "Unknown cartridge RAM size {:#04X}", n),
+            Unsupported::Rtc => write!(f, "Real time clock is not emulated"),
+            Unsupported::Rumble => write!(f, "Rumble output is not connected"),
+            Unsupported::Infrared => write!(f, "Infrared port is not connected"),
+            Unsupported::Camera => write!(f, "Camera is not emulated"),
+            Unsupported::Accelerometer => write!(f, "Accelerometer is not emulated")
+        }
+    }
+}
+
 /// The different types of cartridge Memory Bank Controllers
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum MBC
@@ -49,7 +196,103 @@ enum MBC
     MBC1,
     MBC2,
     MBC3,
-    MBC5
+    MBC5,
+    MBC6,
+    TAMA5,
+    WisdomTree,
+    SachenMMC1
+}
+
+impl MBC
+{
+    fn name(self) -> &'static str
+    {
+        match self
+        {
+            MBC::Unknown => "Unknown",
+            MBC::ROM => "ROM",
+            MBC::MBC1 => "MBC1",
+            MBC::MBC2 => "MBC2",
+            MBC::MBC3 => "MBC3",
+            MBC::MBC5 => "MBC5",
+            MBC::MBC6 => "MBC6",
+            MBC::TAMA5 => "TAMA5",
+            MBC::WisdomTree => "Wisdom Tree",
+            MBC::SachenMMC1 => "Sachen MMC1"
+        }
+    }
+}
+
+/// The banking state of the cartridge mapper, for debuggers that want to
+/// show which parts of the cartridge are currently mapped in. Only
+/// serializable, since the mapper name is a static string.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct MapperState
+{
+    /// Name of the mapper, e.g. "MBC1"
+    pub mapper: &'static str,
+
+    /// ROM bank mapped at 0x0000 thru 0x3FFF. Only unlicensed mappers can
+    /// switch this from bank 0.
+    pub rom_bank0: u16,
+
+    /// ROM bank mapped at 0x4000 thru 0x7FFF
+    pub rom_bank: u16,
+
+    /// RAM bank mapped at 0xA000 thru 0xBFFF
+    pub ram_bank: u8,
+
+    /// Is cartridge RAM enabled?
+    pub ram_enabled: bool,
+
+    /// MBC1 banking mode. 0 is ROM banking mode and 1 is RAM banking mode.
+    pub mode: u8,
+
+    /// Is the MBC3 real time clock latched? Always false for cartridges
+    /// without a clock.
+    pub rtc_latched: bool
+}
+
+/// An address in a specific bank of the cartridge, written `bank:address`
+/// in hex like debuggers show them, e.g. `03:4123`. Addresses outside of
+/// cartridge ROM are always in bank 0.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BankAddress
+{
+    /// ROM bank mapped at the address
+    pub bank: u16,
+
+    /// CPU address
+    pub addr: u16
+}
+
+impl fmt::Display for BankAddress
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        write!(f, "{:02X}:{:04X}", self.bank, self.addr)
+    }
+}
+
+impl FromStr for BankAddress
+{
+    type Err = std::io::Error;
+
+    /// Parse a `bank:address` pair such as "05:4000". The bank can be left
+    /// out for addresses in bank 0, e.g. "C000".
+    fn from_str(s: &str) -> IoResult< Self >
+    {
+        let hex = |s: &str| u16::from_str_radix(s.trim(), 16)
+            .map_err(|_| invalid("Expected a hex address like 05:4000"));
+        let mut parts = s.splitn(2, ':');
+        match (parts.next(), parts.next())
+        {
+            (Some(addr), None) => Ok(BankAddress { bank: 0, addr: hex(addr)? }),
+            (Some(bank), Some(addr)) => Ok(BankAddress { bank: hex(bank)?, addr: hex(addr)? }),
+            _ => Err(invalid("Expected a hex address like 05:4000"))
+        }
+    }
 }
 
 pub struct Memory
@@ -67,25 +310,36 @@ pub struct Memory
     /// Has a speed switch been requested?
     pub speed_switch: bool,
 
+    /// Number of ticks that have been stepped since power on
+    pub cycles: u64,
+
     /// Cartridge ROM memory
     rom: Vec< u8 >,
 
     /// Cartridge RAM memory
     ram: Vec< u8 >,
 
+    /// Which DIRTY_BANK_SIZE blocks of cartridge RAM changed since it was
+    /// last saved
+    ram_dirty: Vec< bool >,
+
     /// Working RAM
-    wram: Box< RAM >,
+    wram: RAM< WRAM_SIZE >,
 
     /// High Speed RAM (Zeropage)
-    hram: Box< RAM >,
+    hram: RAM< HRAM_SIZE >,
 
     /// Current ROM bank swapped in
     rom_bank: u16,
 
+    /// ROM bank swapped in at 0x0000. Only unlicensed mappers can change it.
+    rom_bank0: u16,
+
     /// Current RAM bank swapped in
     ram_bank: u8,
 
-    /// The current WRAM bank currently swapped in
+    /// SVBK as last written. Bank 0 can't be swapped in, so 0 selects bank 1
+    /// but still reads back as 0.
     wram_bank: u8,
 
     /// Is cartridge RAM enabled?
@@ -100,6 +354,25 @@ pub struct Memory
     /// MBC type of current cartridge
     mbc: MBC,
 
+    /// MBC6 registers and flash state
+    mbc6: Mbc6,
+
+    /// TAMA5 registers
+    tama5: Tama5,
+
+    /// MBC3 real time clock, if the cartridge has one
+    rtc: Option< Rtc >,
+
+    /// Sachen MMC1 registers
+    sachen: Sachen,
+
+    /// Features of the current cartridge that aren't emulated
+    unsupported: Vec< Unsupported >,
+
+    /// Game Genie codes applied to ROM reads
+    #[cfg(feature = "cheats")]
+    rom_patches: Vec< Cheat >,
+
     /// Should Super GameBoy functionality be used?
     sgb: bool,
 
@@ -107,13 +380,34 @@ pub struct Memory
     cgb: bool,
 
     /// GameBoy Timer
-    timer: Box< Timer >,
+    timer: Timer,
+
+    /// GameBoy Serial port
+    pub serial: Serial,
 
     /// Gameboy GPU
-    pub gpu: Box< GPU >,
+    pub gpu: GPU,
 
     /// GameBoy Keypad
-    pub keypad: Box< Keypad >,
+    pub keypad: Keypad,
+
+    /// GameBoy Sound Processing Unit
+    pub spu: SPU,
+
+    /// Undocumented CGB registers 0xFF72 thru 0xFF75. Their purpose is
+    /// unknown but they can be read and written.
+    undocumented: [u8; 4],
+
+    /// Whether the CPU is locked out of VRAM and OAM while the GPU uses them
+    accuracy: Accuracy,
+
+    /// Boot ROM to run before the cartridge, empty when the system starts
+    /// in the state the boot ROM leaves it in
+    boot_rom: Vec< u8 >,
+
+    /// Is the boot ROM mapped over the start of the cartridge ROM? Writing
+    /// to 0xFF50 unmaps it until the next power on.
+    boot_rom_mapped: bool,
 }
 
 impl Memory
@@ -127,36 +421,153 @@ impl Memory
             inte: 0,
             speed: Speed::Normal,
             speed_switch: false,
+            cycles: 0,
             rom: Vec::new(),
             ram: Vec::new(),
-            wram: Box::new(RAM::new(WRAM_SIZE)),
-            hram: Box::new(RAM::new(HRAM_SIZE)),
+            ram_dirty: Vec::new(),
+            wram: RAM::new(RamFill::default()),
+            hram: RAM::new(RamFill::default()),
             rom_bank: 1,
+            rom_bank0: 0,
             ram_bank: 0,
-            wram_bank: 1,
+            wram_bank: 0,
             ram_enabled: false,
             bank_mode: false,
             battery: false,
             mbc: MBC::Unknown,
+            mbc6: Mbc6::new(),
+            tama5: Tama5::new(),
+            rtc: None,
+            sachen: Sachen::new(),
+            unsupported: Vec::new(),
+            #[cfg(feature = "cheats")]
+            rom_patches: Vec::new(),
             sgb: false,
             cgb: false,
-            timer: Box::new(Timer::new()),
-            gpu: Box::new(GPU::new(target)),
-            keypad: Box::new(Keypad::new()),
+            timer: Timer::new(),
+            serial: Serial::new(),
+            gpu: GPU::new(target),
+            keypad: Keypad::new(),
+            spu: SPU::new(),
+            undocumented: [0; 4],
+            accuracy: Accuracy::Relaxed,
+            boot_rom: Vec::new(),
+            boot_rom_mapped: false,
         }
     }
 
+    /// Set whether the CPU is locked out of VRAM and OAM while the GPU uses
+    /// them
+    pub fn set_accuracy(&mut self, accuracy: Accuracy)
+    {
+        self.accuracy = accuracy;
+    }
+
+    /// Get whether the CPU is locked out of VRAM and OAM while the GPU uses
+    /// them
+    pub fn accuracy(&self) -> Accuracy
+    {
+        self.accuracy
+    }
+
+    /// Is the CPU locked out of the given VRAM or OAM address right now?
+    fn locked_out(&self, addr: u16) -> bool
+    {
+        self.accuracy == Accuracy::Hardware && match addr
+        {
+            VRAM_START..=VRAM_END => self.gpu.vram_busy(),
+            OAM_START..=OAM_END => self.gpu.oam_busy(),
+            _ => false
+        }
+    }
+
+    /// Set the boot ROM to run on the next power on. An empty boot ROM
+    /// skips straight to the cartridge.
+    pub fn set_boot_rom(&mut self, boot_rom: Vec< u8 >)
+    {
+        self.boot_rom = boot_rom;
+        self.boot_rom_mapped = false;
+    }
+
+    /// Get the boot ROM run on power on, empty if there is none
+    pub fn boot_rom(&self) -> &[u8]
+    {
+        &self.boot_rom
+    }
+
+    /// Map the boot ROM over the cartridge ROM, returning false if there is
+    /// no boot ROM to map
+    pub fn map_boot_rom(&mut self) -> bool
+    {
+        self.boot_rom_mapped = !self.boot_rom.is_empty();
+        self.boot_rom_mapped
+    }
+
+    /// Is the boot ROM mapped over the cartridge ROM?
+    pub fn boot_rom_mapped(&self) -> bool
+    {
+        self.boot_rom_mapped
+    }
+
+    /// Load the cartridge the system powers on with. Its header decides
+    /// whether CGB and SGB functionality is used.
     pub fn load_cartridge(&mut self, rom: Vec< u8 >)
+    {
+        self.insert_cartridge(rom);
+
+        // Determine functionality needed by cartridge
+        if self.target == Target::GameBoyColor
+        {
+            self.cgb = self.rom[0x0143] & 0x80 != 0;
+            self.gpu.is_cgb = self.cgb;
+            self.gpu.obj_x_priority = !self.cgb;
+            self.serial.is_cgb = self.cgb;
+        }
+
+        if self.target == Target::SuperGameBoy || self.target == Target::GameBoyColor
+        {
+            self.sgb = self.rom[0x0146] == 0x03;
+            if self.sgb
+            {
+                self.gpu.is_sgb = self.sgb;
+                self.keypad.is_sgb = self.sgb;
+            }
+        }
+    }
+
+    /// Plug a cartridge into the slot with its mapper in its power on state.
+    /// The system stays in whatever mode the boot cartridge chose.
+    pub fn insert_cartridge(&mut self, rom: Vec< u8 >)
     {
         use MBC::*;
 
+        // Tiny ROMs are padded out to fill the fixed and switchable bank
         self.rom = rom;
+        if self.rom.len() < 0x8000
+        {
+            self.rom.resize(0x8000, 0xFF);
+        }
         self.battery = true;
         self.mbc = Unknown;
-
-        // 0x0147 gives info about cartridge type
+        self.unsupported.clear();
+        self.mbc6 = Mbc6::new();
+        self.tama5 = Tama5::new();
+        self.rtc = None;
+        self.sachen = Sachen::new();
+        self.rom_bank0 = 0;
+        self.rom_bank = 1;
+        self.bank_mode = false;
+
+        // 0x0147 gives info about cartridge type. Unlicensed cartridges
+        // don't fill it in so they are recognized first.
         match self.rom[0x0147]
         {
+            _ if self.is_sachen() => { self.battery = false; self.mbc = SachenMMC1; },
+            0x00 | 0xC0 if self.rom.len() > 0x8000 => { 
+                self.battery = false; 
+                self.mbc = WisdomTree; 
+            },
+
             // 0x00 - ROM Only
             // 0x08 - ROM + RAM
             0x00 | 0x08 => { self.battery = false; self.mbc = ROM; },
@@ -183,61 +594,574 @@ impl Memory
 
             // 0x0F - ROM + MBC3 + Timer + Battery
             // 0x10 - ROM + MBC3 + Timer + Battery + RAM
+            0x0F | 0x10 => { self.mbc = MBC3; self.rtc = Some(self.new_rtc()); },
+
             // 0x13 - ROM + MBC3 + RAM + Battery
-            0x0F | 0x10 | 0x13 => { self.mbc = MBC3; },
+            0x13 => { self.mbc = MBC3; },
 
             // 0x19 - ROM + MBC5
             // 0x1A - ROM + MBC5 + RAM
+            0x19 | 0x1A => { self.battery = false; self.mbc = MBC5; },
+
+            // 0x1B - ROM + MBC5 + RAM + Battery
+            0x1B => { self.mbc = MBC5; },
+
             // 0x1C - ROM + MBC5 + Rumble
             // 0x1D - ROM + MBC5 + Rumble + RAM
-            0x19 | 0x1A | 0x1C | 0x1D => { self.battery = false; self.mbc = MBC5; },
+            0x1C | 0x1D => { 
+                self.battery = false; 
+                self.mbc = MBC5; 
+                self.unsupported.push(Unsupported::Rumble);
+            },
 
-            // 0x1B - ROM + MBC5 + RAM + Battery
             // 0x1E - ROM + MBC5 + Rumble + SRAM + Battery
-            0x1B | 0x1E => { self.mbc = MBC5; },
+            0x1E => { self.mbc = MBC5; self.unsupported.push(Unsupported::Rumble); },
 
-            n => panic!("Unknown cartridge type inserted: {:#X}", n)
+            // 0x20 - ROM + MBC6 + RAM + Battery
+            0x20 => { self.mbc = MBC6; },
+
+            // 0xFD - Bandai TAMA5 + Timer + Battery
+            0xFD => { self.mbc = TAMA5; self.unsupported.push(Unsupported::Rtc); },
+
+            // Everything below isn't emulated. The closest supported MBC is
+            // used instead so the game has a chance of booting.
+
+            // 0x0B - ROM + MMM01
+            // 0x0C - ROM + MMM01 + RAM
+            0x0B | 0x0C => { 
+                self.battery = false; 
+                self.mbc = MBC5; 
+                self.unsupported.push(Unsupported::Mapper("MMM01"));
+            },
+
+            // 0x0D - ROM + MMM01 + RAM + Battery
+            0x0D => { self.mbc = MBC5; self.unsupported.push(Unsupported::Mapper("MMM01")); },
+
+            // 0x22 - ROM + MBC7 + Sensor + Rumble + RAM + Battery
+            0x22 => {
+                self.mbc = MBC5;
+                self.unsupported.push(Unsupported::Mapper("MBC7"));
+                self.unsupported.push(Unsupported::Accelerometer);
+                self.unsupported.push(Unsupported::Rumble);
+            },
+
+            // 0xFC - Pocket Camera
+            0xFC => {
+                self.mbc = MBC5;
+                self.unsupported.push(Unsupported::Mapper("Pocket Camera"));
+                self.unsupported.push(Unsupported::Camera);
+            },
+
+            // 0xFE - Hudson HuC3
+            0xFE => {
+                self.mbc = MBC5;
+                self.unsupported.push(Unsupported::Mapper("HuC3"));
+                self.unsupported.push(Unsupported::Rtc);
+                self.unsupported.push(Unsupported::Infrared);
+            },
+
+            // 0xFF - Hudson HuC1 + RAM + Battery. Banks like an MBC1.
+            0xFF => {
+                self.mbc = MBC1;
+                self.unsupported.push(Unsupported::Mapper("HuC1"));
+                self.unsupported.push(Unsupported::Infrared);
+            },
+
+            n => {
+                self.battery = false;
+                self.mbc = if self.rom.len() <= 0x8000 { ROM } else { MBC5 };
+                self.unsupported.push(Unsupported::CartridgeType(n));
+            }
         }
 
-        // Determine RAM size & initialize RAM with 0's
+        // Determine RAM size & initialize RAM with 0's. Homebrew and test
+        // ROMs without a mapper often leave junk in the RAM size, which is
+        // treated as no RAM.
         let ram_size = self.ram_size();
-        self.ram = repeat(0u8).take(ram_size).collect();
+        if self.rom[0x0149] > 0x05 && self.mbc != MBC2
+        {
+            self.unsupported.push(Unsupported::RamSize(self.rom[0x0149]));
+        }
 
-        // Determine functionality needed by cartridge
-        if self.target == Target::GameBoyColor
+        // Carts without a mapper have no RAM enable register, so any RAM
+        // they have is always accessible
+        self.ram_enabled = self.mbc == ROM;
+        self.ram_bank = 0;
+        self.ram = match self.mbc
         {
-            self.cgb = self.rom[0x0143] & 0x80 != 0;
-            self.gpu.is_cgb = self.cgb;
+            MBC6 => Mbc6::new_ram(),
+            TAMA5 => vec![0; tama5::RAM_SIZE],
+            WisdomTree | SachenMMC1 => Vec::new(),
+            _ => repeat(0u8).take(ram_size).collect()
+        };
+        self.ram_dirty = vec![false; self.ram.len().div_ceil(DIRTY_BANK_SIZE)];
+    }
+
+    /// Remove and return the cartridge ROM, leaving no cartridge inserted
+    pub fn take_rom(&mut self) -> Vec< u8 >
+    {
+        ::std::mem::take(&mut self.rom)
+    }
+
+    /// Pull the cartridge out of the slot, returning its ROM and the
+    /// contents of its RAM if a battery keeps them. The cartridge area reads
+    /// as open bus until another cartridge is inserted.
+    pub fn eject_cartridge(&mut self) -> (Vec< u8 >, Option< Vec< u8 > >)
+    {
+        let save = self.battery_ram().map(|ram| ram.to_vec());
+        let rom = self.take_rom();
+        self.ram = Vec::new();
+        self.ram_dirty = Vec::new();
+        self.ram_enabled = false;
+        self.rtc = None;
+        self.battery = false;
+        self.mbc = MBC::Unknown;
+        self.unsupported.clear();
+        (rom, save)
+    }
+
+    /// Is a cartridge inserted?
+    pub fn has_cartridge(&self) -> bool
+    {
+        !self.rom.is_empty()
+    }
+
+    /// Check that a ROM image looks like a GameBoy cartridge before it is
+    /// loaded. It must be large enough to contain the cartridge header and
+    /// either have the Nintendo logo in the header or be a whole number of
+    /// 16KB banks, since unlicensed cartridges leave the logo out.
+    pub fn check_rom(rom: &[u8]) -> IoResult< () >
+    {
+        if rom.is_empty()
+        {
+            return Err(invalid("ROM file is empty"))
+        }
+        if rom.len() < HEADER_END
+        {
+            return Err(invalid(&format!(
+                "ROM is too small to contain a cartridge header ({} bytes)", rom.len())))
         }
+        if rom[0x0104..0x0134] != NINTENDO_LOGO[..] && !rom.len().is_multiple_of(0x4000)
+        {
+            return Err(invalid("Not a GameBoy ROM: the cartridge header is missing"))
+        }
+        Ok(())
+    }
 
-        if self.target == Target::SuperGameBoy || self.target == Target::GameBoyColor
+    /// Is the cartridge a Sachen one? They are larger than 32KB and show
+    /// their own logo instead of the Nintendo one, so the logo in the header
+    /// is missing.
+    fn is_sachen(&self) -> bool
+    {
+        self.rom.len() > 0x8000 && self.rom[0x0104..0x0134] != NINTENDO_LOGO[..]
+    }
+
+    /// Overwrite WRAM and HRAM with the given fill pattern
+    pub fn fill_ram(&mut self, fill: RamFill)
+    {
+        self.wram.fill(fill);
+        self.hram.fill(fill);
+    }
+
+    /// Handle an SGB command received through the keypad register
+    fn sgb_command(&mut self, data: &[u8])
+    {
+        match data[0] >> 3
         {
-            self.sgb = self.rom[0x0146] == 0x03;
-            if self.sgb
+            command::PAL01 => self.gpu.set_sgb_palettes(0, 1, data),
+            command::PAL23 => self.gpu.set_sgb_palettes(2, 3, data),
+            command::PAL03 => self.gpu.set_sgb_palettes(0, 3, data),
+            command::PAL12 => self.gpu.set_sgb_palettes(1, 2, data),
+            command::CHR_TRN => self.gpu.start_sgb_transfer(Transfer::Chr(data[1] & 1 != 0)),
+            command::PCT_TRN => self.gpu.start_sgb_transfer(Transfer::Pct),
+            command::MLT_REQ => 
             {
-                self.gpu.is_sgb = self.sgb;
-            }
+                let players = match data[1] & 0x3 { 1 => 2, 3 => 4, _ => 1 };
+                self.keypad.set_players(players);
+            },
+            _ => {}
         }
     }
 
+    /// Get the current banking state of the cartridge mapper
+    pub fn mapper_state(&self) -> MapperState
+    {
+        let (rom_bank, ram_bank, ram_enabled) = match self.mbc
+        {
+            MBC::MBC6 => {
+                let (rom_bank, ram_bank) = self.mbc6.banks();
+                (rom_bank, ram_bank, self.mbc6.ram_enabled())
+            },
+            _ => (self.rom_bank, self.ram_bank, self.ram_enabled)
+        };
+
+        MapperState {
+            mapper: self.mbc.name(),
+            rom_bank0: self.rom_bank0,
+            rom_bank,
+            ram_bank,
+            ram_enabled,
+            mode: self.bank_mode as u8,
+            rtc_latched: self.rtc.as_ref().is_some_and(|rtc| rtc.latched())
+        }
+    }
+
+    /// Get the number of ROM banks on the cartridge. Banks are 16KB, except
+    /// on MBC6 where they are 8KB.
+    pub fn rom_bank_count(&self) -> usize
+    {
+        if self.mbc == MBC::MBC6
+        {
+            self.rom.len() >> 13
+        }
+        else
+        {
+            self.rom.len() >> 14
+        }
+    }
+
+    /// Resolve a CPU address to the ROM bank currently mapped there. Banks
+    /// are numbered the same way as in `rom_bank_count`.
+    pub fn bank_address(&self, addr: u16) -> BankAddress
+    {
+        let bank = match addr
+        {
+            ROM_START..=ROM_BANK0_END if self.mbc == MBC::MBC6 => addr >> 13,
+            ROM_START..=ROM_BANK0_END => self.rom_bank0,
+            ROMX_START..=ROM_END if self.mbc == MBC::MBC6 => self.mbc6.rom_bank(((addr >> 13) & 1) as usize),
+            ROMX_START..=ROM_END => self.rom_bank,
+            _ => 0
+        };
+        BankAddress { bank, addr }
+    }
+
+    /// Get the index into cartridge RAM of an address in 0xA000 thru 0xBFFF.
+    /// Returns None when RAM is disabled or the cartridge has no RAM there,
+    /// in which case reads return 0xFF and writes are ignored.
+    fn ext_ram_index(&self, addr: u16) -> Option< usize >
+    {
+        if !self.ram_enabled { return None }
+
+        // MBC2 RAM is mirrored throughout the whole area
+        let i = if self.mbc == MBC::MBC2
+        {
+            (addr & 0x1FF) as usize
+        }
+        else
+        {
+            ((self.ram_bank as usize) << 13) | (addr & 0x1FFF) as usize
+        };
+
+        if i < self.ram.len() { Some(i) } else { None }
+    }
+
+    /// Is one of the MBC3 clock registers mapped in place of cartridge RAM?
+    fn rtc_selected(&self) -> bool
+    {
+        self.mbc == MBC::MBC3 && (RTC_SECONDS..=RTC_DAY_HIGH).contains(&self.ram_bank)
+    }
+
+    /// Create an MBC3 real time clock that counts seconds of emulated time
+    fn new_rtc(&self) -> Rtc
+    {
+        Rtc::new(if self.target == Target::SuperGameBoy { SGB_CLOCK_RATE } else { DMG_CLOCK_RATE })
+    }
+
     fn ram_size(&self) -> usize
     {
+        if self.mbc == MBC::MBC2
+        {
+            return MBC2_RAM_SIZE
+        }
+
         match self.rom[0x0149]
         {
             0x00 => 0,
             0x01 => 2 << 10,    // 2kB
             0x02 => 8 << 10,    // 8kB
             0x03 => 32 << 10,   // 32kB
-            0x04 => 125 << 10,  // 128kB
-            _ => panic!("Unknown RAM size: {:#X}", self.rom[0x0149])
+            0x04 => 128 << 10,  // 128kB
+            0x05 => 64 << 10,   // 64kB
+            _ => 0
+        }
+    }
+
+    /// Get the features of the current cartridge that aren't emulated
+    pub fn unsupported(&self) -> &[Unsupported]
+    {
+        &self.unsupported
+    }
+
+    /// Get the contents of cartridge RAM if the cartridge has a battery to
+    /// keep it powered while the GameBoy is off
+    pub fn battery_ram(&self) -> Option< &[u8] >
+    {
+        if self.battery && !self.ram.is_empty() { Some(&self.ram) } else { None }
+    }
+
+    /// Restore battery backed cartridge RAM from previously saved contents.
+    /// Saves that are shorter or longer than cartridge RAM are truncated or
+    /// padded with 0's.
+    pub fn load_battery_ram(&mut self, data: &[u8])
+    {
+        if !self.battery { return }
+        let ram = (0..self.ram.len()).map(|i| data.get(i).cloned().unwrap_or(0)).collect();
+        self.replace_ram(ram);
+    }
+
+    /// Get the MBC3 clock and latched clock registers, if the cartridge has a
+    /// clock
+    pub fn rtc_registers(&self) -> Option< ([u8; 5], [u8; 5]) >
+    {
+        self.rtc.as_ref().map(|rtc| rtc.registers())
+    }
+
+    /// Restore the MBC3 clock registers from a battery save and run the clock
+    /// the given number of seconds forward. Does nothing if the cartridge
+    /// has no clock.
+    pub fn restore_rtc(&mut self, regs: [u8; 5], latched: [u8; 5], seconds: u64)
+    {
+        if let Some(rtc) = self.rtc.as_mut()
+        {
+            rtc.restore(regs, latched, seconds);
+        }
+    }
+
+    /// Has the MBC3 clock drifted from where clock registers `regs` would
+    /// have counted to in the given number of seconds? False if the
+    /// cartridge has no clock.
+    pub fn rtc_drifted(&self, regs: &[u8; 5], seconds: u64) -> bool
+    {
+        self.rtc.as_ref().is_some_and(|rtc| rtc.drifted(regs, seconds))
+    }
+
+    /// Replace cartridge RAM with new contents of the same size, marking the
+    /// banks that change as dirty
+    fn replace_ram(&mut self, ram: Vec< u8 >)
+    {
+        let banks = self.ram.chunks(DIRTY_BANK_SIZE).zip(ram.chunks(DIRTY_BANK_SIZE));
+        for (dirty, (old, new)) in self.ram_dirty.iter_mut().zip(banks)
+        {
+            *dirty |= old != new;
+        }
+        self.ram = ram;
+    }
+
+    /// Has battery backed cartridge RAM changed since it was last marked as
+    /// saved?
+    pub fn battery_ram_dirty(&self) -> bool
+    {
+        self.battery && self.ram_dirty.contains(&true)
+    }
+
+    /// Mark cartridge RAM as matching what was last saved
+    pub fn mark_ram_saved(&mut self)
+    {
+        self.ram_dirty.fill(false);
+    }
+
+    /// Mark the bank holding an offset into cartridge RAM as dirty
+    fn mark_ram_dirty(&mut self, i: usize)
+    {
+        if let Some(dirty) = self.ram_dirty.get_mut(i / DIRTY_BANK_SIZE)
+        {
+            *dirty = true;
         }
     }
 
-    /// Step the Timer and GPU a given number of ticks forward
-    pub fn step(&mut self, time: u32)
+    /// Get the title of the game from the cartridge header
+    pub fn title(&self) -> String
     {
-        self.timer.step(time, &mut self.intf, self.speed);
+        self.title_of(&self.rom)
+    }
+
+    /// Get the title of the game from the header of a ROM image. CGB games
+    /// use the end of the title area for other information.
+    pub fn title_of(&self, rom: &[u8]) -> String
+    {
+        let end = if self.cgb { 0x013F } else { 0x0144 };
+        rom.get(0x0134..end).unwrap_or(&[]).iter()
+            .take_while(|&&b| b != 0)
+            .map(|&b| b as char)
+            .collect()
+    }
+
+    /// Get the global checksum from the cartridge header (0x014E - 0x014F)
+    pub fn checksum(&self) -> u16
+    {
+        Memory::checksum_of(&self.rom)
+    }
+
+    /// Get the global checksum from the header of a ROM image
+    pub fn checksum_of(rom: &[u8]) -> u16
+    {
+        match (rom.get(0x014E), rom.get(0x014F)) {
+            (Some(&hi), Some(&lo)) => (hi as u16) << 8 | lo as u16,
+            _ => 0
+        }
+    }
+
+    /// Write the memory state, and the state of every component attached to
+    /// it, to a savestate. The cartridge ROM is not part of the state.
+    #[cfg(feature = "savestates")]
+    pub fn save_state(&self, state: &mut StateWriter)
+    {
+        state.chunk(b"MEM ", |w| self.save_mem_state(w));
+        state.chunk(b"MBC ", |w| match self.mbc {
+            MBC::MBC6 => self.mbc6.save_state(w),
+            MBC::TAMA5 => self.tama5.save_state(w),
+            MBC::SachenMMC1 => self.sachen.save_state(w),
+            MBC::MBC3 => if let Some(rtc) = &self.rtc { rtc.save_state(w) },
+            _ => {}
+        });
+        state.chunk(b"TIMR", |w| self.timer.save_state(w));
+        state.chunk(b"SERL", |w| self.serial.save_state(w));
+        state.chunk(b"GPU ", |w| self.gpu.save_state(w));
+        state.chunk(b"SGBB", |w| self.gpu.save_border_state(w));
+        state.chunk(b"JOYP", |w| self.keypad.save_state(w));
+        if self.boot_rom_mapped
+        {
+            state.chunk(b"BOOT", |_| {});
+        }
+    }
+
+    /// Check that a savestate can be loaded by loading it into scratch
+    /// memory for the same cartridge, leaving this memory untouched
+    #[cfg(feature = "savestates")]
+    pub fn check_state(&self, state: &StateReader) -> IoResult< () >
+    {
+        let mut scratch = Memory::new(self.target);
+        scratch.mbc = self.mbc;
+        scratch.rtc = self.rtc.as_ref().map(|_| self.new_rtc());
+        scratch.ram = vec![0; self.ram.len()];
+        scratch.boot_rom = self.boot_rom.clone();
+        scratch.load_state(state)
+    }
+
+    /// Restore the memory state, and the state of every component attached
+    /// to it, from a savestate. A malformed state can leave it partly
+    /// restored, so states should be checked with `check_state` first.
+    #[cfg(feature = "savestates")]
+    pub fn load_state(&mut self, state: &StateReader) -> IoResult< () >
+    {
+        self.load_mem_state(&mut state.chunk(b"MEM ")?)?;
+        let mut mbc = state.chunk(b"MBC ")?;
+        self.mbc6 = Mbc6::new();
+        self.tama5 = Tama5::new();
+        self.sachen = Sachen::new();
+        if self.rtc.is_some()
+        {
+            self.rtc = Some(self.new_rtc());
+        }
+        if !mbc.is_empty()
+        {
+            match (self.mbc, self.rtc.as_mut())
+            {
+                (MBC::MBC6, _) => self.mbc6.load_state(&mut mbc)?,
+                (MBC::TAMA5, _) => self.tama5.load_state(&mut mbc)?,
+                (MBC::SachenMMC1, _) => self.sachen.load_state(&mut mbc)?,
+                (MBC::MBC3, Some(rtc)) => rtc.load_state(&mut mbc)?,
+                _ => {}
+            }
+        }
+        self.rom_bank0 = match self.mbc
+        {
+            MBC::WisdomTree => self.rom_bank & !1,
+            MBC::SachenMMC1 => self.sachen.banks().0,
+            _ => 0
+        };
+        self.timer.load_state(&mut state.chunk(b"TIMR")?)?;
+        self.serial.load_state(&mut state.chunk(b"SERL")?)?;
+        self.gpu.load_state(&mut state.chunk(b"GPU ")?)?;
+        self.gpu.load_border_state(&mut state.chunk(b"SGBB")?)?;
+        self.keypad.load_state(&mut state.chunk(b"JOYP")?)?;
+        self.boot_rom_mapped = state.has_chunk(b"BOOT");
+        if self.boot_rom_mapped && self.boot_rom.is_empty()
+        {
+            return Err(invalid("Savestate was made while running a boot ROM that isn't loaded"))
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "savestates")]
+    fn save_mem_state(&self, w: &mut Writer)
+    {
+        w.bytes(&[self.intf, self.inte]);
+        w.bool(self.speed == Speed::Double);
+        w.bool(self.speed_switch);
+        w.u64(self.cycles);
+        w.vec(&self.ram);
+        w.bytes(self.wram.bytes());
+        w.bytes(self.hram.bytes());
+        w.u16(self.rom_bank);
+        w.bytes(&[self.ram_bank, self.wram_bank]);
+        w.bool(self.ram_enabled);
+        w.bool(self.bank_mode);
+        w.bytes(&self.undocumented);
+    }
+
+    #[cfg(feature = "savestates")]
+    fn load_mem_state(&mut self, r: &mut Reader) -> IoResult< () >
+    {
+        self.intf = r.u8()?;
+        self.inte = r.u8()?;
+        self.speed = if r.bool()? { Speed::Double } else { Speed::Normal };
+        self.speed_switch = r.bool()?;
+        self.cycles = r.u64()?;
+        let ram = r.vec()?;
+        if ram.len() != self.ram.len()
+        {
+            return Err(invalid("Savestate cartridge RAM size doesn't match the cartridge"))
+        }
+        self.replace_ram(ram);
+        r.bytes(self.wram.bytes_mut())?;
+        r.bytes(self.hram.bytes_mut())?;
+        self.rom_bank = r.u16()?;
+        self.ram_bank = r.u8()?;
+        self.wram_bank = r.u8()?;
+        self.ram_enabled = r.bool()?;
+        self.bank_mode = r.bool()?;
+        r.bytes(&mut self.undocumented)?;
+        Ok(())
+    }
+
+    /// Step the Timer and GPU a given number of ticks forward. The Timer is
+    /// frozen while the CPU is in STOP mode.
+    pub fn step(&mut self, time: u32, stopped: bool)
+    {
+        self.cycles = self.cycles.wrapping_add(time as u64);
+        if let Some(rtc) = self.rtc.as_mut()
+        {
+            rtc.step(time);
+        }
+        if !stopped
+        {
+            self.timer.step(time, &mut self.intf, self.speed);
+            self.serial.step(time, &mut self.intf, self.speed);
+        }
         self.gpu.step(time, &mut self.intf);
+        self.spu.step(time);
+    }
+
+    /// Reset the Timer DIV register
+    pub fn reset_div(&mut self)
+    {
+        self.timer.reset_div();
+    }
+
+    /// Set the Timer DIV register, without resetting the counter driving it
+    pub fn set_div(&mut self, div: u8)
+    {
+        self.timer.set_div(div);
+    }
+
+    /// Get the number of ticks until the Timer, Serial port or GPU may next
+    /// request an interrupt
+    pub fn ticks_until_event(&self) -> u32
+    {
+        self.gpu.ticks_until_event()
+            .min(self.timer.ticks_until_interrupt(self.speed))
+            .min(self.serial.ticks_until_interrupt(self.speed))
     }
 
     /// Read a byte from the given address in memory
@@ -245,58 +1169,132 @@ impl Memory
     {
         match addr
         {
-            // ROM Bank 0
-            0x0000...0x3FFF => self.rom[addr as usize],
+            // Boot ROM. The CGB boot ROM leaves a hole for the cartridge
+            // header at 0x0100 thru 0x01FF.
+            0x0000..=0x00FF | 0x0200..=0x08FF if self.boot_rom_mapped && (addr as usize) < self.boot_rom.len() =>
+                self.boot_rom[addr as usize],
 
-            // ROM Bank 1
-            0x4000...0x7FFF => self.rom[(((self.rom_bank as u32) << 14) | 
-                ((addr as u32) & 0x3FFF)) as usize],
+            // Nothing drives the bus without a cartridge
+            ROM_START..=ROM_END | EXT_RAM_START..=EXT_RAM_END if self.rom.is_empty() => 0xFF,
+
+            // ROM, with any Game Genie codes applied
+            #[cfg(feature = "cheats")]
+            ROM_START..=ROM_END if !self.rom_patches.is_empty() =>
+                self.rom_patches.iter().fold(self.read_rom(addr), |value, cheat| cheat.patch_rom(addr, value)),
+            ROM_START..=ROM_END => self.read_rom(addr),
+
+            // VRAM and OAM while the GPU is using them
+            VRAM_START..=VRAM_END | OAM_START..=OAM_END if self.locked_out(addr) => 0xFF,
 
             // VRAM
-            0x8000...0x9FFF => self.gpu.read_byte(addr),
+            VRAM_START..=VRAM_END => self.gpu.read_byte(addr),
 
             // EXT RAM
-            0xA000...0xBFFF => 
+            EXT_RAM_START..=EXT_RAM_END if self.mbc == MBC::MBC6 => self.mbc6.read_ram(&self.ram, addr),
+            EXT_RAM_START..=EXT_RAM_END if self.mbc == MBC::TAMA5 => self.tama5.read(addr),
+            EXT_RAM_START..=EXT_RAM_END if self.rtc_selected() => match self.rtc
             {
-                if self.ram_enabled
-                {
-                    self.ram[(((self.ram_bank as u16) << 12) | 
-                        (addr & 0x1FFF)) as usize]
-                }
-                else
+                Some(ref rtc) if self.ram_enabled => rtc.read(self.ram_bank),
+                _ => 0xFF
+            },
+            EXT_RAM_START..=EXT_RAM_END => 
+            {
+                match self.ext_ram_index(addr)
                 {
-                    0xFF
+                    // Only the low 4 bits of MBC2 RAM exist
+                    Some(i) if self.mbc == MBC::MBC2 => self.ram[i] | 0xF0,
+                    Some(i) => self.ram[i],
+                    None => 0xFF
                 }
             },
 
             // WRAM 0 and WRAM 0 mirror
-            0xC000...0xCFFF | 0xE000...0xEFFF => 
+            WRAM_START..=WRAM_BANK0_END | ECHO_START..=ECHO_BANK0_END => 
                 self.wram.read_byte(addr & 0xFFF),
 
             // WRAM 1 and WRAM 1 mirror
-            0xD000...0xDFFF | 0xF000...0xFDFF => 
-                self.wram.read_byte((self.wram_bank as u16) << 12 | 
-                (addr & 0xFFF)),
+            WRAMX_START..=WRAM_END | ECHOX_START..=ECHO_END => 
+                self.wram.read_byte(self.wram_bank_offset() | (addr & 0xFFF)),
 
             // OAM
-            0xFE00...0xFE9F => self.gpu.read_byte(addr),
+            OAM_START..=OAM_END => self.gpu.read_byte(addr),
 
             // Unused
-            0xFEA0...0xFEFF => 0xFF,
+            UNUSABLE_START..=UNUSABLE_END => 0xFF,
 
             // IO Registers
-            0xFF00...0xFF7F => self.read_byte_io(addr),
+            IO_START..=IO_END => self.read_byte_io(addr),
 
             // HRAM
-            0xFF80...0xFFFE => self.hram.read_byte(addr & 0x7F),
+            HRAM_START..=HRAM_END => self.hram.read_byte(addr & 0x7F),
 
             // IE Register
-            0xFFFF => self.inte
+            IE => self.inte
         }
     }
 
     /// Read a byte from an IO Register address (0xFF00 thru 0xFF7F)
     fn read_byte_io(&self, addr: u16) -> u8
+    {
+        // CGB registers don't exist when not running in CGB mode
+        if !self.cgb && is_cgb_register(addr)
+        {
+            return 0xFF
+        }
+
+        self.read_byte_io_raw(addr) | IO_UNUSED_BITS[(addr & 0x7F) as usize]
+    }
+
+    /// Get the offset into WRAM of the bank swapped in at 0xD000
+    fn wram_bank_offset(&self) -> u16
+    {
+        (self.wram_bank.max(1) as u16) << 12
+    }
+
+    /// Read a byte as seen by the OAM DMA controller, which has its own view
+    /// of the bus. Everything from 0xE000 up is a mirror of WRAM, including
+    /// the OAM, unusable and IO regions the CPU sees there, and VRAM reads
+    /// return junk (0xFF) while the GPU is drawing since it holds the VRAM
+    /// bus.
+    pub fn dma_read(&self, addr: u16) -> u8
+    {
+        match addr
+        {
+            VRAM_START..=VRAM_END if self.gpu.vram_busy() => 0xFF,
+            ECHO_START..=0xFFFF => self.read_byte(addr - (ECHO_START - WRAM_START)),
+            _ => self.read_byte(addr)
+        }
+    }
+
+    /// Read a byte from the ROM banks currently mapped in
+    fn read_rom(&self, addr: u16) -> u8
+    {
+        match addr
+        {
+            // ROM Bank 0
+            ROM_START..=ROM_BANK0_END if self.rom_bank0 != 0 => 
+                self.rom[(((self.rom_bank0 as usize) << 14) | (addr as usize)) % self.rom.len()],
+            ROM_START..=ROM_BANK0_END => self.rom[addr as usize],
+
+            // ROM Bank 1
+            _ if self.mbc == MBC::MBC6 => self.mbc6.read_rom(&self.rom, &self.ram, addr),
+            _ => self.rom[(((self.rom_bank as u32) << 14) | ((addr as u32) & 0x3FFF)) as usize]
+        }
+    }
+
+    /// Set the Game Genie codes applied to ROM reads. Other cheats are
+    /// ignored.
+    #[cfg(feature = "cheats")]
+    pub fn set_rom_patches(&mut self, cheats: &[Cheat])
+    {
+        self.rom_patches = cheats.iter()
+            .filter(|c| matches!(c, Cheat::GameGenie { .. }))
+            .cloned()
+            .collect();
+    }
+
+    /// Read the value stored in an IO register without masking unused bits
+    fn read_byte_io_raw(&self, addr: u16) -> u8
     {
         match addr
         {
@@ -304,48 +1302,45 @@ impl Memory
             0xFF00 => self.keypad.read_byte(addr),
 
             // Serial
-            // TODO: serial interface registers
+            0xFF01..=0xFF02 => self.serial.read_byte(addr),
 
             // Timer
-            0xFF04...0xFF07 => self.timer.read_byte(addr),
+            0xFF04..=0xFF07 => self.timer.read_byte(addr),
 
             // Interrupt Flag
             0xFF0F => self.intf,
 
             // Sound
             // TODO: sound controller registers
-            0xFF10...0xFF3F => 0xFF,
+            0xFF10..=0xFF3F => 0xFF,
+
+            // KEY1 - CGB speed switch. Bit 7 is the current speed and bit 0
+            // is set when a switch has been requested.
+            0xFF4D => {
+                let b = match self.speed {
+                    Speed::Normal => 0x00,
+                    Speed::Double => 0x80
+                };
+                b | (self.speed_switch as u8)
+            },
 
             // GPU
-            0xFF40...0xFF4F => {
-                if self.cgb && addr == 0xFF4D
-                {
-                    let b = match self.speed {
-                        Speed::Normal => 0x00,
-                        Speed::Double => 0x80
-                    };
-                    b | (self.speed_switch as u8)
-                }
-                else
-                {
-                    self.gpu.read_byte(addr)
-                }
-            },
+            0xFF40..=0xFF4F => self.gpu.read_byte(addr),
 
             // GPU DMA Transfer
-            0xFF50...0xFF6F => self.gpu.read_byte(addr),
+            0xFF50..=0xFF6F => self.gpu.read_byte(addr),
 
-            0xFF70 =>
-            {
-                if self.target == Target::GameBoyColor
-                {
-                    self.wram_bank as u8
-                }
-                else
-                {
-                    0xFF
-                }
-            }
+            // WRAM bank for CGB mode
+            0xFF70 => self.wram_bank,
+
+            // Undocumented CGB registers. These exist on CGB hardware even
+            // when running a DMG game, except for 0xFF74.
+            0xFF72..=0xFF75 if self.target == Target::GameBoyColor =>
+                self.undocumented[(addr - 0xFF72) as usize],
+
+            // PCM amplitudes of the sound channels
+            0xFF76 if self.target == Target::GameBoyColor => self.spu.pcm12(),
+            0xFF77 if self.target == Target::GameBoyColor => self.spu.pcm34(),
 
             _ => 0xFF
         }
@@ -357,22 +1352,53 @@ impl Memory
         use MBC::*;
         match addr
         {
+            ROM_START..=ROM_END | EXT_RAM_START..=EXT_RAM_END if self.rom.is_empty() => {},
+
+            // MBC6 registers and flash
+            ROM_START..=ROM_END if self.mbc == MBC6 => 
+            {
+                // Flash commands can rewrite anything up to a whole chip
+                if self.mbc6.write_rom(&mut self.ram, addr, val)
+                {
+                    self.ram_dirty[mbc6::SRAM_SIZE / DIRTY_BANK_SIZE..].fill(true);
+                }
+            },
+
+            // Unlicensed mappers
+            ROM_START..=ROM_BANK0_END if self.mbc == WisdomTree => 
+            {
+                let (bank0, bank1) = unlicensed::wisdom_tree_banks(addr);
+                self.rom_bank0 = bank0;
+                self.rom_bank = bank1;
+            },
+            ROM_START..=ROM_END if self.mbc == SachenMMC1 => 
+            {
+                self.sachen.write(addr, val);
+                let (bank0, bank1) = self.sachen.banks();
+                self.rom_bank0 = bank0;
+                self.rom_bank = bank1;
+            },
+
             // ROM Banks
-            0x0000...0x1FFF => 
+            0x0000..=0x1FFF => 
             {
                 match self.mbc
                 {
                     MBC1 | MBC3 | MBC5 => self.ram_enabled = val & 0xF == 0xA,
+
+                    // MBC2 only decodes the enable register when address
+                    // bit 8 is clear, but it takes the same value as the
+                    // other mappers rather than toggling on every write
                     MBC2 => {
                         if addr & 0x100 == 0
                         {
-                            self.ram_enabled = !self.ram_enabled;
+                            self.ram_enabled = val & 0xF == 0xA;
                         }
                     },
-                    Unknown | ROM => {}
+                    Unknown | ROM | MBC6 | TAMA5 | WisdomTree | SachenMMC1 => {}
                 }
             },
-            0x2000...0x3FFF => 
+            0x2000..=0x3FFF => 
             {
                 let val = val as u16;
                 match self.mbc
@@ -405,10 +1431,10 @@ impl Memory
                             self.rom_bank = (self.rom_bank & 0x00FF) | val;
                         }
                     },
-                    Unknown | ROM => {}
+                    Unknown | ROM | MBC6 | TAMA5 | WisdomTree | SachenMMC1 => {}
                 }
             },
-            0x4000...0x5FFF => 
+            0x4000..=0x5FFF => 
             {
                 match self.mbc
                 {
@@ -424,62 +1450,93 @@ impl Memory
                         }
                     },
                     MBC3 => {
-                        // RTC?
-                        self.ram_bank = val & 0x3;
+                        self.ram_bank = if val >= RTC_SECONDS { val & 0xF } else { val & 0x3 };
                     },
                     MBC5 => {
                         self.ram_bank = val & 0xF;
                     },
-                    Unknown | ROM | MBC2 => {}
+                    Unknown | ROM | MBC2 | MBC6 | TAMA5 | WisdomTree | SachenMMC1 => {}
                 }
             },
-            0x6000...0x7FFF => 
+            0x6000..=0x7FFF => 
             {
                 match self.mbc
                 {
                     MBC1 => self.bank_mode = val & 0x1 != 0,
-                    MBC3 => { /* RTC ? */ },
+                    MBC3 => {
+                        if let Some(rtc) = self.rtc.as_mut()
+                        {
+                            rtc.write_latch(val);
+                        }
+                    },
                     _ => {}
                 }
             },
 
+            // VRAM and OAM while the GPU is using them
+            VRAM_START..=VRAM_END | OAM_START..=OAM_END if self.locked_out(addr) => {},
+
             // VRAM
-            0x8000...0x9FFF => self.gpu.write_byte(addr, val),
+            VRAM_START..=VRAM_END => self.gpu.write_byte(addr, val),
 
             // EXT RAM
-            0xA000...0xBFFF => 
+            EXT_RAM_START..=EXT_RAM_END if self.mbc == MBC6 => 
+            {
+                if let Some(i) = self.mbc6.write_ram(&mut self.ram, addr, val)
+                {
+                    self.mark_ram_dirty(i);
+                }
+            },
+            EXT_RAM_START..=EXT_RAM_END if self.rtc_selected() => 
+            {
+                if let (Some(rtc), true) = (self.rtc.as_mut(), self.ram_enabled)
+                {
+                    rtc.write(self.ram_bank, val);
+                }
+            },
+            EXT_RAM_START..=EXT_RAM_END if self.mbc == TAMA5 => 
+            {
+                if let Some(i) = self.tama5.write(&mut self.ram, addr, val)
+                {
+                    self.mark_ram_dirty(i);
+                }
+                self.rom_bank = self.tama5.rom_bank();
+            },
+            EXT_RAM_START..=EXT_RAM_END => 
             {
-                if self.ram_enabled
+                if let Some(i) = self.ext_ram_index(addr)
                 {
                     let val = if self.mbc == MBC::MBC2 { val & 0xF } else { val };
-                    self.ram[(((self.ram_bank as u16) << 12) | 
-                        (addr & 0x1FFF)) as usize] = val;
+                    if self.ram[i] != val
+                    {
+                        self.ram[i] = val;
+                        self.mark_ram_dirty(i);
+                    }
                 }
             },
 
             // WRAM 0 and WRAM 0 mirror
-            0xC000...0xCFFF | 0xE000...0xEFFF => 
+            WRAM_START..=WRAM_BANK0_END | ECHO_START..=ECHO_BANK0_END => 
                 self.wram.write_byte(addr & 0xFFF, val),
 
             // WRAM 1 and WRAM 1 mirror
-            0xD000...0xDFFF | 0xF000...0xFDFF => 
-                self.wram.write_byte((self.wram_bank as u16) << 12 | 
-                (addr & 0xFFF), val),
+            WRAMX_START..=WRAM_END | ECHOX_START..=ECHO_END => 
+                self.wram.write_byte(self.wram_bank_offset() | (addr & 0xFFF), val),
 
             // OAM
-            0xFE00...0xFE9F => self.gpu.write_byte(addr, val),
+            OAM_START..=OAM_END => self.gpu.write_byte(addr, val),
 
             // Unused
-            0xFEA0...0xFEFF => {},
+            UNUSABLE_START..=UNUSABLE_END => {},
 
             // IO Registers
-            0xFF00...0xFF7F => self.write_byte_io(addr, val),
+            IO_START..=IO_END => self.write_byte_io(addr, val),
 
             // HRAM
-            0xFF80...0xFFFE => self.hram.write_byte(addr & 0x7F, val),
+            HRAM_START..=HRAM_END => self.hram.write_byte(addr & 0x7F, val),
 
             // IE Register
-            0xFFFF => self.inte = val
+            IE => self.inte = val
         }
     }
 
@@ -489,13 +1546,20 @@ impl Memory
         match addr
         {
             // Keypad
-            0xFF00 => self.keypad.write_byte(addr, val),
+            0xFF00 => 
+            {
+                self.keypad.write_byte(addr, val);
+                if let Some(command) = self.keypad.take_sgb_command()
+                {
+                    self.sgb_command(&command);
+                }
+            },
             
             // Serial
-            // TODO: serial interface registers
+            0xFF01..=0xFF02 => self.serial.write_byte(addr, val),
 
             // Timer
-            0xFF04...0xFF07 => self.timer.write_byte(addr, val),
+            0xFF04..=0xFF07 => self.timer.write_byte(addr, val),
 
             // Interrupt flag
             0xFF0F => self.intf = val,
@@ -503,23 +1567,33 @@ impl Memory
             // Sound
             // TODO: sound controller registers
 
+            // KEY1 - CGB speed switch. Only bit 0 is writable and DMG mode
+            // games can't switch speeds.
+            0xFF4D => 
+            {
+                if self.cgb
+                {
+                    self.speed_switch = val & 0x01 != 0;
+                }
+            },
+
+            // Boot ROM disable. Once unmapped the boot ROM stays unmapped.
+            0xFF50 if val != 0 => self.boot_rom_mapped = false,
+
             // GPU
-            0xFF40...0xFF6F => 
+            0xFF40..=0xFF6F => 
             {
                 match addr
                 {
                     0xFF46 => GPU::oam_dma_transfer(self, val),
                     0xFF55 => GPU::hdma_dma_transfer(self, val),
-                    0xFF4D if self.cgb => 
-                    {
-                        if val & 0x01 != 0 {
-                            self.speed_switch = true;
-                        }
-                        else {
-                            self.speed_switch = false;
+                    _ => {
+                        self.gpu.write_byte(addr, val);
+                        if self.gpu.take_stat_interrupt()
+                        {
+                            self.intf |= Interrupts::LCDStat as u8;
                         }
-                    },
-                    _ => self.gpu.write_byte(addr, val)
+                    }
                 }
             },
 
@@ -528,11 +1602,18 @@ impl Memory
             {
                 if self.cgb
                 {
-                    let val = val & 0x7; 
-                    self.wram_bank = if val != 0 { val } else { 1 }; 
+                    self.wram_bank = val & 0x7; 
                 }
             }
 
+            // Undocumented CGB registers. Only bits 4-6 of 0xFF75 are
+            // writable and 0xFF74 is only available in CGB mode.
+            0xFF72..=0xFF75 if self.target == Target::GameBoyColor =>
+            {
+                let val = if addr == 0xFF75 { val & 0x70 } else { val };
+                self.undocumented[(addr - 0xFF72) as usize] = val;
+            }
+
             _ => {}
         }
     }
@@ -554,6 +1635,12 @@ impl Memory
     /// Switches speed if a speed switch is requested by CPU
     pub fn switch_speed(&mut self)
     {
+        if !self.cgb
+        {
+            self.speed_switch = false;
+            return
+        }
+
         self.speed_switch = false;
         self.speed = match self.speed 
         { 
@@ -561,4 +1648,11 @@ impl Memory
             Speed::Double => Speed::Normal 
         };
     }
-}
\ No newline at end of file
+}
+
+/// Returns true if the IO register at the given address only exists on the
+/// GameBoy Color
+fn is_cgb_register(addr: u16) -> bool
+{
+    matches!(addr, 0xFF4D | 0xFF4F | 0xFF51..=0xFF56 | 0xFF68..=0xFF6C | 0xFF70 | 0xFF74)
+}
@@ -1,18 +1,88 @@
-use std::iter;
+/// The pattern RAM is filled with when the system is powered on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RamFill
+{
+    /// Fill RAM with 0x00
+    Zero,
+
+    /// Fill RAM with 0xFF
+    Ones,
+
+    /// Fill RAM with the given byte
+    Pattern(u8),
+
+    /// Fill RAM with pseudo-random bytes generated from the given seed. The
+    /// same seed always produces the same contents.
+    Random(u64)
+}
+
+impl Default for RamFill
+{
+    fn default() -> Self
+    {
+        RamFill::Pattern(0xCA)
+    }
+}
 
-pub struct RAM
+/// A fixed size block of RAM stored inline, so accessing it from the bus
+/// doesn't go through a pointer
+pub struct RAM< const N: usize >
 {
-    data: Vec< u8 >
+    data: [u8; N]
 }
 
-impl RAM
+impl< const N: usize > RAM< N >
 {
     /// Create and return a new instance of RAM. The default values are filled
     /// with garbage since they are usually undetermined
-    pub fn new(size: usize) -> Self
+    pub fn new(fill: RamFill) -> Self
     {
-        let data = iter::repeat(0xCA).take(size).collect();
-        RAM { data: data }
+        let mut ram = RAM { data: [0; N] };
+        ram.fill(fill);
+        ram
+    }
+
+    /// Overwrite the entire contents of RAM with the given fill pattern
+    pub fn fill(&mut self, fill: RamFill)
+    {
+        match fill
+        {
+            RamFill::Zero => self.fill_with(|| 0x00),
+            RamFill::Ones => self.fill_with(|| 0xFF),
+            RamFill::Pattern(b) => self.fill_with(|| b),
+            RamFill::Random(seed) => {
+                // xorshift64, the state must never be zero
+                let mut state = if seed == 0 { 0x9E37_79B9_7F4A_7C15 } else { seed };
+                self.fill_with(|| {
+                    state ^= state << 13;
+                    state ^= state >> 7;
+                    state ^= state << 17;
+                    (state >> 32) as u8
+                });
+            }
+        }
+    }
+
+    fn fill_with< F: FnMut() -> u8 >(&mut self, mut f: F)
+    {
+        for b in self.data.iter_mut()
+        {
+            *b = f();
+        }
+    }
+
+    /// Get the entire contents of RAM
+    #[cfg(feature = "savestates")]
+    pub fn bytes(&self) -> &[u8]
+    {
+        &self.data
+    }
+
+    /// Get the entire contents of RAM for writing
+    #[cfg(feature = "savestates")]
+    pub fn bytes_mut(&mut self) -> &mut [u8]
+    {
+        &mut self.data
     }
 
     /// Read a byte from RAM at the given address
@@ -26,4 +96,4 @@ impl RAM
     {
         self.data[addr as usize] = val;
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,223 @@
+/*
+    MBC3 Real Time Clock Registers:
+    -----------------------------------------------
+    $08: Seconds (0 - 59)
+    $09: Minutes (0 - 59)
+    $0A: Hours (0 - 23)
+    $0B: Day counter, low 8 bits
+    $0C: Bit 0: day counter bit 8, bit 6: halt, bit 7: day counter carry
+    -----------------------------------------------
+    Writing $08 thru $0C to 0x4000 - 0x5FFF maps a register at 0xA000 in
+    place of cartridge RAM. Writing 0 then 1 to 0x6000 - 0x7FFF latches the
+    clock into the registers that are read, while writes go to the clock
+    itself. The clock counts emulated time while the game runs, so it stops
+    while the emulator is paused and stays in step with savestates and
+    movies. Battery saves keep the clock along with the time they were made
+    at, and loading one runs the clock forward by the time since then, like
+    the cartridge's own battery would.
+*/
+
+#[cfg(feature = "savestates")]
+use crate::invalid;
+#[cfg(feature = "savestates")]
+use crate::state::{ Reader, Writer };
+#[cfg(feature = "savestates")]
+use std::io::Result as IoResult;
+
+/// The first register number written to 0x4000 - 0x5FFF that selects a
+/// clock register instead of a RAM bank
+pub const RTC_SECONDS: u8 = 0x08;
+
+/// The last clock register number
+pub const RTC_DAY_HIGH: u8 = 0x0C;
+
+/// Day counter high register halt bit
+const HALT: u8 = 0x40;
+
+/// Day counter high register carry bit, set when the day counter overflows
+const CARRY: u8 = 0x80;
+
+/// Bits of each clock register that exist
+const MASKS: [u8; 5] = [0x3F, 0x3F, 0x1F, 0xFF, 0xC1];
+
+/// Seconds counted before the day counter goes up
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// The state of the MBC3 real time clock
+pub struct Rtc
+{
+    /// The clock: seconds, minutes, hours, day low and day high
+    regs: [u8; 5],
+
+    /// The clock as of the last latch, which is what reads see
+    latched: [u8; 5],
+
+    /// The last value written to the latch register
+    latch: u8,
+
+    /// Ticks counted towards the next second
+    ticks: u32,
+
+    /// Ticks in a second of emulated time
+    ticks_per_second: u32
+}
+
+impl Rtc
+{
+    pub fn new(ticks_per_second: u32) -> Self
+    {
+        Rtc {
+            regs: [0; 5],
+            latched: [0; 5],
+            latch: 0xFF,
+            ticks: 0,
+            ticks_per_second
+        }
+    }
+
+    /// Step the clock a given number of ticks forward
+    pub fn step(&mut self, ticks: u32)
+    {
+        if self.regs[4] & HALT != 0 { return }
+
+        self.ticks += ticks;
+        while self.ticks >= self.ticks_per_second
+        {
+            self.ticks -= self.ticks_per_second;
+            self.tick_second();
+        }
+    }
+
+    /// Run the clock a number of seconds forward at once, e.g. for the time
+    /// a game was switched off. Whole days go straight to the day counter.
+    pub fn advance(&mut self, seconds: u64)
+    {
+        if self.regs[4] & HALT != 0 { return }
+
+        for _ in 0..seconds % SECONDS_PER_DAY
+        {
+            self.tick_second();
+        }
+        let days = (((self.regs[4] & 1) as u64) << 8 | self.regs[3] as u64) + seconds / SECONDS_PER_DAY;
+        self.regs[3] = days as u8;
+        self.regs[4] = (self.regs[4] & !1) | ((days >> 8) & 1) as u8;
+        if days >= 0x200
+        {
+            self.regs[4] |= CARRY;
+        }
+    }
+
+    /// Count a second. Seconds, minutes and hours that have been set out of
+    /// range count up to the top of their register and wrap to 0 without
+    /// carrying.
+    fn tick_second(&mut self)
+    {
+        let limits = [60, 60, 24];
+        for (reg, limit) in limits.iter().enumerate()
+        {
+            self.regs[reg] = (self.regs[reg] + 1) & MASKS[reg];
+            if self.regs[reg] != *limit { return }
+            self.regs[reg] = 0;
+        }
+
+        let days = (((self.regs[4] & 1) as u16) << 8 | self.regs[3] as u16) + 1;
+        self.regs[3] = days as u8;
+        self.regs[4] = (self.regs[4] & !1) | ((days >> 8) & 1) as u8;
+        if days == 0x200
+        {
+            self.regs[4] |= CARRY;
+        }
+    }
+
+    /// Read the latched clock register with the given number
+    pub fn read(&self, reg: u8) -> u8
+    {
+        self.latched[(reg - RTC_SECONDS) as usize]
+    }
+
+    /// Write to the clock register with the given number. Writing the
+    /// seconds also restarts the current second.
+    pub fn write(&mut self, reg: u8, val: u8)
+    {
+        let i = (reg - RTC_SECONDS) as usize;
+        self.regs[i] = val & MASKS[i];
+        if reg == RTC_SECONDS
+        {
+            self.ticks = 0;
+        }
+    }
+
+    /// Write to the latch register (0x6000 - 0x7FFF). Writing 0 then 1
+    /// copies the clock into the registers that are read.
+    pub fn write_latch(&mut self, val: u8)
+    {
+        if self.latch == 0 && val == 1
+        {
+            self.latched = self.regs;
+        }
+        self.latch = val;
+    }
+
+    /// Is the clock latched, i.e. was 1 the last value written to the latch
+    /// register?
+    pub fn latched(&self) -> bool
+    {
+        self.latch == 1
+    }
+
+    /// Get the clock and the latched registers, as kept in battery saves
+    pub fn registers(&self) -> ([u8; 5], [u8; 5])
+    {
+        (self.regs, self.latched)
+    }
+
+    /// Restore the clock and the latched registers from a battery save, then
+    /// run the clock the given number of seconds forward
+    pub fn restore(&mut self, regs: [u8; 5], latched: [u8; 5], seconds: u64)
+    {
+        for i in 0..5
+        {
+            self.regs[i] = regs[i] & MASKS[i];
+            self.latched[i] = latched[i] & MASKS[i];
+        }
+        self.ticks = 0;
+        self.advance(seconds);
+    }
+
+    /// Is the clock more than a second away from where `regs` would have
+    /// counted to in the given number of seconds?
+    pub fn drifted(&self, regs: &[u8; 5], seconds: u64) -> bool
+    {
+        let total = |regs: &[u8; 5]| {
+            let days = ((regs[4] & 1) as u64) << 8 | regs[3] as u64;
+            days * SECONDS_PER_DAY + regs[2] as u64 * 3600 + regs[1] as u64 * 60 + regs[0] as u64
+        };
+        let seconds = if regs[4] & HALT != 0 { 0 } else { seconds };
+        total(&self.regs).abs_diff(total(regs) + seconds) > 1
+    }
+
+    /// Write the clock state to a savestate chunk
+    #[cfg(feature = "savestates")]
+    pub fn save_state(&self, w: &mut Writer)
+    {
+        w.bytes(&self.regs);
+        w.bytes(&self.latched);
+        w.u8(self.latch);
+        w.u32(self.ticks);
+    }
+
+    /// Restore the clock state from a savestate chunk
+    #[cfg(feature = "savestates")]
+    pub fn load_state(&mut self, r: &mut Reader) -> IoResult< () >
+    {
+        r.bytes(&mut self.regs)?;
+        r.bytes(&mut self.latched)?;
+        self.latch = r.u8()?;
+        self.ticks = r.u32()?;
+        if self.ticks >= self.ticks_per_second
+        {
+            return Err(invalid("Invalid real time clock in savestate"))
+        }
+        Ok(())
+    }
+}
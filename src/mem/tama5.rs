@@ -0,0 +1,149 @@
+/*
+    TAMA5 Registers:
+    -----------------------------------------------
+    $A001 (write): Select register
+    $A000 (write): Write the low nibble of the value to the selected register
+    $A000 (read):  Read the selected register
+    -----------------------------------------------
+    $0: ROM bank, low 4 bits
+    $1: ROM bank, high bit
+    $4: Value to write to RAM, low nibble
+    $5: Value to write to RAM, high nibble
+    $6: RAM address high bit (bit 0) and command (bits 1 - 3)
+    $7: RAM address low nibble. Writing runs the command.
+    $A: Status, reads as 1 when the controller is ready
+    $C: Value read from RAM, low nibble
+    $D: Value read from RAM, high nibble
+    -----------------------------------------------
+    Used by Tamagotchi 3. The controller holds 32 bytes of battery backed
+    RAM and a real time clock, which isn't emulated.
+*/
+
+#[cfg(feature = "savestates")]
+use crate::state::{ Reader, Writer };
+#[cfg(feature = "savestates")]
+use std::io::Result as IoResult;
+
+/// Size of the RAM inside the TAMA5 controller
+pub const RAM_SIZE: usize = 32;
+
+/// Command written to register 6 that writes a byte to RAM
+const CMD_WRITE: u8 = 0;
+
+/// Command written to register 6 that reads a byte from RAM
+const CMD_READ: u8 = 1;
+
+/// The state of the TAMA5 registers
+pub struct Tama5
+{
+    /// The register selected through 0xA001
+    reg: u8,
+
+    /// ROM bank swapped in at 0x4000
+    rom_bank: u8,
+
+    /// Value to write to RAM
+    write_val: u8,
+
+    /// Value last read from RAM
+    read_val: u8,
+
+    /// RAM address and command being assembled
+    addr: u8,
+    cmd: u8
+}
+
+impl Tama5
+{
+    pub fn new() -> Self
+    {
+        Tama5 {
+            reg: 0,
+            rom_bank: 1,
+            write_val: 0,
+            read_val: 0,
+            addr: 0,
+            cmd: 0
+        }
+    }
+
+    /// Get the ROM bank swapped in at 0x4000
+    pub fn rom_bank(&self) -> u16
+    {
+        self.rom_bank as u16
+    }
+
+    /// Read from the TAMA5 registers (0xA000 - 0xBFFF)
+    pub fn read(&self, addr: u16) -> u8
+    {
+        if addr & 1 != 0 { return 0xFF }
+        match self.reg
+        {
+            0xA => 0xF1,
+            0xC => 0xF0 | (self.read_val & 0xF),
+            0xD => 0xF0 | (self.read_val >> 4),
+            _ => 0xFF
+        }
+    }
+
+    /// Write to the TAMA5 registers (0xA000 - 0xBFFF). Returns the offset
+    /// into RAM written, if the write ran a RAM write command.
+    pub fn write(&mut self, ram: &mut [u8], addr: u16, val: u8) -> Option< usize >
+    {
+        if addr & 1 != 0
+        {
+            self.reg = val & 0xF;
+            return None
+        }
+
+        let val = val & 0xF;
+        match self.reg
+        {
+            0x0 => self.rom_bank = (self.rom_bank & 0x10) | val,
+            0x1 => self.rom_bank = (self.rom_bank & 0x0F) | ((val & 1) << 4),
+            0x4 => self.write_val = (self.write_val & 0xF0) | val,
+            0x5 => self.write_val = (self.write_val & 0x0F) | (val << 4),
+            0x6 => {
+                self.addr = (self.addr & 0x0F) | ((val & 1) << 4);
+                self.cmd = val >> 1;
+            },
+            0x7 => {
+                self.addr = (self.addr & 0x10) | val;
+                let i = self.addr as usize % RAM_SIZE;
+                match self.cmd
+                {
+                    CMD_WRITE => {
+                        ram[i] = self.write_val;
+                        return Some(i)
+                    },
+                    CMD_READ => self.read_val = ram[i],
+                    _ => {}
+                }
+            },
+            _ => {}
+        }
+        None
+    }
+
+    /// Write the TAMA5 registers to a savestate chunk
+    #[cfg(feature = "savestates")]
+    pub fn save_state(&self, w: &mut Writer)
+    {
+        w.bytes(&[self.reg, self.rom_bank, self.write_val, self.read_val, self.addr, self.cmd]);
+    }
+
+    /// Restore the TAMA5 registers from a savestate chunk
+    #[cfg(feature = "savestates")]
+    pub fn load_state(&mut self, r: &mut Reader) -> IoResult< () >
+    {
+        let mut b = [0; 6];
+        r.bytes(&mut b)?;
+        self.reg = b[0];
+        self.rom_bank = b[1] & 0x1F;
+        self.write_val = b[2];
+        self.read_val = b[3];
+        self.addr = b[4];
+        self.cmd = b[5];
+        Ok(())
+    }
+}
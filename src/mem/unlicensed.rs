@@ -0,0 +1,103 @@
+/*
+    Unlicensed cartridges use their own simple bank switching and usually
+    leave the cartridge header incomplete, so they are detected by heuristics
+    instead of the cartridge type byte.
+
+    Wisdom Tree:
+    -----------------------------------------------
+    $0000-$3FFF (write): The low byte of the address selects a 32KB ROM bank
+                         that is swapped in at $0000-$7FFF
+    -----------------------------------------------
+
+    Sachen MMC1:
+    -----------------------------------------------
+    $0000-$1FFF (write): Base ROM bank
+    $2000-$3FFF (write): ROM bank (0 selects 1)
+    $4000-$5FFF (write): ROM bank mask
+    -----------------------------------------------
+    Bits set in the mask are taken from the base bank instead of the ROM
+    bank, and the base bank is swapped in at $0000-$3FFF. The base bank and
+    mask can only be changed while bits 4 & 5 of the ROM bank are set.
+
+    On hardware Sachen cartridges scramble the header until the boot ROM has
+    finished reading the logo. No boot ROM is run, so they always start
+    unscrambled.
+*/
+
+#[cfg(feature = "savestates")]
+use crate::state::{ Reader, Writer };
+#[cfg(feature = "savestates")]
+use std::io::Result as IoResult;
+
+/// The logo every licensed cartridge has at 0x0104 - 0x0133. The boot ROM
+/// refuses to start cartridges without it.
+pub const NINTENDO_LOGO: [u8; 48] = [
+    0xCE, 0xED, 0x66, 0x66, 0xCC, 0x0D, 0x00, 0x0B, 0x03, 0x73, 0x00, 0x83,
+    0x00, 0x0C, 0x00, 0x0D, 0x00, 0x08, 0x11, 0x1F, 0x88, 0x89, 0x00, 0x0E,
+    0xDC, 0xCC, 0x6E, 0xE6, 0xDD, 0xDD, 0xD9, 0x99, 0xBB, 0xBB, 0x67, 0x63,
+    0x6E, 0x0E, 0xEC, 0xCC, 0xDD, 0xDC, 0x99, 0x9F, 0xBB, 0xB9, 0x33, 0x3E
+];
+
+/// Get the 16KB ROM banks swapped in at 0x0000 and 0x4000 after a Wisdom
+/// Tree bank switch
+pub fn wisdom_tree_banks(addr: u16) -> (u16, u16)
+{
+    let bank = (addr & 0xFF) << 1;
+    (bank, bank + 1)
+}
+
+/// The state of the Sachen MMC1 registers
+pub struct Sachen
+{
+    base: u8,
+    mask: u8,
+    bank: u8
+}
+
+impl Sachen
+{
+    pub fn new() -> Self
+    {
+        Sachen { base: 0, mask: 0, bank: 1 }
+    }
+
+    /// Handle a write to the mapper registers (0x0000 - 0x7FFF)
+    pub fn write(&mut self, addr: u16, val: u8)
+    {
+        let unlocked = self.bank & 0x30 == 0x30;
+        match addr >> 13
+        {
+            0 if unlocked => self.base = val,
+            1 => self.bank = if val == 0 { 1 } else { val },
+            2 if unlocked => self.mask = val,
+            _ => {}
+        }
+    }
+
+    /// Get the 16KB ROM banks swapped in at 0x0000 and 0x4000
+    pub fn banks(&self) -> (u16, u16)
+    {
+        let bank0 = self.base & self.mask;
+        let bank1 = (self.bank & !self.mask) | (self.base & self.mask);
+        (bank0 as u16, bank1 as u16)
+    }
+
+    /// Write the Sachen registers to a savestate chunk
+    #[cfg(feature = "savestates")]
+    pub fn save_state(&self, w: &mut Writer)
+    {
+        w.bytes(&[self.base, self.mask, self.bank]);
+    }
+
+    /// Restore the Sachen registers from a savestate chunk
+    #[cfg(feature = "savestates")]
+    pub fn load_state(&mut self, r: &mut Reader) -> IoResult< () >
+    {
+        let mut b = [0; 3];
+        r.bytes(&mut b)?;
+        self.base = b[0];
+        self.mask = b[1];
+        self.bank = b[2];
+        Ok(())
+    }
+}
@@ -0,0 +1,210 @@
+use crate::ButtonState;
+#[cfg(feature = "savestates")]
+use crate::invalid;
+#[cfg(feature = "savestates")]
+use crate::state::{ Reader, Writer };
+use std::fs::File;
+use std::io::{ Error, ErrorKind, Read, Write };
+use std::io::Result as IoResult;
+use std::path::Path;
+
+/// The characters used for each button in a movie file, in the order they
+/// appear on a line. A '.' in place of a character means the button is not
+/// held.
+const BUTTON_CHARS: [char; 8] = ['U', 'D', 'L', 'R', 's', 'S', 'B', 'A'];
+
+/// The button for each character in `BUTTON_CHARS`. Savestates store the
+/// buttons of each frame as bits in the same order.
+const BUTTON_ORDER: [ButtonState; 8] = [
+    ButtonState::UP, ButtonState::DOWN, ButtonState::LEFT, ButtonState::RIGHT,
+    ButtonState::SELECT, ButtonState::START, ButtonState::B, ButtonState::A
+];
+
+/// The comment line holding the rerecord count of a movie
+const RERECORDS_HEADER: &str = "# rerecords:";
+
+/// A recording of the buttons held on every frame, used to play back the same
+/// inputs deterministically.
+///
+/// Movie files are plain text with one line per frame. Each line has one
+/// character per button in the order `UDLRsSBA` (Up, Down, Left, Right,
+/// Select, Start, B, A) where a '.' means the button isn't held. Empty lines
+/// and lines starting with '#' are ignored, except for a `# rerecords: N`
+/// line holding the number of times a savestate was loaded while the movie
+/// was being recorded.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Movie
+{
+    frames: Vec< ButtonState >,
+    rerecords: u32
+}
+
+impl Movie
+{
+    /// Create and return a new empty movie
+    pub fn new() -> Self
+    {
+        Movie { frames: Vec::new(), rerecords: 0 }
+    }
+
+    /// Load a movie from the given file
+    pub fn load(path: &Path) -> IoResult< Self >
+    {
+        let mut src = File::open(path)?;
+        let mut text = String::new();
+        src.read_to_string(&mut text)?;
+        Movie::parse(&text)
+    }
+
+    /// Save the movie to the given file
+    pub fn save(&self, path: &Path) -> IoResult< () >
+    {
+        let mut dst = File::create(path)?;
+        dst.write_all(self.to_text().as_bytes())
+    }
+
+    /// Parse a movie from its text representation
+    pub fn parse(text: &str) -> IoResult< Self >
+    {
+        let mut movie = Movie::new();
+        for (n, line) in text.lines().enumerate()
+        {
+            let line = line.trim();
+            if let Some(count) = line.strip_prefix(RERECORDS_HEADER)
+            {
+                movie.rerecords = count.trim().parse().map_err(|_| Error::new(ErrorKind::InvalidData,
+                    format!("Invalid rerecord count on line {}: {}", n + 1, line)))?;
+                continue
+            }
+            if line.is_empty() || line.starts_with('#') { continue }
+
+            let chars: Vec< char > = line.chars().collect();
+            if chars.len() != BUTTON_CHARS.len()
+            {
+                return Err(Error::new(ErrorKind::InvalidData,
+                    format!("Invalid movie frame on line {}: {}", n + 1, line)))
+            }
+
+            let mut held = ButtonState::empty();
+            for ((&c, &b), &button) in chars.iter().zip(BUTTON_CHARS.iter()).zip(BUTTON_ORDER.iter())
+            {
+                match c
+                {
+                    '.' => (),
+                    c if c == b => held.insert(button),
+                    _ => return Err(Error::new(ErrorKind::InvalidData,
+                        format!("Invalid button '{}' on line {}", c, n + 1)))
+                }
+            }
+
+            movie.push(held);
+        }
+
+        Ok(movie)
+    }
+
+    /// Get the text representation of the movie
+    pub fn to_text(&self) -> String
+    {
+        let mut text = String::with_capacity(self.frames.len() * 9);
+        if self.rerecords != 0
+        {
+            text.push_str(&format!("{} {}\n", RERECORDS_HEADER, self.rerecords));
+        }
+        for state in self.frames.iter()
+        {
+            for (&button, &c) in BUTTON_ORDER.iter().zip(BUTTON_CHARS.iter())
+            {
+                text.push(if state.contains(button) { c } else { '.' });
+            }
+            text.push('\n');
+        }
+        text
+    }
+
+    /// Append a frame of input to the end of the movie
+    pub fn push(&mut self, state: ButtonState)
+    {
+        self.frames.push(state);
+    }
+
+    /// Get the input for the given frame, if the movie is long enough
+    pub fn frame(&self, i: usize) -> Option< ButtonState >
+    {
+        self.frames.get(i).cloned()
+    }
+
+    /// Get the input for every frame of the movie
+    pub fn frames(&self) -> &[ButtonState]
+    {
+        &self.frames
+    }
+
+    /// Get the number of frames in the movie
+    pub fn len(&self) -> usize
+    {
+        self.frames.len()
+    }
+
+    /// Is the movie empty?
+    pub fn is_empty(&self) -> bool
+    {
+        self.frames.is_empty()
+    }
+
+    /// Get the number of times the movie was rewound to a savestate while it
+    /// was being recorded
+    pub fn rerecords(&self) -> u32
+    {
+        self.rerecords
+    }
+
+    /// Write the frames of the movie to a savestate chunk. The rerecord count
+    /// isn't saved since it belongs to the recording, not to the state.
+    #[cfg(feature = "savestates")]
+    pub(crate) fn save_state(&self, w: &mut Writer)
+    {
+        w.u32(self.frames.len() as u32);
+        for state in self.frames.iter()
+        {
+            w.u8(BUTTON_ORDER.iter().enumerate()
+                .fold(0, |bits, (i, &button)| bits | ((state.contains(button) as u8) << i)));
+        }
+    }
+
+    /// Replace the frames of the movie with those saved by `save_state`
+    #[cfg(feature = "savestates")]
+    pub(crate) fn load_state(&mut self, r: &mut Reader) -> IoResult< () >
+    {
+        let len = r.u32()? as usize;
+        let mut frames = Vec::new();
+        for _ in 0..len
+        {
+            let bits = r.u8()?;
+            frames.push(BUTTON_ORDER.iter().enumerate()
+                .filter(|&(i, _)| bits & (1 << i) != 0)
+                .fold(ButtonState::empty(), |held, (_, &button)| held | button));
+        }
+        if !r.is_empty()
+        {
+            return Err(invalid("Malformed movie in savestate"))
+        }
+        self.frames = frames;
+        Ok(())
+    }
+
+    /// Drop every frame after the first len
+    pub(crate) fn truncate(&mut self, len: usize)
+    {
+        self.frames.truncate(len);
+    }
+
+    /// Rewind the recording to the frames of a movie loaded from a
+    /// savestate, counting a rerecord
+    #[cfg(feature = "savestates")]
+    pub(crate) fn rewind_to(&mut self, state: Movie)
+    {
+        self.frames = state.frames;
+        self.rerecords = self.rerecords.wrapping_add(1);
+    }
+}
@@ -0,0 +1,416 @@
+use crate::gpu::{ BgAttributes, Color, Palette, Video, NUM_TILES, WIDTH };
+use std::sync::Arc;
+use std::sync::mpsc::{ channel, Receiver, Sender };
+use std::thread;
+
+/// The registers and palettes a line is drawn with, copied from the GPU at
+/// the start of HBlank. Together with a snapshot of VRAM and OAM this is
+/// everything needed to draw the line, so lines can be drawn on another
+/// thread.
+#[derive(Clone)]
+pub struct LineState
+{
+    /// The line being drawn (LY)
+    pub ly: u8,
+
+    pub scx: u8,
+    pub scy: u8,
+    pub wx: u8,
+
+    /// The line of the window to draw
+    pub win_line: u8,
+
+    /// Is the window drawn on this line?
+    pub window: bool,
+
+    pub bg_enabled: bool,
+    pub obj_enabled: bool,
+    pub obj_size: bool,
+    pub tile_data: bool,
+    pub bg_tmap: bool,
+    pub win_tmap: bool,
+    pub is_cgb: bool,
+    pub is_sgb: bool,
+
+    /// Are overlapping sprites prioritized by X coordinate (OPRI)?
+    pub obj_x_priority: bool,
+
+    /// Compiled DMG palettes
+    pub pal: Palette,
+
+    /// Compiled CGB BG and OBJ palettes
+    pub cbgp: [[Color; 4]; 8],
+    pub cobp: [[Color; 4]; 8],
+
+    /// SGB attribute file and palettes
+    pub sgb_atf: [u8; 20 * 18],
+    pub sgb_pal: [[Color; 4]; 4],
+
+    /// Layers hidden for debugging are left out
+    pub show_bg: bool,
+    pub show_window: bool,
+    pub show_obj: bool
+}
+
+/// The layer a pixel on the screen was drawn from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum PixelLayer
+{
+    /// Nothing was drawn: the BG is turned off on the DMG, or the LCD is off
+    #[default]
+    Blank,
+    Background,
+    Window,
+    Sprite
+}
+
+/// Where a pixel on the screen came from, before its color index was
+/// looked up in a palette. Lets frontends colorize games themselves or hide
+/// layers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct PixelSource
+{
+    pub layer: PixelLayer,
+
+    /// The color index in the tile, 0 - 3
+    pub color: u8,
+
+    /// The palette the color index was looked up in: 0 - 7 in CGB mode,
+    /// otherwise 0 for the BG and window (BGP) and 0 or 1 for sprites (OBP0
+    /// or OBP1)
+    pub palette: u8
+}
+
+/// A row of a BG or window tile fetched from a tile map, with the attributes
+/// needed to draw it
+#[derive(Clone, Copy)]
+struct TileRow
+{
+    pixels: [u8; 8],
+    priority: bool,
+    hflip: bool,
+    palette: [Color; 4],
+    palette_i: u8
+}
+
+impl LineState
+{
+    /// Draw the line over the given pixels, recording where each one came
+    /// from in sources
+    pub fn draw(&self, video: &Video, pixels: &mut [Color; WIDTH], sources: &mut [PixelSource; WIDTH])
+    {
+        // BG color index of every pixel on the line, used for sprite priority
+        let mut scanline = [0u8; WIDTH];
+
+        // LCDC bit 0 turns the BG and window off on the DMG, leaving the line
+        // white. On the CGB they are always drawn and the bit only takes
+        // away their priority over sprites. A BG hidden for debugging also
+        // leaves the line white, with every sprite drawn over it.
+        let bg_shown = self.bg_enabled || self.is_cgb;
+        if !bg_shown || !self.show_bg
+        {
+            let white = [[0xFF; 4]; 4];
+            for (x, pixel) in pixels.iter_mut().enumerate()
+            {
+                *pixel = self.map_color(&white, 0, x);
+            }
+            sources.fill(PixelSource::default());
+        }
+
+        // Render BG
+        if bg_shown && self.show_bg { self.render_background(video, &mut scanline, pixels, sources); }
+
+        // Render Window
+        if bg_shown && self.window && self.show_window
+        {
+            self.render_window(video, &mut scanline, pixels, sources);
+        }
+
+        // Render Sprites
+        if self.obj_enabled && self.show_obj { self.render_obj(video, &mut scanline, pixels, sources); }
+    }
+
+    fn render_background(&self, video: &Video, scanline: &mut [u8; WIDTH], pixels: &mut [Color; WIDTH],
+        sources: &mut [PixelSource; WIDTH])
+    {
+        let map_base = if self.bg_tmap { 0x1C00 } else { 0x1800 };
+        let y = self.ly.wrapping_add(self.scy);
+        self.render_tile_line(video, scanline, pixels, sources, PixelLayer::Background,
+            map_base, self.scx as usize, y, 0);
+    }
+
+    fn render_window(&self, video: &Video, scanline: &mut [u8; WIDTH], pixels: &mut [Color; WIDTH],
+        sources: &mut [PixelSource; WIDTH])
+    {
+        let map_base = if self.win_tmap { 0x1C00 } else { 0x1800 };
+        let y = self.win_line;
+
+        // The window starts at WX - 7. For WX = 0 thru 6 that's off the left
+        // edge of the screen so the first 7 - WX pixels of the window are
+        // skipped instead.
+        let (map_x, start) = if self.wx < 7 {
+            (7 - self.wx as usize, 0)
+        } else {
+            (0, self.wx as usize - 7)
+        };
+
+        self.render_tile_line(video, scanline, pixels, sources, PixelLayer::Window, map_base, map_x, y, start);
+    }
+
+    /// Render a line of a BG or window tile map to the screen. Pixels are
+    /// drawn from screen x `start` to the right edge of the screen, starting
+    /// at pixel (`map_x`, `y`) of the tile map.
+    #[allow(clippy::too_many_arguments)]
+    fn render_tile_line(&self, video: &Video, scanline: &mut [u8; WIDTH], pixels: &mut [Color; WIDTH],
+        sources: &mut [PixelSource; WIDTH], layer: PixelLayer, map_base: usize, map_x: usize, y: u8, start: usize)
+    {
+        let map_base = map_base + ((y as usize) >> 3) * 32;
+        let tile_y = y % 8;
+
+        let mut row = None;
+        for (i, (out, pixel)) in scanline.iter_mut().zip(pixels.iter_mut()).enumerate().skip(start)
+        {
+            let px = (map_x + i - start) % 256;
+            let map_offset = px >> 3;
+
+            // Only fetch a new tile when the previous one is finished
+            let tile = match row
+            {
+                Some((offset, tile)) if offset == map_offset => tile,
+                _ => {
+                    let tile = self.fetch_tile_row(video, map_base + map_offset, tile_y);
+                    row = Some((map_offset, tile));
+                    tile
+                }
+            };
+
+            let x = px & 7;
+            let color_i = tile.pixels[if tile.hflip { 7 - x } else { x }];
+
+            // Sprites are always drawn on top of a CGB BG with priority off,
+            // as if every BG pixel were color 0
+            *out = if !self.bg_enabled { 0 } else if tile.priority { 4 } else { color_i };
+            *pixel = self.map_color(&tile.palette, color_i, i);
+            sources[i] = PixelSource { layer, color: color_i, palette: tile.palette_i };
+        }
+    }
+
+    /// Fetch row `y` of the tile at the given tile map address, along with
+    /// the tile's CGB attributes
+    fn fetch_tile_row(&self, video: &Video, map_addr: usize, y: u8) -> TileRow
+    {
+        let tile_base = if !self.tile_data { 256 } else { 0 };
+        let tile_i = self.add_tile_i(tile_base, video.vram[0][map_addr]);
+
+        if self.is_cgb
+        {
+            let attrs = BgAttributes::from_byte(video.vram[1][map_addr]);
+            TileRow {
+                pixels: tile_row(video, tile_i, attrs.bank as usize, y, attrs.y_flip),
+                priority: attrs.priority,
+                hflip: attrs.x_flip,
+                palette: self.cbgp[attrs.palette as usize],
+                palette_i: attrs.palette
+            }
+        }
+        else
+        {
+            TileRow {
+                pixels: tile_row(video, tile_i, 0, y, false),
+                priority: false,
+                hflip: false,
+                palette: self.pal.bg,
+                palette_i: 0
+            }
+        }
+    }
+
+    /// Get the color to draw for the given color index of a palette at
+    /// screen x on the current line. Applies the SGB palettes when in SGB
+    /// mode.
+    fn map_color(&self, pal: &[Color; 4], color_i: u8, x: usize) -> Color
+    {
+        if self.is_sgb && !self.is_cgb
+        {
+            let sgb_addr = (x >> 3) + (self.ly as usize >> 3) * 20;
+            let mapped = self.sgb_atf[sgb_addr] as usize;
+            match pal[color_i as usize][0]
+            {
+                0 => self.sgb_pal[mapped][3],
+                96 => self.sgb_pal[mapped][2],
+                192 => self.sgb_pal[mapped][1],
+                255 => self.sgb_pal[mapped][0],
+                _ => [0, 0, 0, 0]
+            }
+        }
+        else
+        {
+            pal[color_i as usize]
+        }
+    }
+
+    fn render_obj(&self, video: &Video, scanline: &mut [u8; WIDTH], pixels: &mut [Color; WIDTH],
+        sources: &mut [PixelSource; WIDTH])
+    {
+        let line = self.ly as i32;
+        let y_size = if self.obj_size { 16 } else { 8 };
+
+        // Sprites on the line in priority order: by OAM index, or by X
+        // coordinate with ties going to the lower index. Each pixel is taken
+        // by the first sprite that is opaque there, even when that sprite is
+        // hidden behind the BG.
+        let mut objs: Vec< &[u8] > = video.oam.chunks_exact(4)
+            .filter(|obj| {
+                let y_offset = (obj[0] as i32) - 16;
+                let x_offset = (obj[1] as i32) - 8;
+                y_offset <= line && y_offset + y_size > line && x_offset > -8 && x_offset < WIDTH as i32
+            })
+            .collect();
+        if self.obj_x_priority
+        {
+            objs.sort_by_key(|obj| obj[1]);
+        }
+        let mut taken = [false; WIDTH];
+
+        for obj in objs
+        {
+            let y_offset = (obj[0] as i32) - 16;
+            let x_offset = (obj[1] as i32) - 8;
+            let flags = obj[3];
+
+            // Vertical flip flips the whole sprite, so a flipped 8x16 sprite
+            // draws the bottom tile upside down at the top. Bit 0 of the
+            // tile index is ignored for 8x16 sprites.
+            let mut y = line - y_offset;
+            if flags & 0x40 != 0
+            {
+                y = y_size - 1 - y;
+            }
+            let tile = if y_size == 16
+            {
+                (obj[2] & 0xFE) as usize + (y >> 3) as usize
+            }
+            else
+            {
+                obj[2] as usize
+            };
+
+            // CGB sprites pick one of the 8 OBJ palettes with bits 0-2 and a
+            // VRAM bank with bit 3
+            let (pal, palette_i, bank) = if self.is_cgb
+            {
+                (self.cobp[(flags & 0x7) as usize], flags & 0x7, ((flags >> 3) & 1) as usize)
+            }
+            else if flags & 0x10 != 0
+            {
+                (self.pal.obp1, 1, 0)
+            }
+            else
+            {
+                (self.pal.obp0, 0, 0)
+            };
+
+            let row = tile_row(video, tile, bank, (y & 7) as u8, false);
+
+            for x in 0..8
+            {
+                let screen_x = x_offset + x;
+                if screen_x < 0 || screen_x >= WIDTH as i32 || taken[screen_x as usize]
+                {
+                    continue
+                }
+
+                let color_i = row[if flags & 0x20 != 0 { 7 - x } else { x } as usize];
+                if color_i == 0 { continue }
+                taken[screen_x as usize] = true;
+
+                if scanline[screen_x as usize] > 3 ||
+                    (flags & 0x80 != 0 && scanline[screen_x as usize] != 0)
+                {
+                    continue
+                }
+
+                pixels[screen_x as usize] = self.map_color(&pal, color_i, screen_x as usize);
+                sources[screen_x as usize] = PixelSource { layer: PixelLayer::Sprite, color: color_i, palette: palette_i };
+            }
+        }
+    }
+
+    fn add_tile_i(&self, base: usize, tile_i: u8) -> usize
+    {
+        if self.tile_data { base + tile_i as usize } else { (base as isize + (tile_i as i8 as isize)) as usize }
+    }
+}
+
+/// Get row `y` of the compiled tile with the given index in the given VRAM
+/// bank
+fn tile_row(video: &Video, tile_i: usize, bank: usize, y: u8, vflip: bool) -> [u8; 8]
+{
+    let y = if vflip { 7 - y } else { y };
+    video.tiles.data[tile_i + bank * NUM_TILES][y as usize]
+}
+
+/// A line waiting to be drawn by the worker thread
+struct Job
+{
+    video: Arc< Video >,
+    line: LineState,
+    pixels: [Color; WIDTH],
+    sources: [PixelSource; WIDTH]
+}
+
+/// Draws lines on a separate thread. Each line is sent with a snapshot of
+/// VRAM and OAM, which the GPU only copies when it writes to them while the
+/// worker still holds the snapshot.
+pub struct RenderWorker
+{
+    jobs: Sender< Job >,
+    lines: Receiver< (u8, [Color; WIDTH], [PixelSource; WIDTH]) >,
+
+    /// Number of lines sent that haven't been collected yet
+    pending: usize
+}
+
+impl RenderWorker
+{
+    /// Start the worker thread. It stops when the worker is dropped.
+    pub fn new() -> Self
+    {
+        let (jobs, job_rx) = channel::< Job >();
+        let (line_tx, lines) = channel();
+        thread::spawn(move || {
+            for job in job_rx
+            {
+                let Job { video, line, mut pixels, mut sources } = job;
+                line.draw(&video, &mut pixels, &mut sources);
+
+                // Release the snapshot before handing the line back so the
+                // GPU doesn't have to copy VRAM on its next write
+                drop(video);
+                if line_tx.send((line.ly, pixels, sources)).is_err() { break }
+            }
+        });
+
+        RenderWorker { jobs, lines, pending: 0 }
+    }
+
+    /// Queue a line to be drawn over the given pixels
+    pub fn draw(&mut self, video: Arc< Video >, line: LineState, pixels: [Color; WIDTH],
+        sources: [PixelSource; WIDTH])
+    {
+        self.jobs.send(Job { video, line, pixels, sources }).expect("Render worker stopped");
+        self.pending += 1;
+    }
+
+    /// Wait for every queued line to be drawn and pass each one to f in
+    /// order, along with its line number and pixel sources
+    pub fn finish< F: FnMut(u8, &[Color; WIDTH], &[PixelSource; WIDTH]) >(&mut self, mut f: F)
+    {
+        while self.pending > 0
+        {
+            let (ly, pixels, sources) = self.lines.recv().expect("Render worker stopped");
+            f(ly, &pixels, &sources);
+            self.pending -= 1;
+        }
+    }
+}
@@ -0,0 +1,251 @@
+use crate::mem::Speed;
+use crate::cpu::Interrupts;
+use crate::link::LinkPort;
+#[cfg(feature = "savestates")]
+use crate::state::{ Reader, Writer };
+#[cfg(feature = "savestates")]
+use std::io::Result as IoResult;
+
+/// Machine cycles it takes to shift out a single bit with the normal 8192Hz
+/// internal clock
+const NORMAL_BIT_CYCLES: u32 = 128;
+
+/// Machine cycles it takes to shift out a single bit with the CGB 262144Hz
+/// fast internal clock
+const FAST_BIT_CYCLES: u32 = 4;
+
+/// The most bytes of serial output kept. Older bytes are dropped once the
+/// game has sent more than this.
+pub const MAX_SERIAL_OUTPUT: usize = 0x10000;
+
+pub struct Serial
+{
+    /// Serial Transfer Data (SB) register. Holds the byte being shifted out
+    /// and the bits shifted in. Located at 0xFF01.
+    sb: u8,
+
+    /// Serial Transfer Control (SC) register. Bit 7 starts a transfer, bit 1
+    /// selects the CGB fast clock and bit 0 selects the internal clock.
+    /// Located at 0xFF02.
+    sc: u8,
+
+    /// Should CGB functionality be used?
+    pub is_cgb: bool,
+
+    /// Number of bits shifted in the current transfer
+    bits: u8,
+
+    /// Machine cycles counted towards shifting the next bit
+    clock: u32,
+
+    /// Ticks left over from the last step that don't make up a whole
+    /// M-cycle yet. Only an overclocked CPU steps by partial M-cycles.
+    ticks: u32,
+
+    /// The last MAX_SERIAL_OUTPUT bytes sent over the serial port
+    output: Vec< u8 >,
+
+    /// The link cable plugged into the serial port, if any
+    link: Option< LinkPort >
+}
+
+impl Serial
+{
+    pub fn new() -> Self
+    {
+        Serial {
+            sb: 0,
+            sc: 0,
+            is_cgb: false,
+            bits: 0,
+            clock: 0,
+            ticks: 0,
+            output: Vec::new(),
+            link: None
+        }
+    }
+
+    /// Step the serial port a given number of ticks forward
+    pub fn step(&mut self, ticks: u32, intf: &mut u8, speed: Speed)
+    {
+        // Transfers driven by an external clock shift a bit in for every
+        // clock edge a link partner sent
+        if self.sc & 0x81 == 0x80
+        {
+            while let Some(bit) = self.link.as_ref().and_then(|link| link.receive())
+            {
+                self.shift_bit(bit, intf);
+                if self.sc & 0x80 == 0 { break }
+            }
+            return
+        }
+        if self.sc & 0x81 != 0x81 { return }
+
+        let per_cycle = match speed
+        {
+            Speed::Normal => 4,
+            Speed::Double => 2
+        };
+        self.ticks += ticks;
+        let ticks = self.ticks / per_cycle;
+        self.ticks %= per_cycle;
+        let period = self.bit_cycles();
+
+        self.clock += ticks;
+        while self.clock >= period
+        {
+            self.clock -= period;
+
+            // Without a link partner the bits shifted in are always 1
+            let bit = match self.link
+            {
+                Some(ref link) => link.shift(self.sb >> 7),
+                None => 1
+            };
+            self.shift_bit(bit, intf);
+            if self.sc & 0x80 == 0 { break }
+        }
+    }
+
+    /// Shift a bit into SB, ending the transfer once all 8 are in
+    fn shift_bit(&mut self, bit: u8, intf: &mut u8)
+    {
+        self.sb = (self.sb << 1) | bit;
+        self.bits += 1;
+        if self.bits == 8
+        {
+            self.finish_transfer(intf);
+        }
+    }
+
+    /// Get the number of ticks until the current transfer completes and
+    /// requests a serial interrupt
+    pub fn ticks_until_interrupt(&self, speed: Speed) -> u32
+    {
+        if self.sc & 0x81 != 0x81
+        {
+            return u32::MAX
+        }
+
+        let period = self.bit_cycles();
+        let remaining = ((8 - self.bits as u32) * period).saturating_sub(self.clock).max(1);
+        let remaining = match speed
+        {
+            Speed::Normal => remaining * 4,
+            Speed::Double => remaining * 2
+        };
+        remaining - self.ticks
+    }
+
+    /// Plug a link cable into the serial port, or unplug it with None.
+    /// Returns the previously plugged in cable.
+    pub fn set_link(&mut self, link: Option< LinkPort >) -> Option< LinkPort >
+    {
+        let old = ::std::mem::replace(&mut self.link, link);
+        if let Some(ref old) = old
+        {
+            old.listen(None);
+        }
+        self.update_link();
+        old
+    }
+
+    /// Get the bytes that have been sent over the serial port
+    pub fn output(&self) -> &[u8]
+    {
+        &self.output
+    }
+
+    /// Take the bytes that have been sent over the serial port, leaving the
+    /// output empty
+    pub fn take_output(&mut self) -> Vec< u8 >
+    {
+        ::std::mem::take(&mut self.output)
+    }
+
+    /// Write the serial port state to a savestate chunk. The output log is
+    /// not part of the state.
+    #[cfg(feature = "savestates")]
+    pub fn save_state(&self, w: &mut Writer)
+    {
+        w.bytes(&[self.sb, self.sc, self.bits]);
+        w.u32(self.clock);
+    }
+
+    /// Restore the serial port state from a savestate chunk
+    #[cfg(feature = "savestates")]
+    pub fn load_state(&mut self, r: &mut Reader) -> IoResult< () >
+    {
+        let mut b = [0; 3];
+        r.bytes(&mut b)?;
+        self.sb = b[0];
+        self.sc = b[1];
+        self.bits = b[2];
+        self.clock = r.u32()?;
+        self.ticks = 0;
+        self.update_link();
+        Ok(())
+    }
+
+    /// End the current transfer and request a serial interrupt
+    fn finish_transfer(&mut self, intf: &mut u8)
+    {
+        self.sc &= 0x7F;
+        self.clock = 0;
+        *intf |= Interrupts::Serial as u8;
+        self.update_link();
+    }
+
+    /// Tell the link partner whether a transfer driven by its clock is
+    /// awaited, which bits it will shift in and how many it has already
+    fn update_link(&self)
+    {
+        if let Some(ref link) = self.link
+        {
+            link.listen(if self.sc & 0x81 == 0x80 { Some((self.sb, self.bits)) } else { None });
+        }
+    }
+
+    /// Get the number of machine cycles it takes to shift a single bit
+    fn bit_cycles(&self) -> u32
+    {
+        if self.is_cgb && self.sc & 0x02 != 0 { FAST_BIT_CYCLES } else { NORMAL_BIT_CYCLES }
+    }
+
+    pub fn read_byte(&self, addr: u16) -> u8
+    {
+        match addr
+        {
+            0xFF01 => self.sb,
+
+            // The fast clock bit doesn't exist on DMG and reads as 1
+            0xFF02 => if self.is_cgb { self.sc } else { self.sc | 0x02 },
+
+            _ => 0xFF
+        }
+    }
+
+    pub fn write_byte(&mut self, addr: u16, val: u8)
+    {
+        match addr
+        {
+            0xFF01 => self.sb = val,
+            0xFF02 => 
+            {
+                self.sc = if self.is_cgb { val & 0x83 } else { val & 0x81 };
+                if self.sc & 0x80 != 0
+                {
+                    if self.output.len() == MAX_SERIAL_OUTPUT
+                    {
+                        self.output.remove(0);
+                    }
+                    self.output.push(self.sb);
+                    self.bits = 0;
+                    self.clock = 0;
+                }
+            },
+            _ => return
+        }
+        self.update_link();
+    }
+}
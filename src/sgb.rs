@@ -0,0 +1,313 @@
+/*
+    Super GameBoy command packets are sent to the SGB by pulsing the P14 and
+    P15 lines of the joypad register (0xFF00):
+    -----------------------------------------------
+    P14 = 0, P15 = 0: Reset, start of a new packet
+    P14 = 0, P15 = 1: A '0' bit
+    P14 = 1, P15 = 0: A '1' bit
+    P14 = 1, P15 = 1: End of pulse
+    -----------------------------------------------
+    Each packet is 16 bytes sent LSB first followed by a '0' stop bit. The low
+    3 bits of the first byte give the number of packets in the command and the
+    upper 5 bits give the command code.
+
+    Larger blocks of data such as the border are sent with VRAM transfers:
+    after the command the game displays the 4KB of data as BG tiles and the
+    SGB reads it back from the screen on the next frame.
+    http://gbdev.gg8.se/wiki/articles/SGB_Functions
+*/
+
+use crate::gpu::rgb555;
+#[cfg(feature = "savestates")]
+use crate::invalid;
+#[cfg(feature = "savestates")]
+use crate::state::{ Reader, Writer };
+#[cfg(feature = "savestates")]
+use std::io::Result as IoResult;
+
+/// Size of a single SGB packet in bytes
+const PACKET_SIZE: usize = 16;
+
+/// SGB command codes
+pub mod command
+{
+    /// Set SGB palettes 0 & 1
+    pub const PAL01: u8 = 0x00;
+
+    /// Set SGB palettes 2 & 3
+    pub const PAL23: u8 = 0x01;
+
+    /// Set SGB palettes 0 & 3
+    pub const PAL03: u8 = 0x02;
+
+    /// Set SGB palettes 1 & 2
+    pub const PAL12: u8 = 0x03;
+
+    /// Request multiplayer mode
+    pub const MLT_REQ: u8 = 0x11;
+
+    /// Transfer border tiles through VRAM
+    pub const CHR_TRN: u8 = 0x13;
+
+    /// Transfer the border tile map and palettes through VRAM
+    pub const PCT_TRN: u8 = 0x14;
+}
+
+/// Width of the SGB border image in pixels
+pub const BORDER_WIDTH: usize = 256;
+
+/// Height of the SGB border image in pixels
+pub const BORDER_HEIGHT: usize = 224;
+
+/// Size of the data sent by a VRAM transfer
+pub const TRANSFER_SIZE: usize = 0x1000;
+
+/// Border tiles are 8x8 with 4 bits per pixel
+const BORDER_TILE_SIZE: usize = 32;
+
+/// The border tile map is 32x28 tiles, each a 16-bit entry
+const BORDER_MAP_SIZE: usize = 32 * 28 * 2;
+
+/// The border uses SGB palettes 4 thru 7, 16 RGB555 colors each
+const BORDER_PALETTES_SIZE: usize = 4 * 16 * 2;
+
+/// Size of the border in a savestate: tiles, tile map, palettes and whether
+/// it is shown
+#[cfg(feature = "savestates")]
+pub const BORDER_STATE_SIZE: usize = 256 * BORDER_TILE_SIZE + BORDER_MAP_SIZE + BORDER_PALETTES_SIZE + 1;
+
+/// A VRAM transfer waiting for the next frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transfer
+{
+    /// Border tiles 0x00 thru 0x7F, or 0x80 thru 0xFF if true
+    Chr(bool),
+
+    /// Border tile map and palettes
+    Pct
+}
+
+/// Assembles the P14/P15 pulses written to the joypad register into complete
+/// SGB commands
+pub struct PacketReceiver
+{
+    /// The command being received. May span several packets.
+    data: Vec< u8 >,
+
+    /// The number of bits received in the current packet
+    bits: usize,
+
+    /// Is a packet currently being received?
+    receiving: bool,
+
+    /// Has the current pulse ended with P14 & P15 both being set high?
+    pulse_ended: bool
+}
+
+impl PacketReceiver
+{
+    pub fn new() -> Self
+    {
+        PacketReceiver {
+            data: Vec::new(),
+            bits: 0,
+            receiving: false,
+            pulse_ended: true
+        }
+    }
+
+    /// Handle a write to the joypad register. Returns the complete command
+    /// data once the final packet of a command has been received.
+    pub fn write(&mut self, val: u8) -> Option< Vec< u8 > >
+    {
+        let lines = val & 0x30;
+
+        // Both lines high ends the current pulse
+        if lines == 0x30
+        {
+            self.pulse_ended = true;
+            return None
+        }
+
+        // A line can't change again until the pulse has ended
+        if !self.pulse_ended { return None }
+        self.pulse_ended = false;
+
+        // Both lines low resets and starts a new packet
+        if lines == 0x00
+        {
+            self.receiving = true;
+            self.bits = 0;
+            self.data.extend_from_slice(&[0; PACKET_SIZE]);
+            return None
+        }
+
+        if !self.receiving { return None }
+
+        let bit = lines == 0x10;
+        if self.bits == PACKET_SIZE * 8
+        {
+            // This is the stop bit, which must be a '0'
+            self.receiving = false;
+            if bit
+            {
+                self.data.clear();
+                return None
+            }
+
+            let len = match self.data[0] & 0x7 { 0 => 1, n => n as usize };
+            if self.data.len() >= len * PACKET_SIZE
+            {
+                return Some(::std::mem::take(&mut self.data))
+            }
+            return None
+        }
+
+        if bit
+        {
+            let byte = self.data.len() - PACKET_SIZE + self.bits / 8;
+            self.data[byte] |= 1 << (self.bits % 8);
+        }
+        self.bits += 1;
+        None
+    }
+
+    /// Write the packet receiver state to a savestate chunk
+    #[cfg(feature = "savestates")]
+    pub fn save_state(&self, w: &mut Writer)
+    {
+        w.vec(&self.data);
+        w.u32(self.bits as u32);
+        w.bool(self.receiving);
+        w.bool(self.pulse_ended);
+    }
+
+    /// Restore the packet receiver state from a savestate chunk
+    #[cfg(feature = "savestates")]
+    pub fn load_state(&mut self, r: &mut Reader) -> IoResult< () >
+    {
+        self.data = r.vec()?;
+        self.bits = r.u32()? as usize;
+        self.receiving = r.bool()?;
+        self.pulse_ended = r.bool()?;
+        if self.receiving && (self.data.len() < PACKET_SIZE || self.bits > PACKET_SIZE * 8)
+        {
+            return Err(invalid("Invalid SGB packet in savestate"))
+        }
+        Ok(())
+    }
+}
+
+/// The SGB border drawn around the game screen, built from the tiles, tile
+/// map and palettes sent with CHR_TRN and PCT_TRN
+pub struct Border
+{
+    tiles: [u8; 256 * BORDER_TILE_SIZE],
+    map: [u8; BORDER_MAP_SIZE],
+    palettes: [u8; BORDER_PALETTES_SIZE],
+
+    /// Has a tile map been sent? Until then there is no border to draw.
+    active: bool,
+
+    /// RGBA image of the border. The game screen is drawn over the middle.
+    image: Box< [u8; BORDER_WIDTH * BORDER_HEIGHT * 4] >
+}
+
+impl Border
+{
+    pub fn new() -> Self
+    {
+        Border {
+            tiles: [0; 256 * BORDER_TILE_SIZE],
+            map: [0; BORDER_MAP_SIZE],
+            palettes: [0; BORDER_PALETTES_SIZE],
+            active: false,
+            image: Box::new([0; BORDER_WIDTH * BORDER_HEIGHT * 4])
+        }
+    }
+
+    /// Apply the data of a VRAM transfer and redraw the border. Transparent
+    /// pixels are drawn in the backdrop color.
+    pub fn transfer(&mut self, transfer: Transfer, data: &[u8], backdrop: [u8; 4])
+    {
+        match transfer
+        {
+            Transfer::Chr(upper) => {
+                let start = if upper { self.tiles.len() / 2 } else { 0 };
+                self.tiles[start..start + TRANSFER_SIZE].copy_from_slice(&data[..TRANSFER_SIZE]);
+            },
+            Transfer::Pct => {
+                self.map.copy_from_slice(&data[..BORDER_MAP_SIZE]);
+                self.palettes.copy_from_slice(&data[0x800..0x800 + BORDER_PALETTES_SIZE]);
+                self.active = true;
+            }
+        }
+        self.render(backdrop);
+    }
+
+    /// Get the border image if a border has been sent
+    pub fn image(&self) -> Option< &[u8] >
+    {
+        if self.active { Some(&self.image[..]) } else { None }
+    }
+
+    /// Redraw the border image from the tiles, tile map and palettes
+    fn render(&mut self, backdrop: [u8; 4])
+    {
+        for (i, entry) in self.map.chunks(2).enumerate()
+        {
+            let (tile_x, tile_y) = (i % 32, i / 32);
+            let entry = entry[0] as u16 | (entry[1] as u16) << 8;
+            let tile = &self.tiles[(entry & 0xFF) as usize * BORDER_TILE_SIZE..][..BORDER_TILE_SIZE];
+            let palette = ((entry >> 10) & 0x3) as usize;
+            let x_flip = entry & 0x4000 != 0;
+            let y_flip = entry & 0x8000 != 0;
+
+            for y in 0..8
+            {
+                let row = if y_flip { 7 - y } else { y };
+                let planes = [tile[row * 2], tile[row * 2 + 1], tile[16 + row * 2], tile[17 + row * 2]];
+                for x in 0..8
+                {
+                    let bit = if x_flip { x } else { 7 - x };
+                    let color_i = planes.iter().enumerate()
+                        .fold(0, |c, (p, plane)| c | ((plane >> bit) & 1) << p) as usize;
+                    let color = if color_i == 0
+                    {
+                        backdrop
+                    }
+                    else
+                    {
+                        let c = (palette * 16 + color_i) * 2;
+                        rgb555(self.palettes[c], self.palettes[c + 1])
+                    };
+
+                    let px = ((tile_y * 8 + y) * BORDER_WIDTH + tile_x * 8 + x) * 4;
+                    self.image[px..px + 4].copy_from_slice(&color);
+                }
+            }
+        }
+    }
+
+    /// Write the border state to a savestate chunk
+    #[cfg(feature = "savestates")]
+    pub fn save_state(&self, w: &mut Writer)
+    {
+        w.bytes(&self.tiles);
+        w.bytes(&self.map);
+        w.bytes(&self.palettes);
+        w.bool(self.active);
+    }
+
+    /// Restore the border state from a savestate chunk and redraw it
+    #[cfg(feature = "savestates")]
+    pub fn load_state(&mut self, r: &mut Reader, backdrop: [u8; 4]) -> IoResult< () >
+    {
+        r.bytes(&mut self.tiles)?;
+        r.bytes(&mut self.map)?;
+        r.bytes(&mut self.palettes)?;
+        self.active = r.bool()?;
+        self.render(backdrop);
+        Ok(())
+    }
+}
@@ -0,0 +1,148 @@
+use crate::gpu::{ WIDTH, HEIGHT };
+use crate::render::PixelSource;
+use std::io::{ Error, Write };
+use std::io::Result as IoResult;
+use std::sync::{ Arc, Mutex };
+
+/// A completed frame, as handed to frame sinks
+pub struct Frame< 'a >
+{
+    /// The number of frames completed since power on, including this one
+    pub number: u64,
+
+    /// RGBA image data of the screen, WIDTH x HEIGHT pixels
+    pub image: &'a [u8],
+
+    /// Where each pixel of the image came from, in the same order
+    pub sources: &'a [PixelSource]
+}
+
+/// Receives every frame the GPU completes, at the start of VBlank. Sinks are
+/// added with `Gameboy::add_frame_sink`. To keep hold of a sink after adding
+/// it, e.g. to read what it collected, add it as an `Arc< Mutex< S > >`.
+pub trait FrameSink
+{
+    /// Called with each frame as it is completed
+    fn push_frame(&mut self, frame: &Frame);
+}
+
+impl< S: FrameSink > FrameSink for Arc< Mutex< S > >
+{
+    fn push_frame(&mut self, frame: &Frame)
+    {
+        // A sink that panicked on another thread still gets frames
+        let mut sink = self.lock().unwrap_or_else(|e| e.into_inner());
+        sink.push_frame(frame);
+    }
+}
+
+/// Discards every frame. Stands in for a sink when nothing needs the frames,
+/// e.g. in headless runs.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NullSink;
+
+impl FrameSink for NullSink
+{
+    fn push_frame(&mut self, _frame: &Frame) {}
+}
+
+/// Keeps a copy of the last completed frame. `Gameboy::get_image_data`
+/// changes line by line as the next frame is drawn, so frontends that read
+/// the screen at other times, like from another thread, can share one of
+/// these instead to always see a whole frame.
+#[derive(Debug, Clone)]
+pub struct FrameBuffer
+{
+    number: u64,
+    image: Vec< u8 >
+}
+
+impl FrameBuffer
+{
+    /// Create a buffer holding a white screen until the first frame
+    pub fn new() -> Self
+    {
+        FrameBuffer { number: 0, image: vec![0xFF; WIDTH * HEIGHT * 4] }
+    }
+
+    /// Get the number of the frame held, 0 before the first one
+    pub fn number(&self) -> u64
+    {
+        self.number
+    }
+
+    /// Get the RGBA image data of the frame held
+    pub fn image(&self) -> &[u8]
+    {
+        &self.image
+    }
+}
+
+impl Default for FrameBuffer
+{
+    fn default() -> Self
+    {
+        FrameBuffer::new()
+    }
+}
+
+impl FrameSink for FrameBuffer
+{
+    fn push_frame(&mut self, frame: &Frame)
+    {
+        self.number = frame.number;
+        self.image.copy_from_slice(frame.image);
+    }
+}
+
+/// Writes every frame to a writer as raw RGBA video, which can be encoded
+/// with e.g.
+/// `ffmpeg -f rawvideo -pixel_format rgba -video_size 160x144 -framerate 59.73 -i <file> out.mp4`.
+/// Writing stops at the first error, which is returned by `finish`.
+pub struct FrameRecorder< W: Write >
+{
+    out: W,
+    frames: u64,
+    error: Option< Error >
+}
+
+impl< W: Write > FrameRecorder< W >
+{
+    /// Record frames to the given writer
+    pub fn new(out: W) -> Self
+    {
+        FrameRecorder { out, frames: 0, error: None }
+    }
+
+    /// Get the number of frames written
+    pub fn frames(&self) -> u64
+    {
+        self.frames
+    }
+
+    /// Flush the writer, failing if any frame couldn't be written
+    pub fn finish(&mut self) -> IoResult< () >
+    {
+        match self.error.take()
+        {
+            Some(e) => Err(e),
+            None => self.out.flush()
+        }
+    }
+}
+
+impl< W: Write > FrameSink for FrameRecorder< W >
+{
+    fn push_frame(&mut self, frame: &Frame)
+    {
+        if self.error.is_some()
+        {
+            return
+        }
+        match self.out.write_all(frame.image)
+        {
+            Ok(()) => self.frames += 1,
+            Err(e) => self.error = Some(e)
+        }
+    }
+}
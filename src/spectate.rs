@@ -0,0 +1,378 @@
+/*
+    Spectator Protocol:
+    -----------------------------------------------
+    Every message starts with a 1 byte type:
+    'S' u32 size, u32 len, [u8]     Full savestate
+    'D' u32 size, u32 len, [u8]     Savestate XORed with the previous one
+    'F' u64 frame, u8 inputs        The host reached the frame and now
+                                    holds the buttons
+    -----------------------------------------------
+    All values are little endian. States are compressed by run length
+    encoding zeros, which makes deltas tiny since most of the system doesn't
+    change between states. `size` is the length of the state and `len` the
+    length of the compressed data that follows.
+
+    The host sends a full state to every new spectator, followed by a delta
+    every few seconds to correct any drift. Between states only the frame
+    number and buttons held are sent, and spectators emulate the frames
+    themselves, so they need their own copy of the ROM.
+*/
+
+use crate::{ ButtonState, Gameboy, RunExit };
+use crate::invalid;
+use std::io::{ ErrorKind, Read, Write };
+use std::io::Result as IoResult;
+use std::mem;
+use std::net::{ SocketAddr, TcpListener, TcpStream, ToSocketAddrs };
+use std::time::Duration;
+
+/// Frames between the states sent to spectators by default, about 5 seconds
+const DEFAULT_INTERVAL: u64 = 300;
+
+/// How long the host waits on a spectator that isn't keeping up before
+/// dropping it
+const WRITE_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Longest run of zeros or literal bytes in compressed data
+const MAX_RUN: usize = 0xFFFF;
+
+/// Largest state a spectator accepts. The system state is at most about
+/// 1.2MB, for MBC6 cartridges, and the rest leaves room for a movie being
+/// recorded. Anything bigger is malformed and isn't buffered.
+const MAX_STATE_SIZE: usize = 16 << 20;
+
+/// A spectator connected to the host
+struct Spectator
+{
+    stream: TcpStream,
+
+    /// Has the spectator been sent a full state? Deltas are useless until
+    /// it has one.
+    synced: bool
+}
+
+/// Streams a running game to spectators over TCP. Call `update` every time
+/// before running the GameBoy, once the inputs for the run have been set,
+/// to accept new spectators and send them what happened since the last
+/// call.
+pub struct SpectateHost
+{
+    listener: TcpListener,
+    spectators: Vec< Spectator >,
+
+    /// Frames between states
+    interval: u64,
+
+    /// The last state sent and the frame it was sent on
+    state: Vec< u8 >,
+    state_frame: u64,
+
+    /// The frame and inputs last sent
+    frame: u64,
+    inputs: ButtonState
+}
+
+impl SpectateHost
+{
+    /// Listen for spectators on the given address
+    pub fn bind< A: ToSocketAddrs >(addr: A) -> IoResult< Self >
+    {
+        let listener = TcpListener::bind(addr)?;
+        listener.set_nonblocking(true)?;
+        Ok(SpectateHost {
+            listener,
+            spectators: Vec::new(),
+            interval: DEFAULT_INTERVAL,
+            state: Vec::new(),
+            state_frame: 0,
+            frame: 0,
+            inputs: ButtonState::empty()
+        })
+    }
+
+    /// Get the address spectators can connect to
+    pub fn local_addr(&self) -> IoResult< SocketAddr >
+    {
+        self.listener.local_addr()
+    }
+
+    /// Set the number of frames between the states sent to spectators.
+    /// Shorter intervals correct drift sooner but use more bandwidth.
+    pub fn set_interval(&mut self, frames: u64)
+    {
+        self.interval = frames.max(1);
+    }
+
+    /// Get the number of spectators connected
+    pub fn spectators(&self) -> usize
+    {
+        self.spectators.len()
+    }
+
+    /// Accept new spectators and send every spectator the state of the
+    /// GameBoy. Spectators that can't keep up or have disconnected are
+    /// dropped.
+    pub fn update(&mut self, gb: &Gameboy) -> IoResult< () >
+    {
+        loop
+        {
+            match self.listener.accept()
+            {
+                Ok((stream, _)) => {
+                    stream.set_nonblocking(false)?;
+                    stream.set_nodelay(true)?;
+                    stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+                    self.spectators.push(Spectator { stream, synced: false });
+                },
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) => return Err(e)
+            }
+        }
+        if self.spectators.is_empty()
+        {
+            return Ok(())
+        }
+
+        let frame = gb.frame_count();
+        let inputs = gb.inputs();
+        let unsynced = self.spectators.iter().any(|s| !s.synced);
+        if unsynced || frame >= self.state_frame + self.interval
+        {
+            let state = gb.save_state();
+            let full = state_message(b'S', &state);
+
+            // Deltas only work between states of the same length, which
+            // changes when a movie starts or stops
+            let delta = if state.len() == self.state.len()
+            {
+                let xored: Vec< u8 > = state.iter().zip(self.state.iter()).map(|(a, b)| a ^ b).collect();
+                state_message(b'D', &xored)
+            }
+            else
+            {
+                full.clone()
+            };
+
+            for spectator in self.spectators.iter_mut()
+            {
+                let message = if spectator.synced { &delta } else { &full };
+                spectator.synced = true;
+                if spectator.stream.write_all(message).is_err()
+                {
+                    spectator.synced = false;
+                }
+            }
+            self.state = state;
+            self.state_frame = frame;
+        }
+        else if frame != self.frame || inputs != self.inputs
+        {
+            let mut message = vec![b'F'];
+            message.extend_from_slice(&frame.to_le_bytes());
+            message.push(inputs.bits());
+            for spectator in self.spectators.iter_mut()
+            {
+                if spectator.stream.write_all(&message).is_err()
+                {
+                    spectator.synced = false;
+                }
+            }
+        }
+        self.frame = frame;
+        self.inputs = inputs;
+
+        // Spectators are only unsynced at this point if they failed a write,
+        // leaving them partway through a message
+        self.spectators.retain(|s| s.synced);
+        Ok(())
+    }
+}
+
+/// Mirrors a game streamed by a `SpectateHost` on a local GameBoy running
+/// the same ROM
+pub struct SpectateClient
+{
+    stream: TcpStream,
+
+    /// Data received that doesn't make up a whole message yet
+    buf: Vec< u8 >,
+
+    /// The last state received, which the next delta applies to
+    state: Vec< u8 >
+}
+
+impl SpectateClient
+{
+    /// Connect to a host
+    pub fn connect< A: ToSocketAddrs >(addr: A) -> IoResult< Self >
+    {
+        let stream = TcpStream::connect(addr)?;
+        stream.set_nonblocking(true)?;
+        Ok(SpectateClient { stream, buf: Vec::new(), state: Vec::new() })
+    }
+
+    /// Has the first state been received yet? Until it has the GameBoy is
+    /// left alone.
+    pub fn is_synced(&self) -> bool
+    {
+        !self.state.is_empty()
+    }
+
+    /// Apply everything the host has sent since the last call to the
+    /// GameBoy, running it up to the frame the host is about to run. Returns
+    /// false once the host has disconnected. Fails if the data sent is
+    /// malformed or the host is running a different game.
+    pub fn update(&mut self, gb: &mut Gameboy) -> IoResult< bool >
+    {
+        let mut open = true;
+        let mut data = [0; 0x1000];
+        loop
+        {
+            match self.stream.read(&mut data)
+            {
+                Ok(0) => {
+                    open = false;
+                    break
+                },
+                Ok(len) => self.buf.extend_from_slice(&data[..len]),
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => {},
+                Err(e) => return Err(e)
+            }
+        }
+
+        let mut buf = mem::take(&mut self.buf);
+        let mut pos = 0;
+        while let Some(len) = self.apply(&buf[pos..], gb)?
+        {
+            pos += len;
+        }
+        buf.drain(..pos);
+        self.buf = buf;
+        Ok(open)
+    }
+
+    /// Apply the message at the start of data. Returns the length of the
+    /// message, or None if it hasn't been received in full yet.
+    fn apply(&mut self, data: &[u8], gb: &mut Gameboy) -> IoResult< Option< usize > >
+    {
+        match data.first()
+        {
+            None => Ok(None),
+            Some(b'F') => {
+                if data.len() < 10
+                {
+                    return Ok(None)
+                }
+                let mut frame = [0; 8];
+                frame.copy_from_slice(&data[1..9]);
+                let frame = u64::from_le_bytes(frame);
+
+                if self.is_synced()
+                {
+                    while gb.frame_count() < frame
+                    {
+                        if gb.run_frame() != RunExit::Completed { break }
+                    }
+                    gb.set_inputs(ButtonState::from_bits_truncate(data[9]));
+                }
+                Ok(Some(10))
+            },
+            Some(&kind) if kind == b'S' || kind == b'D' => {
+                if data.len() < 9
+                {
+                    return Ok(None)
+                }
+                let size = u32::from_le_bytes([data[1], data[2], data[3], data[4]]) as usize;
+                let len = u32::from_le_bytes([data[5], data[6], data[7], data[8]]) as usize;
+
+                // Compressed data is never more than 3 times the size, for
+                // alternating zero and nonzero bytes
+                if size > MAX_STATE_SIZE || len > size * 3 + 4
+                {
+                    return Err(invalid("Spectator state is too large"))
+                }
+                if data.len() < 9 + len
+                {
+                    return Ok(None)
+                }
+
+                let mut state = decompress(&data[9..9 + len], size)?;
+                if kind == b'D'
+                {
+                    if state.len() != self.state.len()
+                    {
+                        return Err(invalid("Spectator state delta doesn't match the previous state"))
+                    }
+                    for (a, b) in state.iter_mut().zip(self.state.iter())
+                    {
+                        *a ^= b;
+                    }
+                }
+                gb.load_state(&state)?;
+                self.state = state;
+                Ok(Some(9 + len))
+            },
+            Some(_) => Err(invalid("Unknown spectator message"))
+        }
+    }
+}
+
+/// Build an 'S' or 'D' message holding the given state
+fn state_message(kind: u8, state: &[u8]) -> Vec< u8 >
+{
+    let data = compress(state);
+    let mut message = vec![kind];
+    message.extend_from_slice(&(state.len() as u32).to_le_bytes());
+    message.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    message.extend_from_slice(&data);
+    message
+}
+
+/// Compress data as a sequence of runs, each a u16 count of zeros followed
+/// by a u16 count of literal bytes and the bytes themselves
+fn compress(data: &[u8]) -> Vec< u8 >
+{
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < data.len()
+    {
+        let zeros = data[i..].iter().take(MAX_RUN).take_while(|&&b| b == 0).count();
+        i += zeros;
+        let literals = data[i..].iter().take(MAX_RUN).take_while(|&&b| b != 0).count();
+        out.extend_from_slice(&(zeros as u16).to_le_bytes());
+        out.extend_from_slice(&(literals as u16).to_le_bytes());
+        out.extend_from_slice(&data[i..i + literals]);
+        i += literals;
+    }
+    out
+}
+
+/// Decompress data created by `compress`, which must expand to size bytes
+fn decompress(data: &[u8], size: usize) -> IoResult< Vec< u8 > >
+{
+    let mut out = Vec::with_capacity(size);
+    let mut i = 0;
+    while i < data.len()
+    {
+        if data.len() < i + 4
+        {
+            return Err(invalid("Spectator state is truncated"))
+        }
+        let zeros = u16::from_le_bytes([data[i], data[i + 1]]) as usize;
+        let literals = u16::from_le_bytes([data[i + 2], data[i + 3]]) as usize;
+        i += 4;
+        if data.len() < i + literals || out.len() + zeros + literals > size
+        {
+            return Err(invalid("Spectator state is malformed"))
+        }
+        out.resize(out.len() + zeros, 0);
+        out.extend_from_slice(&data[i..i + literals]);
+        i += literals;
+    }
+    if out.len() != size
+    {
+        return Err(invalid("Spectator state is truncated"))
+    }
+    Ok(out)
+}
@@ -1,6 +1,7 @@
 
 
-pub type Sample = u8;
+/// A signed 16-bit audio sample
+pub type Sample = i16;
 
 pub const SAMPLES_PER_BUFFER: usize = 0x200;
 
@@ -14,9 +15,30 @@ pub const SOUND_MAX_VOL: u8 = 15;
 
 pub const SAMPLE_MAX_VOL: u8 = SOUND_MAX_VOL * 4 * 2;
 
+/// Receives batches of SAMPLES_PER_BUFFER interleaved stereo samples
+pub type AudioCallback = Box< dyn FnMut(&[Sample]) + Send >;
+
 /// Represents the GameBoy Sound Processing Unit
 pub struct SPU
 {
+    /// Ticks since the last sample was taken
+    clock: u32,
+
+    /// Interleaved left and right samples that haven't been collected yet
+    samples: Vec< Sample >,
+
+    /// Are samples being kept in `samples`? Only while someone is going to
+    /// collect them, so running without collecting doesn't pile them up.
+    collecting: bool,
+
+    /// Number of stereo samples taken since power on
+    produced: u64,
+
+    /// Invoked whenever `batch` fills up
+    callback: Option< AudioCallback >,
+
+    /// Samples waiting to be passed to the callback
+    batch: Vec< Sample >
 }
 
 impl SPU
@@ -25,12 +47,106 @@ impl SPU
     pub fn new() -> Self
     {
         SPU {
+            clock: 0,
+            samples: Vec::with_capacity(SAMPLES_PER_BUFFER * 2),
+            collecting: false,
+            produced: 0,
+            callback: None,
+            batch: Vec::new()
+        }
+    }
+
+    /// Step the SPU a given number of ticks forward, taking a stereo sample
+    /// every SAMPLER_DIVIDER ticks. The sound channels are not emulated yet
+    /// so the samples are silent. No samples are taken without the audio
+    /// feature.
+    pub fn step(&mut self, ticks: u32)
+    {
+        if !cfg!(feature = "audio") { return }
+
+        self.clock += ticks;
+        while self.clock >= SAMPLER_DIVIDER
+        {
+            self.clock -= SAMPLER_DIVIDER;
+            if self.collecting
+            {
+                self.samples.extend_from_slice(&[0, 0]);
+            }
+            self.produced += 1;
+
+            if let Some(ref mut callback) = self.callback
+            {
+                self.batch.extend_from_slice(&[0, 0]);
+                if self.batch.len() >= SAMPLES_PER_BUFFER * 2
+                {
+                    callback(&self.batch);
+                    self.batch.clear();
+                }
+            }
         }
     }
 
-    /// Step the SPU a given number of ticks forward.
-    pub fn step(&mut self, ticks: u32, intf: &mut u8)
+    /// Get the number of stereo samples taken since power on
+    pub fn produced(&self) -> u64
+    {
+        self.produced
+    }
+
+    /// Carry the sample count over from the SPU this one replaces
+    pub fn set_produced(&mut self, produced: u64)
+    {
+        self.produced = produced;
+    }
+
+    /// Set the callback that is passed samples as they are produced, or
+    /// remove it with None
+    pub fn set_callback(&mut self, callback: Option< AudioCallback >)
+    {
+        self.callback = callback;
+        self.batch.clear();
+    }
+
+    /// Take the callback, leaving none set
+    pub fn take_callback(&mut self) -> Option< AudioCallback >
+    {
+        self.callback.take()
+    }
+
+    /// Get the samples taken since they were last cleared
+    pub fn samples(&self) -> &[Sample]
+    {
+        &self.samples
+    }
+
+    /// Discard the samples that have been collected and start keeping the
+    /// samples taken from now on
+    pub fn collect_samples(&mut self)
+    {
+        self.samples.clear();
+        self.collecting = true;
+    }
+
+    /// Discard the samples that have been collected and stop keeping them
+    pub fn stop_collecting(&mut self)
+    {
+        self.samples.clear();
+        self.collecting = false;
+    }
+
+    /// Get the current digital output of channels 1 (low nibble) and 2 (high
+    /// nibble). Readable on the CGB at 0xFF76. The sound channels are not
+    /// emulated yet so they are always silent.
+    pub fn pcm12(&self) -> u8
+    {
+        0x00
+    }
+
+    /// Get the current digital output of channels 3 (low nibble) and 4 (high
+    /// nibble). Readable on the CGB at 0xFF77. The sound channels are not
+    /// emulated yet so they are always silent.
+    pub fn pcm34(&self) -> u8
     {
+        0x00
     }
 
     pub fn read_byte(&self, addr: u16) -> u8
@@ -0,0 +1,363 @@
+/*
+    Savestate Format:
+    -----------------------------------------------
+    "RBST"          Magic bytes
+    u32             Format version
+    Chunks:
+        [u8; 4]     Tag identifying the component, e.g. "CPU "
+        u32         Length of the chunk data
+        [u8]        Chunk data
+    -----------------------------------------------
+    All values are little endian. Each component of the system reads and
+    writes its own chunk, so components can be changed or added without
+    touching the others. When the contents of a chunk change STATE_VERSION is
+    bumped and a migration is added that converts chunks from the previous
+    version, so states saved by older versions can still be loaded.
+
+    The "MOVI" chunk is only written while a movie is being recorded and
+    holds the inputs recorded up to the state.
+*/
+
+use crate::invalid;
+use crate::sgb::BORDER_STATE_SIZE;
+use std::collections::{ BTreeMap, HashMap };
+use std::io::{ Error, ErrorKind };
+use std::io::Result as IoResult;
+
+/// Magic bytes at the start of every savestate
+const MAGIC: &[u8; 4] = b"RBST";
+
+/// The current version of the savestate format
+pub const STATE_VERSION: u32 = 6;
+
+/// Identifies the component a chunk belongs to
+pub type Tag = [u8; 4];
+
+/// The chunks of a savestate, keyed by tag
+pub type Chunks = HashMap< Tag, Vec< u8 > >;
+
+/// Upgrades the chunks of a savestate by one version. Entry i converts a
+/// version i + 1 state into a version i + 2 state, e.g. by adding a chunk
+/// with power on values for a new component or by converting the layout of
+/// an existing chunk.
+type Migration = fn(&mut Chunks) -> IoResult< () >;
+
+/// Migrations from every previous version of the format to the current one
+const MIGRATIONS: [Migration; STATE_VERSION as usize - 1] = [
+    v1_to_v2,
+    v2_to_v3,
+    v3_to_v4,
+    v4_to_v5,
+    v5_to_v6
+];
+
+/// Version 2 added the "MBC " chunk holding the registers of mappers that
+/// have more state than the common bank numbers. Version 1 states predate
+/// those mappers so the chunk is left empty, which resets them.
+fn v1_to_v2(chunks: &mut Chunks) -> IoResult< () >
+{
+    chunks.insert(*b"MBC ", Vec::new());
+    Ok(())
+}
+
+/// Version 3 added the "SGBB" chunk holding the SGB border. Older states
+/// get a zeroed chunk: no border and no transfer waiting.
+fn v2_to_v3(chunks: &mut Chunks) -> IoResult< () >
+{
+    chunks.insert(*b"SGBB", vec![0; BORDER_STATE_SIZE + 1]);
+    Ok(())
+}
+
+/// Version 4 added the ticks left of an OAM DMA to the end of the "GPU "
+/// chunk. DMAs finished instantly before, so none are in progress.
+fn v3_to_v4(chunks: &mut Chunks) -> IoResult< () >
+{
+    // A state without the chunk fails to load anyway
+    if let Some(gpu) = chunks.get_mut(b"GPU ")
+    {
+        gpu.extend_from_slice(&0u32.to_le_bytes());
+    }
+    Ok(())
+}
+
+/// Version 5 added whether TIMA just overflowed to the end of the "TIMR"
+/// chunk. Overflows reloaded TIMA straight away before, so none are
+/// pending.
+fn v4_to_v5(chunks: &mut Chunks) -> IoResult< () >
+{
+    if let Some(timer) = chunks.get_mut(b"TIMR")
+    {
+        timer.push(0);
+    }
+    Ok(())
+}
+
+/// Version 6 added whether TMA was just loaded into TIMA to the end of the
+/// "TIMR" chunk
+fn v5_to_v6(chunks: &mut Chunks) -> IoResult< () >
+{
+    if let Some(timer) = chunks.get_mut(b"TIMR")
+    {
+        timer.push(0);
+    }
+    Ok(())
+}
+
+/// Builds a savestate out of the chunks written by each component
+pub struct StateWriter
+{
+    data: Vec< u8 >
+}
+
+impl StateWriter
+{
+    pub fn new() -> Self
+    {
+        let mut data = MAGIC.to_vec();
+        data.extend_from_slice(&STATE_VERSION.to_le_bytes());
+        StateWriter { data }
+    }
+
+    /// Add a chunk with the given tag containing everything written by f
+    pub fn chunk< F: FnOnce(&mut Writer) >(&mut self, tag: &Tag, f: F)
+    {
+        let mut w = Writer { data: Vec::new() };
+        f(&mut w);
+        self.data.extend_from_slice(tag);
+        self.data.extend_from_slice(&(w.data.len() as u32).to_le_bytes());
+        self.data.extend_from_slice(&w.data);
+    }
+
+    /// Get the complete savestate
+    pub fn finish(self) -> Vec< u8 >
+    {
+        self.data
+    }
+}
+
+/// Splits a savestate into its chunks and brings them up to date
+pub struct StateReader
+{
+    chunks: Chunks
+}
+
+impl StateReader
+{
+    /// Parse a savestate, migrating it from an older version of the format if
+    /// necessary
+    pub fn parse(data: &[u8]) -> IoResult< Self >
+    {
+        let mut r = Reader::new(data);
+        let mut magic = [0; 4];
+        r.bytes(&mut magic)?;
+        if &magic != MAGIC
+        {
+            return Err(invalid("Not a rustboy savestate"))
+        }
+
+        let version = r.u32()?;
+        if version == 0 || version > STATE_VERSION
+        {
+            return Err(Error::new(ErrorKind::InvalidData,
+                format!("Unsupported savestate version {}", version)))
+        }
+
+        let mut chunks = Chunks::new();
+        while !r.is_empty()
+        {
+            let mut tag = [0; 4];
+            r.bytes(&mut tag)?;
+            let len = r.u32()? as usize;
+            chunks.insert(tag, r.take(len)?.to_vec());
+        }
+
+        for migrate in &MIGRATIONS[version as usize - 1..]
+        {
+            migrate(&mut chunks)?;
+        }
+
+        Ok(StateReader { chunks })
+    }
+
+    /// Does the savestate have a chunk with the given tag? Only needed for
+    /// chunks that are written some of the time, like "MOVI".
+    pub fn has_chunk(&self, tag: &Tag) -> bool
+    {
+        self.chunks.contains_key(tag)
+    }
+
+    /// Get a reader for the chunk with the given tag
+    pub fn chunk(&self, tag: &Tag) -> IoResult< Reader< '_ > >
+    {
+        match self.chunks.get(tag)
+        {
+            Some(data) => Ok(Reader::new(data)),
+            None => Err(Error::new(ErrorKind::InvalidData,
+                format!("Savestate is missing the {} chunk", String::from_utf8_lossy(tag))))
+        }
+    }
+}
+
+/// The chunks of a savestate keyed by tag, for tools that inspect or compare
+/// states chunk by chunk. States from older versions of the format are
+/// migrated when parsed, so the chunks are always in the current layout.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StateChunks
+{
+    /// The data of every chunk, keyed by its four character tag
+    pub chunks: BTreeMap< String, Vec< u8 > >
+}
+
+impl StateChunks
+{
+    /// Split a savestate created by `Gameboy::save_state` into its chunks
+    pub fn parse(data: &[u8]) -> IoResult< Self >
+    {
+        let chunks = StateReader::parse(data)?.chunks.into_iter()
+            .map(|(tag, data)| (String::from_utf8_lossy(&tag).into_owned(), data))
+            .collect();
+        Ok(StateChunks { chunks })
+    }
+
+    /// Put the chunks back together into a savestate that can be passed to
+    /// `Gameboy::load_state`. Fails if a tag isn't four bytes long.
+    pub fn to_state(&self) -> IoResult< Vec< u8 > >
+    {
+        let mut state = StateWriter::new();
+        for (tag, data) in self.chunks.iter()
+        {
+            let tag: Tag = match tag.as_bytes().try_into()
+            {
+                Ok(tag) => tag,
+                Err(_) => return Err(Error::new(ErrorKind::InvalidInput,
+                    format!("Invalid savestate chunk tag {:?}", tag)))
+            };
+            state.chunk(&tag, |w| w.bytes(data));
+        }
+        Ok(state.finish())
+    }
+}
+
+/// Writes values into a chunk
+pub struct Writer
+{
+    data: Vec< u8 >
+}
+
+impl Writer
+{
+    pub fn u8(&mut self, val: u8)
+    {
+        self.data.push(val);
+    }
+
+    pub fn bool(&mut self, val: bool)
+    {
+        self.data.push(val as u8);
+    }
+
+    pub fn u16(&mut self, val: u16)
+    {
+        self.data.extend_from_slice(&val.to_le_bytes());
+    }
+
+    pub fn u32(&mut self, val: u32)
+    {
+        self.data.extend_from_slice(&val.to_le_bytes());
+    }
+
+    pub fn u64(&mut self, val: u64)
+    {
+        self.data.extend_from_slice(&val.to_le_bytes());
+    }
+
+    /// Write a fixed number of bytes. The reader must know how many to read.
+    pub fn bytes(&mut self, val: &[u8])
+    {
+        self.data.extend_from_slice(val);
+    }
+
+    /// Write a variable number of bytes prefixed with their length
+    pub fn vec(&mut self, val: &[u8])
+    {
+        self.u32(val.len() as u32);
+        self.bytes(val);
+    }
+}
+
+/// Reads values out of a chunk
+pub struct Reader< 'a >
+{
+    data: &'a [u8]
+}
+
+impl< 'a > Reader< 'a >
+{
+    fn new(data: &'a [u8]) -> Self
+    {
+        Reader { data }
+    }
+
+    /// Has everything in the chunk been read?
+    pub fn is_empty(&self) -> bool
+    {
+        self.data.is_empty()
+    }
+
+    fn take(&mut self, len: usize) -> IoResult< &'a [u8] >
+    {
+        if self.data.len() < len
+        {
+            return Err(invalid("Savestate is truncated"))
+        }
+        let (head, tail) = self.data.split_at(len);
+        self.data = tail;
+        Ok(head)
+    }
+
+    pub fn u8(&mut self) -> IoResult< u8 >
+    {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn bool(&mut self) -> IoResult< bool >
+    {
+        Ok(self.u8()? != 0)
+    }
+
+    pub fn u16(&mut self) -> IoResult< u16 >
+    {
+        let mut b = [0; 2];
+        self.bytes(&mut b)?;
+        Ok(u16::from_le_bytes(b))
+    }
+
+    pub fn u32(&mut self) -> IoResult< u32 >
+    {
+        let mut b = [0; 4];
+        self.bytes(&mut b)?;
+        Ok(u32::from_le_bytes(b))
+    }
+
+    pub fn u64(&mut self) -> IoResult< u64 >
+    {
+        let mut b = [0; 8];
+        self.bytes(&mut b)?;
+        Ok(u64::from_le_bytes(b))
+    }
+
+    /// Read exactly enough bytes to fill the given buffer
+    pub fn bytes(&mut self, buf: &mut [u8]) -> IoResult< () >
+    {
+        buf.copy_from_slice(self.take(buf.len())?);
+        Ok(())
+    }
+
+    /// Read a length prefixed run of bytes
+    pub fn vec(&mut self) -> IoResult< Vec< u8 > >
+    {
+        let len = self.u32()? as usize;
+        Ok(self.take(len)?.to_vec())
+    }
+}
@@ -0,0 +1,361 @@
+use crate::invalid;
+use std::collections::HashMap;
+use std::fs::{ self, File };
+use std::io::{ ErrorKind, Read, Write };
+use std::io::Result as IoResult;
+use std::path::{ Path, PathBuf };
+use std::time::{ SystemTime, UNIX_EPOCH };
+
+/// A callback invoked with the key and path of every file FileStorage writes,
+/// once the file is complete
+pub type ChangeCallback = Box< dyn FnMut(&str, &Path) + Send >;
+
+/// Size of the RTC footer VBA and BGB append to MBC3 saves: the five clock
+/// registers, the five latched registers (each as a u32) and a u64 UNIX
+/// timestamp, all little endian
+const RTC_FOOTER_SIZE: usize = 48;
+
+/// Older versions of VBA write the timestamp as a u32
+const RTC_FOOTER_SIZE_OLD: usize = 44;
+
+/// Magic at the end of a BESS (best effort save state) savestate, which
+/// SameBoy appends to its own savestates. It follows the u32 offset of the
+/// first block.
+const BESS_MAGIC: &[u8] = b"BESS";
+
+/// Offset of the cartridge RAM buffer's size and offset in a BESS CORE block
+const BESS_MBC_RAM: usize = 0xA8;
+
+/// Size of a BESS CORE block
+const BESS_CORE_SIZE: usize = 0xD0;
+
+/// Name and start of the data of the first block in a BGB savestate
+const BGB_HEADER: (&str, &[u8]) = ("header", b"BGB");
+
+/// The (name, data) blocks of a savestate from another emulator
+type Blocks< 'a > = Vec< (&'a [u8], &'a [u8]) >;
+
+/// The layouts battery saves can be exported in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SaveFormat
+{
+    /// The contents of cartridge RAM and nothing else, as used by most
+    /// emulators and flash carts
+    Raw,
+
+    /// Cartridge RAM followed by the 48 byte RTC footer used by VBA and BGB
+    /// for MBC3 games with a clock, which holds the clock registers as of
+    /// the given UNIX timestamp. Cartridges without a clock save them as 0.
+    Rtc { timestamp: u64 }
+}
+
+/// The MBC3 clock as kept in the RTC footer of a battery save
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SavedClock
+{
+    /// Seconds, minutes, hours, day low and day high
+    pub regs: [u8; 5],
+
+    /// The clock as of the last latch
+    pub latched: [u8; 5],
+
+    /// UNIX timestamp the registers were saved at
+    pub timestamp: u64
+}
+
+/// Get the current time as seconds since the UNIX epoch
+pub fn unix_time() -> u64
+{
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+/// Encode the contents of cartridge RAM as a save file, with an RTC footer
+/// if a clock is given
+pub fn encode_save(ram: &[u8], clock: Option< &SavedClock >) -> Vec< u8 >
+{
+    let mut data = ram.to_vec();
+    if let Some(clock) = clock
+    {
+        for reg in clock.regs.iter().chain(clock.latched.iter())
+        {
+            data.extend_from_slice(&(*reg as u32).to_le_bytes());
+        }
+        data.extend_from_slice(&clock.timestamp.to_le_bytes());
+    }
+    data
+}
+
+/// Get the cartridge RAM contents and the clock of a save file for a
+/// cartridge with ram_size bytes of RAM. Raw saves, saves with an RTC
+/// footer and SameBoy and BGB savestates are accepted; anything else is the
+/// wrong size for the game. The clock is None for saves without one.
+pub fn decode_save(data: &[u8], ram_size: usize) -> IoResult< (&[u8], Option< SavedClock >) >
+{
+    let (ram, clock) = match (bess_blocks(data)?, bgb_blocks(data)?)
+    {
+        (Some(blocks), _) => bess_ram(data, &blocks)?,
+        (None, Some(blocks)) => bgb_ram(&blocks)?,
+        (None, None) => (data, None)
+    };
+    match ram.len().checked_sub(ram_size)
+    {
+        Some(0) => Ok((ram, clock)),
+        Some(RTC_FOOTER_SIZE) | Some(RTC_FOOTER_SIZE_OLD) =>
+        {
+            let (ram, footer) = ram.split_at(ram_size);
+            Ok((ram, decode_clock(footer)))
+        },
+        _ => Err(invalid("Save file is the wrong size for this game"))
+    }
+}
+
+/// Read the clock from an RTC footer or savestate block, in either size.
+/// Each register is the low byte of a u32.
+fn decode_clock(footer: &[u8]) -> Option< SavedClock >
+{
+    let timestamp = match footer.len()
+    {
+        RTC_FOOTER_SIZE => u64::from_le_bytes(footer[40..48].try_into().unwrap()),
+        RTC_FOOTER_SIZE_OLD => u32_at(footer, 40)? as u64,
+        _ => return None
+    };
+    let mut clock = SavedClock { regs: [0; 5], latched: [0; 5], timestamp };
+    for i in 0..5
+    {
+        clock.regs[i] = footer[i * 4];
+        clock.latched[i] = footer[20 + i * 4];
+    }
+    Some(clock)
+}
+
+/// Read a little endian u32 at the given offset, if there is one
+fn u32_at(data: &[u8], offset: usize) -> Option< usize >
+{
+    let bytes = data.get(offset..offset.checked_add(4)?)?;
+    Some(u32::from_le_bytes(bytes.try_into().unwrap()) as usize)
+}
+
+/// Split a BESS savestate into its (name, data) blocks. Returns None if
+/// the data isn't a BESS savestate. Each block is a four character name
+/// and a u32 length followed by the data, ending with an "END " block.
+fn bess_blocks(data: &[u8]) -> IoResult< Option< Blocks< '_ > > >
+{
+    if data.len() < 8 || !data.ends_with(BESS_MAGIC)
+    {
+        return Ok(None)
+    }
+    let truncated = || invalid("BESS savestate is truncated");
+    let mut pos = u32_at(data, data.len() - 8).unwrap();
+    let mut blocks = Vec::new();
+    loop
+    {
+        let name = data.get(pos..pos + 4).ok_or_else(truncated)?;
+        let len = u32_at(data, pos + 4).ok_or_else(truncated)?;
+        let block = data.get(pos + 8..pos + 8 + len).ok_or_else(truncated)?;
+        if name == b"END " { return Ok(Some(blocks)) }
+        blocks.push((name, block));
+        pos += 8 + len;
+    }
+}
+
+/// Get the cartridge RAM and the clock, if it has one, of a BESS savestate.
+/// The CORE block points to the RAM with a u32 size and a u32 offset from
+/// the start of the file.
+fn bess_ram< 'a >(data: &'a [u8], blocks: &[(&[u8], &[u8])]) -> IoResult< (&'a [u8], Option< SavedClock >) >
+{
+    let find = |name: &[u8]| blocks.iter().find(|(n, _)| *n == name).map(|&(_, b)| b);
+    let core = match find(b"CORE")
+    {
+        Some(core) if core.len() >= BESS_CORE_SIZE => core,
+        _ => return Err(invalid("BESS savestate has no CORE block"))
+    };
+    let clock = match find(b"RTC ")
+    {
+        Some(rtc) if rtc.len() == RTC_FOOTER_SIZE => decode_clock(rtc),
+        Some(_) => return Err(invalid("BESS savestate has an invalid RTC block")),
+        None => None
+    };
+    let size = u32_at(core, BESS_MBC_RAM).unwrap();
+    let offset = u32_at(core, BESS_MBC_RAM + 4).unwrap();
+    let ram = data.get(offset..offset.saturating_add(size))
+        .ok_or_else(|| invalid("BESS savestate is truncated"))?;
+    Ok((ram, clock))
+}
+
+/// Split a BGB savestate into its (name, data) blocks. Returns None if the
+/// data isn't a BGB savestate. Each block is a zero terminated name and a
+/// u32 length followed by the data, starting with a header block.
+fn bgb_blocks(data: &[u8]) -> IoResult< Option< Blocks< '_ > > >
+{
+    let mut blocks = Vec::new();
+    let mut rest = data;
+    while !rest.is_empty()
+    {
+        let block = rest.iter().position(|&b| b == 0).and_then(|end| {
+            let len = u32_at(rest, end + 1)?;
+            Some((&rest[..end], rest.get(end + 5..end + 5 + len)?, end + 5 + len))
+        });
+        let (name, block, next) = match block
+        {
+            Some(block) => block,
+            None if blocks.is_empty() => return Ok(None),
+            None => return Err(invalid("BGB savestate is truncated"))
+        };
+        if blocks.is_empty() && (name != BGB_HEADER.0.as_bytes() || !block.starts_with(BGB_HEADER.1))
+        {
+            return Ok(None)
+        }
+        blocks.push((name, block));
+        rest = &rest[next..];
+    }
+    Ok(if blocks.is_empty() { None } else { Some(blocks) })
+}
+
+/// Get the cartridge RAM of a BGB savestate, which is in its SRAM block,
+/// and the clock in its RTC block if it has one
+fn bgb_ram< 'a >(blocks: &[(&[u8], &'a [u8])]) -> IoResult< (&'a [u8], Option< SavedClock >) >
+{
+    let find = |name: &str| blocks.iter()
+        .find(|(n, _)| n.eq_ignore_ascii_case(name.as_bytes()))
+        .map(|&(_, b)| b);
+    let clock = match find("RTC")
+    {
+        Some(rtc) => Some(decode_clock(rtc).ok_or_else(|| invalid("BGB savestate has an invalid RTC block"))?),
+        None => None
+    };
+    let ram = find("SRAM").ok_or_else(|| invalid("BGB savestate has no SRAM block"))?;
+    Ok((ram, clock))
+}
+
+/// Somewhere save data can be persisted to and loaded from. Data is stored as
+/// opaque blobs under string keys, e.g. "tetris.sav".
+pub trait StorageBackend: Send
+{
+    /// Load the data stored under the given key. Returns None if nothing has
+    /// been stored under the key.
+    fn load(&mut self, key: &str) -> IoResult< Option< Vec< u8 > > >;
+
+    /// Store data under the given key, replacing anything previously stored
+    fn store(&mut self, key: &str, data: &[u8]) -> IoResult< () >;
+}
+
+/// Stores each key as a file in a directory on the filesystem.
+///
+/// Files are replaced atomically: the data is written to a temporary file in
+/// the same directory, flushed to disk and then renamed over the old file.
+/// A crash or a sync tool like Dropbox or Syncthing reading the file
+/// mid-write sees either the old or the new contents, never a truncated
+/// file.
+pub struct FileStorage
+{
+    dir: PathBuf,
+    on_change: Option< ChangeCallback >
+}
+
+impl FileStorage
+{
+    /// Create and return storage backed by files in the given directory
+    pub fn new< P: Into< PathBuf > >(dir: P) -> Self
+    {
+        FileStorage { dir: dir.into(), on_change: None }
+    }
+
+    /// Set or clear the callback invoked after a file has been written
+    pub fn set_change_callback(&mut self, callback: Option< ChangeCallback >)
+    {
+        self.on_change = callback;
+    }
+
+    /// Write a file by way of a temporary file that is renamed over it
+    fn write_atomic(&self, path: &Path, data: &[u8]) -> IoResult< () >
+    {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        let tmp = path.with_file_name(format!(".{}.tmp", name));
+
+        let written = File::create(&tmp).and_then(|mut dst| {
+            dst.write_all(data)?;
+            dst.sync_all()
+        });
+        if let Err(e) = written.and_then(|_| fs::rename(&tmp, path))
+        {
+            let _ = fs::remove_file(&tmp);
+            return Err(e)
+        }
+
+        // Make the rename itself durable. Directories can't be opened for
+        // syncing everywhere, so this is best effort.
+        if cfg!(unix)
+        {
+            if let Ok(dir) = File::open(&self.dir)
+            {
+                let _ = dir.sync_all();
+            }
+        }
+        Ok(())
+    }
+}
+
+impl StorageBackend for FileStorage
+{
+    fn load(&mut self, key: &str) -> IoResult< Option< Vec< u8 > > >
+    {
+        let mut src = match File::open(self.dir.join(key)) {
+            Ok(f) => f,
+            Err(ref e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e)
+        };
+        let mut data = Vec::new();
+        src.read_to_end(&mut data)?;
+        Ok(Some(data))
+    }
+
+    fn store(&mut self, key: &str, data: &[u8]) -> IoResult< () >
+    {
+        fs::create_dir_all(&self.dir)?;
+        let path = self.dir.join(key);
+        self.write_atomic(&path, data)?;
+
+        if let Some(callback) = self.on_change.as_mut()
+        {
+            callback(key, &path);
+        }
+        Ok(())
+    }
+}
+
+/// Keeps everything in memory and never touches the filesystem. Useful for
+/// tests and for frontends that manage persistence themselves.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryStorage
+{
+    entries: HashMap< String, Vec< u8 > >
+}
+
+impl MemoryStorage
+{
+    /// Create and return empty in-memory storage
+    pub fn new() -> Self
+    {
+        MemoryStorage::default()
+    }
+
+    /// Get the data stored under the given key
+    pub fn get(&self, key: &str) -> Option< &[u8] >
+    {
+        self.entries.get(key).map(|d| &d[..])
+    }
+}
+
+impl StorageBackend for MemoryStorage
+{
+    fn load(&mut self, key: &str) -> IoResult< Option< Vec< u8 > > >
+    {
+        Ok(self.entries.get(key).cloned())
+    }
+
+    fn store(&mut self, key: &str, data: &[u8]) -> IoResult< () >
+    {
+        self.entries.insert(key.to_string(), data.to_vec());
+        Ok(())
+    }
+}
@@ -1,5 +1,9 @@
 use crate::mem::Speed;
 use crate::cpu::Interrupts;
+#[cfg(feature = "savestates")]
+use crate::state::{ Reader, Writer };
+#[cfg(feature = "savestates")]
+use std::io::Result as IoResult;
 
 struct InternalClock
 {
@@ -25,9 +29,22 @@ pub struct Timer
     /// Located at 0xFF07.
     tac: u8,
 
+    /// Did TIMA overflow on the last M-cycle? It reads 0 for one M-cycle
+    /// before TMA is loaded into it and the interrupt is requested, and
+    /// writing TIMA in that cycle cancels both.
+    overflow: bool,
+
+    /// Was TMA loaded into TIMA on the last M-cycle? Writes to TIMA in that
+    /// cycle are ignored and writes to TMA go to TIMA as well.
+    reloading: bool,
+
     clock: InternalClock,
 
-    speed: u32
+    speed: u32,
+
+    /// Ticks left over from the last step that don't make up a whole
+    /// M-cycle yet. Only an overclocked CPU steps by partial M-cycles.
+    ticks: u32
 }
 
 impl Timer
@@ -39,20 +56,40 @@ impl Timer
             tima: 0,
             tma: 0,
             tac: 0,
+            overflow: false,
+            reloading: false,
             clock: InternalClock { tima: 0, div: 0 },
-            speed: 256
+            speed: 256,
+            ticks: 0
         }
     }
 
     /// Step the timer a given number of ticks forward
     pub fn step(&mut self, ticks: u32, intf: &mut u8, speed: Speed)
     {
-        let ticks = match speed
+        // The timer counts machine cycles, which take 4 ticks at normal
+        // speed and 2 ticks at double speed
+        let per_cycle = match speed
         {
-            Speed::Normal => ticks / 4,
-            Speed::Double => ticks
+            Speed::Normal => 4,
+            Speed::Double => 2
         };
+        self.ticks += ticks;
+        let ticks = self.ticks / per_cycle;
+        self.ticks %= per_cycle;
         self.clock.div = self.clock.div.overflowing_add(ticks).0;
+        if ticks > 0
+        {
+            self.reloading = false;
+        }
+
+        // An overflow at the very end of the last step reloads TIMA at the
+        // start of this one
+        if self.overflow && ticks > 0
+        {
+            self.reload(intf);
+            self.reloading = ticks == 1;
+        }
 
         // Increment DIV as necessary
         while self.clock.div >= 64
@@ -67,17 +104,104 @@ impl Timer
             self.clock.tima = self.clock.tima.overflowing_add(ticks).0;
             while self.clock.tima >= self.speed
             {
+                self.clock.tima = self.clock.tima.overflowing_sub(self.speed).0;
                 self.tima = self.tima.overflowing_add(1).0;
                 if self.tima == 0
                 {
-                    self.tima = self.tma;
-                    *intf |= Interrupts::Timer as u8;
+                    // What is left on the clock has passed since the
+                    // overflow, so the reload is only still pending if
+                    // nothing is
+                    self.overflow = true;
+                    if self.clock.tima > 0
+                    {
+                        self.reload(intf);
+                        self.reloading = self.clock.tima == 1;
+                    }
                 }
-                self.clock.tima = self.clock.tima.overflowing_sub(self.speed).0;
             }
         }
     }
 
+    /// Load TMA into TIMA and request a timer interrupt, one M-cycle after
+    /// TIMA overflowed
+    fn reload(&mut self, intf: &mut u8)
+    {
+        self.overflow = false;
+        self.tima = self.tma;
+        *intf |= Interrupts::Timer as u8;
+    }
+
+    /// Get the number of ticks until TIMA next overflows and requests a timer
+    /// interrupt
+    pub fn ticks_until_interrupt(&self, speed: Speed) -> u32
+    {
+        let remaining = if self.overflow
+        {
+            1
+        }
+        else if self.tac & 0x4 == 0
+        {
+            return u32::MAX
+        }
+        else
+        {
+            // The interrupt is requested an M-cycle after the overflow
+            ((0x100 - self.tima as u32) * self.speed)
+                .saturating_sub(self.clock.tima)
+                .max(1) + 1
+        };
+        let remaining = match speed
+        {
+            Speed::Normal => remaining * 4,
+            Speed::Double => remaining * 2
+        };
+        remaining - self.ticks
+    }
+
+    /// Reset DIV and the internal counter that drives it. Happens when DIV is
+    /// written to and when the CPU enters STOP mode.
+    pub fn reset_div(&mut self)
+    {
+        self.div = 0;
+        self.clock.div = 0;
+    }
+
+    /// Set DIV, as the boot ROM leaves it
+    pub fn set_div(&mut self, div: u8)
+    {
+        self.div = div;
+    }
+
+    /// Write the timer state to a savestate chunk
+    #[cfg(feature = "savestates")]
+    pub fn save_state(&self, w: &mut Writer)
+    {
+        w.bytes(&[self.div, self.tima, self.tma, self.tac]);
+        w.u32(self.clock.div);
+        w.u32(self.clock.tima);
+        w.bool(self.overflow);
+        w.bool(self.reloading);
+    }
+
+    /// Restore the timer state from a savestate chunk
+    #[cfg(feature = "savestates")]
+    pub fn load_state(&mut self, r: &mut Reader) -> IoResult< () >
+    {
+        let mut b = [0; 4];
+        r.bytes(&mut b)?;
+        self.div = b[0];
+        self.tima = b[1];
+        self.tma = b[2];
+        self.tac = b[3];
+        self.clock.div = r.u32()?;
+        self.clock.tima = r.u32()?;
+        self.overflow = r.bool()?;
+        self.reloading = r.bool()?;
+        self.ticks = 0;
+        self.update();
+        Ok(())
+    }
+
     fn update(&mut self)
     {
         match self.tac & 0x3
@@ -107,9 +231,19 @@ impl Timer
     {
         match addr
         {
-            0xFF04 => self.div = 0,
-            0xFF05 => self.tima = val,
-            0xFF06 => self.tma = val,
+            0xFF04 => self.reset_div(),
+            0xFF05 if self.reloading => {},
+            0xFF05 => {
+                self.tima = val;
+                self.overflow = false;
+            },
+            0xFF06 => {
+                self.tma = val;
+                if self.reloading
+                {
+                    self.tima = val;
+                }
+            },
             0xFF07 => { self.tac = val; self.update(); },
             _ => {}
         }
@@ -0,0 +1,174 @@
+use crate::{ Gameboy, Movie };
+use std::fmt;
+use std::fs;
+use std::io::{ Error, ErrorKind };
+use std::io::Result as IoResult;
+use std::path::Path;
+
+/// Movie frames between the hashes in a hash log
+pub const HASH_INTERVAL: u64 = 1000;
+
+/// Frame hashes taken every HASH_INTERVAL frames, and on the last frame,
+/// while replaying a movie. Comparing a fresh replay against a log recorded
+/// earlier catches any change in emulation, which makes it a regression
+/// check for accuracy work:
+///
+/// ```no_run
+/// # use rustboy::{ Gameboy, HashLog, Movie };
+/// # use std::path::Path;
+/// let movie = Movie::load(Path::new("run.movie"))?;
+/// let expected = HashLog::load(Path::new("run.hashes"))?;
+/// let mut gb = Gameboy::try_from_rom(std::fs::read("game.gb")?)?;
+/// if let Err(divergence) = expected.verify(&mut gb, &movie)
+/// {
+///     panic!("{}", divergence);
+/// }
+/// # Ok::< (), std::io::Error >(())
+/// ```
+///
+/// Hash log files have one `<frame> <hash>` line per hash, with the hash in
+/// hex. Empty lines and lines starting with '#' are ignored.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HashLog
+{
+    hashes: Vec< (u64, u64) >
+}
+
+/// The first frame a replay didn't match its hash log on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Divergence
+{
+    /// The movie frame the hash was taken on, counting from 1
+    pub frame: u64,
+
+    /// The hash in the log
+    pub expected: u64,
+
+    /// The hash of the replay, None if the replay had no hash on this frame
+    /// because the movie is a different length than the one logged
+    pub actual: Option< u64 >
+}
+
+impl fmt::Display for Divergence
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        match self.actual
+        {
+            Some(actual) => write!(f, "Frame {} hashed to {:016x}, expected {:016x}",
+                self.frame, actual, self.expected),
+            None => write!(f, "No hash was taken on frame {}, the movie is a different length", self.frame)
+        }
+    }
+}
+
+impl HashLog
+{
+    /// Replay a movie from the GameBoy's current state and log the hashes.
+    /// Start from a freshly created GameBoy without storage, e.g. from
+    /// `Gameboy::try_from_rom`, so a save file can't change the replay.
+    pub fn record(gb: &mut Gameboy, movie: &Movie) -> Self
+    {
+        let mut log = HashLog::default();
+        replay(gb, movie, |frame, hash| {
+            log.hashes.push((frame, hash));
+            true
+        });
+        log
+    }
+
+    /// Replay a movie from the GameBoy's current state and check it against
+    /// the log, stopping at the first hash that doesn't match. Frames past
+    /// the end of the log aren't checked.
+    pub fn verify(&self, gb: &mut Gameboy, movie: &Movie) -> Result< (), Divergence >
+    {
+        let mut expected = self.hashes.iter();
+        let mut divergence = None;
+        replay(gb, movie, |frame, hash| {
+            match expected.next()
+            {
+                Some(&(f, h)) if f == frame && h == hash => return true,
+                Some(&(f, h)) if f == frame => {
+                    divergence = Some(Divergence { frame, expected: h, actual: Some(hash) })
+                },
+                // The log was taken on different frames, i.e. from a movie of
+                // another length
+                Some(&(f, h)) => divergence = Some(Divergence { frame: f, expected: h, actual: None }),
+                None => {}
+            }
+            false
+        });
+
+        match (divergence, expected.next())
+        {
+            (Some(divergence), _) => Err(divergence),
+            (None, Some(&(frame, expected))) => Err(Divergence { frame, expected, actual: None }),
+            (None, None) => Ok(())
+        }
+    }
+
+    /// Get the logged hashes as (movie frame, hash) pairs
+    pub fn hashes(&self) -> &[(u64, u64)]
+    {
+        &self.hashes
+    }
+
+    /// Load a hash log from the given file
+    pub fn load(path: &Path) -> IoResult< Self >
+    {
+        HashLog::parse(&fs::read_to_string(path)?)
+    }
+
+    /// Save the hash log to the given file
+    pub fn save(&self, path: &Path) -> IoResult< () >
+    {
+        fs::write(path, self.to_text())
+    }
+
+    /// Parse a hash log from its text representation
+    pub fn parse(text: &str) -> IoResult< Self >
+    {
+        let mut log = HashLog::default();
+        for (n, line) in text.lines().enumerate()
+        {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') { continue }
+
+            let mut parts = line.split_whitespace();
+            let frame = parts.next().and_then(|f| f.parse().ok());
+            let hash = parts.next().and_then(|h| u64::from_str_radix(h, 16).ok());
+            match (frame, hash, parts.next())
+            {
+                (Some(frame), Some(hash), None) => log.hashes.push((frame, hash)),
+                _ => return Err(Error::new(ErrorKind::InvalidData,
+                    format!("Invalid hash on line {}: {}", n + 1, line)))
+            }
+        }
+        Ok(log)
+    }
+
+    /// Get the text representation of the hash log
+    pub fn to_text(&self) -> String
+    {
+        self.hashes.iter().map(|(frame, hash)| format!("{} {:016x}\n", frame, hash)).collect()
+    }
+}
+
+/// Play every frame of the movie, passing the frame number and hash to f
+/// every HASH_INTERVAL frames and on the last frame. Stops early when f
+/// returns false.
+fn replay< F: FnMut(u64, u64) -> bool >(gb: &mut Gameboy, movie: &Movie, mut f: F)
+{
+    let last = movie.len() as u64;
+    for (i, &state) in movie.frames().iter().enumerate()
+    {
+        gb.set_inputs(state);
+        gb.run_frame();
+
+        let frame = i as u64 + 1;
+        if (frame.is_multiple_of(HASH_INTERVAL) || frame == last) && !f(frame, gb.frame_hash())
+        {
+            return
+        }
+    }
+}
@@ -0,0 +1,105 @@
+use crate::mem::BankAddress;
+use std::fmt;
+
+/// How close together the addresses a hung CPU keeps executing have to be
+const WINDOW_SIZE: u16 = 0x20;
+
+/// How long the CPU has to stay in one window with the LCD off to count as
+/// hung. About two seconds at normal speed.
+const TIMEOUT_TICKS: u64 = 8 << 20;
+
+/// Why a call to `Gameboy::run` or `Gameboy::run_frame` returned
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunExit
+{
+    /// Ran everything that was asked for
+    Completed,
+
+    /// Stopped at a breakpoint added with `Gameboy::add_breakpoint`
+    Breakpoint(BankAddress),
+
+    /// Stopped because the watchdog thinks the game has crashed
+    Hung(Hang)
+}
+
+/// A report from the watchdog of where the game got stuck
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Hang
+{
+    /// Lowest address executed while stuck
+    pub start: BankAddress,
+
+    /// Highest address executed while stuck
+    pub end: BankAddress,
+
+    /// Number of ticks spent stuck with the LCD off
+    pub ticks: u64
+}
+
+impl fmt::Display for Hang
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        write!(f, "CPU stuck between {} and {} with the LCD off for {} ticks", self.start, self.end, self.ticks)
+    }
+}
+
+/// Watches for the CPU spinning in a tiny loop with the LCD turned off, which
+/// is what most crashed games end up doing. A game that is running normally
+/// keeps the LCD on or moves on to other code before long.
+pub struct Watchdog
+{
+    start: BankAddress,
+    end: BankAddress,
+    ticks: u64
+}
+
+impl Watchdog
+{
+    /// Create and return a new watchdog
+    pub fn new() -> Self
+    {
+        let addr = BankAddress { bank: 0, addr: 0 };
+        Watchdog { start: addr, end: addr, ticks: 0 }
+    }
+
+    /// Start watching a new window at the given address
+    fn reset(&mut self, pc: BankAddress)
+    {
+        self.start = pc;
+        self.end = pc;
+        self.ticks = 0;
+    }
+
+    /// Account for an instruction that took the given number of ticks and
+    /// left the CPU at pc. Returns a report once the CPU has been stuck for
+    /// long enough, after which watching starts over.
+    pub fn step(&mut self, pc: BankAddress, ticks: u32, lcd_on: bool) -> Option< Hang >
+    {
+        // The window starts at the first instruction run with the LCD off
+        if self.ticks == 0
+        {
+            self.reset(pc);
+        }
+
+        let start = self.start.addr.min(pc.addr);
+        let end = self.end.addr.max(pc.addr);
+        if lcd_on || pc.bank != self.start.bank || end - start >= WINDOW_SIZE
+        {
+            self.reset(pc);
+            return None
+        }
+
+        self.start.addr = start;
+        self.end.addr = end;
+        self.ticks += ticks as u64;
+        if self.ticks < TIMEOUT_TICKS
+        {
+            return None
+        }
+
+        let hang = Hang { start: self.start, end: self.end, ticks: self.ticks };
+        self.reset(pc);
+        Some(hang)
+    }
+}
@@ -0,0 +1,50 @@
+//! With hardware accuracy the CPU is locked out of VRAM and OAM while the GPU
+//! is drawing a line.
+
+mod common;
+
+use common::rom_with_program;
+use rustboy::{ Accuracy, GameboyBuilder };
+
+/// A ROM that writes 0x42 to VRAM and OAM while the GPU is drawing, then
+/// reads them back into B and D right away and into C and E in HBlank
+fn test_rom() -> Vec< u8 >
+{
+    rom_with_program(&[
+        0xF0, 0x41, 0xE6, 0x03,         // ldh a, (STAT); and 3
+        0xFE, 0x03, 0x20, 0xF8,         // cp 3; jr nz, -8
+        0x3E, 0x42,                     // ld a, 0x42
+        0xEA, 0x00, 0x80,               // ld (0x8000), a
+        0xEA, 0x00, 0xFE,               // ld (0xFE00), a
+        0xFA, 0x00, 0x80, 0x47,         // ld a, (0x8000); ld b, a
+        0xFA, 0x00, 0xFE, 0x57,         // ld a, (0xFE00); ld d, a
+        0xF0, 0x41, 0xE6, 0x03,         // ldh a, (STAT); and 3
+        0x20, 0xFA,                     // jr nz, -6
+        0xFA, 0x00, 0x80, 0x4F,         // ld a, (0x8000); ld c, a
+        0xFA, 0x00, 0xFE, 0x5F,         // ld a, (0xFE00); ld e, a
+        0x18, 0xFE                      // jr -2
+    ])
+}
+
+/// Run the test ROM and get B, C, D and E
+fn run(accuracy: Accuracy) -> [u8; 4]
+{
+    let mut gb = GameboyBuilder::new().rom_bytes(test_rom()).accuracy(accuracy).build().unwrap();
+    assert_eq!(gb.accuracy(), accuracy);
+    gb.run_frame();
+    let regs = gb.registers();
+    [regs.b, regs.c, regs.d, regs.e]
+}
+
+#[test]
+fn relaxed_allows_access()
+{
+    assert_eq!(run(Accuracy::Relaxed), [0x42; 4]);
+}
+
+#[test]
+fn hardware_locks_out_cpu()
+{
+    // Reads during drawing see 0xFF and the writes never happened
+    assert_eq!(run(Accuracy::Hardware), [0xFF, 0x00, 0xFF, 0x00]);
+}
@@ -0,0 +1,94 @@
+//! Audio is handed to the audio callback in batches as it is produced, on
+//! top of being returned by `next_av_chunk`, and counted in the A/V stats.
+
+#![cfg(feature = "audio")]
+
+mod common;
+
+use common::rom_with_program;
+use rustboy::{ AvStats, Gameboy };
+use std::sync::{ Arc, Mutex };
+
+/// A ROM that spins forever
+fn test_rom() -> Vec< u8 >
+{
+    rom_with_program(&[0x18, 0xFE])
+}
+
+#[test]
+fn callback_gets_every_sample()
+{
+    let batches = Arc::new(Mutex::new(Vec::new()));
+    let mut gb = Gameboy::from_rom(test_rom());
+    let b = batches.clone();
+    gb.set_audio_callback(move |samples| b.lock().unwrap().push(samples.len()));
+
+    let mut pulled = 0;
+    for _ in 0..60
+    {
+        pulled += gb.next_av_chunk().audio.len();
+    }
+
+    // Only whole batches are passed on, so up to one batch is still pending
+    let batches = batches.lock().unwrap();
+    let pushed: usize = batches.iter().sum();
+    assert!(batches.iter().all(|&len| len == batches[0]));
+    assert!(pushed <= pulled && pulled - pushed < batches[0]);
+
+    gb.clear_audio_callback();
+    gb.next_av_chunk();
+    assert_eq!(batches.iter().sum::< usize >(), pushed);
+}
+
+#[test]
+fn av_stats_track_throughput()
+{
+    let mut gb = Gameboy::from_rom(test_rom());
+    let mut samples = 0;
+    let mut frames = 0;
+    while frames < 10
+    {
+        let chunk = gb.next_av_chunk();
+        samples += chunk.audio.len() as u64 / 2;
+        if chunk.frame.is_some()
+        {
+            frames += 1;
+        }
+    }
+    for _ in 0..8
+    {
+        gb.report_frame_presented();
+    }
+    gb.report_samples_played(100);
+
+    let av = gb.av_stats();
+    assert_eq!(av.frames_produced, 10);
+    assert_eq!(av.frames_behind(), 2);
+    assert_eq!(av.samples_produced, samples);
+    assert_eq!(av.audio_backlog(), samples as i64 - 100);
+    assert!(av.audio_rate().unwrap() < 1.0);
+
+    gb.reset_av_stats();
+    assert_eq!(gb.av_stats(), AvStats::default());
+    assert_eq!(gb.av_stats().audio_rate(), None);
+    gb.run_frame();
+    assert_eq!(gb.av_stats().frames_produced, 1);
+    assert!(gb.av_stats().samples_produced > 0);
+}
+
+#[test]
+fn running_without_collecting_keeps_no_backlog()
+{
+    // Three seconds of run_frame, which nothing collects the audio of
+    let mut gb = Gameboy::from_rom(test_rom());
+    for _ in 0..180
+    {
+        gb.run_frame();
+    }
+    assert!(gb.av_stats().samples_produced > 3 * rustboy::SAMPLE_RATE as u64 - 1000);
+
+    // The next chunk only has the audio produced while running it
+    let frame_samples = (rustboy::SAMPLE_RATE as f64 / gb.frame_rate()).ceil() as usize;
+    let chunk = gb.next_av_chunk();
+    assert!(!chunk.audio.is_empty() && chunk.audio.len() <= frame_samples * 2);
+}
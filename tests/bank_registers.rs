@@ -0,0 +1,51 @@
+//! VBK and SVBK read back with their unused bits set, and SVBK keeps the
+//! value written even when it selects WRAM bank 1 by writing 0.
+
+mod common;
+
+use common::rom_with_header;
+use rustboy::{ Gameboy, Target };
+
+/// A ROM that reads SVBK and VBK around writes to them, keeping what it
+/// reads in the registers
+fn test_rom(cgb: bool) -> Vec< u8 >
+{
+    rom_with_header(&[(0x143, if cgb { 0x80 } else { 0x00 })], &[
+        0xF0, 0x70, 0x47,               // ldh a, (SVBK); ld b, a
+        0xAF, 0xE0, 0x70,               // xor a; ldh (SVBK), a
+        0x3E, 0x42, 0xEA, 0x00, 0xD0,   // ld a, 0x42; ld (0xD000), a
+        0xF0, 0x70, 0x4F,               // ldh a, (SVBK); ld c, a
+        0x3E, 0x09, 0xE0, 0x70,         // ld a, 9; ldh (SVBK), a
+        0xFA, 0x00, 0xD0, 0x57,         // ld a, (0xD000); ld d, a
+        0xF0, 0x70, 0x5F,               // ldh a, (SVBK); ld e, a
+        0x3E, 0xFF, 0xE0, 0x4F,         // ld a, 0xFF; ldh (VBK), a
+        0xF0, 0x4F, 0x67,               // ldh a, (VBK); ld h, a
+        0xAF, 0xE0, 0x4F,               // xor a; ldh (VBK), a
+        0xF0, 0x4F, 0x6F,               // ldh a, (VBK); ld l, a
+        0x18, 0xFE                      // jr -2
+    ])
+}
+
+/// Run the test ROM and get B, C, D, E, H and L
+fn run(cgb: bool, target: Target) -> [u8; 6]
+{
+    let mut gb = Gameboy::from_rom_as(test_rom(cgb), target);
+    gb.run_frame();
+    let r = gb.registers();
+    [r.b, r.c, r.d, r.e, r.h, r.l]
+}
+
+#[test]
+fn cgb_bank_registers()
+{
+    // Writing 0 to SVBK maps in bank 1, the same bank as writing 9
+    assert_eq!(run(true, Target::GameBoyColor), [0xF8, 0xF8, 0x42, 0xF9, 0xFF, 0xFE]);
+}
+
+#[test]
+fn dmg_bank_registers()
+{
+    // Neither register exists outside CGB mode
+    assert_eq!(run(false, Target::GameBoyColor), [0xFF, 0xFF, 0x42, 0xFF, 0xFF, 0xFF]);
+    assert_eq!(run(false, Target::GameBoy), [0xFF, 0xFF, 0x42, 0xFF, 0xFF, 0xFF]);
+}
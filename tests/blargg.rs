@@ -0,0 +1,54 @@
+//! blargg's instruction and memory timing test ROMs. The ROMs are not
+//! distributed with rustboy; get `instr_timing.gb` and `mem_timing.gb` from
+//! https://github.com/retrio/gb-test-roms and place them in tests/blargg, or
+//! point the BLARGG_ROMS environment variable at the directory containing
+//! them. Run with:
+//!
+//!     cargo test --features blargg --test blargg
+
+#![cfg(feature = "blargg")]
+
+use rustboy::Gameboy;
+use std::env;
+use std::fs;
+use std::path::{ Path, PathBuf };
+
+/// The maximum number of frames a test ROM may run before it is failed
+const TIMEOUT_FRAMES: u32 = 60 * 30;
+
+/// Run a blargg test ROM until it reports a result. The tests print their
+/// results over the serial port, ending with "Passed" or "Failed". Returns
+/// whether it passed along with everything it printed.
+fn run_test(name: &str) -> (bool, String)
+{
+    let dir = env::var("BLARGG_ROMS")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/blargg"));
+    let path = dir.join(name);
+    let rom = fs::read(&path).unwrap_or_else(|e| panic!("Unable to read {}: {}", path.display(), e));
+
+    let mut gb = Gameboy::from_rom(rom);
+    for _ in 0..TIMEOUT_FRAMES
+    {
+        gb.run_frame();
+
+        let output = String::from_utf8_lossy(gb.serial_output()).into_owned();
+        if output.contains("Passed") { return (true, output) }
+        if output.contains("Failed") { return (false, output) }
+    }
+    (false, String::from_utf8_lossy(gb.serial_output()).into_owned())
+}
+
+#[test]
+fn instr_timing()
+{
+    let (passed, output) = run_test("instr_timing.gb");
+    assert!(passed, "{}", output);
+}
+
+#[test]
+fn mem_timing()
+{
+    let (passed, output) = run_test("mem_timing.gb");
+    assert!(passed, "{}", output);
+}
@@ -0,0 +1,233 @@
+//! Running a boot ROM before the cartridge, which is mapped over the start
+//! of the cartridge ROM until it writes to 0xFF50.
+
+mod common;
+
+use common::rom_with_program;
+use rustboy::{ Frame, FrameSink, Gameboy, GameboyBuilder, Target };
+use std::sync::{ Arc, Mutex };
+
+/// A ROM that loads the byte at 0x0000 into D and spins, with "TEST" as the
+/// title
+fn test_rom() -> Vec< u8 >
+{
+    let mut rom = rom_with_program(&[
+        0xFA, 0x00, 0x00,               // ld a, (0x0000)
+        0x57,                           // ld d, a
+        0x18, 0xFE                      // jr -2
+    ]);
+    rom[0x0000] = 0x99;
+    rom[0x134..0x138].copy_from_slice(b"TEST");
+    rom
+}
+
+/// A DMG boot ROM that sets B to 0x42, loads its own first byte into C and
+/// unmaps itself as its last instruction, like the real one
+fn dmg_boot_rom() -> Vec< u8 >
+{
+    let mut boot = vec![0; 0x100];
+    let prog = [
+        0x06, 0x42,                     // ld b, 0x42
+        0xFA, 0x00, 0x00,               // ld a, (0x0000)
+        0x4F                            // ld c, a
+    ];
+    boot[..prog.len()].copy_from_slice(&prog);
+    boot[0xFA..0xFE].copy_from_slice(&[0x3E, 0x01, 0xE0, 0x50]);
+    boot
+}
+
+#[test]
+fn runs_before_cartridge()
+{
+    let mut gb = GameboyBuilder::new()
+        .rom_bytes(test_rom())
+        .target(Target::GameBoy)
+        .boot_rom(dmg_boot_rom())
+        .build()
+        .unwrap();
+    assert!(gb.in_boot_rom());
+    assert_eq!(gb.registers().pc, 0x0000);
+    assert_eq!(gb.registers().sp, 0x0000);
+
+    gb.run_frame();
+    let regs = gb.registers();
+    assert!(!gb.in_boot_rom());
+    assert_eq!(regs.b, 0x42);
+    assert_eq!(regs.c, 0x06);
+    assert_eq!(regs.d, 0x99);
+
+    // Restarting runs it again
+    gb.restart_as(Target::GameBoy);
+    assert!(gb.in_boot_rom());
+}
+
+#[test]
+fn cgb_boot_rom_leaves_header_visible()
+{
+    // Jump over the header to the second part and back to unmap
+    let mut boot = vec![0; 0x900];
+    boot[..3].copy_from_slice(&[0xC3, 0x00, 0x02]);     // jp 0x0200
+    let prog = [
+        0xFA, 0x34, 0x01,               // ld a, (0x0134)
+        0x5F,                           // ld e, a
+        0xC3, 0xFA, 0x00                // jp 0x00FA
+    ];
+    boot[0x200..0x200 + prog.len()].copy_from_slice(&prog);
+    boot[0xFA..0xFE].copy_from_slice(&[0x3E, 0x01, 0xE0, 0x50]);
+
+    let mut gb = GameboyBuilder::new()
+        .rom_bytes(test_rom())
+        .target(Target::GameBoyColor)
+        .boot_rom(boot)
+        .build()
+        .unwrap();
+    gb.run_frame();
+    assert!(!gb.in_boot_rom());
+    assert_eq!(gb.registers().e, b'T');
+    assert_eq!(gb.registers().d, 0x99);
+}
+
+#[test]
+fn rejects_wrong_size()
+{
+    let build = |target, size| GameboyBuilder::new()
+        .rom_bytes(test_rom())
+        .target(target)
+        .boot_rom(vec![0; size])
+        .build();
+    assert!(build(Target::GameBoy, 0x900).is_err());
+    assert!(build(Target::SuperGameBoy, 0x80).is_err());
+    assert!(build(Target::GameBoyColor, 0x100).is_err());
+    assert!(build(Target::SuperGameBoy, 0x100).is_ok());
+}
+
+#[test]
+fn savestates_remember_mapping()
+{
+    let mut gb = GameboyBuilder::new()
+        .rom_bytes(test_rom())
+        .boot_rom(dmg_boot_rom())
+        .build()
+        .unwrap();
+    let state = gb.save_state();
+    gb.run_frame();
+    assert!(!gb.in_boot_rom());
+    gb.load_state(&state).unwrap();
+    assert!(gb.in_boot_rom());
+
+    // Without the boot ROM there is nothing to go back to
+    let mut other = Gameboy::from_rom(test_rom());
+    assert!(other.load_state(&state).is_err());
+}
+
+/// The logo the logo checking boot ROM expects in the cartridge header
+fn logo() -> Vec< u8 >
+{
+    (0..48).map(|i| i * 5 + 1).collect()
+}
+
+/// A DMG boot ROM that turns the LCD on and waits about 2.5 seconds, like
+/// the logo scroll, then compares the logo at 0x0104 with its own copy. It
+/// locks up if they differ and unmaps itself at 0x00FC otherwise.
+fn logo_boot_rom() -> Vec< u8 >
+{
+    let mut boot = vec![0; 0x100];
+    let prog = [
+        0x31, 0xFE, 0xFF,               // ld sp, 0xFFFE
+        0x3E, 0x91, 0xE0, 0x40,         // ld a, 0x91; ldh (LCDC), a
+        0x16, 0x06,                     // ld d, 6
+        0x01, 0x00, 0x00,               // wait: ld bc, 0
+        0x0B, 0x78, 0xB1,               // count: dec bc; ld a, b; or c
+        0x20, 0xFB,                     // jr nz, count
+        0x15, 0x20, 0xF5,               // dec d; jr nz, wait
+        0x21, 0x04, 0x01,               // ld hl, 0x0104
+        0x11, 0xA8, 0x00,               // ld de, 0x00A8
+        0x0E, 0x30,                     // ld c, 48
+        0x1A, 0xBE,                     // check: ld a, (de); cp (hl)
+        0x20, 0xFE,                     // jr nz, -2
+        0x23, 0x13,                     // inc hl; inc de
+        0x0D, 0x20, 0xF7,               // dec c; jr nz, check
+        0xC3, 0xFC, 0x00                // jp 0x00FC
+    ];
+    boot[..prog.len()].copy_from_slice(&prog);
+    boot[0xA8..0xD8].copy_from_slice(&logo());
+    boot[0xFC..].copy_from_slice(&[0x3E, 0x01, 0xE0, 0x50]);
+    boot
+}
+
+/// Build a DMG running the logo checking boot ROM before the test ROM,
+/// which has the right logo or not
+fn logo_gameboy(good_logo: bool, fast_boot: bool) -> Gameboy
+{
+    let mut rom = test_rom();
+    if good_logo
+    {
+        rom[0x104..0x134].copy_from_slice(&logo());
+    }
+    GameboyBuilder::new()
+        .rom_bytes(rom)
+        .target(Target::GameBoy)
+        .boot_rom(logo_boot_rom())
+        .fast_boot(fast_boot)
+        .build()
+        .unwrap()
+}
+
+/// Counts the frames it is passed
+struct Counter(u32);
+
+impl FrameSink for Counter
+{
+    fn push_frame(&mut self, _frame: &Frame)
+    {
+        self.0 += 1;
+    }
+}
+
+#[test]
+fn fast_boot_hands_over_before_returning()
+{
+    let mut gb = logo_gameboy(true, true);
+    assert!(!gb.in_boot_rom());
+    assert_eq!(gb.registers().pc, 0x0100);
+
+    // The boot ROM took as long as it would have otherwise
+    assert!(gb.frame_count() > 120, "Boot took {} frames", gb.frame_count());
+    gb.run_frame();
+    assert_eq!(gb.registers().d, 0x99);
+
+    // Restarting runs it again without drawing anything
+    let frames = Arc::new(Mutex::new(Counter(0)));
+    gb.add_frame_sink(frames.clone());
+    gb.restart_as(Target::GameBoy);
+    assert!(!gb.in_boot_rom());
+    assert_eq!(frames.lock().unwrap().0, 0);
+    gb.run_frame();
+    assert_eq!(frames.lock().unwrap().0, 1);
+
+    // Without fast boot the boot ROM runs at its own pace
+    let mut gb = logo_gameboy(true, false);
+    assert!(gb.in_boot_rom());
+    for _ in 0..60
+    {
+        gb.run_frame();
+    }
+    assert!(gb.in_boot_rom());
+}
+
+#[test]
+fn fast_boot_still_checks_logo()
+{
+    let mut gb = logo_gameboy(false, true);
+    assert!(gb.in_boot_rom());
+    let pc = gb.registers().pc;
+    assert!(pc < 0x100, "PC {:04X} isn't in the boot ROM", pc);
+
+    // Locked up in the boot ROM for good
+    for _ in 0..60
+    {
+        gb.run_frame();
+    }
+    assert!(gb.in_boot_rom());
+    assert_eq!(gb.registers().pc, pc);
+}
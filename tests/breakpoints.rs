@@ -0,0 +1,73 @@
+//! Breakpoints are given as `bank:address` pairs and only stop emulation
+//! when that ROM bank is mapped in.
+
+#![cfg(feature = "debugger")]
+
+mod common;
+
+use common::rom_with_header;
+use rustboy::{ BankAddress, Gameboy };
+
+/// A 4 bank MBC1 ROM that keeps calling a routine in bank 2 which counts
+/// calls in B. Bank 1 has a different routine at the same address.
+fn test_rom() -> Vec< u8 >
+{
+    let mut rom = rom_with_header(&[(0x147, 0x01), (0x148, 0x01)], &[
+        0x3E, 0x02, 0xEA, 0x00, 0x20,   // ld a, 2; ld (0x2000), a
+        0xCD, 0x00, 0x40,               // call 0x4000
+        0x18, 0xFB                      // jr -5
+    ]);
+    rom.resize(0x10000, 0);
+    rom[0x4000..0x4003].copy_from_slice(&[0x06, 0x99, 0xC9]);   // ld b, 0x99; ret
+    rom[0x8000..0x8002].copy_from_slice(&[0x04, 0xC9]);         // inc b; ret
+    rom
+}
+
+#[test]
+fn parse_and_display()
+{
+    let addr: BankAddress = "05:4000".parse().unwrap();
+    assert_eq!(addr, BankAddress { bank: 5, addr: 0x4000 });
+    assert_eq!(addr.to_string(), "05:4000");
+    assert_eq!("C000".parse::< BankAddress >().unwrap(), BankAddress { bank: 0, addr: 0xC000 });
+    assert!("05:xyz".parse::< BankAddress >().is_err());
+    assert!("".parse::< BankAddress >().is_err());
+}
+
+#[test]
+fn resolves_banked_addresses()
+{
+    let mut gb = Gameboy::from_rom(test_rom());
+    assert_eq!(gb.rom_bank_count(), 4);
+    gb.run_frame();
+
+    assert_eq!(gb.bank_address(0x0150).to_string(), "00:0150");
+    assert_eq!(gb.bank_address(0x4123).to_string(), "02:4123");
+    assert_eq!(gb.bank_address(0xC000).to_string(), "00:C000");
+}
+
+#[test]
+fn stops_only_in_the_right_bank()
+{
+    let mut gb = Gameboy::from_rom(test_rom());
+    gb.add_breakpoint("01:4000".parse().unwrap());
+    gb.run_frame();
+    assert_eq!(gb.breakpoint_hit(), None);
+    assert_ne!(gb.registers().b, 0x99);
+
+    gb.add_breakpoint("02:4000".parse().unwrap());
+    gb.run_frame();
+    assert_eq!(gb.breakpoint_hit(), Some(BankAddress { bank: 2, addr: 0x4000 }));
+    assert_eq!(gb.registers().pc, 0x4000);
+
+    // Carrying on runs past the breakpoint to the next call
+    let b = gb.registers().b;
+    gb.run_frame();
+    assert_eq!(gb.registers().pc, 0x4000);
+    assert_eq!(gb.registers().b, b.wrapping_add(1));
+
+    gb.clear_breakpoints();
+    assert!(gb.breakpoints().is_empty());
+    gb.run_frame();
+    assert_eq!(gb.breakpoint_hit(), None);
+}
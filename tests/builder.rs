@@ -0,0 +1,89 @@
+//! Building GameBoys with GameboyBuilder and the option combinations it
+//! refuses.
+
+mod common;
+
+use common::rom_with_header;
+use rustboy::{ Gameboy, GameboyBuilder, MemoryStorage, RamFill, Revision, SaveFormat, StorageBackend, Target };
+
+/// A ROM that spins forever, with the given CGB flag
+fn test_rom(cgb_flag: u8) -> Vec< u8 >
+{
+    rom_with_header(&[(0x143, cgb_flag)], &[0x18, 0xFE])
+}
+
+#[test]
+fn applies_options()
+{
+    // An MBC1 cartridge with 8KB of battery backed RAM, saved under its title
+    let mut battery = test_rom(0x80);
+    battery[0x134..0x138].copy_from_slice(b"TEST");
+    battery[0x147] = 0x03;
+    battery[0x149] = 0x02;
+    let mut storage = MemoryStorage::new();
+    storage.store("TEST.sav", &[0x42; 0x2000]).unwrap();
+
+    let gb = GameboyBuilder::new()
+        .rom_bytes(battery)
+        .target(Target::GameBoyColor)
+        .storage(storage)
+        .ram_fill(RamFill::Zero)
+        .emulation_speed(2.0)
+        .build()
+        .unwrap();
+    assert_eq!(gb.target(), Target::GameBoyColor);
+    assert_eq!(gb.emulation_speed(), 2.0);
+    assert_eq!(gb.export_save(SaveFormat::Raw).unwrap(), vec![0x42; 0x2000]);
+}
+
+#[test]
+fn rejects_conflicting_options()
+{
+    assert!(GameboyBuilder::new().build().is_err());
+    assert!(GameboyBuilder::new().rom_bytes(test_rom(0)).rom_path("game.gb").build().is_err());
+    assert!(GameboyBuilder::new().rom_bytes(test_rom(0)).emulation_speed(10.0).build().is_err());
+    assert!(GameboyBuilder::new().rom_bytes(test_rom(0)).emulation_speed(f32::NAN).build().is_err());
+    assert!(GameboyBuilder::new().rom_bytes(Vec::new()).build().is_err());
+
+    // CGB only games need a CGB, but only strict mode minds
+    assert!(GameboyBuilder::new().rom_bytes(test_rom(0xC0)).target(Target::GameBoy).build().is_ok());
+    assert!(GameboyBuilder::new().rom_bytes(test_rom(0xC0)).strict(true)
+        .target(Target::GameBoy).build().is_err());
+    assert!(GameboyBuilder::new().rom_bytes(test_rom(0xC0)).strict(true)
+        .target(Target::GameBoyColor).build().is_ok());
+}
+
+#[test]
+fn default_target_from_header()
+{
+    for (flag, target) in [(0x00, Target::GameBoy), (0x80, Target::GameBoyColor), (0xC0, Target::GameBoyColor)]
+    {
+        let gb = GameboyBuilder::new().rom_bytes(test_rom(flag)).strict(true).build().unwrap();
+        assert_eq!(gb.target(), target, "CGB flag {:02x}", flag);
+    }
+
+    // try_from_rom is a strict build without a target
+    let gb = Gameboy::try_from_rom(test_rom(0xC0)).unwrap();
+    assert_eq!(gb.target(), Target::GameBoyColor);
+}
+
+#[test]
+fn sgb_border_needs_sgb()
+{
+    // Asking for the border picks the SGB
+    let gb = GameboyBuilder::new().rom_bytes(test_rom(0x80)).sgb_border(true).build().unwrap();
+    assert_eq!(gb.target(), Target::SuperGameBoy);
+    assert!(gb.sgb_border());
+
+    assert!(GameboyBuilder::new().rom_bytes(test_rom(0)).sgb_border(true)
+        .target(Target::GameBoyColor).build().is_err());
+    assert!(GameboyBuilder::new().rom_bytes(test_rom(0)).sgb_border(true)
+        .revision(Revision::Mgb).build().is_err());
+
+    // Turning it off works anywhere
+    let gb = GameboyBuilder::new().rom_bytes(test_rom(0)).sgb_border(false)
+        .target(Target::SuperGameBoy).build().unwrap();
+    assert!(!gb.sgb_border());
+    assert!(gb.get_border_image().is_none());
+    assert!(GameboyBuilder::new().rom_bytes(test_rom(0)).sgb_border(false).build().is_ok());
+}
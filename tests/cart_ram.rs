@@ -0,0 +1,149 @@
+//! Cartridge RAM is only accessible while the mapper's RAM enable register
+//! holds 0xA, and MBC2 RAM only stores the low half of each byte. RAM banks
+//! are 8KB each.
+
+mod common;
+
+use common::cart_with_program;
+use rustboy::{ Gameboy, SaveFormat };
+
+/// A ROM with the given cartridge type and 8KB of RAM that runs a program
+fn test_rom(cart_type: u8, prog: &[u8]) -> Vec< u8 >
+{
+    cart_with_program(cart_type, 0x02, prog)
+}
+
+/// Run a ROM for a couple of frames
+fn run(rom: Vec< u8 >) -> Gameboy
+{
+    let mut gb = Gameboy::from_rom(rom);
+    gb.run_frame();
+    gb.run_frame();
+    gb
+}
+
+#[test]
+fn mbc1_ram_enable()
+{
+    let gb = run(test_rom(0x03, &[
+        0x3E, 0x42, 0xEA, 0x00, 0xA0,   // ld a, 0x42; ld (0xA000), a
+        0xFA, 0x00, 0xA0, 0x47,         // ld a, (0xA000); ld b, a
+        0x3E, 0x1A, 0xEA, 0x00, 0x00,   // ld a, 0x1A; ld (0x0000), a
+        0x3E, 0x42, 0xEA, 0x00, 0xA0,   // ld a, 0x42; ld (0xA000), a
+        0xFA, 0x00, 0xA0, 0x4F,         // ld a, (0xA000); ld c, a
+        0x3E, 0x0B, 0xEA, 0x00, 0x00,   // ld a, 0x0B; ld (0x0000), a
+        0xFA, 0x00, 0xA0, 0x57,         // ld a, (0xA000); ld d, a
+        0x18, 0xFE                      // jr -2
+    ]));
+
+    let r = gb.registers();
+    assert_eq!(r.b, 0xFF, "RAM is disabled at power on");
+    assert_eq!(r.c, 0x42, "only the low 4 bits of the enable value matter");
+    assert_eq!(r.d, 0xFF, "any other value disables RAM");
+    assert!(!gb.mapper_state().ram_enabled);
+    assert_eq!(gb.export_save(SaveFormat::Raw).unwrap()[0], 0x42);
+}
+
+#[test]
+fn mbc2_ram_enable()
+{
+    let gb = run(test_rom(0x06, &[
+        0x3E, 0x0A, 0xEA, 0x00, 0x00,   // ld a, 0x0A; ld (0x0000), a
+        0xEA, 0x00, 0x00,               // ld (0x0000), a
+        0x3E, 0x5C, 0xEA, 0x00, 0xA0,   // ld a, 0x5C; ld (0xA000), a
+        0xFA, 0x00, 0xA0, 0x47,         // ld a, (0xA000); ld b, a
+        0xFA, 0x00, 0xA2, 0x4F,         // ld a, (0xA200); ld c, a
+        0x3E, 0x00, 0xEA, 0x00, 0x01,   // ld a, 0x00; ld (0x0100), a
+        0xFA, 0x00, 0xA0, 0x57,         // ld a, (0xA000); ld d, a
+        0xEA, 0x00, 0x00,               // ld (0x0000), a
+        0xFA, 0x00, 0xA0, 0x5F,         // ld a, (0xA000); ld e, a
+        0x18, 0xFE                      // jr -2
+    ]));
+
+    let r = gb.registers();
+    assert_eq!(r.b, 0xFC, "enabling twice leaves RAM enabled and only 4 bits are stored");
+    assert_eq!(r.c, 0xFC, "MBC2 RAM is mirrored every 512 bytes");
+    assert_eq!(r.d, 0xFC, "writes with address bit 8 set are not RAM enable writes");
+    assert_eq!(r.e, 0xFF, "writing 0x00 disables RAM");
+    assert_eq!(gb.export_save(SaveFormat::Raw).unwrap()[0], 0x0C);
+}
+
+#[test]
+fn ram_banks_are_8kb_apart()
+{
+    // MBC5 with 32KB of RAM
+    let mut rom = test_rom(0x1B, &[
+        0x3E, 0x0A, 0xEA, 0x00, 0x00,   // ld a, 0x0A; ld (0x0000), a
+        0x3E, 0x11, 0xEA, 0x00, 0xA0,   // ld a, 0x11; ld (0xA000), a
+        0x3E, 0x01, 0xEA, 0x00, 0x40,   // ld a, 0x01; ld (0x4000), a
+        0x3E, 0x22, 0xEA, 0x00, 0xA0,   // ld a, 0x22; ld (0xA000), a
+        0x3E, 0x03, 0xEA, 0x00, 0x40,   // ld a, 0x03; ld (0x4000), a
+        0x3E, 0x33, 0xEA, 0xFF, 0xBF,   // ld a, 0x33; ld (0xBFFF), a
+        0xAF, 0xEA, 0x00, 0x40,         // xor a; ld (0x4000), a
+        0xFA, 0x00, 0xA0, 0x47,         // ld a, (0xA000); ld b, a
+        0x18, 0xFE                      // jr -2
+    ]);
+    rom[0x149] = 0x03;
+    let gb = run(rom);
+
+    assert_eq!(gb.registers().b, 0x11, "switching banks doesn't touch bank 0");
+    let ram = gb.export_save(SaveFormat::Raw).unwrap();
+    assert_eq!(ram.len(), 32 << 10);
+    assert_eq!((ram[0x0000], ram[0x2000], ram[0x7FFF]), (0x11, 0x22, 0x33));
+    assert_eq!(ram[0x1000], 0x00);
+}
+
+#[test]
+fn largest_ram_size()
+{
+    // MBC5 with 128KB of RAM, written to at the very end of the last bank
+    let mut rom = test_rom(0x1B, &[
+        0x3E, 0x0A, 0xEA, 0x00, 0x00,   // ld a, 0x0A; ld (0x0000), a
+        0x3E, 0x0F, 0xEA, 0x00, 0x40,   // ld a, 0x0F; ld (0x4000), a
+        0x3E, 0x42, 0xEA, 0xFF, 0xBF,   // ld a, 0x42; ld (0xBFFF), a
+        0x18, 0xFE                      // jr -2
+    ]);
+    rom[0x149] = 0x04;
+    let ram = run(rom).export_save(SaveFormat::Raw).unwrap();
+    assert_eq!(ram.len(), 128 << 10);
+    assert_eq!(ram[0x1FFFF], 0x42);
+}
+
+#[test]
+fn mbc2_ram_is_512_half_bytes()
+{
+    // The header's RAM size is ignored, MBC2 RAM is built into the mapper
+    let gb = run(test_rom(0x06, &[
+        0x3E, 0x0A, 0xEA, 0x00, 0x00,   // ld a, 0x0A; ld (0x0000), a
+        0x3E, 0xA7, 0xEA, 0xFF, 0xA1,   // ld a, 0xA7; ld (0xA1FF), a
+        0xFA, 0xFF, 0xBF, 0x47,         // ld a, (0xBFFF); ld b, a
+        0x18, 0xFE                      // jr -2
+    ]));
+
+    assert_eq!(gb.registers().b, 0xF7, "the last byte is mirrored at the end of the area");
+    let ram = gb.export_save(SaveFormat::Raw).unwrap();
+    assert_eq!(ram.len(), 512);
+    assert_eq!(ram[0x1FF], 0x07);
+}
+
+#[test]
+fn ram_without_mapper_is_always_enabled()
+{
+    // ROM + RAM + battery has no enable register
+    let gb = run(test_rom(0x09, &[
+        0x3E, 0x42, 0xEA, 0x00, 0xA0,   // ld a, 0x42; ld (0xA000), a
+        0xFA, 0x00, 0xA0, 0x47,         // ld a, (0xA000); ld b, a
+        0x18, 0xFE                      // jr -2
+    ]));
+    assert_eq!(gb.registers().b, 0x42);
+    assert_eq!(gb.export_save(SaveFormat::Raw).unwrap()[0], 0x42);
+
+    // A junk RAM size means no RAM rather than a crash
+    let mut rom = test_rom(0x00, &[
+        0x3E, 0x42, 0xEA, 0x00, 0xA0,   // ld a, 0x42; ld (0xA000), a
+        0xFA, 0x00, 0xA0, 0x47,         // ld a, (0xA000); ld b, a
+        0x18, 0xFE                      // jr -2
+    ]);
+    rom[0x149] = 0x37;
+    assert_eq!(run(rom).registers().b, 0xFF);
+}
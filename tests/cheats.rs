@@ -0,0 +1,109 @@
+//! Game Genie and GameShark codes.
+
+#![cfg(feature = "cheats")]
+
+mod common;
+
+use common::rom_with_program;
+use rustboy::{ Cheat, Gameboy };
+
+/// A ROM that loads 0x11 into C and the byte at 0xC000 into B, forever
+fn test_rom() -> Vec< u8 >
+{
+    rom_with_program(&[
+        0x0E, 0x11,             // ld c, 0x11
+        0xFA, 0x00, 0xC0,       // ld a, (0xC000)
+        0x47,                   // ld b, a
+        0x18, 0xF8              // jr -8
+    ])
+}
+
+#[test]
+fn parse_codes()
+{
+    assert_eq!(Cheat::parse("771-51F-A0E").unwrap(),
+        Cheat::GameGenie { addr: 0x0151, value: 0x77, compare: Some(0x11) });
+    assert_eq!(Cheat::parse("771 51F").unwrap(),
+        Cheat::GameGenie { addr: 0x0151, value: 0x77, compare: None });
+    assert_eq!(Cheat::parse("014200C0").unwrap(),
+        Cheat::GameShark { addr: 0xC000, value: 0x42 });
+
+    assert!(Cheat::parse("").is_err());
+    assert!(Cheat::parse("771-51F-A0G").is_err());
+    assert!(Cheat::parse("771-517-A0E").is_err());
+    assert!(Cheat::parse("914200C0").is_err());
+}
+
+#[test]
+fn game_genie_patches_rom()
+{
+    let mut gb = Gameboy::from_rom(test_rom());
+    gb.run_frame();
+    assert_eq!(gb.registers().c, 0x11);
+
+    // Only applies while the ROM holds the compare value
+    gb.add_cheat("771-51F-602").unwrap();
+    gb.run_frame();
+    assert_eq!(gb.registers().c, 0x11);
+
+    gb.add_cheat("771-51F-A0E").unwrap();
+    gb.run_frame();
+    assert_eq!(gb.registers().c, 0x77);
+
+    gb.clear_cheats();
+    gb.run_frame();
+    assert_eq!(gb.registers().c, 0x11);
+}
+
+#[test]
+fn gameshark_writes_ram()
+{
+    let mut gb = Gameboy::from_rom(test_rom());
+    gb.add_cheat("014200C0+771-51F").unwrap();
+    assert_eq!(gb.cheats().len(), 2);
+    gb.run_frame();
+    gb.run_frame();
+    assert_eq!(gb.registers().b, 0x42);
+
+    // Nothing is enabled when one of several codes is invalid
+    assert!(gb.add_cheat("015500C0+nonsense").is_err());
+    assert_eq!(gb.cheats().len(), 2);
+}
+
+#[cfg(feature = "cheat-db")]
+#[test]
+fn database_lookup()
+{
+    // Pokemon Red's global checksum
+    let mut rom = test_rom();
+    rom[0x14E..0x150].copy_from_slice(&[0x91, 0xE6]);
+    let cheats = Gameboy::from_rom(rom).known_cheats();
+    assert!(cheats.iter().any(|cheat| cheat.name == "Infinite money"));
+    for cheat in cheats
+    {
+        assert!(!cheat.name.is_empty());
+        for code in cheat.codes.split('+')
+        {
+            assert!(Cheat::parse(code).is_ok(), "{}: {}", cheat.name, code);
+        }
+    }
+
+    assert!(Gameboy::from_rom(test_rom()).known_cheats().is_empty());
+}
+
+/// The database is built in compressed. Set UPDATE_CHEAT_DB to recompress it
+/// after editing data/cheats.txt.
+#[cfg(feature = "cheat-db")]
+#[test]
+fn database_is_up_to_date()
+{
+    let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("data");
+    let text = std::fs::read(dir.join("cheats.txt")).unwrap();
+    if std::env::var_os("UPDATE_CHEAT_DB").is_some()
+    {
+        std::fs::write(dir.join("cheats.txt.deflate"), miniz_oxide::deflate::compress_to_vec(&text, 10)).unwrap();
+    }
+    let compressed = std::fs::read(dir.join("cheats.txt.deflate")).unwrap();
+    assert!(miniz_oxide::inflate::decompress_to_vec(&compressed).unwrap() == text,
+        "data/cheats.txt.deflate is out of date, run with UPDATE_CHEAT_DB=1 to update it");
+}
@@ -0,0 +1,32 @@
+//! ROMs shared by the integration tests
+
+// Each test file is its own crate and uses only some of these
+#![allow(dead_code)]
+
+/// Make a 32KB ROM with no mapper whose entry point jumps to `prog` at 0x0150
+pub fn rom_with_program(prog: &[u8]) -> Vec< u8 >
+{
+    let mut rom = vec![0; 0x8000];
+    rom[0x100..0x104].copy_from_slice(&[0x00, 0xC3, 0x50, 0x01]);
+    rom[0x150..0x150 + prog.len()].copy_from_slice(prog);
+    rom
+}
+
+/// Make a ROM like `rom_with_program` with the given header bytes set, as
+/// (address, value) pairs, e.g. the CGB flag at 0x0143
+pub fn rom_with_header(header: &[(usize, u8)], prog: &[u8]) -> Vec< u8 >
+{
+    let mut rom = rom_with_program(prog);
+    for &(addr, val) in header
+    {
+        rom[addr] = val;
+    }
+    rom
+}
+
+/// Make a ROM like `rom_with_program` for the given cartridge type and RAM
+/// size header values
+pub fn cart_with_program(cart_type: u8, ram_size: u8, prog: &[u8]) -> Vec< u8 >
+{
+    rom_with_header(&[(0x147, cart_type), (0x149, ram_size)], prog)
+}
@@ -0,0 +1,88 @@
+//! Keeping track of which games run in a compatibility database.
+
+mod common;
+
+use common::rom_with_program;
+use rustboy::{ CompatDb, CompatEntry, Compatibility, Revision };
+
+/// A ROM titled "COMPAT" that draws a block in the top left corner of the
+/// screen, but only on a GameBoy Pocket if pocket_only is set. Otherwise it
+/// spins forever with a blank screen.
+fn test_rom(pocket_only: bool) -> Vec< u8 >
+{
+    let mut prog = Vec::new();
+    if pocket_only
+    {
+        prog.extend_from_slice(&[
+            0xFE, 0xFF,                 // cp 0xFF
+            0x20, 0xFE                  // jr nz, -2
+        ]);
+    }
+    prog.extend_from_slice(&[
+        0xAF, 0xE0, 0x40,               // xor a; ldh (LCDC), a
+        0x21, 0x10, 0x80,               // ld hl, 0x8010
+        0x3E, 0xFF,                     // ld a, 0xFF
+        0x06, 0x10,                     // ld b, 16
+        0x22, 0x05, 0x20, 0xFC,         // ld (hl+), a; dec b; jr nz, -4
+        0x3E, 0x01, 0xEA, 0x00, 0x98,   // ld a, 1; ld (0x9800), a
+        0x3E, 0x91, 0xE0, 0x40,         // ld a, 0x91; ldh (LCDC), a
+        0x18, 0xFE                      // jr -2
+    ]);
+    let mut rom = rom_with_program(&prog);
+    rom[0x134..0x13A].copy_from_slice(b"COMPAT");
+    rom[0x14E..0x150].copy_from_slice(&[0x12, 0x34]);
+    rom
+}
+
+/// An entry for the test ROM
+fn entry(status: Compatibility, revision: Option< Revision >, notes: &str) -> CompatEntry
+{
+    CompatEntry { title: "COMPAT".to_string(), checksum: 0x1234, status, revision, notes: notes.to_string() }
+}
+
+#[test]
+fn test_records_boots()
+{
+    let mut db = CompatDb::default();
+    assert_eq!(db.test(test_rom(false), 10).unwrap(), Compatibility::Boots);
+    assert_eq!(db.entries(), [entry(Compatibility::Boots, None, "")]);
+
+    // A rating from playing the game stands as long as it still boots
+    db.insert(entry(Compatibility::Perfect, None, "Played through"));
+    assert_eq!(db.test(test_rom(false), 10).unwrap(), Compatibility::Perfect);
+
+    // A game that never draws anything is broken, whatever it was before
+    assert_eq!(db.test(test_rom(true), 10).unwrap(), Compatibility::Broken);
+    assert_eq!(db.entries(), [entry(Compatibility::Broken, None, "Played through")]);
+}
+
+#[test]
+fn test_uses_known_revision()
+{
+    let mut db = CompatDb::default();
+    db.insert(entry(Compatibility::Broken, Some(Revision::Mgb), ""));
+    assert_eq!(db.test(test_rom(true), 10).unwrap(), Compatibility::Boots);
+    assert_eq!(db.get("COMPAT", 0x1234).unwrap().revision, Some(Revision::Mgb));
+}
+
+#[test]
+fn text_and_report()
+{
+    let text = "# Tested games\n\
+        1234\tplayable\tmgb\tCOMPAT\tWrong colors | flicker\n\
+        \n\
+        abcd\tbroken\t-\tOTHER\t\n";
+    let db = CompatDb::parse(text).unwrap();
+    assert_eq!(db.entries()[0], entry(Compatibility::Playable, Some(Revision::Mgb), "Wrong colors | flicker"));
+    assert_eq!(db.get("OTHER", 0xABCD).unwrap().status, Compatibility::Broken);
+    assert_eq!(CompatDb::parse(&db.to_text()).unwrap(), db);
+
+    let report = db.to_markdown();
+    assert!(report.contains("| playable | 1 |"));
+    assert!(report.contains("| perfect | 0 |"));
+    assert!(report.contains("| COMPAT | 1234 | playable | mgb | Wrong colors \\| flicker |"));
+    assert!(report.find("COMPAT").unwrap() < report.find("OTHER").unwrap());
+
+    assert!(CompatDb::parse("1234\tfine\t-\tCOMPAT\t\n").is_err());
+    assert!(CompatDb::parse("1234\tbroken\n").is_err());
+}
@@ -0,0 +1,88 @@
+//! The disassembly, memory and OAM views used by the debug panel and the
+//! viewer window.
+
+#![cfg(feature = "debugger")]
+
+mod common;
+
+use common::rom_with_program;
+use rustboy::{ Gameboy, OAM_VIEW_WIDTH, OAM_VIEW_HEIGHT };
+
+/// A ROM that fills tile 1 with color 3, sets up sprite 1 to use it
+/// with OBP1 (all black) and spins. The program covers a few kinds of
+/// operands for the disassembler.
+fn test_rom() -> Vec< u8 >
+{
+    rom_with_program(&[
+        0xAF, 0xE0, 0x40,               // xor a; ldh (LCDC), a
+        0x21, 0x10, 0x80,               // ld hl, 0x8010
+        0x3E, 0xFF,                     // ld a, 0xFF
+        0x22,                           // ld (hl+), a
+        0xCB, 0x6D,                     // bit 5, l
+        0x28, 0xFB,                     // jr z, -5
+        0x21, 0x04, 0xFE,               // ld hl, 0xFE04
+        0x36, 0x10, 0x23,               // ld (hl), 0x10; inc hl
+        0x36, 0x08, 0x23,               // ld (hl), 0x08; inc hl
+        0x36, 0x01, 0x23,               // ld (hl), 0x01; inc hl
+        0x36, 0x10,                     // ld (hl), 0x10
+        0x3E, 0xFF, 0xE0, 0x49,         // ld a, 0xFF; ldh (OBP1), a
+        0x18, 0xFE                      // jr -2
+    ])
+}
+
+#[test]
+fn disassembles_program()
+{
+    let gb = Gameboy::from_rom(test_rom());
+    let mut addr = 0x0150;
+    let mut listing = Vec::new();
+    for _ in 0..8
+    {
+        let (text, len) = gb.disassemble(addr);
+        listing.push(text);
+        addr += len;
+    }
+    assert_eq!(listing, [
+        "XOR A", "LDH ($40), A", "LD HL, $8010", "LD A, $FF",
+        "LD (HL+), A", "BIT 5, L", "JR Z, $0158", "LD HL, $FE04"
+    ]);
+
+    assert_eq!(gb.disassemble(0x0101), ("JP $0150".to_string(), 3));
+    assert_eq!(gb.disassemble(0x0000), ("NOP".to_string(), 1));
+}
+
+#[test]
+fn every_opcode_has_a_length()
+{
+    // Opcodes that don't exist are a single byte, everything else is as long
+    // as its operands
+    let mut rom = test_rom();
+    for op in 0..=0xFF
+    {
+        rom[0x4000] = op;
+        let gb = Gameboy::from_rom(rom.clone());
+        let (text, len) = gb.disassemble(0x4000);
+        assert!((1..=3).contains(&len), "{:02X}: {}", op, text);
+        assert_eq!(text.starts_with("DB"), [0xD3, 0xDB, 0xDD, 0xE3, 0xE4, 0xEB, 0xEC, 0xED, 0xF4, 0xFC, 0xFD].contains(&op));
+    }
+}
+
+#[test]
+fn peek_and_oam_image()
+{
+    let mut gb = Gameboy::from_rom(test_rom());
+    gb.run_frame();
+    assert_eq!(gb.peek(0x8010), 0xFF);
+    assert_eq!(gb.peek(0xFE07), 0x10);
+    assert_eq!(gb.peek(0x0150), 0xAF);
+
+    // Sprite 1 is the second cell of the first row, drawn in black, and the
+    // rest is transparent
+    let image = gb.oam_image();
+    assert_eq!(image.len(), OAM_VIEW_WIDTH * OAM_VIEW_HEIGHT * 4);
+    let pixel = |x: usize, y: usize| &image[(y * OAM_VIEW_WIDTH + x) * 4..][..4];
+    assert_eq!(pixel(8, 0), [0, 0, 0, 255]);
+    assert_eq!(pixel(15, 7), [0, 0, 0, 255]);
+    assert_eq!(pixel(15, 8)[3], 0);
+    assert_eq!(pixel(16, 0)[3], 0);
+}
@@ -0,0 +1,57 @@
+//! Frame sinks are passed every frame the GPU completes.
+
+mod common;
+
+use common::rom_with_program;
+use rustboy::{ Frame, FrameBuffer, FrameRecorder, FrameSink, Gameboy, Target, DISPLAY_WIDTH, DISPLAY_HEIGHT };
+use std::sync::{ Arc, Mutex };
+
+/// A ROM that spins forever
+fn test_rom() -> Vec< u8 >
+{
+    rom_with_program(&[0x18, 0xFE])
+}
+
+/// Remembers the number of every frame it is passed
+struct Numbers(Vec< u64 >);
+
+impl FrameSink for Numbers
+{
+    fn push_frame(&mut self, frame: &Frame)
+    {
+        assert_eq!(frame.image.len(), DISPLAY_WIDTH * DISPLAY_HEIGHT * 4);
+        self.0.push(frame.number);
+    }
+}
+
+#[test]
+fn sinks_get_every_frame()
+{
+    let numbers = Arc::new(Mutex::new(Numbers(Vec::new())));
+    let buffer = Arc::new(Mutex::new(FrameBuffer::new()));
+    let recorder = Arc::new(Mutex::new(FrameRecorder::new(Vec::new())));
+
+    let mut gb = Gameboy::from_rom(test_rom());
+    gb.add_frame_sink(numbers.clone());
+    gb.add_frame_sink(buffer.clone());
+    gb.add_frame_sink(recorder.clone());
+    for _ in 0..3
+    {
+        gb.run_frame();
+    }
+
+    assert_eq!(numbers.lock().unwrap().0, [1, 2, 3]);
+    assert_eq!(buffer.lock().unwrap().number(), 3);
+    assert_eq!(buffer.lock().unwrap().image(), gb.get_image_data());
+
+    assert_eq!(recorder.lock().unwrap().frames(), 3);
+    recorder.lock().unwrap().finish().unwrap();
+
+    // Sinks stay across restarts until they are cleared
+    gb.restart_as(Target::GameBoyColor);
+    gb.run_frame();
+    assert_eq!(numbers.lock().unwrap().0, [1, 2, 3, 1]);
+    gb.clear_frame_sinks();
+    gb.run_frame();
+    assert_eq!(numbers.lock().unwrap().0.len(), 4);
+}
@@ -0,0 +1,97 @@
+//! Ejecting and inserting cartridges while the game runs.
+
+mod common;
+
+use common::cart_with_program;
+use rustboy::{ Cartridge, Gameboy, SaveFormat, Target };
+
+/// An MBC1 cartridge with battery backed RAM that writes 0x42 to RAM, then
+/// runs a loop from WRAM reading the first RAM byte into B and the first
+/// byte of the title into C
+fn test_rom(title: &[u8]) -> Vec< u8 >
+{
+    let lo_ram = [
+        0xFA, 0x00, 0xA0,       // ld a, (0xA000)
+        0x47,                   // ld b, a
+        0xFA, 0x34, 0x01,       // ld a, (0x0134)
+        0x4F,                   // ld c, a
+        0x18, 0xF6              // jr -10
+    ];
+    let mut prog = vec![
+        0x3E, 0x0A,             // ld a, 0x0A
+        0xEA, 0x00, 0x00,       // ld (0x0000), a
+        0x3E, 0x42,             // ld a, 0x42
+        0xEA, 0x00, 0xA0,       // ld (0xA000), a
+        0x21, 0x00, 0xC0        // ld hl, 0xC000
+    ];
+    for b in lo_ram
+    {
+        prog.extend_from_slice(&[0x36, b, 0x23]);   // ld (hl), b; inc hl
+    }
+    prog.extend_from_slice(&[0xC3, 0x00, 0xC0]);    // jp 0xC000
+
+    let mut rom = cart_with_program(0x03, 0x02, &prog);
+    rom[0x134..0x134 + title.len()].copy_from_slice(title);
+    rom[0x14E] = title[0];
+    rom
+}
+
+#[test]
+fn ejected_slot_reads_open_bus()
+{
+    let mut gb = Gameboy::from_rom(test_rom(b"SWAP"));
+    gb.run_frame();
+    assert_eq!((gb.registers().b, gb.registers().c), (0x42, b'S'));
+
+    let cart = gb.eject_cartridge().unwrap();
+    assert_eq!(cart.rom, test_rom(b"SWAP"));
+    assert_eq!(cart.save.as_ref().unwrap()[0], 0x42);
+    assert!(!gb.has_cartridge());
+    assert!(gb.eject_cartridge().is_none());
+
+    // The loop in WRAM keeps running
+    gb.run_frame();
+    assert_eq!((gb.registers().b, gb.registers().c), (0xFF, 0xFF));
+    assert!(gb.export_save(SaveFormat::Raw).is_none());
+}
+
+#[test]
+fn insert_while_running()
+{
+    let mut gb = Gameboy::from_rom(test_rom(b"SWAP"));
+    gb.run_frame();
+    assert!(gb.insert_cartridge(Cartridge::new(test_rom(b"TEST"))).is_err());
+
+    let cart = gb.eject_cartridge().unwrap();
+    gb.insert_cartridge(Cartridge::new(test_rom(b"TEST"))).unwrap();
+    gb.run_frame();
+
+    // The new mapper powers on with RAM disabled
+    assert_eq!((gb.registers().b, gb.registers().c), (0xFF, b'T'));
+    assert_eq!(gb.title(), "TEST");
+    assert_eq!(gb.export_save(SaveFormat::Raw).unwrap(), vec![0; 0x2000]);
+
+    // Swapping back brings the save along
+    gb.eject_cartridge().unwrap();
+    gb.insert_cartridge(cart).unwrap();
+    gb.run_frame();
+    assert_eq!(gb.registers().c, b'S');
+    assert_eq!(gb.export_save(SaveFormat::Raw).unwrap()[0], 0x42);
+
+    assert!(gb.insert_cartridge(Cartridge::new(Vec::new())).is_err());
+}
+
+#[test]
+fn restart_without_cartridge()
+{
+    let mut gb = Gameboy::from_rom(test_rom(b"SWAP"));
+    let cart = gb.eject_cartridge().unwrap();
+    gb.restart_as(Target::GameBoyColor);
+    assert!(!gb.has_cartridge());
+    gb.run_frame();
+
+    gb.insert_cartridge(cart).unwrap();
+    gb.restart_as(Target::GameBoy);
+    gb.run_frame();
+    assert_eq!((gb.registers().b, gb.registers().c), (0x42, b'S'));
+}
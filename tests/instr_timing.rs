@@ -0,0 +1,156 @@
+//! The machine cycles taken by every opcode, checked against the tables in
+//! the pan docs. Each instruction is measured on its own by stopping at a
+//! breakpoint before and after it.
+
+#![cfg(feature = "debugger")]
+
+mod common;
+
+use common::rom_with_program;
+use rustboy::{ BankAddress, Gameboy, RunExit };
+
+/// Machine cycles taken by each opcode, with conditional jumps, calls and
+/// returns not taken. 0 marks HALT, STOP and opcodes that don't exist, which
+/// aren't measured.
+const TIMINGS: [u8; 256] = [
+    1, 3, 2, 2, 1, 1, 2, 1, 5, 2, 2, 2, 1, 1, 2, 1,
+    0, 3, 2, 2, 1, 1, 2, 1, 3, 2, 2, 2, 1, 1, 2, 1,
+    2, 3, 2, 2, 1, 1, 2, 1, 2, 2, 2, 2, 1, 1, 2, 1,
+    2, 3, 2, 2, 3, 3, 3, 1, 2, 2, 2, 2, 1, 1, 2, 1,
+    1, 1, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1,
+    1, 1, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1,
+    1, 1, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1,
+    2, 2, 2, 2, 2, 2, 0, 2, 1, 1, 1, 1, 1, 1, 2, 1,
+    1, 1, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1,
+    1, 1, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1,
+    1, 1, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1,
+    1, 1, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1,
+    2, 3, 3, 4, 3, 4, 2, 4, 2, 4, 3, 0, 3, 6, 2, 4,
+    2, 3, 3, 0, 3, 4, 2, 4, 2, 4, 3, 0, 3, 0, 2, 4,
+    3, 3, 2, 0, 0, 4, 2, 4, 4, 1, 4, 0, 0, 0, 2, 4,
+    3, 3, 2, 1, 0, 4, 2, 4, 3, 2, 4, 1, 0, 0, 2, 4
+];
+
+/// Extra machine cycles taken by a conditional jump, call or return when its
+/// condition holds
+fn taken_extra(op: u8) -> u8
+{
+    match op
+    {
+        0x20 | 0x28 | 0x30 | 0x38 => 1,     // JR cc, e
+        0xC0 | 0xC8 | 0xD0 | 0xD8 => 3,     // RET cc
+        0xC2 | 0xCA | 0xD2 | 0xDA => 1,     // JP cc, nn
+        0xC4 | 0xCC | 0xD4 | 0xDC => 3,     // CALL cc, nn
+        _ => 0
+    }
+}
+
+/// Whether the condition of a conditional opcode holds with the given flags
+fn condition_holds(op: u8, f: u8) -> bool
+{
+    let (z, c) = (f & 0x80 != 0, f & 0x10 != 0);
+    match (op >> 3) & 3
+    {
+        0 => !z,
+        1 => z,
+        2 => !c,
+        _ => c
+    }
+}
+
+/// Machine cycles taken by each CB prefixed opcode, including the prefix
+fn cb_timing(op: u8) -> u8
+{
+    match (op >> 6, op & 7)
+    {
+        (1, 6) => 3,    // BIT n, (HL)
+        (_, 6) => 4,    // Everything else on (HL) reads and writes it back
+        _ => 2
+    }
+}
+
+/// A ROM that points BC, DE and HL at WRAM, puts a return address of 0x0300
+/// on the stack, sets F and jumps to 0x0200, where the instruction being
+/// measured is followed by `operands`. 16-bit operands are 0xC100.
+fn test_rom(op: u8, operand: u8, f: u8) -> Vec< u8 >
+{
+    let mut rom = rom_with_program(&[
+        0x31, 0x00, 0xD0,               // ld sp, 0xD000
+        0x3E, 0x00, 0xEA, 0x00, 0xD0,   // ld a, 0x00; ld (0xD000), a
+        0x3E, 0x03, 0xEA, 0x01, 0xD0,   // ld a, 0x03; ld (0xD001), a
+        0x01, 0x00, 0xC0,               // ld bc, 0xC000
+        0x11, 0x00, 0xC0,               // ld de, 0xC000
+        0x21, f, 0x00,                  // ld hl, f
+        0xE5, 0xF1,                     // push hl; pop af
+        0x21, 0x00, 0xC0,               // ld hl, 0xC000
+        0xC3, 0x00, 0x02                // jp 0x0200
+    ]);
+    rom[0x200..0x203].copy_from_slice(&[op, operand, 0xC1]);
+    rom
+}
+
+/// Run the instruction at 0x0200 of the test ROM and get the machine cycles
+/// it took
+fn measure(op: u8, operand: u8, f: u8) -> u64
+{
+    let mut gb = Gameboy::from_rom(test_rom(op, operand, f));
+
+    // Everywhere an instruction can go: straight on, a jump to the operand
+    // or HL, a return, a relative jump or a restart
+    let mut targets = vec![0x0201, 0x0202, 0x0203, 0x0300, 0xC000, 0xC100, 0x0202u16.wrapping_add(operand as i8 as u16)];
+    targets.extend((0..8).map(|i| i * 8));
+    for addr in targets
+    {
+        gb.add_breakpoint(BankAddress { bank: 0, addr });
+    }
+    gb.add_breakpoint(BankAddress { bank: 0, addr: 0x0200 });
+
+    let start = BankAddress { bank: 0, addr: 0x0200 };
+    assert_eq!(gb.run_frame(), RunExit::Breakpoint(start));
+    let before = gb.cycle_count();
+    assert!(matches!(gb.run_frame(), RunExit::Breakpoint(_)), "{:02X} didn't finish", op);
+    (gb.cycle_count() - before) / 4
+}
+
+#[test]
+fn every_opcode()
+{
+    let mut wrong = Vec::new();
+    for op in 0..=0xFF
+    {
+        if TIMINGS[op as usize] == 0 || op == 0xCB { continue }
+
+        // Run everything with the flags clear and set, so conditional
+        // opcodes are measured both taken and not
+        for f in [0x00, 0xF0]
+        {
+            let mut expected = TIMINGS[op as usize] as u64;
+            if taken_extra(op) > 0 && condition_holds(op, f)
+            {
+                expected += taken_extra(op) as u64;
+            }
+
+            let cycles = measure(op, 0x00, f);
+            if cycles != expected
+            {
+                wrong.push(format!("{:02X} with F={:02X}: {} cycles, expected {}", op, f, cycles, expected));
+            }
+        }
+    }
+    assert!(wrong.is_empty(), "\n{}", wrong.join("\n"));
+}
+
+#[test]
+fn every_cb_opcode()
+{
+    let mut wrong = Vec::new();
+    for op in 0..=0xFF
+    {
+        let cycles = measure(0xCB, op, 0x00);
+        if cycles != cb_timing(op) as u64
+        {
+            wrong.push(format!("CB {:02X}: {} cycles, expected {}", op, cycles, cb_timing(op)));
+        }
+    }
+    assert!(wrong.is_empty(), "\n{}", wrong.join("\n"));
+}
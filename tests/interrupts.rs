@@ -0,0 +1,29 @@
+//! Interrupt dispatch only considers the five interrupts that exist.
+
+mod common;
+
+use common::rom_with_program;
+use rustboy::Gameboy;
+
+#[test]
+fn unused_bits_do_not_dispatch()
+{
+    // Sets the top 3 bits of IE and IF with interrupts enabled, then B to
+    // 0x42. Jumping to 0x68, 0x70 or 0x78 sets B to 0x66 instead.
+    let mut rom = rom_with_program(&[
+        0x3E, 0xE0, 0xE0, 0xFF,         // ld a, 0xE0; ldh (IE), a
+        0xE0, 0x0F,                     // ldh (IF), a
+        0xFB, 0x00,                     // ei; nop
+        0x06, 0x42,                     // ld b, 0x42
+        0x18, 0xFE                      // jr -2
+    ]);
+    for vector in [0x68, 0x70, 0x78]
+    {
+        rom[vector..vector + 4].copy_from_slice(&[0x06, 0x66, 0x18, 0xFE]);
+    }
+
+    let mut gb = Gameboy::from_rom(rom);
+    gb.run_frame();
+    assert_eq!(gb.registers().b, 0x42);
+    assert_eq!(gb.registers().ime, 1);
+}
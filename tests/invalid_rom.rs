@@ -0,0 +1,73 @@
+//! Files that aren't GameBoy ROMs must be reported as errors instead of
+//! panicking while the cartridge header is read.
+
+mod common;
+
+use common::rom_with_program;
+use rustboy::Gameboy;
+
+/// The text of a file that was opened as a ROM by mistake
+fn text_file() -> Vec< u8 >
+{
+    "# Save the date\n\nThis is a plain text file, not a GameBoy ROM.\n"
+        .repeat(40)
+        .into_bytes()
+}
+
+#[test]
+fn reject_empty()
+{
+    assert!(Gameboy::check_rom(&[]).is_err());
+    assert!(Gameboy::try_from_rom(Vec::new()).is_err());
+}
+
+#[test]
+fn reject_too_small()
+{
+    let rom = vec![0; 0x14F];
+    assert!(Gameboy::check_rom(&rom).is_err());
+    assert!(Gameboy::try_from_rom(rom).is_err());
+}
+
+#[test]
+fn reject_text_file()
+{
+    let rom = text_file();
+    assert!(rom.len() > 0x150);
+    assert!(Gameboy::check_rom(&rom).is_err());
+    assert!(Gameboy::try_from_rom(rom).is_err());
+}
+
+#[test]
+fn garbage_does_not_panic()
+{
+    // Loading without validation falls back to open bus for anything the
+    // image doesn't cover
+    for rom in [Vec::new(), vec![0xAB; 0x20], text_file()]
+    {
+        let mut gb = Gameboy::from_rom(rom);
+        gb.run_frame();
+    }
+}
+
+#[test]
+fn accept_headerless_test_rom()
+{
+    // Test ROMs often leave the header blank but are a whole number of banks
+    let rom = rom_with_program(&[0x18, 0xFE]);
+    assert!(Gameboy::check_rom(&rom).is_ok());
+    assert!(Gameboy::try_from_rom(rom).is_ok());
+}
+
+#[test]
+fn report_unloadable_files()
+{
+    let dir = std::env::temp_dir().join(format!("rustboy-invalid-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    assert!(Gameboy::try_new(&dir.join("missing.gb")).is_err());
+
+    let path = dir.join("notes.gb");
+    std::fs::write(&path, text_file()).unwrap();
+    assert!(Gameboy::try_new(&path).is_err());
+    let _ = std::fs::remove_dir_all(&dir);
+}
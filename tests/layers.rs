@@ -0,0 +1,90 @@
+//! The BG, window and sprites can each be hidden for debugging without the
+//! game noticing.
+
+#![cfg(feature = "debugger")]
+
+mod common;
+
+use common::rom_with_program;
+use rustboy::{ Gameboy, PixelLayer };
+
+/// A ROM that fills the BG and a window starting at x = 80 with black, and
+/// draws a light gray sprite in the top left corner
+fn test_rom() -> Vec< u8 >
+{
+    rom_with_program(&[
+        0xAF, 0xE0, 0x40,               // xor a; ldh (LCDC), a
+        0x21, 0x00, 0x80,               // ld hl, 0x8000
+        0x3E, 0xFF, 0x06, 0x10,         // ld a, 0xFF; ld b, 16
+        0x22, 0x05, 0x20, 0xFC,         // ld (hl+), a; dec b; jr nz, -4
+        0x06, 0x08,                     // ld b, 8
+        0x3E, 0xFF, 0x22, 0xAF, 0x22,   // ld a, 0xFF; ld (hl+), a; xor a; ld (hl+), a
+        0x05, 0x20, 0xF8,               // dec b; jr nz, -8
+
+        0x21, 0x00, 0xFE,               // ld hl, 0xFE00
+        0x3E, 0x10, 0x22,               // Y = 16
+        0x3E, 0x08, 0x22,               // X = 8
+        0x3E, 0x01, 0x22,               // Tile 1
+        0xAF, 0x22,                     // OBP0
+
+        0x3E, 0xE4, 0xE0, 0x48,         // ld a, 0xE4; ldh (OBP0), a
+        0x3E, 0x57, 0xE0, 0x4B,         // ld a, 87; ldh (WX), a
+        0x3E, 0xB3, 0xE0, 0x40,         // LCD, window, sprites and BG on
+        0x18, 0xFE                      // jr -2
+    ])
+}
+
+/// Run a couple of frames with the given layers hidden and get the layer
+/// and red component of the pixels at x = 0, 8 and 80 of the first line
+fn pixels(hidden: &[PixelLayer]) -> Vec< (PixelLayer, u8) >
+{
+    let mut gb = Gameboy::from_rom(test_rom());
+    for &layer in hidden
+    {
+        gb.set_layer_shown(layer, false);
+        assert!(!gb.layer_shown(layer));
+    }
+    gb.run_frame();
+    gb.run_frame();
+    [0, 8, 80].iter().map(|&x| (gb.pixel_sources()[x].layer, gb.get_image_data()[x * 4])).collect()
+}
+
+#[test]
+fn every_layer_shown_by_default()
+{
+    assert_eq!(pixels(&[]), vec![
+        (PixelLayer::Sprite, 192),
+        (PixelLayer::Background, 0),
+        (PixelLayer::Window, 0)
+    ]);
+}
+
+#[test]
+fn hide_each_layer()
+{
+    assert_eq!(pixels(&[PixelLayer::Sprite])[0], (PixelLayer::Background, 0));
+    assert_eq!(pixels(&[PixelLayer::Window])[2], (PixelLayer::Background, 0));
+    assert_eq!(pixels(&[PixelLayer::Background]), vec![
+        (PixelLayer::Sprite, 192),
+        (PixelLayer::Blank, 255),
+        (PixelLayer::Window, 0)
+    ]);
+}
+
+#[test]
+fn hiding_layers_leaves_the_game_alone()
+{
+    let mut shown = Gameboy::from_rom(test_rom());
+    let mut hidden = Gameboy::from_rom(test_rom());
+    for &layer in &[PixelLayer::Background, PixelLayer::Window, PixelLayer::Sprite]
+    {
+        hidden.set_layer_shown(layer, false);
+    }
+    for _ in 0..3
+    {
+        shown.run_frame();
+        hidden.run_frame();
+    }
+    assert_eq!(format!("{:?}", shown.registers()), format!("{:?}", hidden.registers()));
+    assert_eq!(shown.frame_count(), hidden.frame_count());
+}
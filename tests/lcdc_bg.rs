@@ -0,0 +1,50 @@
+//! LCDC bit 0 turns the BG and window off on the DMG, but on the CGB they
+//! are still drawn and only lose their priority over sprites.
+
+mod common;
+
+use common::rom_with_header;
+use rustboy::{ Gameboy, Target };
+
+/// A CGB compatible ROM that fills tile 0 with color 3, makes color 3 of
+/// CGB BG palette 0 black and turns the LCD on with the given LCDC value.
+/// Every tile map entry is tile 0, so the BG and window are solid black.
+fn test_rom(lcdc: u8) -> Vec< u8 >
+{
+    rom_with_header(&[(0x143, 0x80)], &[
+        0xAF, 0xE0, 0x40,               // xor a; ldh (LCDC), a
+        0x21, 0x00, 0x80,               // ld hl, 0x8000
+        0x3E, 0xFF, 0x06, 0x10,         // ld a, 0xFF; ld b, 16
+        0x22, 0x05, 0x20, 0xFC,         // ld (hl+), a; dec b; jr nz, -4
+        0x3E, 0x86, 0xE0, 0x68,         // ld a, 0x86; ldh (BCPS), a
+        0xAF, 0xE0, 0x69, 0xE0, 0x69,   // xor a; ldh (BCPD), a; ldh (BCPD), a
+        0x3E, lcdc, 0xE0, 0x40,         // ld a, lcdc; ldh (LCDC), a
+        0x18, 0xFE                      // jr -2
+    ])
+}
+
+/// Run a couple of frames and get the color of the top left pixel
+fn first_pixel(lcdc: u8, target: Target) -> [u8; 3]
+{
+    let mut gb = Gameboy::from_rom_as(test_rom(lcdc), target);
+    gb.run_frame();
+    gb.run_frame();
+    let image = gb.get_image_data();
+    [image[0], image[1], image[2]]
+}
+
+#[test]
+fn dmg_hides_bg_and_window()
+{
+    assert_eq!(first_pixel(0x91, Target::GameBoy), [0, 0, 0]);
+    assert_eq!(first_pixel(0x90, Target::GameBoy), [255, 255, 255]);
+    assert_eq!(first_pixel(0xB0, Target::GameBoy), [255, 255, 255]);
+}
+
+#[test]
+fn cgb_still_draws_bg_and_window()
+{
+    assert_eq!(first_pixel(0x91, Target::GameBoyColor), [0, 0, 0]);
+    assert_eq!(first_pixel(0x90, Target::GameBoyColor), [0, 0, 0]);
+    assert_eq!(first_pixel(0xB0, Target::GameBoyColor), [0, 0, 0]);
+}
@@ -0,0 +1,138 @@
+//! Two GameBoys connected by a link cable, in-process or over TCP, swap
+//! bytes a bit at a time when one of them clocks a transfer.
+
+mod common;
+
+use common::rom_with_program;
+use rustboy::{ Gameboy, LinkPort };
+use std::net::TcpListener;
+use std::thread;
+use std::time::Duration;
+
+/// A ROM that writes the given byte to SB, starts a transfer with the given
+/// SC value, waits for it to finish and then copies SB to B
+fn test_rom(sb: u8, sc: u8) -> Vec< u8 >
+{
+    rom_with_program(&[
+        0x3E, sb, 0xE0, 0x01,           // ld a, sb; ldh (SB), a
+        0x3E, sc, 0xE0, 0x02,           // ld a, sc; ldh (SC), a
+        0xF0, 0x02,                     // ldh a, (SC)
+        0xCB, 0x7F,                     // bit 7, a
+        0x20, 0xFA,                     // jr nz, -6
+        0xF0, 0x01,                     // ldh a, (SB)
+        0x47,                           // ld b, a
+        0x18, 0xFE                      // jr -2
+    ])
+}
+
+#[test]
+fn linked_gameboys_swap_bytes()
+{
+    let (a, b) = LinkPort::pair();
+    let mut master = Gameboy::from_rom(test_rom(0x42, 0x81));
+    let mut slave = Gameboy::from_rom(test_rom(0x99, 0x80));
+    master.connect_link(a);
+    slave.connect_link(b);
+
+    slave.run_frame();
+    assert_eq!(slave.registers().b, 0x00);
+
+    master.run_frame();
+    slave.run_frame();
+    assert_eq!(master.registers().b, 0x99);
+    assert_eq!(slave.registers().b, 0x42);
+}
+
+#[test]
+fn nothing_is_received_without_a_waiting_partner()
+{
+    let (a, _b) = LinkPort::pair();
+    let mut master = Gameboy::from_rom(test_rom(0x42, 0x81));
+    master.connect_link(a);
+
+    master.run_frame();
+    assert_eq!(master.registers().b, 0xFF);
+}
+
+#[test]
+fn external_clock_waits_until_linked_partner_clocks()
+{
+    let mut slave = Gameboy::from_rom(test_rom(0x99, 0x80));
+    for _ in 0..3
+    {
+        slave.run_frame();
+        assert_eq!(slave.registers().b, 0x00);
+    }
+    assert!(slave.disconnect_link().is_none());
+}
+
+/// A ROM that writes 0x42 to SB, starts a transfer with the internal clock
+/// and stops it after 4 and a half bit periods, then copies SB to C
+fn partial_rom() -> Vec< u8 >
+{
+    rom_with_program(&[
+        0x3E, 0x42, 0xE0, 0x01,         // ld a, 0x42; ldh (SB), a
+        0x3E, 0x81, 0xE0, 0x02,         // ld a, 0x81; ldh (SC), a
+        0x06, 0x90,                     // ld b, 144
+        0x05, 0x20, 0xFD,               // dec b; jr nz, -3
+        0x3E, 0x01, 0xE0, 0x02,         // ld a, 0x01; ldh (SC), a
+        0xF0, 0x01, 0x4F,               // ldh a, (SB); ld c, a
+        0x18, 0xFE                      // jr -2
+    ])
+}
+
+/// A ROM that writes 0x99 to SB, waits for a transfer with the external
+/// clock and keeps copying SB to C and SC to D
+fn partial_slave_rom() -> Vec< u8 >
+{
+    rom_with_program(&[
+        0x3E, 0x99, 0xE0, 0x01,         // ld a, 0x99; ldh (SB), a
+        0x3E, 0x80, 0xE0, 0x02,         // ld a, 0x80; ldh (SC), a
+        0xF0, 0x01, 0x4F,               // ldh a, (SB); ld c, a
+        0xF0, 0x02, 0x57,               // ldh a, (SC); ld d, a
+        0x18, 0xF8                      // jr -8
+    ])
+}
+
+#[test]
+fn bits_are_shifted_one_clock_period_at_a_time()
+{
+    let (a, b) = LinkPort::pair();
+    let mut master = Gameboy::from_rom(partial_rom());
+    let mut slave = Gameboy::from_rom(partial_slave_rom());
+    master.connect_link(a);
+    slave.connect_link(b);
+
+    slave.run_frame();
+    master.run_frame();
+    slave.run_frame();
+
+    // 4 of the 8 bits went each way before the master stopped clocking, so
+    // the slave is still waiting for the rest
+    assert_eq!(master.registers().c, 0x29);
+    assert_eq!(slave.registers().c, 0x94);
+    assert_eq!(slave.registers().d & 0x80, 0x80);
+}
+
+#[test]
+fn linked_over_tcp()
+{
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let a = LinkPort::connect_tcp(listener.local_addr().unwrap()).unwrap();
+    let b = LinkPort::from_tcp(listener.accept().unwrap().0).unwrap();
+
+    let mut master = Gameboy::from_rom(test_rom(0x42, 0x81));
+    let mut slave = Gameboy::from_rom(test_rom(0x99, 0x80));
+    master.connect_link(a);
+    slave.connect_link(b);
+
+    // Give each message time to cross the connection
+    let wait = || thread::sleep(Duration::from_millis(100));
+    slave.run_frame();
+    wait();
+    master.run_frame();
+    wait();
+    slave.run_frame();
+    assert_eq!(master.registers().b, 0x99);
+    assert_eq!(slave.registers().b, 0x42);
+}
@@ -0,0 +1,126 @@
+//! mooneye-gb acceptance test suite. The test ROMs are not distributed with
+//! rustboy; build them from https://github.com/Gekkio/mooneye-gb and place the
+//! `acceptance` ROMs in tests/mooneye, or point the MOONEYE_ROMS environment
+//! variable at the directory containing them. Run with:
+//!
+//!     cargo test --features mooneye --test mooneye
+
+#![cfg(feature = "mooneye")]
+
+use rustboy::Gameboy;
+use std::env;
+use std::fs;
+use std::panic::{ self, AssertUnwindSafe };
+use std::path::{ Path, PathBuf };
+
+/// The maximum number of frames a test ROM may run before it is failed
+const TIMEOUT_FRAMES: u32 = 60 * 10;
+
+/// Timer ROMs that must be present and pass, for the TIMA reload timing
+const TIMER_ROMS: [&str; 2] = [
+    "timer/tima_write_reloading.gb",
+    "timer/tma_write_reloading.gb"
+];
+
+/// The outcome of running a single test ROM
+#[derive(Debug, PartialEq, Eq)]
+enum Outcome
+{
+    Pass,
+    Fail,
+    Timeout,
+    Crash
+}
+
+/// Run a mooneye test ROM until it reports a result. Mooneye tests load the
+/// Fibonacci numbers 3, 5, 8, 13, 21, 34 into B, C, D, E, H, L on success and
+/// 0x42 into every register on failure.
+fn run_test(rom: Vec< u8 >) -> Outcome
+{
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        let mut gb = Gameboy::from_rom(rom);
+        for _ in 0..TIMEOUT_FRAMES
+        {
+            gb.run_frame();
+
+            let r = gb.registers();
+            let regs = [r.b, r.c, r.d, r.e, r.h, r.l];
+            if regs == [3, 5, 8, 13, 21, 34] { return Outcome::Pass }
+            if regs == [0x42; 6] { return Outcome::Fail }
+        }
+        Outcome::Timeout
+    }));
+
+    result.unwrap_or(Outcome::Crash)
+}
+
+/// Recursively collect every .gb file in the given directory
+fn collect_roms(dir: &Path, roms: &mut Vec< PathBuf >)
+{
+    let entries = match fs::read_dir(dir) {
+        Ok(e) => e,
+        Err(_) => return
+    };
+
+    for entry in entries.filter_map(|e| e.ok())
+    {
+        let path = entry.path();
+        if path.is_dir()
+        {
+            collect_roms(&path, roms);
+        }
+        else if path.extension().map_or(false, |e| e == "gb")
+        {
+            roms.push(path);
+        }
+    }
+}
+
+/// The directory holding the test ROMs
+fn rom_dir() -> PathBuf
+{
+    env::var("MOONEYE_ROMS")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/mooneye"))
+}
+
+#[test]
+fn mooneye_timer_reload()
+{
+    let dir = rom_dir();
+    for name in TIMER_ROMS
+    {
+        let rom = fs::read(dir.join(name))
+            .unwrap_or_else(|e| panic!("Unable to read {}: {}", name, e));
+        assert_eq!(run_test(rom), Outcome::Pass, "{}", name);
+    }
+}
+
+#[test]
+fn mooneye_acceptance()
+{
+    let dir = rom_dir();
+
+    let mut roms = Vec::new();
+    collect_roms(&dir, &mut roms);
+    roms.sort();
+    assert!(!roms.is_empty(), "No mooneye test ROMs found in {}", dir.display());
+
+    // Silence the default panic output of ROMs that crash the emulator
+    panic::set_hook(Box::new(|_| {}));
+
+    let mut failed = Vec::new();
+    for path in roms.iter()
+    {
+        let rom = fs::read(path).expect("Unable to read test ROM");
+        let outcome = run_test(rom);
+        println!("{:?}: {}", outcome, path.display());
+        if outcome != Outcome::Pass
+        {
+            failed.push(path);
+        }
+    }
+
+    let _ = panic::take_hook();
+    assert!(failed.is_empty(), "{} of {} mooneye tests failed", failed.len(), roms.len());
+}
@@ -0,0 +1,95 @@
+//! OAM DMA reads its source through the DMA controller's view of the bus,
+//! and the CPU can't touch OAM until the transfer is done.
+
+mod common;
+
+use common::rom_with_program;
+use rustboy::Gameboy;
+
+/// A ROM that runs OAM DMAs from a few sources, reading back the first
+/// byte of OAM after each into B, C, D and E
+fn test_rom() -> Vec< u8 >
+{
+    rom_with_program(&[
+        0x3E, 0x5A, 0xEA, 0x00, 0xC1,   // ld a, 0x5A; ld (0xC100), a
+        0x3E, 0x77, 0xEA, 0x00, 0xD1,   // ld a, 0x77; ld (0xD100), a
+        0x3E, 0x33, 0xEA, 0x00, 0xDE,   // ld a, 0x33; ld (0xDE00), a
+
+        0x3E, 0xE1, 0xE0, 0x46,         // DMA from 0xE100
+        0x3E, 0x28, 0x3D, 0x20, 0xFD,   // Wait for it to finish
+        0xFA, 0x00, 0xFE, 0x47,         // ld a, (0xFE00); ld b, a
+        0x3E, 0xF1, 0xE0, 0x46,         // DMA from 0xF100
+        0x3E, 0x28, 0x3D, 0x20, 0xFD,
+        0xFA, 0x00, 0xFE, 0x4F,         // ld a, (0xFE00); ld c, a
+        0x3E, 0xFE, 0xE0, 0x46,         // DMA from 0xFE00
+        0x3E, 0x28, 0x3D, 0x20, 0xFD,
+        0xFA, 0x00, 0xFE, 0x57,         // ld a, (0xFE00); ld d, a
+
+        0xF0, 0x41, 0xE6, 0x03,         // ldh a, (STAT); and 3
+        0xFE, 0x03, 0x28, 0xF8,         // cp 3; jr z, -8
+        0xF0, 0x41, 0xE6, 0x03,         // ldh a, (STAT); and 3
+        0xFE, 0x03, 0x20, 0xF8,         // cp 3; jr nz, -8
+        0x3E, 0x80, 0xE0, 0x46,         // DMA from 0x8000 while drawing
+        0x3E, 0x28, 0x3D, 0x20, 0xFD,
+        0xFA, 0x00, 0xFE, 0x5F,         // ld a, (0xFE00); ld e, a
+        0x18, 0xFE                      // jr -2
+    ])
+}
+
+#[test]
+fn dma_sources()
+{
+    let mut gb = Gameboy::from_rom(test_rom());
+    gb.run_frame();
+    gb.run_frame();
+
+    let r = gb.registers();
+    assert_eq!(r.b, 0x5A, "0xE100 mirrors 0xC100");
+    assert_eq!(r.c, 0x77, "0xF100 mirrors 0xD100");
+    assert_eq!(r.d, 0x33, "0xFE00 mirrors 0xDE00");
+    assert_eq!(r.e, 0xFF, "VRAM is blocked while the GPU draws");
+}
+
+/// A ROM that copies the usual DMA routine into HRAM and uses it to copy
+/// 0xC100 into OAM. Before that it starts a DMA by hand and tries to read
+/// and write OAM while it runs. The results go in B, C and D.
+fn hram_routine_rom() -> Vec< u8 >
+{
+    let mut rom = rom_with_program(&[
+        0x3E, 0x5A, 0xEA, 0x00, 0xC1,   // ld a, 0x5A; ld (0xC100), a
+        0x3E, 0x22, 0xEA, 0x01, 0xC1,   // ld a, 0x22; ld (0xC101), a
+
+        0x21, 0x00, 0x02,               // ld hl, 0x0200
+        0x0E, 0x80, 0x06, 0x08,         // ld c, 0x80; ld b, 8
+        0x2A, 0xE2, 0x0C,               // ld a, (hl+); ld (c), a; inc c
+        0x05, 0x20, 0xFA,               // dec b; jr nz, -6
+
+        0x3E, 0xC1, 0xE0, 0x46,         // DMA from 0xC100
+        0xFA, 0x00, 0xFE, 0x4F,         // ld a, (0xFE00); ld c, a
+        0x3E, 0x11, 0xEA, 0x01, 0xFE,   // ld a, 0x11; ld (0xFE01), a
+        0x3E, 0x28, 0x3D, 0x20, 0xFD,   // Wait for it to finish
+        0xFA, 0x01, 0xFE, 0x57,         // ld a, (0xFE01); ld d, a
+
+        0xAF, 0xEA, 0x00, 0xFE,         // xor a; ld (0xFE00), a
+        0x3E, 0xC1, 0xCD, 0x80, 0xFF,   // ld a, 0xC1; call 0xFF80
+        0xFA, 0x00, 0xFE, 0x47,         // ld a, (0xFE00); ld b, a
+        0x18, 0xFE                      // jr -2
+    ]);
+
+    // ldh (DMA), a; ld a, 40; dec a; jr nz, -3; ret
+    rom[0x200..0x208].copy_from_slice(&[0xE0, 0x46, 0x3E, 0x28, 0x3D, 0x20, 0xFD, 0xC9]);
+    rom
+}
+
+#[test]
+fn oam_blocked_during_dma()
+{
+    let mut gb = Gameboy::from_rom(hram_routine_rom());
+    gb.run_frame();
+    gb.run_frame();
+
+    let r = gb.registers();
+    assert_eq!(r.b, 0x5A, "OAM holds the data once the routine returns");
+    assert_eq!(r.c, 0xFF, "OAM reads 0xFF during DMA");
+    assert_eq!(r.d, 0x22, "OAM writes are ignored during DMA");
+}
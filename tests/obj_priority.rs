@@ -0,0 +1,77 @@
+//! Overlapping sprites are prioritized by X coordinate on the DMG and by OAM
+//! index in CGB mode, as selected by OPRI (0xFF6C).
+
+mod common;
+
+use common::rom_with_header;
+use rustboy::{ Gameboy, Target };
+
+/// A ROM that draws two overlapping sprites of solid color 1 on the top
+/// line at the given X coordinates, the first with palette 0 and the second
+/// with palette 1, then tries to switch OPRI and keeps what it reads in B
+fn test_rom(cgb: bool, x0: u8, x1: u8) -> Vec< u8 >
+{
+    rom_with_header(&[(0x143, if cgb { 0x80 } else { 0x00 })], &[
+        0xAF, 0xE0, 0x40,               // xor a; ldh (LCDC), a
+        0x21, 0x10, 0x80,               // ld hl, 0x8010
+        0x06, 0x08,                     // ld b, 8
+        0x3E, 0xFF, 0x22, 0xAF, 0x22,   // ld a, 0xFF; ld (hl+), a; xor a; ld (hl+), a
+        0x05, 0x20, 0xF8,               // dec b; jr nz, -8
+
+        0x21, 0x00, 0xFE,               // ld hl, 0xFE00
+        0x3E, 0x10, 0x22,               // Y = 16
+        0x3E, x0, 0x22,                 // X
+        0x3E, 0x01, 0x22,               // Tile 1
+        0x3E, 0x00, 0x22,               // Palette 0
+        0x3E, 0x10, 0x22,               // Y = 16
+        0x3E, x1, 0x22,                 // X
+        0x3E, 0x01, 0x22,               // Tile 1
+        0x3E, 0x11, 0x22,               // OBP1, or CGB palette 1
+
+        0x3E, 0x83, 0xE0, 0x40,         // LCD on with sprites
+        0x3E, 0x01, 0xE0, 0x6C,         // ld a, 1; ldh (OPRI), a
+        0xF0, 0x6C, 0x47,               // ldh a, (OPRI); ld b, a
+        0x18, 0xFE                      // jr -2
+    ])
+}
+
+/// Run a couple of frames and get the palette of each sprite pixel on the
+/// top line from x = 8 to 19, which both sprites cover some of
+fn palettes(target: Target, cgb: bool, x0: u8, x1: u8) -> (Vec< u8 >, u8)
+{
+    let mut gb = Gameboy::from_rom_as(test_rom(cgb, x0, x1), target);
+    gb.run_frame();
+    gb.run_frame();
+    let line = gb.pixel_sources()[8..20].iter().map(|s| s.palette).collect();
+    (line, gb.registers().b)
+}
+
+#[test]
+fn dmg_prioritizes_by_x()
+{
+    // The leftmost sprite wins even though it comes first in OAM
+    let (line, _) = palettes(Target::GameBoy, false, 16, 20);
+    assert_eq!(line, [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1]);
+    let (line, _) = palettes(Target::GameBoy, false, 20, 16);
+    assert_eq!(line, [1, 1, 1, 1, 1, 1, 1, 1, 0, 0, 0, 0]);
+
+    // Ties go to the first in OAM
+    let (line, _) = palettes(Target::GameBoy, false, 16, 16);
+    assert_eq!(line[..8], [0; 8]);
+
+    // DMG games keep DMG priority on a CGB
+    let (line, opri) = palettes(Target::GameBoyColor, false, 20, 16);
+    assert_eq!(line, [1, 1, 1, 1, 1, 1, 1, 1, 0, 0, 0, 0]);
+    assert_eq!(opri, 0xFF);
+}
+
+#[test]
+fn cgb_prioritizes_by_oam_index()
+{
+    // The first sprite in OAM wins wherever they overlap
+    let (line, opri) = palettes(Target::GameBoyColor, true, 20, 16);
+    assert_eq!(line, [1, 1, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0]);
+
+    // OPRI is locked by the time the game runs
+    assert_eq!(opri, 0xFE);
+}
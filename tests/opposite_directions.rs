@@ -0,0 +1,47 @@
+//! Holding opposite directions at once is hidden from the game unless the
+//! policy allows it.
+
+mod common;
+
+use common::rom_with_program;
+use rustboy::{ ButtonState, Gameboy, OppositeDirections };
+
+/// A ROM that selects the D-pad and copies the keypad lines to B forever
+fn test_rom() -> Vec< u8 >
+{
+    rom_with_program(&[
+        0x3E, 0x20, 0xE0, 0x00,         // ld a, 0x20; ldh (P1), a
+        0xF0, 0x00,                     // ldh a, (P1)
+        0xE6, 0x0F,                     // and 0x0F
+        0x47,                           // ld b, a
+        0x18, 0xF7                      // jr -9
+    ])
+}
+
+/// Get the D-pad lines the game sees while the given buttons are held
+fn lines(policy: OppositeDirections, held: ButtonState) -> u8
+{
+    let mut gb = Gameboy::from_rom(test_rom());
+    gb.set_opposite_directions(policy);
+    gb.set_inputs(held);
+    gb.run_frame();
+    gb.registers().b
+}
+
+#[test]
+fn opposite_directions_are_blocked_by_default()
+{
+    let mut gb = Gameboy::from_rom(test_rom());
+    gb.set_inputs(ButtonState::LEFT | ButtonState::RIGHT | ButtonState::UP);
+    gb.run_frame();
+    assert_eq!(gb.registers().b, 0xB);
+    assert_eq!(gb.inputs(), ButtonState::LEFT | ButtonState::RIGHT | ButtonState::UP);
+}
+
+#[test]
+fn opposite_directions_can_be_allowed()
+{
+    let held = ButtonState::UP | ButtonState::DOWN | ButtonState::LEFT;
+    assert_eq!(lines(OppositeDirections::Block, held), 0xD);
+    assert_eq!(lines(OppositeDirections::Allow, held), 0x1);
+}
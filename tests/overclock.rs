@@ -0,0 +1,88 @@
+//! Overclocking speeds up the CPU without changing the timing of the rest
+//! of the system.
+
+mod common;
+
+use common::rom_with_program;
+use rustboy::{ Gameboy, GameboyBuilder, MAX_OVERCLOCK };
+
+/// A ROM that counts loops in DE forever
+fn test_rom() -> Vec< u8 >
+{
+    rom_with_program(&[0x13, 0x18, 0xFD])     // inc de; jr -3
+}
+
+/// Run a frame and get the number of loops the CPU ran and the ticks that
+/// passed
+fn run_frame(gb: &mut Gameboy) -> (u32, u64)
+{
+    let r = gb.registers();
+    let (de, cycles) = ((r.d as u32) << 8 | r.e as u32, gb.cycle_count());
+    gb.run_frame();
+    let r = gb.registers();
+    let loops = ((r.d as u32) << 8 | r.e as u32).wrapping_sub(de) & 0xFFFF;
+    (loops, gb.cycle_count() - cycles)
+}
+
+#[test]
+fn cpu_runs_faster()
+{
+    let mut stock = Gameboy::from_rom(test_rom());
+    let mut fast = GameboyBuilder::new().rom_bytes(test_rom()).overclock(2).build().unwrap();
+    run_frame(&mut stock);
+    run_frame(&mut fast);
+
+    let (stock_loops, stock_ticks) = run_frame(&mut stock);
+    let (fast_loops, fast_ticks) = run_frame(&mut fast);
+    assert!(fast_loops.abs_diff(stock_loops * 2) <= 2, "{} vs {}", fast_loops, stock_loops);
+
+    // Frames take just as long
+    assert!(fast_ticks.abs_diff(stock_ticks) <= 8, "{} vs {}", fast_ticks, stock_ticks);
+    assert_eq!(fast.frame_count(), stock.frame_count());
+}
+
+#[test]
+fn factor_is_limited()
+{
+    let mut gb = Gameboy::from_rom(test_rom());
+    gb.set_overclock(0);
+    assert_eq!(gb.overclock(), 1);
+    gb.set_overclock(100);
+    assert_eq!(gb.overclock(), MAX_OVERCLOCK);
+
+    assert!(GameboyBuilder::new().rom_bytes(test_rom()).overclock(0).build().is_err());
+    assert!(GameboyBuilder::new().rom_bytes(test_rom()).overclock(MAX_OVERCLOCK + 1).build().is_err());
+}
+
+/// A ROM that starts TIMA counting at 4096Hz and keeps reading DIV into D
+/// and TIMA into E
+fn timer_rom() -> Vec< u8 >
+{
+    rom_with_program(&[
+        0x3E, 0x04, 0xE0, 0x07,         // ld a, 0x04; ldh (TAC), a
+        0xF0, 0x04, 0x57,               // ldh a, (DIV); ld d, a
+        0xF0, 0x05, 0x5F,               // ldh a, (TIMA); ld e, a
+        0x18, 0xF8                      // jr -8
+    ])
+}
+
+#[test]
+fn timer_keeps_stock_rate()
+{
+    for factor in 2..=MAX_OVERCLOCK
+    {
+        let mut stock = Gameboy::from_rom(timer_rom());
+        let mut fast = GameboyBuilder::new().rom_bytes(timer_rom()).overclock(factor).build().unwrap();
+        for _ in 0..10
+        {
+            stock.run_frame();
+            fast.run_frame();
+        }
+
+        // The registers are read at slightly different times in the loop
+        let (stock, fast) = (stock.registers(), fast.registers());
+        let close = |a: u8, b: u8| (a.wrapping_sub(b) as i8).unsigned_abs() <= 1;
+        assert!(close(fast.d, stock.d), "DIV {} vs {} at {}x", fast.d, stock.d, factor);
+        assert!(close(fast.e, stock.e), "TIMA {} vs {} at {}x", fast.e, stock.e, factor);
+    }
+}
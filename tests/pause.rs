@@ -0,0 +1,90 @@
+//! Pausing stops time passing for the game until it is resumed, apart from
+//! explicit frame advances, so a pause is invisible to the game's timers and
+//! clock.
+
+mod common;
+
+use common::{ cart_with_program, rom_with_program };
+use rustboy::Gameboy;
+
+/// A ROM that spins forever
+fn test_rom() -> Vec< u8 >
+{
+    rom_with_program(&[0x18, 0xFE])
+}
+
+/// A ROM for an MBC3 cartridge with a clock that starts the timer at
+/// 262144Hz and keeps copying DIV to B, TIMA to C and the latched clock
+/// seconds to D
+fn timer_rom() -> Vec< u8 >
+{
+    cart_with_program(0x10, 0x02, &[
+        0x3E, 0x05, 0xE0, 0x07,         // ld a, 0x05; ldh (TAC), a
+        0x3E, 0x0A, 0xEA, 0x00, 0x00,   // ld a, 0x0A; ld (0x0000), a
+        0x3E, 0x08, 0xEA, 0x00, 0x40,   // ld a, 0x08; ld (0x4000), a
+        0xF0, 0x04, 0x47,               // loop: ldh a, (DIV); ld b, a
+        0xF0, 0x05, 0x4F,               // ldh a, (TIMA); ld c, a
+        0xAF, 0xEA, 0x00, 0x60,         // xor a; ld (0x6000), a
+        0x3C, 0xEA, 0x00, 0x60,         // inc a; ld (0x6000), a
+        0xFA, 0x00, 0xA0, 0x57,         // ld a, (0xA000); ld d, a
+        0x18, 0xEC                      // jr loop
+    ])
+}
+
+#[test]
+fn pause_freezes_emulation()
+{
+    let mut gb = Gameboy::from_rom(test_rom());
+    gb.run();
+    gb.pause();
+    assert!(gb.is_paused());
+
+    let (cycles, status) = (gb.cycle_count(), gb.status());
+    for _ in 0..10
+    {
+        gb.run();
+        let chunk = gb.next_av_chunk();
+        assert!(chunk.frame.is_none() && chunk.audio.is_empty());
+    }
+    assert_eq!(gb.cycle_count(), cycles);
+    assert_eq!(gb.status(), status);
+
+    // Frame advance still works while paused
+    gb.run_frame();
+    assert_eq!(gb.frame_count(), status.frame_count + 1);
+    assert!(gb.is_paused());
+
+    gb.resume();
+    gb.run();
+    assert!(gb.cycle_count() > cycles);
+}
+
+#[test]
+fn pause_freezes_timers_and_clock()
+{
+    // The same game run straight through and with a pause in the middle
+    // sees the same DIV, TIMA and clock
+    let mut paused = Gameboy::from_rom(timer_rom());
+    let mut straight = Gameboy::from_rom(timer_rom());
+    for _ in 0..90
+    {
+        paused.run_frame();
+        straight.run_frame();
+    }
+
+    paused.pause();
+    for _ in 0..100
+    {
+        paused.run();
+    }
+    paused.resume();
+
+    for _ in 0..40
+    {
+        paused.run_frame();
+        straight.run_frame();
+    }
+    let (a, b) = (paused.registers(), straight.registers());
+    assert_eq!((a.b, a.c, a.d), (b.b, b.c, b.d));
+    assert_eq!(a.d, 2);
+}
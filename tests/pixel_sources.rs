@@ -0,0 +1,72 @@
+//! Every pixel on the screen records the layer, color index and palette it
+//! was drawn from, for frontends that colorize games themselves.
+
+mod common;
+
+use common::rom_with_program;
+use rustboy::{ Gameboy, PixelLayer, PixelSource };
+
+/// A ROM that fills the BG and a window starting at x = 80 with color 3 of
+/// tile 0, and draws a sprite of color 1 using OBP1 in the top left corner,
+/// then turns the LCD on with the given LCDC value
+fn test_rom(lcdc: u8) -> Vec< u8 >
+{
+    rom_with_program(&[
+        0xAF, 0xE0, 0x40,               // xor a; ldh (LCDC), a
+        0x21, 0x00, 0x80,               // ld hl, 0x8000
+        0x3E, 0xFF, 0x06, 0x10,         // ld a, 0xFF; ld b, 16
+        0x22, 0x05, 0x20, 0xFC,         // ld (hl+), a; dec b; jr nz, -4
+        0x06, 0x08,                     // ld b, 8
+        0x3E, 0xFF, 0x22, 0xAF, 0x22,   // ld a, 0xFF; ld (hl+), a; xor a; ld (hl+), a
+        0x05, 0x20, 0xF8,               // dec b; jr nz, -8
+
+        0x21, 0x00, 0xFE,               // ld hl, 0xFE00
+        0x3E, 0x10, 0x22,               // Y = 16
+        0x3E, 0x08, 0x22,               // X = 8
+        0x3E, 0x01, 0x22,               // Tile 1
+        0x3E, 0x10, 0x22,               // OBP1
+
+        0x3E, 0x57, 0xE0, 0x4B,         // ld a, 87; ldh (WX), a
+        0x3E, lcdc, 0xE0, 0x40,         // ld a, lcdc; ldh (LCDC), a
+        0x18, 0xFE                      // jr -2
+    ])
+}
+
+/// Run a couple of frames and get the sources of the first line
+fn first_line(lcdc: u8, worker: bool) -> Vec< PixelSource >
+{
+    let mut gb = Gameboy::from_rom(test_rom(lcdc));
+    gb.set_render_worker(worker);
+    gb.run_frame();
+    gb.run_frame();
+    gb.pixel_sources()[..160].to_vec()
+}
+
+fn source(layer: PixelLayer, color: u8, palette: u8) -> PixelSource
+{
+    PixelSource { layer, color, palette }
+}
+
+#[test]
+fn pixels_record_their_layer()
+{
+    for &worker in &[false, true]
+    {
+        let line = first_line(0xB3, worker);
+        assert_eq!(line[0], source(PixelLayer::Sprite, 1, 1));
+        assert_eq!(line[7], source(PixelLayer::Sprite, 1, 1));
+        assert_eq!(line[8], source(PixelLayer::Background, 3, 0));
+        assert_eq!(line[79], source(PixelLayer::Background, 3, 0));
+        assert_eq!(line[80], source(PixelLayer::Window, 3, 0));
+        assert_eq!(line[159], source(PixelLayer::Window, 3, 0));
+    }
+}
+
+#[test]
+fn hidden_bg_is_blank()
+{
+    let line = first_line(0xB2, false);
+    assert_eq!(line[0], source(PixelLayer::Sprite, 1, 1));
+    assert_eq!(line[8], PixelSource::default());
+    assert_eq!(line[8].layer, PixelLayer::Blank);
+}
@@ -0,0 +1,97 @@
+//! Playing back movies in the emulator and taking control part way through.
+
+mod common;
+
+use common::rom_with_program;
+use rustboy::{ ButtonState, Gameboy, Movie, TakeOver };
+
+/// A ROM that spins forever
+fn test_rom() -> Vec< u8 >
+{
+    rom_with_program(&[0x18, 0xFE])
+}
+
+/// A movie that holds each direction in turn for 5 frames
+fn test_movie() -> Movie
+{
+    let text = ["U.......\n", "...R....\n", ".D......\n"].map(|line| line.repeat(5)).concat();
+    Movie::parse(&text).unwrap()
+}
+
+#[test]
+fn plays_every_frame()
+{
+    let movie = test_movie();
+    let mut gb = Gameboy::from_rom(test_rom());
+    gb.play_movie(movie.clone());
+
+    for (i, &state) in movie.frames().iter().enumerate()
+    {
+        assert_eq!(gb.playback().map(|(_, frame)| frame), Some(i));
+        assert_eq!(gb.inputs(), state);
+        gb.run_frame();
+    }
+
+    // Every button is released once the movie ends
+    assert!(gb.playback().is_none());
+    assert_eq!(gb.inputs(), ButtonState::default());
+}
+
+#[test]
+fn take_over_and_record()
+{
+    let a = ButtonState::A;
+
+    let mut gb = Gameboy::from_rom(test_rom());
+    gb.play_movie(test_movie());
+    for _ in 0..7 { gb.run_frame(); }
+
+    gb.take_over(TakeOver::Record);
+    assert!(gb.playback().is_none());
+    gb.set_inputs(a);
+    for _ in 0..3 { gb.run_frame(); }
+
+    let movie = gb.stop_recording().unwrap();
+    assert_eq!(movie.len(), 10);
+    assert_eq!(movie.frames()[..7], test_movie().frames()[..7]);
+    assert!(movie.frames()[7..].iter().all(|&f| f == a));
+}
+
+#[test]
+fn take_over_free_play()
+{
+    let mut gb = Gameboy::from_rom(test_rom());
+    gb.play_movie(test_movie());
+    for _ in 0..3 { gb.run_frame(); }
+
+    gb.take_over(TakeOver::FreePlay);
+    assert!(gb.playback().is_none());
+    assert!(gb.recording().is_none());
+
+    // The movie no longer drives the buttons
+    gb.set_inputs(ButtonState::default());
+    for _ in 0..5 { gb.run_frame(); }
+    assert_eq!(gb.inputs(), ButtonState::default());
+}
+
+#[test]
+#[cfg(feature = "savestates")]
+fn savestates_seek_playback()
+{
+    let movie = test_movie();
+    let mut gb = Gameboy::from_rom(test_rom());
+    gb.play_movie(movie.clone());
+    for _ in 0..4 { gb.run_frame(); }
+    let state = gb.save_state();
+    for _ in 0..8 { gb.run_frame(); }
+
+    gb.load_state(&state).unwrap();
+    assert_eq!(gb.playback().map(|(_, frame)| frame), Some(4));
+    assert_eq!(gb.inputs(), movie.frames()[4]);
+    assert_eq!(gb.playback().unwrap().0, &movie);
+
+    // States from outside of a movie can't be placed in it
+    let mut free = Gameboy::from_rom(test_rom());
+    free.run_frame();
+    assert!(gb.load_state(&free.save_state()).is_err());
+}
@@ -0,0 +1,129 @@
+//! A trade between two copies of Pokemon Red linked by a cable, which relies
+//! on the serial port's timing to get the game's link protocol through. The
+//! ROM and saves are not distributed with rustboy; place them in
+//! tests/pokemon, or point the POKEMON_ROMS environment variable at the
+//! directory containing them:
+//!
+//! - `red.gb`: Pokemon Red
+//! - `red_a.sav` and `red_b.sav`: battery saves of the two players, each
+//!   made standing in front of the Cable Club receptionist on the second
+//!   floor of a Pokemon Center, with different Pokemon first in the party
+//!
+//! Run with:
+//!
+//!     cargo test --release --features pokemon --test pokemon_trade
+
+#![cfg(feature = "pokemon")]
+
+use rustboy::{ ButtonState, Gameboy, LinkPort, RunExit };
+use std::env;
+use std::fs;
+use std::path::{ Path, PathBuf };
+
+/// The maximum number of frames the trade may take before it is failed
+const TIMEOUT_FRAMES: u32 = 60 * 60 * 3;
+
+/// Ticks each GameBoy runs before the other catches up, a little under
+/// one bit period of the serial clock
+const LOCKSTEP_TICKS: u64 = 256;
+
+/// Ticks in a frame
+const TICKS_PER_FRAME: u64 = 70224;
+
+/// Frames A is held and then released for while mashing through the
+/// dialogue and menus
+const MASH_FRAMES: u64 = 6;
+
+/// Number of Pokemon in the party (wPartyCount)
+const PARTY_COUNT: u16 = 0xD163;
+
+/// Species of each Pokemon in the party (wPartySpecies)
+const PARTY_SPECIES: u16 = 0xD164;
+
+/// The current map (wCurMap), reported when the trade times out
+const CUR_MAP: u16 = 0xD35E;
+
+/// Offset of the party count in a save, followed by the party's species
+const SAVE_PARTY: usize = 0x2F2C;
+
+/// The directory holding the ROM and saves
+fn rom_dir() -> PathBuf
+{
+    env::var("POKEMON_ROMS")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/pokemon"))
+}
+
+/// Read a file from the ROM directory
+fn read(name: &str) -> Vec< u8 >
+{
+    let path = rom_dir().join(name);
+    fs::read(&path).unwrap_or_else(|e| panic!("Unable to read {}: {}", path.display(), e))
+}
+
+/// Load Pokemon Red with one of the players' saves and get the species of
+/// every Pokemon in the saved party
+fn player(name: &str) -> (Gameboy, Vec< u8 >)
+{
+    let save = read(name);
+    let count = save[SAVE_PARTY].min(6) as usize;
+    let party = save[SAVE_PARTY + 1..SAVE_PARTY + 1 + count].to_vec();
+
+    let mut gb = Gameboy::from_rom(read("red.gb"));
+    gb.import_save(&save).unwrap();
+    (gb, party)
+}
+
+/// Get the species of every Pokemon in the party
+fn party(gb: &Gameboy) -> Vec< u8 >
+{
+    let count = gb.peek(PARTY_COUNT).min(6) as u16;
+    (0..count).map(|i| gb.peek(PARTY_SPECIES + i)).collect()
+}
+
+#[test]
+fn trade_first_pokemon()
+{
+    let ((mut a, party_a), (mut b, party_b)) = (player("red_a.sav"), player("red_b.sav"));
+    assert_ne!(party_a.first(), party_b.first(), "Both players would trade the same Pokemon");
+    let (port_a, port_b) = LinkPort::pair();
+    a.connect_link(port_a);
+    b.connect_link(port_b);
+    let mut gbs = [a, b];
+    let before = [party_a, party_b];
+    let frames = |gb: &Gameboy| gb.cycle_count() / TICKS_PER_FRAME;
+
+    // Mashing A continues the saved game, talks to the receptionist, agrees
+    // to save, picks the Trade Center, sits at the table, picks the first
+    // Pokemon and confirms the trade. Both GameBoys run in lockstep so each
+    // side answers every byte the other sends before the next one.
+    let mut cycle = 0;
+    while frames(&gbs[0]) < TIMEOUT_FRAMES as u64
+    {
+        cycle += LOCKSTEP_TICKS;
+        for gb in gbs.iter_mut()
+        {
+            let mash = frames(gb) / MASH_FRAMES % 2 == 0;
+            gb.set_inputs(if mash { ButtonState::A } else { ButtonState::empty() });
+            assert_eq!(gb.run_until_cycle(cycle), RunExit::Completed);
+        }
+
+        // The traded Pokemon leaves the party and the one received joins
+        // at the end
+        let after = [party(&gbs[0]), party(&gbs[1])];
+        let traded = |i: usize| {
+            after[i].len() == before[i].len() && after[i].last() == before[1 - i].first()
+        };
+        if traded(0) && traded(1)
+        {
+            for i in 0..2
+            {
+                assert_eq!(after[i][..after[i].len() - 1], before[i][1..], "Player {} party", i);
+            }
+            return
+        }
+    }
+
+    panic!("No trade after {} frames, on maps {:02X} and {:02X} with parties {:02X?} and {:02X?}",
+        TIMEOUT_FRAMES, gbs[0].peek(CUR_MAP), gbs[1].peek(CUR_MAP), party(&gbs[0]), party(&gbs[1]));
+}
@@ -0,0 +1,58 @@
+//! Drawing lines on the render worker gives the same frames as drawing them
+//! while the CPU waits.
+
+mod common;
+
+use common::rom_with_program;
+use rustboy::{ Gameboy, DISPLAY_HEIGHT };
+use std::sync::{ Arc, Mutex };
+
+/// A ROM that turns the LCD on with the BG, window and sprites enabled and
+/// then keeps rewriting tile data and scrolling the BG
+fn test_rom() -> Vec< u8 >
+{
+    rom_with_program(&[
+        0x3E, 0x30, 0xE0, 0x4B,         // ld a, 0x30; ldh (WX), a
+        0x3E, 0x40, 0xE0, 0x4A,         // ld a, 0x40; ldh (WY), a
+        0x21, 0x00, 0xFE,               // ld hl, 0xFE00
+        0x06, 0xA0,                     // ld b, 0xA0
+        0x78, 0x22,                     // ld a, b; ld (hl+), a
+        0x05, 0x20, 0xFB,               // dec b; jr nz, -5
+        0x3E, 0xF3, 0xE0, 0x40,         // ld a, 0xF3; ldh (LCDC), a
+        0x21, 0x00, 0x80,               // ld hl, 0x8000
+        0x7D, 0xAC, 0x22,               // ld a, l; xor h; ld (hl+), a
+        0xE0, 0x43,                     // ldh (SCX), a
+        0x7C, 0xFE, 0x98,               // ld a, h; cp 0x98
+        0x20, 0xF6,                     // jr nz, -10
+        0x18, 0xF1                      // jr -15
+    ])
+}
+
+#[test]
+fn render_worker_draws_the_same_frames()
+{
+    let mut gb = Gameboy::from_rom(test_rom());
+    let mut threaded = Gameboy::from_rom(test_rom());
+    threaded.set_render_worker(true);
+
+    for _ in 0..30
+    {
+        gb.run_frame();
+        threaded.run_frame();
+        assert_eq!(gb.get_image_data(), threaded.get_image_data());
+    }
+}
+
+#[test]
+fn render_worker_hands_every_line_to_the_scanline_callback()
+{
+    let lines = Arc::new(Mutex::new(Vec::new()));
+    let mut gb = Gameboy::from_rom(test_rom());
+    gb.set_render_worker(true);
+    gb.run_frame();
+
+    let seen = lines.clone();
+    gb.set_scanline_callback(move |ly, _| seen.lock().unwrap().push(ly));
+    gb.run_frame();
+    assert_eq!(*lines.lock().unwrap(), (0..DISPLAY_HEIGHT as u8).collect::< Vec< u8 > >());
+}
@@ -0,0 +1,116 @@
+//! Recording movies across savestates: loading a state while recording
+//! rewinds the movie to the frame the state was saved on.
+
+#![cfg(feature = "savestates")]
+
+mod common;
+
+use common::rom_with_program;
+use rustboy::{ ButtonState, Gameboy, Movie };
+
+/// A ROM that spins forever
+fn test_rom() -> Vec< u8 >
+{
+    rom_with_program(&[0x18, 0xFE])
+}
+
+/// Hold the given buttons for a number of frames
+fn hold(gb: &mut Gameboy, state: ButtonState, frames: usize)
+{
+    gb.set_inputs(state);
+    for _ in 0..frames
+    {
+        gb.run_frame();
+    }
+}
+
+#[test]
+fn records_every_frame()
+{
+    let a = ButtonState::A;
+
+    let mut gb = Gameboy::from_rom(test_rom());
+    gb.start_recording();
+    hold(&mut gb, ButtonState::default(), 5);
+    hold(&mut gb, a, 3);
+
+    let movie = gb.stop_recording().unwrap();
+    assert_eq!(movie.len(), 8);
+    assert_eq!(movie.frame(4), Some(ButtonState::default()));
+    assert_eq!(movie.frame(5), Some(a));
+    assert_eq!(movie.rerecords(), 0);
+    assert!(gb.recording().is_none());
+}
+
+#[test]
+fn loading_state_rewinds_recording()
+{
+    let left = ButtonState::LEFT;
+    let right = ButtonState::RIGHT;
+
+    let mut gb = Gameboy::from_rom(test_rom());
+    gb.start_recording();
+    hold(&mut gb, ButtonState::default(), 10);
+    let state = gb.save_state();
+    hold(&mut gb, left, 10);
+    assert_eq!(gb.recording().unwrap().len(), 20);
+
+    gb.load_state(&state).unwrap();
+    assert_eq!(gb.recording().unwrap().len(), 10);
+    assert_eq!(gb.recording().unwrap().rerecords(), 1);
+
+    hold(&mut gb, right, 5);
+    gb.load_state(&state).unwrap();
+    hold(&mut gb, right, 5);
+
+    let movie = gb.stop_recording().unwrap();
+    assert_eq!(movie.len(), 15);
+    assert_eq!(movie.rerecords(), 2);
+    assert!(movie.frames()[10..].iter().all(|&f| f == right));
+
+    // Replaying the movie from power on ends up in the same place
+    let mut replay = Gameboy::from_rom(test_rom());
+    for &f in movie.frames()
+    {
+        hold(&mut replay, f, 1);
+    }
+    assert_eq!(replay.frame_hash(), gb.frame_hash());
+    assert_eq!(replay.save_state(), gb.save_state());
+}
+
+#[test]
+fn state_without_movie_is_rejected_while_recording()
+{
+    let mut gb = Gameboy::from_rom(test_rom());
+    hold(&mut gb, ButtonState::default(), 3);
+    let state = gb.save_state();
+
+    gb.start_recording();
+    hold(&mut gb, ButtonState::default(), 3);
+    assert!(gb.load_state(&state).is_err());
+    assert_eq!(gb.recording().unwrap().len(), 3);
+    assert_eq!(gb.recording().unwrap().rerecords(), 0);
+
+    // Outside of a recording the movie in a state is ignored
+    gb.start_recording();
+    let recorded = gb.save_state();
+    gb.stop_recording();
+    assert!(gb.load_state(&recorded).is_ok());
+}
+
+#[test]
+fn rerecords_are_kept_in_movie_files()
+{
+    let mut gb = Gameboy::from_rom(test_rom());
+    gb.start_recording();
+    hold(&mut gb, ButtonState::default(), 2);
+    let state = gb.save_state();
+    gb.load_state(&state).unwrap();
+    gb.load_state(&state).unwrap();
+    let movie = gb.stop_recording().unwrap();
+
+    let text = movie.to_text();
+    assert!(text.starts_with("# rerecords: 2\n"));
+    assert_eq!(Movie::parse(&text).unwrap(), movie);
+    assert!(Movie::parse("# rerecords: lots\n").is_err());
+}
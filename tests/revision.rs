@@ -0,0 +1,79 @@
+//! Hardware revisions boot with the register values their boot ROMs leave.
+
+mod common;
+
+use common::rom_with_program;
+use rustboy::{ Gameboy, GameboyBuilder, Revision, Target };
+
+/// A ROM that keeps the DIV value it starts with in A
+fn test_rom() -> Vec< u8 >
+{
+    rom_with_program(&[
+        0xF0, 0x04,             // ldh a, (0x04)
+        0x18, 0xFE              // jr -2
+    ])
+}
+
+#[test]
+fn boot_registers()
+{
+    let expected = [
+        (Revision::Dmg0, [0x01, 0x00, 0xFF, 0x13, 0x00, 0xC1, 0x84, 0x03]),
+        (Revision::Dmg,  [0x01, 0xB0, 0x00, 0x13, 0x00, 0xD8, 0x01, 0x4D]),
+        (Revision::Mgb,  [0xFF, 0xB0, 0x00, 0x13, 0x00, 0xD8, 0x01, 0x4D]),
+        (Revision::Sgb,  [0x01, 0x00, 0x00, 0x14, 0x00, 0x00, 0xC0, 0x60]),
+        (Revision::Sgb2, [0xFF, 0x00, 0x00, 0x14, 0x00, 0x00, 0xC0, 0x60]),
+        (Revision::Cgb,  [0x11, 0x80, 0x00, 0x00, 0xFF, 0x56, 0x00, 0x0D]),
+        (Revision::Agb,  [0x11, 0x00, 0x01, 0x00, 0xFF, 0x56, 0x00, 0x0D])
+    ];
+    for (revision, values) in expected
+    {
+        let gb = Gameboy::from_rom_revision(test_rom(), revision);
+        let r = gb.registers();
+        assert_eq!([r.a, r.f, r.b, r.c, r.d, r.e, r.h, r.l], values, "{:?}", revision);
+        assert_eq!(gb.target(), revision.target());
+        assert_eq!(gb.revision(), revision);
+    }
+}
+
+#[test]
+fn boot_div()
+{
+    for (revision, div) in [(Revision::Dmg0, 0x18), (Revision::Dmg, 0xAB), (Revision::Mgb, 0xAB)]
+    {
+        let mut gb = Gameboy::from_rom_revision(test_rom(), revision);
+        gb.run_frame();
+        assert_eq!(gb.registers().a, div, "{:?}", revision);
+    }
+}
+
+#[test]
+fn resets_keep_revision()
+{
+    let mut gb = Gameboy::from_rom(test_rom());
+    assert_eq!(gb.revision(), Revision::Dmg);
+
+    gb.restart_as_revision(Revision::Mgb);
+    gb.run_frame();
+    gb.soft_reset();
+    assert_eq!(gb.revision(), Revision::Mgb);
+    assert_eq!(gb.registers().a, 0xFF);
+
+    // Restarting as a target picks its usual revision
+    gb.restart_as(Target::GameBoyColor);
+    assert_eq!(gb.revision(), Revision::Cgb);
+}
+
+#[test]
+fn builder_revision()
+{
+    let gb = GameboyBuilder::new().rom_bytes(test_rom()).revision(Revision::Sgb2).build().unwrap();
+    assert_eq!(gb.target(), Target::SuperGameBoy);
+    assert_eq!(gb.registers().a, 0xFF);
+
+    let gb = GameboyBuilder::new().rom_bytes(test_rom()).target(Target::GameBoyColor).build().unwrap();
+    assert_eq!(gb.revision(), Revision::Cgb);
+
+    assert!(GameboyBuilder::new().rom_bytes(test_rom()).target(Target::GameBoyColor)
+        .revision(Revision::Mgb).build().is_err());
+}
@@ -0,0 +1,169 @@
+//! The MBC3 real time clock counts seconds of emulated time, carrying into
+//! minutes, hours and days, and games read it through latched registers.
+//! Battery saves keep it in the VBA/BGB RTC footer, and loading one runs
+//! it forward by the wall clock time since the save was made.
+
+mod common;
+
+use common::cart_with_program;
+use rustboy::{ Gameboy, SaveFormat };
+use std::time::{ SystemTime, UNIX_EPOCH };
+
+/// A ROM for an MBC3 cartridge with a clock that sets the clock registers
+/// to `start`, latches them and keeps copying the latched seconds, minutes,
+/// hours, day low and day high to B, C, D, E and H. If `latch` is set the
+/// clock is latched again every time round.
+fn test_rom(start: [u8; 5], latch: bool) -> Vec< u8 >
+{
+    clock_rom(Some(start), latch)
+}
+
+/// A ROM like `test_rom` that sets the clock registers only if `start` is
+/// given
+fn clock_rom(start: Option< [u8; 5] >, latch: bool) -> Vec< u8 >
+{
+    let latch_clock = [
+        0xAF, 0xEA, 0x00, 0x60,         // xor a; ld (0x6000), a
+        0x3C, 0xEA, 0x00, 0x60          // inc a; ld (0x6000), a
+    ];
+    let mut prog = vec![0x3E, 0x0A, 0xEA, 0x00, 0x00];                     // ld a, 0x0A; ld (0x0000), a
+    for (i, val) in start.iter().flatten().enumerate()
+    {
+        prog.extend_from_slice(&[0x3E, 0x08 + i as u8, 0xEA, 0x00, 0x40]);   // ld a, reg; ld (0x4000), a
+        prog.extend_from_slice(&[0x3E, *val, 0xEA, 0x00, 0xA0]);             // ld a, val; ld (0xA000), a
+    }
+    prog.extend_from_slice(&latch_clock);
+
+    let loop_start = prog.len();
+    if latch
+    {
+        prog.extend_from_slice(&latch_clock);
+    }
+    for (i, ld_r_a) in [0x47, 0x4F, 0x57, 0x5F, 0x67].iter().enumerate()
+    {
+        prog.extend_from_slice(&[0x3E, 0x08 + i as u8, 0xEA, 0x00, 0x40]);   // ld a, reg; ld (0x4000), a
+        prog.extend_from_slice(&[0xFA, 0x00, 0xA0, *ld_r_a]);                // ld a, (0xA000); ld r, a
+    }
+    let back = loop_start as isize - (prog.len() + 2) as isize;
+    prog.extend_from_slice(&[0x18, back as u8]);                            // jr loop
+
+    // MBC3 + Timer + Battery + RAM, with 8KB of RAM
+    cart_with_program(0x10, 0x02, &prog)
+}
+
+/// Run a ROM for the given number of frames and get the clock registers it
+/// read last
+fn clock_after(rom: Vec< u8 >, frames: u32) -> [u8; 5]
+{
+    let mut gb = Gameboy::from_rom(rom);
+    for _ in 0..frames
+    {
+        gb.run_frame();
+    }
+    let regs = gb.registers();
+    [regs.b, regs.c, regs.d, regs.e, regs.h]
+}
+
+#[test]
+fn counts_emulated_seconds()
+{
+    // A second is a little under 60 frames
+    assert_eq!(clock_after(test_rom([0; 5], true), 125)[0], 2);
+    assert_eq!(clock_after(test_rom([10, 20, 3, 4, 0], true), 125)[..4], [12, 20, 3, 4]);
+}
+
+#[test]
+fn carries_into_minutes_hours_and_days()
+{
+    let clock = clock_after(test_rom([59, 59, 23, 0xFF, 0x00], true), 70);
+    assert_eq!(clock[..4], [0, 0, 0, 0]);
+    assert_eq!(clock[4] & 0xC1, 0x01);
+
+    // Past day 511 the day counter wraps and sets the carry bit
+    let clock = clock_after(test_rom([59, 59, 23, 0xFF, 0x01], true), 70);
+    assert_eq!(clock[..4], [0, 0, 0, 0]);
+    assert_eq!(clock[4] & 0xC1, 0x80);
+}
+
+#[test]
+fn halt_stops_clock()
+{
+    assert_eq!(clock_after(test_rom([30, 0, 0, 0, 0x40], true), 125)[0], 30);
+}
+
+#[test]
+fn reads_see_latched_clock()
+{
+    assert_eq!(clock_after(test_rom([30, 0, 0, 0, 0], false), 125)[0], 30);
+}
+
+#[cfg(feature = "savestates")]
+#[test]
+fn clock_is_saved_in_savestates()
+{
+    let rom = test_rom([0; 5], true);
+    let mut gb = Gameboy::from_rom(rom.clone());
+    for _ in 0..90
+    {
+        gb.run_frame();
+    }
+    let state = gb.save_state();
+
+    let mut loaded = Gameboy::from_rom(rom);
+    loaded.load_state(&state).unwrap();
+    for _ in 0..35
+    {
+        gb.run_frame();
+        loaded.run_frame();
+    }
+    assert_eq!(loaded.registers().b, 2);
+    assert_eq!(loaded.save_state(), gb.save_state());
+}
+
+/// Get the current time as seconds since the UNIX epoch
+fn unix_time() -> u64
+{
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
+}
+
+/// Save a GameBoy running `test_rom(start)` with an RTC footer dated the
+/// given number of seconds ago, load the save into one that only reads the
+/// clock and get the clock it reads
+fn clock_after_save(start: [u8; 5], seconds_ago: u64) -> [u8; 5]
+{
+    let mut gb = Gameboy::from_rom(test_rom(start, true));
+    gb.run_frame();
+    let save = gb.export_save(SaveFormat::Rtc { timestamp: unix_time() - seconds_ago }).unwrap();
+    assert_eq!(save.len(), 0x2000 + 48);
+
+    // Each register is a u32, first the clock and then the latched clock
+    let footer = &save[0x2000..];
+    for (i, reg) in start.iter().enumerate()
+    {
+        assert_eq!(footer[i * 4..i * 4 + 4], [*reg, 0, 0, 0]);
+        assert_eq!(footer[20 + i * 4..20 + i * 4 + 4], [*reg, 0, 0, 0]);
+    }
+
+    let mut loaded = Gameboy::from_rom(clock_rom(None, true));
+    loaded.import_save(&save).unwrap();
+    loaded.run_frame();
+    let regs = loaded.registers();
+    [regs.b, regs.c, regs.d, regs.e, regs.h]
+}
+
+#[test]
+fn clock_is_kept_in_battery_saves()
+{
+    // A day, an hour, a minute and a second, give or take a second for
+    // the wall clock ticking over during the test
+    let clock = clock_after_save([10, 20, 3, 4, 0], 24 * 60 * 60 + 3600 + 60 + 1);
+    assert!((11..=12).contains(&clock[0]), "Seconds {}", clock[0]);
+    assert_eq!(clock[1..], [21, 4, 5, 0]);
+
+    // Days carry into the day counter's high bit and then the carry bit
+    let clock = clock_after_save([0, 0, 0, 0xFF, 0x01], 24 * 60 * 60);
+    assert_eq!(clock[1..], [0, 0, 0, 0x80]);
+
+    // A halted clock stays where it was saved
+    assert_eq!(clock_after_save([30, 0, 0, 0, 0x40], 3600), [30, 0, 0, 0, 0x40]);
+}
@@ -0,0 +1,145 @@
+//! Savestate round trips and compatibility with states saved by earlier
+//! versions of the format. States in tests/fixtures are never regenerated:
+//! when the format changes they must keep loading through the migrations.
+
+#![cfg(feature = "savestates")]
+
+mod common;
+
+use common::rom_with_program;
+use rustboy::{ Gameboy, StateChunks };
+use std::fs;
+use std::path::Path;
+
+/// A ROM that draws to VRAM and changes the scroll registers in a loop
+fn test_rom() -> Vec< u8 >
+{
+    rom_with_program(&[
+        0x3E, 0x55, 0xEA, 0x00, 0x80,   // ld a, 0x55; ld (0x8000), a
+        0x3E, 0xC8, 0xE0, 0x42,         // ld a, 200; ldh (SCY), a
+        0x3C, 0xE0, 0x43,               // inc a; ldh (SCX), a
+        0x18, 0xFA                      // jr -6
+    ])
+}
+
+/// Create a GameBoy running the test ROM for the given number of frames
+fn run_frames(frames: u32) -> Gameboy
+{
+    let mut gb = Gameboy::from_rom(test_rom());
+    for _ in 0..frames
+    {
+        gb.run_frame();
+    }
+    gb
+}
+
+#[test]
+fn round_trip()
+{
+    let mut gb = run_frames(30);
+    let state = gb.save_state();
+    for _ in 0..10 { gb.run_frame(); }
+
+    let mut restored = Gameboy::from_rom(test_rom());
+    restored.load_state(&state).unwrap();
+    assert_eq!(restored.save_state(), state);
+    for _ in 0..10 { restored.run_frame(); }
+
+    assert_eq!(restored.frame_hash(), gb.frame_hash());
+    assert_eq!(restored.save_state(), gb.save_state());
+}
+
+#[test]
+fn chunks_round_trip()
+{
+    let mut gb = run_frames(30);
+    let state = gb.save_state();
+
+    let chunks = StateChunks::parse(&state).unwrap();
+    assert!(chunks.chunks.contains_key("CPU "));
+
+    // Chunks may come back in a different order, so compare by loading
+    gb.run_frame();
+    gb.load_state(&chunks.to_state().unwrap()).unwrap();
+    assert_eq!(StateChunks::parse(&gb.save_state()).unwrap(), chunks);
+
+    let mut bad = chunks.clone();
+    bad.chunks.insert("CPU".to_string(), Vec::new());
+    assert!(bad.to_state().is_err());
+}
+
+#[test]
+fn load_version_1()
+{
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/savestate_v1.bin");
+    let state = fs::read(path).unwrap();
+
+    // The fixture was saved after 30 frames of the test ROM
+    let mut gb = Gameboy::from_rom(test_rom());
+    gb.load_state(&state).unwrap();
+    for _ in 0..10 { gb.run_frame(); }
+
+    let expected = run_frames(40);
+    assert_eq!(gb.frame_hash(), expected.frame_hash());
+
+    // Power on values changed after the fixture was saved: DIV starts at
+    // 0xAB, SVBK starts at 0, RAM of carts without a mapper is always
+    // enabled and the SGB palettes start as shades of grey. Take those from
+    // the expected state and compare everything else.
+    let mut loaded = StateChunks::parse(&gb.save_state()).unwrap();
+    let fresh = StateChunks::parse(&expected.save_state()).unwrap();
+    let mut take = |tag: &str, from_end: usize, len: usize| {
+        let src = &fresh.chunks[tag];
+        let dst = loaded.chunks.get_mut(tag).unwrap();
+        let start = dst.len() - from_end;
+        dst[start..start + len].copy_from_slice(&src[start..start + len]);
+    };
+    take("TIMR", 14, 1);     // DIV
+    take("MEM ", 7, 2);      // SVBK and the RAM enable
+    take("GPU ", 100, 64);   // SGB palettes
+
+    assert!(loaded.chunks.keys().eq(fresh.chunks.keys()));
+    for (tag, data) in fresh.chunks.iter()
+    {
+        assert!(loaded.chunks[tag] == *data, "{:?} chunk differs", tag);
+    }
+}
+
+#[test]
+fn reject_invalid()
+{
+    let state = run_frames(1).save_state();
+    let mut gb = Gameboy::from_rom(test_rom());
+
+    assert!(gb.load_state(&[]).is_err());
+    assert!(gb.load_state(b"not a savestate").is_err());
+    assert!(gb.load_state(&state[..state.len() - 1]).is_err());
+
+    // Newer versions of the format can't be loaded
+    let mut newer = state.clone();
+    newer[4] = 0xFF;
+    assert!(gb.load_state(&newer).is_err());
+
+    // States from other games can't be loaded
+    let mut other = test_rom();
+    other[0x014E] = 0x12;
+    let mut gb = Gameboy::from_rom(other);
+    assert!(gb.load_state(&state).is_err());
+}
+
+#[test]
+fn failed_load_changes_nothing()
+{
+    let mut chunks = StateChunks::parse(&run_frames(10).save_state()).unwrap();
+
+    // The joypad is loaded last, after the CPU and the rest of memory
+    let joypad = chunks.chunks.get_mut("JOYP").unwrap();
+    joypad[3] = 0x30;
+    let state = chunks.to_state().unwrap();
+
+    let mut gb = run_frames(30);
+    let before = gb.save_state();
+    assert!(gb.load_state(&state).is_err());
+    assert_eq!(gb.save_state(), before);
+}
+
@@ -0,0 +1,53 @@
+//! Bytes sent over the serial port are kept for test ROMs and tools, up to
+//! a limit.
+
+mod common;
+
+use common::rom_with_program;
+use rustboy::{ Gameboy, MAX_SERIAL_OUTPUT };
+
+/// A ROM that keeps starting transfers of an incrementing counter
+fn test_rom() -> Vec< u8 >
+{
+    rom_with_program(&[
+        0x78, 0xE0, 0x01,               // ld a, b; ldh (SB), a
+        0x3E, 0x81, 0xE0, 0x02,         // ld a, 0x81; ldh (SC), a
+        0x04,                           // inc b
+        0x18, 0xF6                      // jr -10
+    ])
+}
+
+#[test]
+fn output_keeps_newest_bytes()
+{
+    let mut gb = Gameboy::from_rom(test_rom());
+    while gb.serial_output().len() < MAX_SERIAL_OUTPUT
+    {
+        gb.run_frame();
+    }
+    for _ in 0..10
+    {
+        gb.run_frame();
+    }
+
+    // The oldest bytes are dropped, so the output is still one unbroken run
+    // of the counter
+    let output = gb.serial_output();
+    assert_eq!(output.len(), MAX_SERIAL_OUTPUT);
+    assert!(output.windows(2).all(|w| w[1] == w[0].wrapping_add(1)));
+}
+
+#[test]
+fn take_drains_output()
+{
+    let mut gb = Gameboy::from_rom(test_rom());
+    gb.run_frame();
+    let first = gb.take_serial_output();
+    assert!(!first.is_empty());
+    assert!(gb.serial_output().is_empty());
+
+    // Taking again picks up where the last one stopped
+    gb.run_frame();
+    let second = gb.take_serial_output();
+    assert_eq!(second[0], first[first.len() - 1].wrapping_add(1));
+}
@@ -0,0 +1,178 @@
+//! The SGB border is sent with CHR_TRN and PCT_TRN: the game shows the data
+//! as BG tiles and the SGB reads it back off the screen at the next VBlank.
+
+mod common;
+
+use common::rom_with_header;
+use rustboy::{ Gameboy, Target, BORDER_WIDTH, BORDER_HEIGHT };
+
+/// Border tiles sent with CHR_TRN. Tile 1 has color 1 at (0, 0), color 2
+/// at (7, 0) and color 15 at (0, 7).
+const CHR_DATA: usize = 0x1000;
+
+/// Border tile map and palettes sent with PCT_TRN
+const PCT_DATA: usize = 0x2000;
+
+/// The PAL01, CHR_TRN and PCT_TRN packets
+const PACKETS: usize = 0x3000;
+
+/// Set an RGB555 color in the border palettes of the PCT_TRN data
+fn set_color(rom: &mut [u8], palette: usize, color: usize, rgb: u16)
+{
+    let addr = PCT_DATA + 0x800 + (palette * 16 + color) * 2;
+    rom[addr..addr + 2].copy_from_slice(&rgb.to_le_bytes());
+}
+
+/// Set a border tile map entry in the PCT_TRN data
+fn set_entry(rom: &mut [u8], x: usize, y: usize, entry: u16)
+{
+    let addr = PCT_DATA + (y * 32 + x) * 2;
+    rom[addr..addr + 2].copy_from_slice(&entry.to_le_bytes());
+}
+
+/// A ROM that fills the first 13 rows of the BG map with consecutive tiles,
+/// sets the backdrop with PAL01, then loads the tiles and map into VRAM in
+/// turn and sends CHR_TRN and PCT_TRN for each
+fn test_rom() -> Vec< u8 >
+{
+    let mut rom = rom_with_header(&[(0x146, 0x03)], &[
+        0x31, 0xFE, 0xFF,               // ld sp, 0xFFFE
+        0xAF, 0xE0, 0x40,               // xor a; ldh (LCDC), a
+        0x21, 0x00, 0x98,               // ld hl, 0x9800
+        0x06, 0x00, 0x16, 0x0D,         // ld b, 0; ld d, 13
+        0x0E, 0x14,                     // row: ld c, 20
+        0x78, 0x22, 0x04,               // col: ld a, b; ld (hl+), a; inc b
+        0x0D, 0x20, 0xFA,               // dec c; jr nz, col
+        0x7D, 0xC6, 0x0C, 0x6F,         // ld a, l; add a, 12; ld l, a
+        0x30, 0x01, 0x24,               // jr nc, +1; inc h
+        0x15, 0x20, 0xEE,               // dec d; jr nz, row
+        0x21, 0x00, 0x30,               // ld hl, PAL01
+        0xCD, 0x00, 0x02,               // call send_packet
+        0x11, 0x00, 0x10,               // ld de, CHR_DATA
+        0xCD, 0x60, 0x02,               // call load_vram
+        0x21, 0x10, 0x30,               // ld hl, CHR_TRN
+        0xCD, 0x00, 0x02,               // call send_packet
+        0xCD, 0x40, 0x02,               // call wait_transfer
+        0x11, 0x00, 0x20,               // ld de, PCT_DATA
+        0xCD, 0x60, 0x02,               // call load_vram
+        0x21, 0x20, 0x30,               // ld hl, PCT_TRN
+        0xCD, 0x00, 0x02,               // call send_packet
+        0xCD, 0x40, 0x02,               // call wait_transfer
+        0x18, 0xFE                      // jr -2
+    ]);
+
+    // send_packet: send the 16 bytes at HL, LSB first
+    let send_packet = [
+        0xAF, 0xE0, 0x00,               // xor a; ldh (P1), a
+        0x3E, 0x30, 0xE0, 0x00,         // ld a, 0x30; ldh (P1), a
+        0x06, 0x10,                     // ld b, 16
+        0x2A, 0x57, 0x0E, 0x08,         // byte: ld a, (hl+); ld d, a; ld c, 8
+        0x3E, 0x10,                     // bit: ld a, 0x10
+        0xCB, 0x42, 0x20, 0x02,         // bit 0, d; jr nz, +2
+        0x3E, 0x20,                     // ld a, 0x20
+        0xE0, 0x00,                     // ldh (P1), a
+        0x3E, 0x30, 0xE0, 0x00,         // ld a, 0x30; ldh (P1), a
+        0xCB, 0x3A,                     // srl d
+        0x0D, 0x20, 0xED,               // dec c; jr nz, bit
+        0x05, 0x20, 0xE6,               // dec b; jr nz, byte
+        0x3E, 0x20, 0xE0, 0x00,         // ld a, 0x20; ldh (P1), a
+        0x3E, 0x30, 0xE0, 0x00,         // ld a, 0x30; ldh (P1), a
+        0xC9                            // ret
+    ];
+    rom[0x200..0x200 + send_packet.len()].copy_from_slice(&send_packet);
+
+    // wait_transfer: wait until VBlank has started after LY 143
+    let wait_transfer = [
+        0xF0, 0x44, 0xFE, 0x8F,         // ldh a, (LY); cp 143
+        0x20, 0xFA,                     // jr nz, -6
+        0xF0, 0x44, 0xFE, 0x91,         // ldh a, (LY); cp 145
+        0x20, 0xFA,                     // jr nz, -6
+        0xC9                            // ret
+    ];
+    rom[0x240..0x240 + wait_transfer.len()].copy_from_slice(&wait_transfer);
+
+    // load_vram: copy 4KB from DE to the tiles at 0x8000 with the LCD off
+    let load_vram = [
+        0xAF, 0xE0, 0x40,               // xor a; ldh (LCDC), a
+        0x21, 0x00, 0x80,               // ld hl, 0x8000
+        0x1A, 0x13, 0x22,               // copy: ld a, (de); inc de; ld (hl+), a
+        0x7C, 0xFE, 0x90,               // ld a, h; cp 0x90
+        0x20, 0xF8,                     // jr nz, copy
+        0x3E, 0x91, 0xE0, 0x40,         // ld a, 0x91; ldh (LCDC), a
+        0xC9                            // ret
+    ];
+    rom[0x260..0x260 + load_vram.len()].copy_from_slice(&load_vram);
+
+    // PAL01 with color 0 a dark red, then CHR_TRN of tiles 0x00 thru 0x7F
+    // and PCT_TRN
+    rom[PACKETS..PACKETS + 3].copy_from_slice(&[0x01, 0x10, 0x00]);
+    rom[PACKETS + 0x10..PACKETS + 0x12].copy_from_slice(&[0x99, 0x00]);
+    rom[PACKETS + 0x20] = 0xA1;
+
+    // Tile 1, with bitplanes 0 and 1 interleaved by row, then 2 and 3
+    let tile = CHR_DATA + 32;
+    rom[tile] = 0x80;
+    rom[tile + 1] = 0x01;
+    for plane in [14, 15, 30, 31]
+    {
+        rom[tile + plane] = 0x80;
+    }
+
+    // Tile 1 at (0, 0) with palette 0, at (1, 0) flipped horizontally with
+    // palette 1 and at (0, 1) flipped vertically with palette 2. Everything
+    // else is tile 0, which is transparent.
+    set_entry(&mut rom, 0, 0, 0x0001);
+    set_entry(&mut rom, 1, 0, 0x4401);
+    set_entry(&mut rom, 0, 1, 0x8801);
+    set_color(&mut rom, 0, 1, 0x001F);
+    set_color(&mut rom, 0, 2, 0x03E0);
+    set_color(&mut rom, 0, 15, 0x7C00);
+    set_color(&mut rom, 1, 1, 0x7FFF);
+    set_color(&mut rom, 2, 15, 0x0210);
+    rom
+}
+
+#[test]
+fn border_is_drawn_from_transfers()
+{
+    let mut gb = Gameboy::from_rom_as(test_rom(), Target::SuperGameBoy);
+    gb.run_frame();
+    assert!(gb.get_border_image().is_none());
+    for _ in 0..10
+    {
+        gb.run_frame();
+    }
+
+    let image = gb.get_border_image().expect("no border was sent");
+    assert_eq!(image.len(), BORDER_WIDTH * BORDER_HEIGHT * 4);
+    let pixel = |x: usize, y: usize| &image[(y * BORDER_WIDTH + x) * 4..][..4];
+    let backdrop = [128, 0, 0, 255];
+
+    // Colors 1, 2 and 15 of palette 0
+    assert_eq!(pixel(0, 0), [248, 0, 0, 255]);
+    assert_eq!(pixel(7, 0), [0, 248, 0, 255]);
+    assert_eq!(pixel(0, 7), [0, 0, 248, 255]);
+    assert_eq!(pixel(1, 0), backdrop);
+
+    // Flipped horizontally, color 1 is on the right and color 2 on the left
+    assert_eq!(pixel(15, 0), [248, 248, 248, 255]);
+    assert_eq!(pixel(8, 0), [0, 0, 0, 255]);
+
+    // Flipped vertically, color 15 is on the top row
+    assert_eq!(pixel(0, 8), [128, 128, 0, 255]);
+    assert_eq!(pixel(1, 8), backdrop);
+    assert_eq!(pixel(0, 15), [0, 0, 0, 255]);
+
+    assert_eq!(pixel(BORDER_WIDTH - 1, BORDER_HEIGHT - 1), backdrop);
+}
+
+#[test]
+fn no_border_without_sgb()
+{
+    let mut gb = Gameboy::from_rom_as(test_rom(), Target::GameBoy);
+    for _ in 0..10
+    {
+        gb.run_frame();
+    }
+    assert!(gb.get_border_image().is_none());
+}
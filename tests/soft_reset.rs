@@ -0,0 +1,63 @@
+//! Soft resets restart the game without clearing RAM.
+
+mod common;
+
+use common::rom_with_program;
+use rustboy::{ ButtonState, Gameboy };
+
+/// A ROM that counts how many times it has booted at 0xC000 and keeps the
+/// count in B
+fn test_rom() -> Vec< u8 >
+{
+    rom_with_program(&[
+        0xFA, 0x00, 0xC0,       // ld a, (0xC000)
+        0x3C,                   // inc a
+        0xEA, 0x00, 0xC0,       // ld (0xC000), a
+        0x47,                   // ld b, a
+        0x18, 0xFE              // jr -2
+    ])
+}
+
+#[test]
+fn soft_reset_keeps_ram()
+{
+    let mut gb = Gameboy::from_rom(test_rom());
+    gb.run_frame();
+    let boots = gb.registers().b;
+
+    gb.soft_reset();
+    assert_eq!(gb.registers().pc, 0x0100);
+    gb.run_frame();
+    assert_eq!(gb.registers().b, boots.wrapping_add(1));
+}
+
+#[test]
+fn reset_combo()
+{
+    let combo = ButtonState::A | ButtonState::B | ButtonState::START | ButtonState::SELECT;
+
+    let mut gb = Gameboy::from_rom(test_rom());
+    gb.run_frame();
+    let boots = gb.registers().b;
+
+    // Ignored unless enabled
+    gb.set_inputs(combo);
+    gb.run_frame();
+    gb.run_frame();
+    assert_eq!(gb.registers().b, boots);
+
+    // Holding the combo only resets once
+    gb.set_reset_combo(true);
+    gb.set_inputs(ButtonState::default());
+    gb.run_frame();
+    gb.set_inputs(combo);
+    for _ in 0..5 { gb.run_frame(); }
+    assert_eq!(gb.registers().b, boots.wrapping_add(1));
+
+    gb.set_inputs(ButtonState::default());
+    gb.run_frame();
+    gb.set_inputs(combo);
+    gb.run_frame();
+    gb.run_frame();
+    assert_eq!(gb.registers().b, boots.wrapping_add(2));
+}
@@ -0,0 +1,118 @@
+//! Spectators mirror a game streamed by a host from the states and inputs
+//! it sends.
+
+#![cfg(feature = "spectate")]
+
+mod common;
+
+use common::rom_with_program;
+use rustboy::{ ButtonState, Gameboy, SpectateClient, SpectateHost };
+use std::io::Write;
+use std::net::TcpListener;
+use std::thread;
+use std::time::{ Duration, Instant };
+
+/// A ROM that keeps adding the D-pad bits of the keypad register to B
+fn test_rom() -> Vec< u8 >
+{
+    rom_with_program(&[
+        0x3E, 0x20, 0xE0, 0x00,         // ld a, 0x20; ldh (P1), a
+        0xF0, 0x00,                     // ldh a, (P1)
+        0x80,                           // add a, b
+        0x47,                           // ld b, a
+        0x18, 0xF6                      // jr -10
+    ])
+}
+
+/// Apply what the host sent until the mirror reaches the given frame
+fn catch_up(client: &mut SpectateClient, mirror: &mut Gameboy, frame: u64)
+{
+    let start = Instant::now();
+    while !client.is_synced() || mirror.frame_count() < frame
+    {
+        assert!(client.update(mirror).unwrap());
+        assert!(start.elapsed() < Duration::from_secs(5), "Spectator never caught up");
+        thread::sleep(Duration::from_millis(1));
+    }
+}
+
+#[test]
+fn spectator_mirrors_host()
+{
+    let mut host = SpectateHost::bind("127.0.0.1:0").unwrap();
+    host.set_interval(20);
+    let mut client = SpectateClient::connect(host.local_addr().unwrap()).unwrap();
+
+    let mut gb = Gameboy::from_rom(test_rom());
+    let mut mirror = Gameboy::from_rom(test_rom());
+    for _ in 0..10 { gb.run_frame(); }
+
+    let buttons = [ButtonState::empty(), ButtonState::UP, ButtonState::LEFT | ButtonState::DOWN];
+    for i in 0..60
+    {
+        gb.set_inputs(buttons[i / 7 % 3]);
+        host.update(&gb).unwrap();
+        catch_up(&mut client, &mut mirror, gb.frame_count());
+
+        assert_eq!(mirror.frame_count(), gb.frame_count());
+        assert_eq!(format!("{:?}", mirror.registers()), format!("{:?}", gb.registers()));
+        gb.run_frame();
+    }
+    assert_eq!(host.spectators(), 1);
+
+    // The spectator finds out when the host goes away
+    drop(host);
+    let start = Instant::now();
+    while client.update(&mut mirror).unwrap()
+    {
+        assert!(start.elapsed() < Duration::from_secs(5));
+        thread::sleep(Duration::from_millis(1));
+    }
+}
+
+#[test]
+fn rejects_other_games()
+{
+    let mut host = SpectateHost::bind("127.0.0.1:0").unwrap();
+    let mut client = SpectateClient::connect(host.local_addr().unwrap()).unwrap();
+
+    let gb = Gameboy::from_rom(test_rom());
+    let mut other = test_rom();
+    other[0x014E] = 0x12;
+    let mut mirror = Gameboy::from_rom(other);
+
+    host.update(&gb).unwrap();
+    let start = Instant::now();
+    loop
+    {
+        if client.update(&mut mirror).is_err() { break }
+        assert!(start.elapsed() < Duration::from_secs(5), "State from another game was accepted");
+        thread::sleep(Duration::from_millis(1));
+    }
+}
+
+#[test]
+fn rejects_oversized_states()
+{
+    // A state that would take 4GB is refused as soon as its header arrives,
+    // rather than buffered or allocated
+    for (size, len) in [(u32::MAX, 16), (0x1000, u32::MAX)]
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = SpectateClient::connect(listener.local_addr().unwrap()).unwrap();
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut message = vec![b'S'];
+        message.extend_from_slice(&size.to_le_bytes());
+        message.extend_from_slice(&len.to_le_bytes());
+        stream.write_all(&message).unwrap();
+
+        let mut mirror = Gameboy::from_rom(test_rom());
+        let start = Instant::now();
+        loop
+        {
+            if client.update(&mut mirror).is_err() { break }
+            assert!(start.elapsed() < Duration::from_secs(5), "Oversized state was waited for");
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+}
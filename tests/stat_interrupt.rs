@@ -0,0 +1,75 @@
+//! The STAT interrupt is requested when the line ORing every enabled source
+//! goes high, so sources that overlap only request it once.
+
+mod common;
+
+use common::rom_with_program;
+use rustboy::{ Gameboy, Target };
+
+/// A ROM that sets LYC and STAT, enables the STAT interrupt and counts STAT
+/// interrupts in C. If `rewrite` is set it keeps writing the same value to
+/// STAT.
+fn test_rom(stat: u8, lyc: u8, rewrite: bool) -> Vec< u8 >
+{
+    let mut prog = vec![
+        0x3E, lyc, 0xE0, 0x45,          // ld a, lyc; ldh (LYC), a
+        0x3E, stat, 0xE0, 0x41,         // ld a, stat; ldh (STAT), a
+        0x3E, 0x02, 0xE0, 0xFF,         // ld a, 0x02; ldh (IE), a
+        0xAF, 0xE0, 0x0F,               // xor a; ldh (IF), a
+        0x0E, 0x00, 0xFB                // ld c, 0; ei
+    ];
+    if rewrite
+    {
+        prog.extend_from_slice(&[0x3E, stat, 0xE0, 0x41, 0x18, 0xFA]);  // ld a, stat; ldh (STAT), a; jr -6
+    }
+    else
+    {
+        prog.extend_from_slice(&[0x18, 0xFE]);                          // jr -2
+    }
+    let mut rom = rom_with_program(&prog);
+    rom[0x48..0x4A].copy_from_slice(&[0x0C, 0xD9]);                 // inc c; reti
+    rom
+}
+
+/// Get the number of STAT interrupts in a whole frame, once the ROM has
+/// set up
+fn interrupts_per_frame(rom: Vec< u8 >, target: Target) -> u8
+{
+    let mut gb = Gameboy::from_rom_as(rom, target);
+    gb.run_frame();
+    let before = gb.registers().c;
+    gb.run_frame();
+    gb.registers().c.wrapping_sub(before)
+}
+
+#[test]
+fn overlapping_sources_fire_once()
+{
+    // HBlank alone fires on every visible line
+    assert_eq!(interrupts_per_frame(test_rom(0x08, 0xFF, false), Target::GameBoy), 144);
+
+    // VBlank follows the HBlank of the last line without the line going low
+    assert_eq!(interrupts_per_frame(test_rom(0x18, 0xFF, false), Target::GameBoy), 144);
+    assert_eq!(interrupts_per_frame(test_rom(0x10, 0xFF, false), Target::GameBoy), 1);
+}
+
+#[test]
+fn lyc_match_fires_once()
+{
+    assert_eq!(interrupts_per_frame(test_rom(0x40, 0x40, false), Target::GameBoy), 1);
+    assert_eq!(interrupts_per_frame(test_rom(0x40, 0x99, false), Target::GameBoy), 1);
+
+    // The line stays high from the HBlank before LY = LYC until the end of
+    // that line's HBlank, which merges two HBlank interrupts into one
+    assert_eq!(interrupts_per_frame(test_rom(0x48, 0x40, false), Target::GameBoy), 143);
+}
+
+#[test]
+fn stat_write_glitch_only_on_dmg()
+{
+    // With no sources enabled, writing STAT requests an interrupt on the DMG
+    // during HBlank and VBlank
+    assert!(interrupts_per_frame(test_rom(0x00, 0xFF, true), Target::GameBoy) > 0);
+    assert_eq!(interrupts_per_frame(test_rom(0x00, 0xFF, true), Target::GameBoyColor), 0);
+    assert_eq!(interrupts_per_frame(test_rom(0x00, 0xFF, false), Target::GameBoy), 0);
+}
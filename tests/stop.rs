@@ -0,0 +1,200 @@
+//! STOP mode ends when a selected joypad line goes low, whether or not the
+//! joypad interrupt is enabled. Entering it resets DIV, and the timer stands
+//! still until it ends.
+
+mod common;
+
+use common::{ rom_with_header, rom_with_program };
+use rustboy::{ Button, Gameboy, Target };
+
+/// A ROM that selects a group of buttons with P1, disables interrupts with
+/// IE and stops, then sets B to 0x42 once it wakes up
+fn test_rom(select: u8) -> Vec< u8 >
+{
+    rom_with_program(&[
+        0x3E, select, 0xE0, 0x00,       // ld a, select; ldh (P1), a
+        0xAF, 0xE0, 0xFF,               // xor a; ldh (IE), a
+        0x10, 0x00,                     // stop
+        0x06, 0x42,                     // ld b, 0x42
+        0x18, 0xFE                      // jr -2
+    ])
+}
+
+/// Is the CPU still stopped after running a frame?
+fn stopped(gb: &mut Gameboy) -> bool
+{
+    gb.run_frame();
+    let stopped = gb.status().stopped;
+    assert_eq!(gb.registers().b == 0x42, !stopped);
+    stopped
+}
+
+#[test]
+fn button_press_wakes_cpu()
+{
+    let mut gb = Gameboy::from_rom(test_rom(0x10));
+    assert!(stopped(&mut gb));
+    assert!(stopped(&mut gb));
+
+    gb.key_down(Button::Start);
+    assert!(!stopped(&mut gb));
+}
+
+#[test]
+fn only_selected_buttons_wake_cpu()
+{
+    let mut gb = Gameboy::from_rom(test_rom(0x20));
+    assert!(stopped(&mut gb));
+
+    gb.key_down(Button::A);
+    assert!(stopped(&mut gb));
+
+    gb.key_down(Button::Left);
+    assert!(!stopped(&mut gb));
+}
+
+#[test]
+fn held_button_does_not_wake_cpu()
+{
+    let mut gb = Gameboy::from_rom(test_rom(0x10));
+    gb.key_down(Button::A);
+    assert!(stopped(&mut gb));
+
+    gb.key_up(Button::A);
+    assert!(stopped(&mut gb));
+
+    gb.key_down(Button::A);
+    assert!(!stopped(&mut gb));
+}
+
+/// A ROM that enables the VBlank and timer interrupts, with IME set or not,
+/// then selects the D-pad and stops. Sets B to 0x42 once it wakes up.
+fn interrupt_rom(ime: bool) -> Vec< u8 >
+{
+    let mut rom = rom_with_program(&[
+        0x3E, 0x04, 0xE0, 0x07,         // ld a, 0x04; ldh (TAC), a
+        0x3E, 0x05, 0xE0, 0xFF,         // ld a, 0x05; ldh (IE), a
+        0x3E, 0x20, 0xE0, 0x00,         // ld a, 0x20; ldh (P1), a
+        if ime { 0xFB } else { 0xF3 },  // ei or di
+        0x10, 0x00,                     // stop
+        0x06, 0x42,                     // ld b, 0x42
+        0x18, 0xFE                      // jr -2
+    ]);
+    rom[0x40] = 0xD9;                   // reti
+    rom[0x50] = 0xD9;                   // reti
+    rom
+}
+
+#[test]
+fn other_interrupts_do_not_wake_cpu()
+{
+    for ime in [false, true]
+    {
+        let mut gb = Gameboy::from_rom(interrupt_rom(ime));
+        assert!(stopped(&mut gb));
+        assert!(stopped(&mut gb));
+
+        gb.key_down(Button::Up);
+        assert!(!stopped(&mut gb));
+    }
+}
+
+#[test]
+fn speed_switch_happens_before_pending_interrupt()
+{
+    // A CGB game that arms a speed switch and stops with VBlank pending and
+    // interrupts disabled, then reads KEY1 into B
+    let rom = rom_with_header(&[(0x143, 0x80)], &[
+        0x3E, 0x01, 0xE0, 0x4D,         // ld a, 1; ldh (KEY1), a
+        0xE0, 0xFF, 0xE0, 0x0F,         // ldh (IE), a; ldh (IF), a
+        0x10, 0x00,                     // stop
+        0xF0, 0x4D, 0x47,               // ldh a, (KEY1); ld b, a
+        0x18, 0xFE                      // jr -2
+    ]);
+
+    let mut gb = Gameboy::from_rom_as(rom, Target::GameBoyColor);
+    gb.run_frame();
+    assert!(!gb.status().stopped);
+    assert_eq!(gb.registers().b & 0x81, 0x80);
+}
+
+/// A ROM that reads DIV into H, starts TIMA from 0 at one increment every 4
+/// M-cycles and stops with the D-pad selected. Once it wakes up it reads
+/// DIV into C, TIMA into D and IF into E, then sets B to 0x42.
+fn timer_rom() -> Vec< u8 >
+{
+    rom_with_program(&[
+        0xAF, 0xE0, 0xFF, 0xE0, 0x0F,   // xor a; ldh (IE), a; ldh (IF), a
+        0xE0, 0x06,                     // ldh (TMA), a
+        0x3E, 0x20, 0xE0, 0x00,         // ld a, 0x20; ldh (P1), a
+        0x3E, 0x05, 0xE0, 0x07,         // ld a, 0x05; ldh (TAC), a
+        0xF0, 0x04, 0x67,               // ldh a, (DIV); ld h, a
+        0xAF, 0xE0, 0x05,               // xor a; ldh (TIMA), a
+        0x10, 0x00,                     // stop
+        0xF0, 0x04, 0x4F,               // ldh a, (DIV); ld c, a
+        0xF0, 0x05, 0x57,               // ldh a, (TIMA); ld d, a
+        0xF0, 0x0F, 0x5F,               // ldh a, (IF); ld e, a
+        0x06, 0x42,                     // ld b, 0x42
+        0x18, 0xFE                      // jr -2
+    ])
+}
+
+#[test]
+fn stop_resets_div_and_freezes_timer()
+{
+    let mut gb = Gameboy::from_rom(timer_rom());
+    for _ in 0..3
+    {
+        assert!(stopped(&mut gb));
+    }
+    gb.key_down(Button::Up);
+    assert!(!stopped(&mut gb));
+
+    // TIMA only counts the few M-cycles run around STOP. Over 3 frames it
+    // would have overflowed about 50 times.
+    let r = gb.registers();
+    assert_ne!(r.h, 0, "DIV was running before STOP");
+    assert_eq!(r.c, 0, "DIV after STOP");
+    assert!(r.d <= 2, "TIMA counted to {:02X} during STOP", r.d);
+    assert_eq!(r.e & 0x04, 0, "TIMA overflowed during STOP");
+}
+
+/// A CGB ROM that switches to double speed if `double` is set, then starts
+/// TIMA from 0 at one increment every 4 M-cycles, waits 200 M-cycles and
+/// reads TIMA into C and KEY1 into D
+fn speed_rom(double: bool) -> Vec< u8 >
+{
+    let mut prog = vec![0xAF, 0xE0, 0xFF];                     // xor a; ldh (IE), a
+    if double
+    {
+        prog.extend_from_slice(&[
+            0x3E, 0x01, 0xE0, 0x4D,     // ld a, 1; ldh (KEY1), a
+            0x10, 0x00                  // stop
+        ]);
+    }
+    prog.extend_from_slice(&[
+        0x3E, 0x05, 0xE0, 0x07,         // ld a, 0x05; ldh (TAC), a
+        0xAF, 0xE0, 0x05,               // xor a; ldh (TIMA), a
+        0x06, 0x32,                     // ld b, 50
+        0x05, 0x20, 0xFD,               // dec b; jr nz, -3
+        0xF0, 0x05, 0x4F,               // ldh a, (TIMA); ld c, a
+        0xF0, 0x4D, 0x57,               // ldh a, (KEY1); ld d, a
+        0x18, 0xFE                      // jr -2
+    ]);
+    rom_with_header(&[(0x143, 0x80)], &prog)
+}
+
+#[test]
+fn timer_counts_m_cycles_at_double_speed()
+{
+    // TIMA counts M-cycles, which take half as long at double speed, so it
+    // gets as far in the same number of them at either speed
+    for double in [false, true]
+    {
+        let mut gb = Gameboy::from_rom_as(speed_rom(double), Target::GameBoyColor);
+        gb.run_frame();
+        let r = gb.registers();
+        assert_eq!(r.d & 0x80 != 0, double, "Double speed");
+        assert!((50..=51).contains(&r.c), "TIMA counted to {} at double speed {}", r.c, double);
+    }
+}
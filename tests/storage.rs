@@ -0,0 +1,251 @@
+//! Persisting battery saves and savestates through the storage backends, and
+//! moving saves between emulators.
+
+mod common;
+
+use common::{ cart_with_program, rom_with_program };
+use rustboy::{ FileStorage, Gameboy, MemoryStorage, SaveFormat, StorageBackend };
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{ Arc, Mutex };
+
+/// A ROM that spins forever
+fn test_rom() -> Vec< u8 >
+{
+    rom_with_program(&[0x18, 0xFE])
+}
+
+/// An empty directory for a test to write files in
+fn test_dir(name: &str) -> PathBuf
+{
+    let dir = std::env::temp_dir().join(format!("rustboy-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    dir
+}
+
+#[test]
+fn file_writes_replace_atomically()
+{
+    let dir = test_dir("atomic");
+    let mut storage = FileStorage::new(&dir);
+    storage.store("game.sav", &[1; 64]).unwrap();
+    storage.store("game.sav", &[2; 16]).unwrap();
+
+    assert_eq!(storage.load("game.sav").unwrap(), Some(vec![2; 16]));
+    assert_eq!(storage.load("other.sav").unwrap(), None);
+
+    // Only the finished file is left behind
+    let names: Vec< _ > = fs::read_dir(&dir).unwrap()
+        .map(|e| e.unwrap().file_name().into_string().unwrap())
+        .collect();
+    assert_eq!(names, ["game.sav"]);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn file_writes_emit_change_events()
+{
+    let dir = test_dir("events");
+    let changes = Arc::new(Mutex::new(Vec::new()));
+    let seen = changes.clone();
+
+    let mut storage = FileStorage::new(&dir);
+    storage.set_change_callback(Some(Box::new(move |key, path| {
+        seen.lock().unwrap().push((key.to_string(), path.to_path_buf()));
+    })));
+    storage.store("a.sav", &[0]).unwrap();
+    storage.store("b.ss1", &[0]).unwrap();
+
+    let changes = changes.lock().unwrap();
+    assert_eq!(changes.len(), 2);
+    assert_eq!(changes[0], ("a.sav".to_string(), dir.join("a.sav")));
+    assert_eq!(changes[1], ("b.ss1".to_string(), dir.join("b.ss1")));
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+#[cfg(feature = "savestates")]
+fn savestate_slots()
+{
+    let mut gb = Gameboy::from_rom(test_rom());
+    assert!(gb.store_state(0).is_err());
+
+    gb.set_storage(MemoryStorage::new()).unwrap();
+    for _ in 0..5 { gb.run_frame(); }
+    let state = gb.save_state();
+    gb.store_state(3).unwrap();
+    assert!(gb.load_stored_state(4).is_err());
+
+    for _ in 0..5 { gb.run_frame(); }
+    gb.load_stored_state(3).unwrap();
+    assert_eq!(gb.save_state(), state);
+}
+
+/// The test ROM on an MBC1 cartridge with 8K of battery backed RAM
+fn battery_rom() -> Vec< u8 >
+{
+    cart_with_program(0x03, 0x02, &[0x18, 0xFE])
+}
+
+#[test]
+fn export_and_import_saves()
+{
+    let mut gb = Gameboy::from_rom(battery_rom());
+    let save: Vec< u8 > = (0..0x2000).map(|i| i as u8).collect();
+    gb.import_save(&save).unwrap();
+    assert_eq!(gb.export_save(SaveFormat::Raw).unwrap(), save);
+
+    let rtc = gb.export_save(SaveFormat::Rtc { timestamp: 0x1234_5678 }).unwrap();
+    assert_eq!(rtc.len(), 0x2000 + 48);
+    assert_eq!(rtc[..0x2000], save[..]);
+    assert_eq!(rtc[0x2000 + 40..], 0x1234_5678u64.to_le_bytes());
+
+    // Saves with either size of RTC footer load into another GameBoy
+    for footer in [48, 44]
+    {
+        let mut data = rtc[..0x2000].to_vec();
+        data.resize(0x2000 + footer, 0xAA);
+        let mut other = Gameboy::from_rom(battery_rom());
+        other.import_save(&data).unwrap();
+        assert_eq!(other.export_save(SaveFormat::Raw).unwrap(), save);
+    }
+}
+
+/// A BESS savestate as SameBoy writes it: its own state, then the BESS
+/// blocks pointing back into it, then the footer
+fn bess_state(ram: &[u8], rtc_size: usize) -> Vec< u8 >
+{
+    let mut data = vec![0xEE; 0x100];
+    let ram_offset = data.len() as u32;
+    data.extend_from_slice(ram);
+
+    let first = data.len() as u32;
+    let mut core = vec![0; 0xD0];
+    core[..2].copy_from_slice(&1u16.to_le_bytes());
+    core[0xA8..0xAC].copy_from_slice(&(ram.len() as u32).to_le_bytes());
+    core[0xAC..0xB0].copy_from_slice(&ram_offset.to_le_bytes());
+    for (name, block) in [(b"NAME", b"SameBoy v0.16".to_vec()), (b"CORE", core), (b"RTC ", vec![0; rtc_size]), (b"END ", Vec::new())]
+    {
+        data.extend_from_slice(name);
+        data.extend_from_slice(&(block.len() as u32).to_le_bytes());
+        data.extend_from_slice(&block);
+    }
+    data.extend_from_slice(&first.to_le_bytes());
+    data.extend_from_slice(b"BESS");
+    data
+}
+
+/// A BGB savestate with the given blocks after the header
+fn bgb_state(blocks: &[(&str, &[u8])]) -> Vec< u8 >
+{
+    let mut data = Vec::new();
+    for (name, block) in [("header", &b"BGB"[..])].iter().chain(blocks)
+    {
+        data.extend_from_slice(name.as_bytes());
+        data.push(0);
+        data.extend_from_slice(&(block.len() as u32).to_le_bytes());
+        data.extend_from_slice(block);
+    }
+    data
+}
+
+#[test]
+fn import_from_savestates()
+{
+    let save: Vec< u8 > = (0..0x2000).map(|i| (i * 7) as u8).collect();
+
+    let mut gb = Gameboy::from_rom(battery_rom());
+    gb.import_save(&bess_state(&save, 48)).unwrap();
+    assert_eq!(gb.export_save(SaveFormat::Raw).unwrap(), save);
+
+    let mut gb = Gameboy::from_rom(battery_rom());
+    gb.import_save(&bgb_state(&[("PC", &[0x50, 0x01]), ("SRAM", &save), ("RTC", &[0; 48])])).unwrap();
+    assert_eq!(gb.export_save(SaveFormat::Raw).unwrap(), save);
+
+    // Broken savestates and ones for another game are rejected
+    let mut gb = Gameboy::from_rom(battery_rom());
+    assert!(gb.import_save(&bess_state(&save[..0x1000], 48)).is_err());
+    assert!(gb.import_save(&bess_state(&save, 12)).is_err());
+    let state = bess_state(&save, 48);
+    assert!(gb.import_save(&state[0x2000..]).is_err());
+    assert!(gb.import_save(&bgb_state(&[("PC", &[0x50, 0x01])])).is_err());
+    let state = bgb_state(&[("SRAM", &save)]);
+    assert!(gb.import_save(&state[..state.len() - 1]).is_err());
+    assert_eq!(gb.export_save(SaveFormat::Raw).unwrap(), vec![0; 0x2000]);
+}
+
+#[test]
+fn reject_mismatched_saves()
+{
+    let mut gb = Gameboy::from_rom(battery_rom());
+    assert!(gb.import_save(&[0; 0x1000]).is_err());
+    assert!(gb.import_save(&[0; 0x2000 + 16]).is_err());
+    assert_eq!(gb.export_save(SaveFormat::Raw).unwrap().len(), 0x2000);
+
+    let mut no_battery = Gameboy::from_rom(test_rom());
+    assert!(no_battery.import_save(&[0; 0x2000]).is_err());
+    assert!(no_battery.export_save(SaveFormat::Raw).is_none());
+}
+
+/// Counts the writes made to the storage it wraps
+struct CountingStorage
+{
+    inner: MemoryStorage,
+    writes: Arc< Mutex< usize > >
+}
+
+impl StorageBackend for CountingStorage
+{
+    fn load(&mut self, key: &str) -> std::io::Result< Option< Vec< u8 > > >
+    {
+        self.inner.load(key)
+    }
+
+    fn store(&mut self, key: &str, data: &[u8]) -> std::io::Result< () >
+    {
+        *self.writes.lock().unwrap() += 1;
+        self.inner.store(key, data)
+    }
+}
+
+#[test]
+fn saves_skip_unchanged_ram()
+{
+    // Writes 0x42 to the start of cartridge RAM once, then spins
+    let mut rom = cart_with_program(0x03, 0x02, &[
+        0x3E, 0x0A, 0xEA, 0x00, 0x00,   // ld a, 0x0A; ld (0x0000), a
+        0x3E, 0x42, 0xEA, 0x00, 0xA0,   // ld a, 0x42; ld (0xA000), a
+        0x18, 0xFE                      // jr -2
+    ]);
+    rom[0x134..0x138].copy_from_slice(b"TEST");
+
+    let writes = Arc::new(Mutex::new(0));
+    let mut gb = Gameboy::from_rom(rom.clone());
+    gb.set_storage(CountingStorage { inner: MemoryStorage::new(), writes: writes.clone() }).unwrap();
+    assert!(!gb.has_unsaved_changes());
+    gb.save().unwrap();
+    assert_eq!(*writes.lock().unwrap(), 0);
+
+    gb.run_frame();
+    assert!(gb.has_unsaved_changes());
+    gb.save().unwrap();
+    gb.save().unwrap();
+    assert_eq!(*writes.lock().unwrap(), 1);
+
+    // Importing the same data doesn't change anything, other data does
+    let save = gb.export_save(SaveFormat::Raw).unwrap();
+    gb.import_save(&save).unwrap();
+    assert!(!gb.has_unsaved_changes());
+    gb.import_save(&[0; 0x2000]).unwrap();
+    assert!(gb.has_unsaved_changes());
+
+    // A save loaded from storage is already saved, until the game rewrites
+    // what it had overwritten
+    let mut storage = MemoryStorage::new();
+    storage.store("TEST.sav", &[0; 0x2000]).unwrap();
+    let mut gb = Gameboy::from_rom(rom);
+    gb.set_storage(storage).unwrap();
+    assert!(!gb.has_unsaved_changes());
+    gb.run_frame();
+    assert!(gb.has_unsaved_changes());
+}
@@ -0,0 +1,63 @@
+//! 8x16 sprites are drawn from an even/odd pair of tiles, and vertical flip
+//! flips the whole 16 pixels rather than each tile on its own.
+
+mod common;
+
+use common::rom_with_program;
+use rustboy::Gameboy;
+
+/// A ROM that draws an 8x16 sprite in the top left corner with the given
+/// OAM flags. The top tile is solid black and the bottom one light gray
+/// except for a black last row.
+fn test_rom(flags: u8) -> Vec< u8 >
+{
+    rom_with_program(&[
+        0xAF, 0xE0, 0x40,               // xor a; ldh (LCDC), a
+        0x21, 0x20, 0x80,               // ld hl, 0x8020
+        0x3E, 0xFF, 0x06, 0x10,         // ld a, 0xFF; ld b, 16
+        0x22, 0x05, 0x20, 0xFC,         // ld (hl+), a; dec b; jr nz, -4
+        0x06, 0x07,                     // ld b, 7
+        0x3E, 0xFF, 0x22, 0xAF, 0x22,   // ld a, 0xFF; ld (hl+), a; xor a; ld (hl+), a
+        0x05, 0x20, 0xF8,               // dec b; jr nz, -8
+        0x3E, 0xFF, 0x22, 0x22,         // ld a, 0xFF; ld (hl+), a; ld (hl+), a
+
+        0x21, 0x00, 0xFE,               // ld hl, 0xFE00
+        0x3E, 0x10, 0x22,               // Y = 16
+        0x3E, 0x08, 0x22,               // X = 8
+        0x3E, 0x03, 0x22,               // Tile 3, drawn as tiles 2 and 3
+        0x3E, flags, 0x22,              // Flags
+
+        0x3E, 0xE4, 0xE0, 0x48,         // ld a, 0xE4; ldh (OBP0), a
+        0x3E, 0x97, 0xE0, 0x40,         // LCD on with 8x16 sprites
+        0x18, 0xFE                      // jr -2
+    ])
+}
+
+/// Run a couple of frames and get the red component of the first pixel of
+/// each of the top 16 lines
+fn sprite_column(flags: u8) -> Vec< u8 >
+{
+    let mut gb = Gameboy::from_rom(test_rom(flags));
+    gb.run_frame();
+    gb.run_frame();
+    let image = gb.get_image_data();
+    (0..16).map(|y| image[y * 160 * 4]).collect()
+}
+
+#[test]
+fn tall_sprite()
+{
+    let mut expected = vec![0; 8];
+    expected.extend_from_slice(&[192; 7]);
+    expected.push(0);
+    assert_eq!(sprite_column(0x00), expected);
+}
+
+#[test]
+fn flipped_tall_sprite()
+{
+    let mut expected = vec![0];
+    expected.extend_from_slice(&[192; 7]);
+    expected.extend_from_slice(&[0; 8]);
+    assert_eq!(sprite_column(0x40), expected);
+}
@@ -0,0 +1,106 @@
+//! When TIMA overflows it reads 0 for one M-cycle before TMA is loaded
+//! into it and the timer interrupt is requested. In the M-cycle TMA is
+//! loaded, writes to TIMA are ignored and writes to TMA load TIMA too.
+
+mod common;
+
+use common::rom_with_program;
+use rustboy::Gameboy;
+
+/// Samples of TIMA taken
+const SAMPLES: usize = 16;
+
+/// A ROM that runs the timer at one increment every 4 M-cycles with TMA =
+/// 0xFE, so TIMA overflows every 8 M-cycles, and samples TIMA into 0xC000
+/// every 5 M-cycles. Over 8 samples every M-cycle of the overflow period is
+/// sampled once. Afterwards the samples that read 0x00 are counted in D and
+/// those that read 0xFE in E, and IF is copied into B.
+fn test_rom() -> Vec< u8 >
+{
+    let mut prog = vec![
+        0x3E, 0xFE, 0xE0, 0x06,         // ld a, 0xFE; ldh (TMA), a
+        0xE0, 0x05,                     // ldh (TIMA), a
+        0x3E, 0x05, 0xE0, 0x07,         // ld a, 0x05; ldh (TAC), a
+        0xAF, 0xE0, 0x0F,               // xor a; ldh (IF), a
+        0x21, 0x00, 0xC0, 0x0E, 0x05    // ld hl, 0xC000; ld c, TIMA
+    ];
+    for _ in 0..SAMPLES
+    {
+        prog.extend_from_slice(&[0xF2, 0x22, 0x00]);    // ld a, (c); ld (hl+), a; nop
+    }
+    prog.extend_from_slice(&[
+        0xF0, 0x0F, 0xF5,               // ldh a, (IF); push af
+        0x21, 0x00, 0xC0, 0x06, SAMPLES as u8,  // ld hl, 0xC000; ld b, SAMPLES
+        0x16, 0x00, 0x1E, 0x00,         // ld d, 0; ld e, 0
+        0x2A, 0xB7, 0x20, 0x01, 0x14,   // ld a, (hl+); or a; jr nz, +1; inc d
+        0xFE, 0xFE, 0x20, 0x01, 0x1C,   // cp 0xFE; jr nz, +1; inc e
+        0x05, 0x20, 0xF3,               // dec b; jr nz, -13
+        0xF1, 0x47,                     // pop af; ld b, a
+        0x18, 0xFE                      // jr -2
+    ]);
+    rom_with_program(&prog)
+}
+
+#[test]
+fn tima_reads_zero_before_reload()
+{
+    let mut gb = Gameboy::from_rom(test_rom());
+    gb.run_frame();
+
+    // Each 8 M-cycle period TIMA reads 0x00 for 1 M-cycle, TMA for 3 and
+    // 0xFF for 4, and every M-cycle was sampled twice
+    let r = gb.registers();
+    assert_eq!(r.d, 2, "Samples of 0x00");
+    assert_eq!(r.e, 6, "Samples of 0xFE");
+    assert_ne!(r.b & 0x04, 0, "The overflow requested an interrupt");
+}
+
+/// A ROM that lets TIMA overflow with TMA = 0x80 and waits for it halted
+/// with interrupts disabled, which wakes it in the M-cycle TMA is loaded.
+/// After `delay` NOPs it writes 0x42 to `reg` (TIMA or TMA), then reads TIMA
+/// into B.
+fn reload_rom(reg: u8, delay: usize) -> Vec< u8 >
+{
+    let mut prog = vec![
+        0xF3,                           // di
+        0x3E, 0x80, 0xE0, 0x06,         // ld a, 0x80; ldh (TMA), a
+        0x3E, 0xF0, 0xE0, 0x05,         // ld a, 0xF0; ldh (TIMA), a
+        0x3E, 0x04, 0xE0, 0xFF,         // ld a, 0x04; ldh (IE), a
+        0x3E, 0x05, 0xE0, 0x07,         // ld a, 0x05; ldh (TAC), a
+        0xAF, 0xE0, 0x0F,               // xor a; ldh (IF), a
+        0x3E, 0x42,                     // ld a, 0x42
+        0x76                            // halt
+    ];
+    prog.resize(prog.len() + delay, 0x00);  // nop
+    prog.extend_from_slice(&[
+        0xE0, reg,                      // ldh (reg), a
+        0xF0, 0x05, 0x47,               // ldh a, (TIMA); ld b, a
+        0x18, 0xFE                      // jr -2
+    ]);
+    rom_with_program(&prog)
+}
+
+/// Run the reload ROM and get the TIMA it read, with the low bits that
+/// count the few M-cycles after the write cleared
+fn tima_after_write(reg: u8, delay: usize) -> u8
+{
+    let mut gb = Gameboy::from_rom(reload_rom(reg, delay));
+    gb.run_frame();
+    gb.registers().b & 0xF0
+}
+
+#[test]
+fn tima_write_during_reload_is_ignored()
+{
+    assert_eq!(tima_after_write(0x05, 0), 0x80);
+    assert_eq!(tima_after_write(0x05, 1), 0x40);
+    assert_eq!(tima_after_write(0x05, 2), 0x40);
+}
+
+#[test]
+fn tma_write_during_reload_loads_tima()
+{
+    assert_eq!(tima_after_write(0x06, 0), 0x40);
+    assert_eq!(tima_after_write(0x06, 1), 0x80);
+    assert_eq!(tima_after_write(0x06, 2), 0x80);
+}
@@ -0,0 +1,45 @@
+//! `run_until_vblank` stops as soon as a VBlank interrupt is requested,
+//! whether by the GPU or by the game itself.
+
+mod common;
+
+use common::rom_with_program;
+use rustboy::{ Gameboy, PpuMode, RunExit };
+
+/// A ROM that runs the given code and then spins forever
+fn test_rom(code: &[u8]) -> Vec< u8 >
+{
+    let mut prog = code.to_vec();
+    prog.extend_from_slice(&[0x18, 0xFE]);     // jr -2
+    rom_with_program(&prog)
+}
+
+#[test]
+fn stops_when_gpu_enters_vblank()
+{
+    let mut gb = Gameboy::from_rom(test_rom(&[]));
+    for frame in 1..=3
+    {
+        assert_eq!(gb.run_until_vblank(), RunExit::Completed);
+        assert_eq!(gb.frame_count(), frame);
+        assert_eq!(gb.ly(), 144);
+        assert_eq!(gb.ppu_mode(), PpuMode::VBlank);
+    }
+}
+
+#[test]
+fn stops_when_game_requests_vblank()
+{
+    let mut gb = Gameboy::from_rom(test_rom(&[
+        0xAF, 0xE0, 0x0F,   // xor a; ldh (IF), a
+        0x3C, 0xE0, 0x0F    // inc a; ldh (IF), a
+    ]));
+    assert_eq!(gb.run_until_vblank(), RunExit::Completed);
+    assert_eq!(gb.frame_count(), 0);
+    assert_eq!(gb.registers().pc, 0x156);
+
+    // The request is still pending, which doesn't stop the next VBlank from
+    // counting
+    assert_eq!(gb.run_until_vblank(), RunExit::Completed);
+    assert_eq!(gb.frame_count(), 1);
+}
@@ -0,0 +1,78 @@
+//! Replaying a movie against a log of frame hashes catches any change in
+//! what the game draws.
+
+mod common;
+
+use common::rom_with_program;
+use rustboy::{ ButtonState, Gameboy, HashLog, Movie };
+
+/// A ROM that keeps copying the action buttons into BGP, so the blank BG
+/// changes shade with the A and B buttons
+fn test_rom() -> Vec< u8 >
+{
+    rom_with_program(&[
+        0x3E, 0x91, 0xE0, 0x40,         // ld a, 0x91; ldh (LCDC), a
+        0x3E, 0x10, 0xE0, 0x00,         // ld a, 0x10; ldh (P1), a
+        0xF0, 0x00, 0xE0, 0x47,         // ldh a, (P1); ldh (BGP), a
+        0x18, 0xFA                      // jr -6
+    ])
+}
+
+/// A movie of the given length that presses A on every frame in `pressed`
+fn movie(len: usize, pressed: std::ops::Range< usize >) -> Movie
+{
+    let mut movie = Movie::new();
+    for i in 0..len
+    {
+        movie.push(if pressed.contains(&i) { ButtonState::A } else { ButtonState::empty() });
+    }
+    movie
+}
+
+fn record(movie: &Movie) -> HashLog
+{
+    HashLog::record(&mut Gameboy::from_rom(test_rom()), movie)
+}
+
+fn verify(log: &HashLog, movie: &Movie) -> Result< (), rustboy::Divergence >
+{
+    log.verify(&mut Gameboy::from_rom(test_rom()), movie)
+}
+
+#[test]
+fn replay_matches_log()
+{
+    let movie = movie(1200, 100..200);
+    let log = record(&movie);
+    let frames: Vec< u64 > = log.hashes().iter().map(|&(frame, _)| frame).collect();
+    assert_eq!(frames, vec![1000, 1200]);
+    assert_eq!(verify(&log, &movie), Ok(()));
+    assert_eq!(HashLog::parse(&log.to_text()).unwrap(), log);
+}
+
+#[test]
+fn changed_input_diverges()
+{
+    let log = record(&movie(300, 250..300));
+    let divergence = verify(&log, &movie(300, 0..0)).unwrap_err();
+    assert_eq!(divergence.frame, 300);
+    assert_eq!(divergence.expected, log.hashes()[0].1);
+    assert!(divergence.actual.is_some());
+}
+
+#[test]
+fn shorter_movie_diverges()
+{
+    let log = record(&movie(300, 0..0));
+    let divergence = verify(&log, &movie(200, 0..0)).unwrap_err();
+    assert_eq!(divergence.frame, 300);
+    assert_eq!(divergence.actual, None);
+}
+
+#[test]
+fn invalid_log()
+{
+    assert!(HashLog::parse("# comment\n\n1000 00ff\n").is_ok());
+    assert!(HashLog::parse("1000\n").is_err());
+    assert!(HashLog::parse("1000 xyz\n").is_err());
+}
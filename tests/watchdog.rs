@@ -0,0 +1,74 @@
+//! The watchdog stops emulation when a game spins in a tiny loop with the
+//! LCD off, and leaves games that are running normally alone.
+
+mod common;
+
+use common::rom_with_program;
+use rustboy::{ BankAddress, Gameboy, RunExit };
+
+/// A ROM that runs a program after turning the LCD off
+fn test_rom(prog: &[u8]) -> Vec< u8 >
+{
+    let mut code = vec![0xAF, 0xE0, 0x40];     // xor a; ldh (LCDC), a
+    code.extend_from_slice(prog);
+    rom_with_program(&code)
+}
+
+/// Run until something other than completing a frame happens, giving up
+/// after a while
+fn run_until_exit(gb: &mut Gameboy) -> RunExit
+{
+    for _ in 0..200
+    {
+        match gb.run_frame()
+        {
+            RunExit::Completed => {},
+            exit => return exit
+        }
+    }
+    RunExit::Completed
+}
+
+#[test]
+fn reports_tight_loop_with_lcd_off()
+{
+    let mut gb = Gameboy::from_rom(test_rom(&[
+        0x00, 0x00,                     // nop; nop
+        0x18, 0xFC                      // jr -4
+    ]));
+    gb.set_watchdog(true);
+
+    match run_until_exit(&mut gb)
+    {
+        RunExit::Hung(hang) => {
+            assert_eq!(hang.start, BankAddress { bank: 0, addr: 0x0153 });
+            assert_eq!(hang.end, BankAddress { bank: 0, addr: 0x0155 });
+            assert!(hang.to_string().contains("00:0153 and 00:0155"));
+        },
+        exit => panic!("Expected a hang, got {:?}", exit)
+    }
+}
+
+#[test]
+fn disabled_by_default()
+{
+    let mut gb = Gameboy::from_rom(test_rom(&[0x18, 0xFE]));
+    assert_eq!(run_until_exit(&mut gb), RunExit::Completed);
+}
+
+#[test]
+fn lcd_on_and_stop_are_not_hangs()
+{
+    // A game waiting for the next frame with the LCD on
+    let mut gb = Gameboy::from_rom(test_rom(&[
+        0x3E, 0x80, 0xE0, 0x40,         // ld a, 0x80; ldh (LCDC), a
+        0x18, 0xFE                      // jr -2
+    ]));
+    gb.set_watchdog(true);
+    assert_eq!(run_until_exit(&mut gb), RunExit::Completed);
+
+    // A game sleeping until a button is pressed
+    let mut gb = Gameboy::from_rom(test_rom(&[0x10, 0x00, 0x18, 0xFC]));
+    gb.set_watchdog(true);
+    assert_eq!(run_until_exit(&mut gb), RunExit::Completed);
+}